@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY UNIQUE NOT NULL,
+    name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    password TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+-- databases created before migrations existed already have the type
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'role') THEN
+        CREATE TYPE ROLE AS ENUM ('User', 'Manager', 'Admin');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS roles (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    role ROLE NOT NULL default 'User',
+    firebase_uid TEXT not null references users(id) on delete cascade,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS local_identities (
+    uid TEXT PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    display_name TEXT,
+    email_verified BOOLEAN NOT NULL default false,
+    disabled BOOLEAN NOT NULL default false,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
@@ -0,0 +1 @@
+ALTER TABLE IF EXISTS roles DROP CONSTRAINT IF EXISTS roles_firebase_uid_role_key;
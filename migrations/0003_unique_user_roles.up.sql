@@ -0,0 +1,8 @@
+-- keep the oldest row of every duplicated (firebase_uid, role)
+DELETE FROM roles duplicate
+USING roles kept
+WHERE duplicate.firebase_uid = kept.firebase_uid
+    AND duplicate.role = kept.role
+    AND (duplicate.created_at, duplicate.id) > (kept.created_at, kept.id);
+
+ALTER TABLE roles ADD CONSTRAINT roles_firebase_uid_role_key UNIQUE (firebase_uid, role);
@@ -0,0 +1,34 @@
+CREATE TABLE IF NOT EXISTS permissions (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL default '',
+    created_at TIMESTAMPTZ NOT NULL default now()
+);
+
+-- a role also gets the permissions of the roles below it
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role ROLE NOT NULL,
+    permission TEXT NOT NULL references permissions(name) on delete cascade,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    PRIMARY KEY (role, permission)
+);
+
+-- keep in sync with DEFAULT_PERMISSIONS and DEFAULT_ROLE_PERMISSIONS in src/structs/permission.rs
+INSERT INTO permissions (name, description) VALUES
+    ('user:read', 'Read your own profile'),
+    ('user:update', 'Update your own profile'),
+    ('user:read_email', 'Read the email of other users'),
+    ('dataset:read', 'Read datasets'),
+    ('dataset:write', 'Create and update datasets'),
+    ('role:write', 'Grant and revoke roles'),
+    ('permission:write', 'Edit the permissions of roles')
+ON CONFLICT DO NOTHING;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('User', 'user:read'),
+    ('User', 'user:update'),
+    ('Manager', 'user:read_email'),
+    ('Manager', 'dataset:read'),
+    ('Manager', 'dataset:write'),
+    ('Admin', 'role:write'),
+    ('Admin', 'permission:write')
+ON CONFLICT DO NOTHING;
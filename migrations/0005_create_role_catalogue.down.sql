@@ -0,0 +1,32 @@
+-- custom roles can't be represented by the enum, their grants are dropped
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'role') THEN
+        CREATE TYPE ROLE AS ENUM ('User', 'Manager', 'Admin');
+    END IF;
+END
+$$;
+
+DO $$
+BEGIN
+    IF to_regclass('roles') IS NOT NULL THEN
+        DELETE FROM roles WHERE role NOT IN ('User', 'Manager', 'Admin');
+    END IF;
+END
+$$;
+ALTER TABLE IF EXISTS roles DROP CONSTRAINT IF EXISTS roles_role_fkey;
+ALTER TABLE IF EXISTS roles ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE IF EXISTS roles ALTER COLUMN role TYPE ROLE USING role::ROLE;
+ALTER TABLE IF EXISTS roles ALTER COLUMN role SET DEFAULT 'User';
+
+DO $$
+BEGIN
+    IF to_regclass('role_permissions') IS NOT NULL THEN
+        DELETE FROM role_permissions WHERE role NOT IN ('User', 'Manager', 'Admin');
+    END IF;
+END
+$$;
+ALTER TABLE IF EXISTS role_permissions DROP CONSTRAINT IF EXISTS role_permissions_role_fkey;
+ALTER TABLE IF EXISTS role_permissions ALTER COLUMN role TYPE ROLE USING role::ROLE;
+
+DROP TABLE IF EXISTS role_definitions;
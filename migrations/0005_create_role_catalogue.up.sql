@@ -0,0 +1,28 @@
+-- roles become data, a role implies its parent and so on up the chain
+CREATE TABLE IF NOT EXISTS role_definitions (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL default '',
+    parent TEXT references role_definitions(name) on update cascade,
+    built_in BOOLEAN NOT NULL default false,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+-- keep in sync with RoleDefinition::defaults in src/structs/role.rs
+INSERT INTO role_definitions (name, description, parent, built_in) VALUES
+    ('User', 'Every signed up user', NULL, true),
+    ('Manager', 'Manages datasets and reads other users', 'User', true),
+    ('Admin', 'Manages users, roles and permissions', 'Manager', true)
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE roles ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE roles ALTER COLUMN role TYPE TEXT USING role::text;
+ALTER TABLE roles ALTER COLUMN role SET DEFAULT 'User';
+ALTER TABLE roles ADD CONSTRAINT roles_role_fkey
+    FOREIGN KEY (role) REFERENCES role_definitions(name) on update cascade on delete cascade;
+
+ALTER TABLE role_permissions ALTER COLUMN role TYPE TEXT USING role::text;
+ALTER TABLE role_permissions ADD CONSTRAINT role_permissions_role_fkey
+    FOREIGN KEY (role) REFERENCES role_definitions(name) on update cascade on delete cascade;
+
+DROP TYPE IF EXISTS ROLE;
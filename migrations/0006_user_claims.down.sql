@@ -0,0 +1,3 @@
+ALTER TABLE IF EXISTS local_identities DROP COLUMN IF EXISTS custom_claims;
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS claims_version;
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS plan;
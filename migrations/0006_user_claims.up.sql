@@ -0,0 +1,7 @@
+-- pushed into the custom claims of the auth provider along with the roles
+ALTER TABLE users ADD COLUMN IF NOT EXISTS plan TEXT NOT NULL default 'free'
+    CHECK (plan IN ('free', 'premium'));
+-- bumped whenever roles or plan change, tokens carrying an older version hold stale claims
+ALTER TABLE users ADD COLUMN IF NOT EXISTS claims_version BIGINT NOT NULL default 0;
+
+ALTER TABLE local_identities ADD COLUMN IF NOT EXISTS custom_claims JSONB NOT NULL default '{}';
@@ -0,0 +1,21 @@
+-- customers, users belong to them through organization_members
+CREATE TABLE IF NOT EXISTS organizations (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+-- one role per member, it implies the roles above it like the global ones,
+-- members of a deleted custom role fall back to User
+CREATE TABLE IF NOT EXISTS organization_members (
+    organization_id UUID NOT NULL references organizations(id) on delete cascade,
+    user_id TEXT NOT NULL references users(id) on delete cascade,
+    role TEXT NOT NULL default 'User'
+        references role_definitions(name) on update cascade on delete set default,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now(),
+    PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS organization_members_user_id_idx ON organization_members (user_id);
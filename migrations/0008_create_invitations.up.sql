@@ -0,0 +1,21 @@
+-- invitations to join the platform (no organization) or an organization with a role,
+-- only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS invitations (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL references role_definitions(name) on update cascade on delete cascade,
+    organization_id UUID references organizations(id) on delete cascade,
+    token_hash TEXT NOT NULL UNIQUE,
+    invited_by TEXT references users(id) on delete set null,
+    expires_at TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ,
+    accepted_by TEXT references users(id) on delete set null,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+-- a single open invitation per email and organization, resend it rather than inviting again
+CREATE UNIQUE INDEX IF NOT EXISTS invitations_pending_key
+    ON invitations (lower(email), coalesce(organization_id::text, ''))
+    WHERE accepted_at IS NULL AND revoked_at IS NULL;
@@ -0,0 +1,34 @@
+-- the tables may be gone already, policies can only be dropped from an existing table
+DO $$
+BEGIN
+    IF to_regclass('roles') IS NOT NULL THEN
+        DROP POLICY IF EXISTS roles_write_admin ON roles;
+        DROP POLICY IF EXISTS roles_insert_base_role ON roles;
+        DROP POLICY IF EXISTS roles_read_self_or_admin ON roles;
+    END IF;
+    IF to_regclass('users') IS NOT NULL THEN
+        DROP POLICY IF EXISTS users_self_or_admin ON users;
+    END IF;
+END
+$$;
+ALTER TABLE IF EXISTS roles DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS users DISABLE ROW LEVEL SECURITY;
+
+DROP FUNCTION IF EXISTS app_can_access(TEXT);
+DROP FUNCTION IF EXISTS app_is_admin();
+DROP FUNCTION IF EXISTS app_uid();
+
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'data_intuitive_request') THEN
+        -- privileges and default privileges in this database
+        DROP OWNED BY data_intuitive_request;
+        -- roles belong to the cluster, another database may still grant it privileges
+        BEGIN
+            DROP ROLE data_intuitive_request;
+        EXCEPTION WHEN dependent_objects_still_exist THEN
+            RAISE NOTICE 'data_intuitive_request is still used by another database, kept';
+        END;
+    END IF;
+END
+$$;
@@ -0,0 +1,45 @@
+-- role taken by the transactions of a request, see database/connection.rs. It has no BYPASSRLS and owns
+-- no table, so the policies below apply to it even when the server connects as a superuser
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'data_intuitive_request') THEN
+        CREATE ROLE data_intuitive_request NOLOGIN NOSUPERUSER NOBYPASSRLS;
+    END IF;
+END
+$$;
+GRANT data_intuitive_request TO CURRENT_USER;
+
+GRANT USAGE ON SCHEMA public TO data_intuitive_request;
+GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO data_intuitive_request;
+GRANT USAGE, SELECT ON ALL SEQUENCES IN SCHEMA public TO data_intuitive_request;
+REVOKE ALL ON schema_migrations FROM data_intuitive_request;
+-- tables of later migrations
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO data_intuitive_request;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    GRANT USAGE, SELECT ON SEQUENCES TO data_intuitive_request;
+
+-- uid of the request, NULL outside of one
+CREATE OR REPLACE FUNCTION app_uid() RETURNS TEXT LANGUAGE sql STABLE AS $$
+    SELECT nullif(current_setting('app.uid', true), '')
+$$;
+
+-- the request holds the global Admin role
+CREATE OR REPLACE FUNCTION app_is_admin() RETURNS BOOLEAN LANGUAGE sql STABLE AS $$
+    SELECT coalesce(nullif(current_setting('app.roles', true), ''), '[]')::jsonb ? 'Admin'
+$$;
+
+-- rows of a user are for the user itself and for Admins, the policies of later tables reuse it
+CREATE OR REPLACE FUNCTION app_can_access(owner_uid TEXT) RETURNS BOOLEAN LANGUAGE sql STABLE AS $$
+    SELECT coalesce(owner_uid = app_uid(), false) OR app_is_admin()
+$$;
+
+ALTER TABLE users ENABLE ROW LEVEL SECURITY;
+CREATE POLICY users_self_or_admin ON users USING (app_can_access(id));
+
+ALTER TABLE roles ENABLE ROW LEVEL SECURITY;
+CREATE POLICY roles_read_self_or_admin ON roles FOR SELECT USING (app_can_access(firebase_uid));
+-- a user takes the base role when signing up, anything else is granted by Admins
+CREATE POLICY roles_insert_base_role ON roles FOR INSERT
+    WITH CHECK (firebase_uid = app_uid() AND role = 'User');
+CREATE POLICY roles_write_admin ON roles USING (app_is_admin());
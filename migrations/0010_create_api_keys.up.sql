@@ -0,0 +1,19 @@
+-- keys of machine clients, each acts as its user with at most the roles in scopes
+CREATE TABLE IF NOT EXISTS api_keys (
+    id UUID PRIMARY KEY,
+    user_id TEXT NOT NULL references users(id) on delete cascade,
+    name TEXT NOT NULL,
+    scopes TEXT[] NOT NULL default '{User}',
+    -- bcrypt of the secret part of the key, the id part finds the row
+    key_hash TEXT NOT NULL,
+    created_by TEXT references users(id) on delete set null,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+CREATE INDEX IF NOT EXISTS api_keys_user_id_idx ON api_keys (user_id);
+
+ALTER TABLE api_keys ENABLE ROW LEVEL SECURITY;
+CREATE POLICY api_keys_self_or_admin ON api_keys USING (app_can_access(user_id));
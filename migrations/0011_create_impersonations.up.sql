@@ -0,0 +1,36 @@
+-- sessions in which an Admin acts as another user, no foreign keys so the audit outlives both users
+CREATE TABLE IF NOT EXISTS impersonations (
+    id UUID PRIMARY KEY,
+    admin_uid TEXT NOT NULL,
+    target_uid TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    read_only BOOLEAN NOT NULL default true,
+    expires_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL default now(),
+    updated_at TIMESTAMPTZ NOT NULL default now()
+);
+
+CREATE INDEX IF NOT EXISTS impersonations_admin_uid_idx ON impersonations (admin_uid);
+CREATE INDEX IF NOT EXISTS impersonations_target_uid_idx ON impersonations (target_uid);
+
+-- every request made during a session, with the uid making it and the uid it acts as
+CREATE TABLE IF NOT EXISTS impersonation_events (
+    id UUID PRIMARY KEY,
+    impersonation_id UUID NOT NULL references impersonations(id),
+    real_uid TEXT NOT NULL,
+    effective_uid TEXT NOT NULL,
+    operation_type TEXT NOT NULL,
+    operation_name TEXT,
+    blocked BOOLEAN NOT NULL default false,
+    created_at TIMESTAMPTZ NOT NULL default now()
+);
+
+CREATE INDEX IF NOT EXISTS impersonation_events_impersonation_id_idx ON impersonation_events (impersonation_id);
+
+ALTER TABLE impersonations ENABLE ROW LEVEL SECURITY;
+CREATE POLICY impersonations_admin ON impersonations USING (app_is_admin());
+ALTER TABLE impersonation_events ENABLE ROW LEVEL SECURITY;
+CREATE POLICY impersonation_events_admin ON impersonation_events USING (app_is_admin());
+-- the audit is append only for requests
+REVOKE UPDATE, DELETE ON impersonation_events FROM data_intuitive_request;
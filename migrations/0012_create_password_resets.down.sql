@@ -0,0 +1,2 @@
+ALTER TABLE IF EXISTS local_identities DROP COLUMN IF EXISTS sessions_valid_after;
+DROP TABLE IF EXISTS password_resets;
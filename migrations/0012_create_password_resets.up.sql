@@ -0,0 +1,17 @@
+-- single-use links to choose a new password, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS password_resets (
+    id UUID PRIMARY KEY,
+    user_id TEXT NOT NULL references users(id) on delete cascade,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL default now()
+);
+
+CREATE INDEX IF NOT EXISTS password_resets_user_id_idx ON password_resets (user_id);
+
+-- requests never read them, resets are redeemed by the server itself before anyone is signed in
+ALTER TABLE password_resets ENABLE ROW LEVEL SECURITY;
+
+-- tokens of the local provider issued before are refused, like validSince of firebase
+ALTER TABLE local_identities ADD COLUMN IF NOT EXISTS sessions_valid_after TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS email_verified;
+DROP TABLE IF EXISTS email_verifications;
@@ -0,0 +1,18 @@
+-- single-use links proving a user owns its email, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS email_verifications (
+    id UUID PRIMARY KEY,
+    user_id TEXT NOT NULL references users(id) on delete cascade,
+    -- the email the link was sent to, it no longer verifies anything once the user changes it
+    email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL default now()
+);
+
+CREATE INDEX IF NOT EXISTS email_verifications_user_id_idx ON email_verifications (user_id);
+
+-- requests never read them, links are redeemed by the server itself, maybe before anyone is signed in
+ALTER TABLE email_verifications ENABLE ROW LEVEL SECURITY;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified BOOLEAN NOT NULL default false;
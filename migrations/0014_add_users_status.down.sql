@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS users_deleted_at_idx;
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS status_reason;
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE IF EXISTS users DROP COLUMN IF EXISTS disabled;
@@ -0,0 +1,36 @@
+-- the tables may be gone already, policies can only be dropped from an existing table
+DO $$
+BEGIN
+    IF to_regclass('role_permissions') IS NOT NULL THEN
+        DROP POLICY IF EXISTS role_permissions_write_admin ON role_permissions;
+        DROP POLICY IF EXISTS role_permissions_read ON role_permissions;
+    END IF;
+    IF to_regclass('permissions') IS NOT NULL THEN
+        DROP POLICY IF EXISTS permissions_write_admin ON permissions;
+        DROP POLICY IF EXISTS permissions_read ON permissions;
+    END IF;
+    IF to_regclass('role_definitions') IS NOT NULL THEN
+        DROP POLICY IF EXISTS role_definitions_write_admin ON role_definitions;
+        DROP POLICY IF EXISTS role_definitions_read ON role_definitions;
+    END IF;
+    IF to_regclass('invitations') IS NOT NULL THEN
+        DROP POLICY IF EXISTS invitations_manager_or_member ON invitations;
+    END IF;
+    IF to_regclass('organization_members') IS NOT NULL THEN
+        DROP POLICY IF EXISTS organization_members_member_or_admin ON organization_members;
+    END IF;
+    IF to_regclass('organizations') IS NOT NULL THEN
+        DROP POLICY IF EXISTS organizations_member_or_admin ON organizations;
+    END IF;
+END
+$$;
+ALTER TABLE IF EXISTS role_permissions DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS permissions DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS role_definitions DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS invitations DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS organization_members DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS organizations DISABLE ROW LEVEL SECURITY;
+ALTER TABLE IF EXISTS local_identities DISABLE ROW LEVEL SECURITY;
+
+DROP FUNCTION IF EXISTS app_is_member(UUID);
+DROP FUNCTION IF EXISTS app_has_role(TEXT);
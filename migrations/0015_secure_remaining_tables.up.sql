@@ -0,0 +1,51 @@
+-- every table the request role was granted gets row level security, see 0009
+
+-- the request holds a global role, app.roles already carries the roles it implies
+CREATE OR REPLACE FUNCTION app_has_role(name TEXT) RETURNS BOOLEAN LANGUAGE sql STABLE AS $$
+    SELECT coalesce(nullif(current_setting('app.roles', true), ''), '[]')::jsonb ? name
+$$;
+
+-- the request is a member of the organization, runs as the owner of organization_members
+-- so its own policy doesn't recurse into it
+CREATE OR REPLACE FUNCTION app_is_member(organization UUID) RETURNS BOOLEAN
+    LANGUAGE sql STABLE SECURITY DEFINER SET search_path = public AS $$
+    SELECT EXISTS (
+        SELECT 1 FROM organization_members WHERE organization_id = organization AND user_id = app_uid()
+    )
+$$;
+
+-- requests never read them, the local provider reads password hashes and claims as the server
+ALTER TABLE local_identities ENABLE ROW LEVEL SECURITY;
+
+-- a new organization has no member yet, it is created by the server, see Mutation::create_organization
+ALTER TABLE organizations ENABLE ROW LEVEL SECURITY;
+CREATE POLICY organizations_member_or_admin ON organizations
+    USING (app_is_member(id) OR app_is_admin());
+
+ALTER TABLE organization_members ENABLE ROW LEVEL SECURITY;
+CREATE POLICY organization_members_member_or_admin ON organization_members
+    USING (app_is_member(organization_id) OR app_is_admin());
+
+-- invitations to the platform are for global Managers, those to an organization for its members,
+-- the invitee redeems its token through the server
+ALTER TABLE invitations ENABLE ROW LEVEL SECURITY;
+CREATE POLICY invitations_manager_or_member ON invitations
+    USING (
+        app_is_admin() OR CASE
+            WHEN organization_id IS NULL THEN app_has_role('Manager')
+            ELSE app_is_member(organization_id)
+        END
+    );
+
+-- the catalogue is read by every guard, written by Admins only
+ALTER TABLE role_definitions ENABLE ROW LEVEL SECURITY;
+CREATE POLICY role_definitions_read ON role_definitions FOR SELECT USING (true);
+CREATE POLICY role_definitions_write_admin ON role_definitions USING (app_is_admin());
+
+ALTER TABLE permissions ENABLE ROW LEVEL SECURITY;
+CREATE POLICY permissions_read ON permissions FOR SELECT USING (true);
+CREATE POLICY permissions_write_admin ON permissions USING (app_is_admin());
+
+ALTER TABLE role_permissions ENABLE ROW LEVEL SECURITY;
+CREATE POLICY role_permissions_read ON role_permissions FOR SELECT USING (true);
+CREATE POLICY role_permissions_write_admin ON role_permissions USING (app_is_admin());
@@ -0,0 +1,209 @@
+use chrono::Utc;
+
+use crate::{
+    database::error::DatabaseError,
+    errors::main::AppError,
+    structs::account_export::AccountExport,
+    traits::{
+        api_key::ApiKeyTrait,
+        auth::{AuthError, AuthProvider},
+        email_verification::EmailVerificationTrait,
+        identity::IdentityTrait,
+        impersonation::ImpersonationTrait,
+        invitation::InvitationTrait,
+        organization::OrganizationTrait,
+        password_reset::PasswordResetTrait,
+        user::UserTrait,
+    },
+};
+
+/*
+    * Delete a user for good, its rows then the identity of the auth provider
+    The rows go first, the last Admin is refused while it can still sign in. A deletion stopped
+    half way is finished by calling it again.
+    @param auth: &A, the auth provider
+    @param database: &D, unscoped, the last Admin checks need every Admin row
+    @param uid: &str
+    @return (), NotFound when neither exists, Conflict for the last Admin
+*/
+pub async fn delete_account<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+) -> Result<(), AppError> {
+    let deleted = match database.delete_user(uid).await {
+        Ok(()) => true,
+        Err(DatabaseError::NotFound) => false,
+        Err(e) => return Err(e.into()),
+    };
+    match auth.delete_identity(uid).await {
+        Ok(()) => Ok(()),
+        // users seeded straight into the database have no identity
+        Err(AuthError::NotFound) if deleted => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    * Delete the account of a signed in user, knowing its password
+    @param auth: &A, the auth provider
+    @param database: &D, unscoped
+    @param uid: &str
+    @param password: &str, checked by the auth provider
+    @return (), InvalidCredentials when the password is wrong
+*/
+pub async fn delete_my_account<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    password: &str,
+) -> Result<(), AppError> {
+    if !auth.check_password(uid, password).await? {
+        return Err(AppError::InvalidCredentials);
+    }
+    delete_account(auth, database, uid).await
+}
+
+/*
+    * Gather every record stored about a user
+    @param database: &D, unscoped, password resets and impersonations aren't readable by requests
+    @param uid: &str
+    @return AccountExport, NotFound for an unknown user
+*/
+pub async fn export_account<D>(database: &D, uid: &str) -> Result<AccountExport, AppError>
+where
+    D: UserTrait
+        + IdentityTrait
+        + OrganizationTrait
+        + ApiKeyTrait
+        + InvitationTrait
+        + ImpersonationTrait
+        + PasswordResetTrait
+        + EmailVerificationTrait,
+{
+    let user = database.get_user(uid).await?;
+    let mut memberships = Vec::new();
+    for organization in database.get_user_organizations(uid).await? {
+        if let Some(membership) = database.get_membership(&organization.id, uid).await? {
+            memberships.push((organization, membership));
+        }
+    }
+    Ok(AccountExport {
+        exported_at: Utc::now(),
+        user,
+        roles: database.get_user_roles(uid).await?,
+        identity: database.get_identity(uid).await?,
+        memberships,
+        api_keys: database.get_user_api_keys(uid).await?,
+        invitations: database.get_user_invitations(uid).await?,
+        impersonations: database.get_impersonations(Some(uid)).await?,
+        password_resets: database.get_user_password_resets(uid).await?,
+        email_verifications: database.get_user_email_verifications(uid).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{main::AuthService, signup::sign_up},
+        database::backend::Database,
+        structs::{claims::Plan, role::Role, user::User},
+        utils::Utils,
+    };
+
+    fn new_user(email: &str) -> User {
+        User {
+            id: None,
+            name: "Jane".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    async fn signed_up(auth: &AuthService, database: &Database, email: &str) -> String {
+        sign_up(auth, database, &new_user(email))
+            .await
+            .unwrap()
+            .id
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delete_my_account() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let uid = signed_up(&auth, &database, "jane@example.com").await;
+        let admin = signed_up(&auth, &database, "john@example.com").await;
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+
+        assert!(matches!(
+            delete_my_account(&auth, &database, &uid, "wrong").await,
+            Err(AppError::InvalidCredentials)
+        ));
+        delete_my_account(&auth, &database, &uid, "password")
+            .await
+            .unwrap();
+        assert!(matches!(
+            database.get_user(&uid).await,
+            Err(DatabaseError::NotFound)
+        ));
+        assert!(database.get_identity(&uid).await.unwrap().is_none());
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        assert!(matches!(
+            delete_account(&auth, &database, &uid).await,
+            Err(AppError::NotFound)
+        ));
+
+        // the last Admin keeps its identity
+        assert!(matches!(
+            delete_my_account(&auth, &database, &admin, "password").await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(auth.sign_in("john@example.com", "password").await.is_ok());
+
+        // an identity left behind by an interrupted deletion
+        let uid = signed_up(&auth, &database, "jack@example.com").await;
+        database.delete_user(&uid).await.unwrap();
+        delete_account(&auth, &database, &uid).await.unwrap();
+        assert!(database.get_identity(&uid).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_export_account() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let uid = signed_up(&auth, &database, "jane@example.com").await;
+        let organization = database.create_organization("Acme", &uid).await.unwrap();
+
+        let export = export_account(&database, &uid).await.unwrap();
+        assert_eq!(export.user.email, "jane@example.com");
+        assert_eq!(export.roles, vec![Role::USER]);
+        assert_eq!(
+            export
+                .identity
+                .as_ref()
+                .map(|identity| identity.email.as_str()),
+            Some("jane@example.com")
+        );
+        assert_eq!(export.memberships.len(), 1);
+        assert_eq!(export.memberships[0].0.id, organization.id);
+        assert_eq!(export.memberships[0].1.role, Role::ADMIN);
+        assert!(export.api_keys.is_empty());
+        let json = export.to_json();
+        assert_eq!(json["organizations"][0]["name"], "Acme");
+        assert!(!json.to_string().contains("$2b$"));
+
+        assert!(matches!(
+            export_account(&database, "nobody").await,
+            Err(AppError::NotFound)
+        ));
+    }
+}
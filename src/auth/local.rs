@@ -0,0 +1,427 @@
+use std::{path::PathBuf, time::Duration};
+
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{backend::Database, error::DatabaseError},
+    structs::{
+        claims::UserClaims,
+        identity::LocalIdentity,
+        user::{hash_password, User},
+    },
+    traits::{
+        auth::{AuthClaims, AuthError, AuthProvider},
+        identity::IdentityTrait,
+    },
+};
+
+#[derive(Clone, Debug)]
+pub struct LocalAuthConfig {
+    // PEM encoded RSA keys, tokens are signed with RS256
+    pub private_key: PathBuf,
+    pub public_key: PathBuf,
+    pub issuer: String,
+    pub token_ttl: Duration,
+}
+
+impl LocalAuthConfig {
+    /*
+        * Read and parse both keys
+        @return (EncodingKey, DecodingKey)
+    */
+    pub fn keys(&self) -> Result<(EncodingKey, DecodingKey), String> {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))
+        };
+        let encoding_key = EncodingKey::from_rsa_pem(&read(&self.private_key)?)
+            .map_err(|e| format!("invalid private key {}: {}", self.private_key.display(), e))?;
+        let decoding_key = DecodingKey::from_rsa_pem(&read(&self.public_key)?)
+            .map_err(|e| format!("invalid public key {}: {}", self.public_key.display(), e))?;
+        Ok((encoding_key, decoding_key))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocalClaims {
+    iss: String,
+    sub: String,
+    iat: i64,
+    exp: i64,
+    email: String,
+    email_verified: bool,
+    #[serde(flatten)]
+    custom_claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/*
+    * Auth provider without any external service, identities live in postgres
+    and tokens are signed with a key read from disk. Used by tests and on-prem deployments.
+*/
+#[derive(Clone)]
+pub struct LocalAuth {
+    database: Database,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    issuer: String,
+    token_ttl: Duration,
+}
+
+fn internal(e: impl std::fmt::Display) -> AuthError {
+    AuthError::Upstream(e.to_string())
+}
+
+impl LocalAuth {
+    /*
+        * Create a local auth provider
+        @param config: &LocalAuthConfig, validated when the configuration is loaded
+        @param database: Database where identities are stored
+        @return LocalAuth
+    */
+    pub fn new(config: &LocalAuthConfig, database: Database) -> LocalAuth {
+        let (encoding_key, decoding_key) = config
+            .keys()
+            .unwrap_or_else(|e| panic!("Invalid local auth keys: {}", e));
+        LocalAuth {
+            database,
+            encoding_key,
+            decoding_key,
+            issuer: config.issuer.clone(),
+            token_ttl: config.token_ttl,
+        }
+    }
+
+    /*
+        * Sign a token for an identity
+        @param identity: &LocalIdentity
+        @return signed token
+    */
+    pub fn issue_token(&self, identity: &LocalIdentity) -> Result<String, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let claims = LocalClaims {
+            iss: self.issuer.clone(),
+            sub: identity.uid.clone(),
+            iat: now,
+            exp: now + self.token_ttl.as_secs() as i64,
+            email: identity.email.clone(),
+            email_verified: identity.email_verified,
+            custom_claims: identity.custom_claims.clone(),
+        };
+        encode(&Header::new(Algorithm::RS256), &claims, &self.encoding_key).map_err(internal)
+    }
+
+    async fn identity(&self, uid: &str) -> Result<LocalIdentity, AuthError> {
+        self.database
+            .get_identity(uid)
+            .await
+            .map_err(internal)?
+            .ok_or(AuthError::NotFound)
+    }
+
+    async fn save(&self, identity: &LocalIdentity) -> Result<(), AuthError> {
+        match self.database.update_identity(identity).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(AuthError::NotFound),
+            Err(DatabaseError::Conflict(_)) => Err(AuthError::Conflict(identity.email.clone())),
+            Err(e) => Err(internal(e)),
+        }
+    }
+}
+
+fn hash(password: &str) -> Result<String, AuthError> {
+    hash_password(password).map_err(internal)
+}
+
+impl AuthProvider for LocalAuth {
+    async fn verify_token(&self, token: &str) -> Result<AuthClaims, AuthError> {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[&self.issuer]);
+        validation.leeway = 0;
+        let token = decode::<serde_json::Map<String, serde_json::Value>>(
+            token,
+            &self.decoding_key,
+            &validation,
+        )
+        .map_err(|_| AuthError::Unauthorized)?;
+        let claims = token.claims;
+        let uid = claims["sub"].as_str().ok_or(AuthError::Unauthorized)?;
+        // revoked by a password change, disabled, or gone
+        let identity = self.identity(uid).await.map_err(|e| match e {
+            AuthError::NotFound => AuthError::Unauthorized,
+            e => e,
+        })?;
+        let issued_at = claims["iat"].as_i64().ok_or(AuthError::Unauthorized)?;
+        if identity.disabled
+            || identity
+                .sessions_valid_after
+                .is_some_and(|valid_after| issued_at < valid_after.timestamp())
+        {
+            return Err(AuthError::Unauthorized);
+        }
+        Ok(AuthClaims {
+            uid: uid.to_string(),
+            email: claims
+                .get("email")
+                .and_then(|e| e.as_str())
+                .map(String::from),
+            email_verified: claims.get("email_verified").and_then(|e| e.as_bool()) == Some(true),
+            expires_at: claims["exp"].as_i64().ok_or(AuthError::Unauthorized)?,
+            claims,
+        })
+    }
+
+    async fn sign_in(&self, email: &str, password: &str) -> Result<String, AuthError> {
+        let identity = self
+            .database
+            .get_identity_by_email(email)
+            .await
+            .map_err(internal)?
+            .ok_or(AuthError::InvalidCredentials)?;
+        if identity.disabled || !bcrypt::verify(password, &identity.password).unwrap_or(false) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        self.issue_token(&identity)
+    }
+
+    async fn create_identity(&self, user: &User) -> Result<String, AuthError> {
+        let identity = LocalIdentity {
+            uid: user
+                .id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            email: user.email.clone(),
+            password: hash(&user.password)?,
+            display_name: Some(user.name.clone()),
+            email_verified: false,
+            disabled: false,
+            custom_claims: serde_json::Map::new(),
+            sessions_valid_after: None,
+            created_at: None,
+            updated_at: None,
+        };
+        match self.database.create_identity(&identity).await {
+            Ok(()) => Ok(identity.uid),
+            Err(DatabaseError::Conflict(_)) => Err(AuthError::Conflict(identity.email)),
+            Err(e) => Err(internal(e)),
+        }
+    }
+
+    async fn update_identity(&self, uid: &str, user: &User) -> Result<(), AuthError> {
+        let mut identity = self.identity(uid).await?;
+        // like firebase, a new email has to be verified again
+        if identity.email != user.email {
+            identity.email_verified = false;
+        }
+        identity.email = user.email.clone();
+        identity.display_name = Some(user.name.clone());
+        identity.password = hash(&user.password)?;
+        self.save(&identity).await
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<(), AuthError> {
+        match self.database.delete_identity(uid).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(AuthError::NotFound),
+            Err(e) => Err(internal(e)),
+        }
+    }
+
+    async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AuthError> {
+        let identity = self.identity(uid).await?;
+        Ok(bcrypt::verify(password, &identity.password).unwrap_or(false))
+    }
+
+    async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AuthError> {
+        let mut identity = self.identity(uid).await?;
+        identity.password = hash(new_password)?;
+        identity.sessions_valid_after = Some(chrono::Utc::now());
+        self.save(&identity).await
+    }
+
+    async fn set_email_verified(&self, uid: &str) -> Result<(), AuthError> {
+        let mut identity = self.identity(uid).await?;
+        identity.email_verified = true;
+        self.save(&identity).await
+    }
+
+    async fn set_disabled(&self, uid: &str, disabled: bool) -> Result<(), AuthError> {
+        let mut identity = self.identity(uid).await?;
+        identity.disabled = disabled;
+        self.save(&identity).await
+    }
+
+    async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AuthError> {
+        let mut identity = self.identity(uid).await?;
+        identity.custom_claims = claims.to_map();
+        self.save(&identity).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        structs::{claims::Plan, role::Role},
+        utils::Utils,
+    };
+    use fake::{faker::internet::en::SafeEmail, faker::name::raw::Name, locales::EN, Fake};
+
+    fn user() -> User {
+        User {
+            id: None,
+            name: Name(EN).fake(),
+            email: SafeEmail().fake(),
+            password: "11794581oooooo&".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_and_verify() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database);
+        let user = user();
+        let uid = auth.create_identity(&user).await.unwrap();
+
+        let token = auth.sign_in(&user.email, &user.password).await.unwrap();
+        let claims = auth.verify_token(&token).await.unwrap();
+        assert_eq!(claims.uid, uid);
+        assert_eq!(claims.email, Some(user.email.clone()));
+        assert!(!claims.email_verified);
+
+        assert_eq!(
+            auth.sign_in(&user.email, "wrong password").await,
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            auth.sign_in("nobody@example.com", &user.password).await,
+            Err(AuthError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_tokens() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database.clone());
+        let uid = auth.create_identity(&user()).await.unwrap();
+        let identity = auth.identity(&uid).await.unwrap();
+
+        let token = auth.issue_token(&identity).unwrap();
+        let mut tampered = token.clone();
+        tampered.pop();
+        assert_eq!(
+            auth.verify_token(&tampered).await,
+            Err(AuthError::Unauthorized)
+        );
+
+        let mut config = Utils::local_auth_config();
+        config.token_ttl = Duration::ZERO;
+        let expired = LocalAuth::new(&config, database.clone())
+            .issue_token(&identity)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(
+            auth.verify_token(&expired).await,
+            Err(AuthError::Unauthorized)
+        );
+
+        config.issuer = "someone else".to_string();
+        let foreign = LocalAuth::new(&config, database)
+            .issue_token(&identity)
+            .unwrap();
+        assert_eq!(
+            auth.verify_token(&foreign).await,
+            Err(AuthError::Unauthorized)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_claims() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database);
+        let user = user();
+        let uid = auth.create_identity(&user).await.unwrap();
+        let token = auth.sign_in(&user.email, &user.password).await.unwrap();
+        let verified = auth.verify_token(&token).await.unwrap();
+        assert_eq!(UserClaims::from_token(&verified.claims), None);
+
+        let claims = UserClaims {
+            roles: vec![Role::USER, Role::MANAGER],
+            plan: Plan::Premium,
+            claims_version: 3,
+        };
+        auth.set_custom_claims(&uid, &claims).await.unwrap();
+        // only tokens issued afterwards carry them
+        assert_eq!(auth.verify_token(&token).await.unwrap(), verified);
+        let token = auth.sign_in(&user.email, &user.password).await.unwrap();
+        let verified = auth.verify_token(&token).await.unwrap();
+        assert_eq!(verified.uid, uid);
+        assert_eq!(
+            UserClaims::from_token(&verified.claims),
+            Some(claims.clone())
+        );
+
+        assert_eq!(
+            auth.set_custom_claims("nobody", &claims).await,
+            Err(AuthError::NotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_email_verified() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database);
+        let mut user = user();
+        let uid = auth.create_identity(&user).await.unwrap();
+        auth.set_email_verified(&uid).await.unwrap();
+        let token = auth.sign_in(&user.email, &user.password).await.unwrap();
+        assert!(auth.verify_token(&token).await.unwrap().email_verified);
+
+        // the same email stays verified, a new one doesn't
+        user.name = Name(EN).fake();
+        auth.update_identity(&uid, &user).await.unwrap();
+        assert!(auth.identity(&uid).await.unwrap().email_verified);
+        user.email = SafeEmail().fake();
+        auth.update_identity(&uid, &user).await.unwrap();
+        let token = auth.sign_in(&user.email, &user.password).await.unwrap();
+        assert!(!auth.verify_token(&token).await.unwrap().email_verified);
+
+        assert_eq!(
+            auth.set_email_verified("nobody").await,
+            Err(AuthError::NotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_identity() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database);
+        let mut user = user();
+        let uid = auth.create_identity(&user).await.unwrap();
+        assert!(matches!(
+            auth.create_identity(&user).await,
+            Err(AuthError::Conflict(_))
+        ));
+
+        auth.change_password(&uid, "new password").await.unwrap();
+        assert!(auth.sign_in(&user.email, &user.password).await.is_err());
+        assert!(auth.sign_in(&user.email, "new password").await.is_ok());
+
+        user.email = SafeEmail().fake();
+        auth.update_identity(&uid, &user).await.unwrap();
+        assert!(auth.sign_in(&user.email, &user.password).await.is_ok());
+
+        auth.delete_identity(&uid).await.unwrap();
+        assert_eq!(auth.delete_identity(&uid).await, Err(AuthError::NotFound));
+        assert_eq!(
+            auth.sign_in(&user.email, &user.password).await,
+            Err(AuthError::InvalidCredentials)
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use super::local::LocalAuth;
+use crate::{
+    firebase::main::Firebase,
+    structs::{claims::UserClaims, user::User},
+    traits::auth::{AuthClaims, AuthError, AuthProvider},
+};
+
+/*
+    * Auth provider selected by the configuration (auth.provider)
+    Resolvers and guards only depend on this type, never on a concrete provider.
+*/
+#[derive(Clone)]
+pub enum AuthService {
+    Firebase(Arc<Firebase>),
+    Local(LocalAuth),
+}
+
+impl AuthProvider for AuthService {
+    async fn verify_token(&self, token: &str) -> Result<AuthClaims, AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.verify_token(token).await,
+            AuthService::Local(local) => local.verify_token(token).await,
+        }
+    }
+
+    async fn sign_in(&self, email: &str, password: &str) -> Result<String, AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.sign_in(email, password).await,
+            AuthService::Local(local) => local.sign_in(email, password).await,
+        }
+    }
+
+    async fn create_identity(&self, user: &User) -> Result<String, AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.create_identity(user).await,
+            AuthService::Local(local) => local.create_identity(user).await,
+        }
+    }
+
+    async fn update_identity(&self, uid: &str, user: &User) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.update_identity(uid, user).await,
+            AuthService::Local(local) => local.update_identity(uid, user).await,
+        }
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.delete_identity(uid).await,
+            AuthService::Local(local) => local.delete_identity(uid).await,
+        }
+    }
+
+    async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => {
+                AuthProvider::check_password(firebase.as_ref(), uid, password).await
+            }
+            AuthService::Local(local) => local.check_password(uid, password).await,
+        }
+    }
+
+    async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => {
+                AuthProvider::change_password(firebase.as_ref(), uid, new_password).await
+            }
+            AuthService::Local(local) => local.change_password(uid, new_password).await,
+        }
+    }
+
+    async fn set_email_verified(&self, uid: &str) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.set_email_verified(uid).await,
+            AuthService::Local(local) => local.set_email_verified(uid).await,
+        }
+    }
+
+    async fn set_disabled(&self, uid: &str, disabled: bool) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => firebase.set_disabled(uid, disabled).await,
+            AuthService::Local(local) => local.set_disabled(uid, disabled).await,
+        }
+    }
+
+    async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AuthError> {
+        match self {
+            AuthService::Firebase(firebase) => {
+                AuthProvider::set_custom_claims(firebase.as_ref(), uid, claims).await
+            }
+            AuthService::Local(local) => local.set_custom_claims(uid, claims).await,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod account;
+pub mod local;
+pub mod main;
+pub mod password;
+pub mod signup;
+pub mod suspension;
+pub mod verification;
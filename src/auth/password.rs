@@ -0,0 +1,267 @@
+use chrono::Utc;
+
+use super::signup::validate_password;
+use crate::{
+    database::error::DatabaseError,
+    errors::main::AppError,
+    structs::{
+        invitation::Invitation,
+        one_time_token::OneTimeToken,
+        password_reset::{PasswordReset, PasswordResetConfig},
+    },
+    traits::{
+        auth::AuthProvider, mailer::Mailer, password_reset::PasswordResetTrait, user::UserTrait,
+    },
+};
+
+/*
+    * Change the password of a signed in user, knowing the current one
+    Every session of the user is revoked, the one asking included, a new token is signed in for it.
+    @param auth: &A, the auth provider
+    @param database: &D
+    @param uid: &str
+    @param email: &str, of the identity, to sign in with the new password
+    @param current: &str, checked by the auth provider
+    @param new: &str
+    @return token of the new session, InvalidCredentials when current is wrong
+*/
+pub async fn change_password<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    email: &str,
+    current: &str,
+    new: &str,
+) -> Result<String, AppError> {
+    validate_password(new)?;
+    if !auth.check_password(uid, current).await? {
+        return Err(AppError::InvalidCredentials);
+    }
+    set_password(auth, database, uid, new).await?;
+    Ok(auth.sign_in(email, new).await?)
+}
+
+/*
+    * Send a reset link to the user with an email, its previous ones stop working
+    @param database: &D, unscoped, nobody is signed in
+    @param mailer: &M
+    @param email: &str
+    @param config: &PasswordResetConfig
+    @return the reset, None when no user has the email
+*/
+pub async fn request_password_reset<D: PasswordResetTrait, M: Mailer>(
+    database: &D,
+    mailer: &M,
+    email: &str,
+    config: &PasswordResetConfig,
+) -> Result<Option<PasswordReset>, AppError> {
+    let email = Invitation::normalize_email(email).map_err(AppError::Validation)?;
+    let token = OneTimeToken::generate();
+    match database
+        .create_password_reset(&email, &token.hash, config.expires_at(Utc::now()))
+        .await
+    {
+        Ok(reset) => {
+            mailer.send(&config.email(&email, &token.token)).await?;
+            Ok(Some(reset))
+        }
+        Err(DatabaseError::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    * Choose a new password with a reset token, every session of the user is revoked
+    The token is used up first, a failure afterwards needs a new one.
+    @param auth: &A, the auth provider
+    @param database: &D, unscoped, nobody is signed in
+    @param token: &str
+    @param new: &str
+    @return PasswordReset, NotFound for an unknown token, Validation once used or expired
+*/
+pub async fn reset_password<A: AuthProvider, D: UserTrait + PasswordResetTrait>(
+    auth: &A,
+    database: &D,
+    token: &str,
+    new: &str,
+) -> Result<PasswordReset, AppError> {
+    validate_password(new)?;
+    let reset = database
+        .consume_password_reset(&OneTimeToken::hash(token))
+        .await?;
+    set_password(auth, database, &reset.user_id, new).await?;
+    Ok(reset)
+}
+
+/*
+    * Change the password with the auth provider, then the hash kept with the user
+    @param uid: &str
+    @param new: &str
+*/
+async fn set_password<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    new: &str,
+) -> Result<(), AppError> {
+    auth.change_password(uid, new).await?;
+    match database.update_user_password(uid, new).await {
+        // identities without a user yet have nothing else to update
+        Ok(()) | Err(DatabaseError::NotFound) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        auth::{main::AuthService, signup::sign_up},
+        database::backend::Database,
+        mailer::{file::FileMailer, main::MailConfig},
+        structs::{claims::Plan, user::User},
+        traits::auth::AuthError,
+        utils::Utils,
+    };
+
+    async fn signed_up(email: &str) -> (Database, AuthService, String) {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let user = User {
+            id: None,
+            name: "Jane".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        };
+        let uid = sign_up(&auth, &database, &user).await.unwrap().id.unwrap();
+        (database, auth, uid)
+    }
+
+    async fn password_is(database: &Database, uid: &str, password: &str) -> bool {
+        let user = database.get_user(uid).await.unwrap();
+        bcrypt::verify(password, &user.password).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        let (database, auth, uid) = signed_up("jane@example.com").await;
+        let old_session = auth.sign_in("jane@example.com", "password").await.unwrap();
+        // revocation is to the second, like the issue time of tokens
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let change = |current: &'static str, new: &'static str| {
+            let (auth, database, uid) = (auth.clone(), database.clone(), uid.clone());
+            async move {
+                change_password(&auth, &database, &uid, "jane@example.com", current, new).await
+            }
+        };
+        assert!(matches!(
+            change("wrong", "new password").await,
+            Err(AppError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            change("password", "short").await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(auth.verify_token(&old_session).await.is_ok());
+
+        let new_session = change("password", "new password").await.unwrap();
+        assert_eq!(
+            auth.verify_token(&old_session).await,
+            Err(AuthError::Unauthorized)
+        );
+        assert_eq!(auth.verify_token(&new_session).await.unwrap().uid, uid);
+        assert_eq!(
+            auth.sign_in("jane@example.com", "password").await,
+            Err(AuthError::InvalidCredentials)
+        );
+        assert!(password_is(&database, &uid, "new password").await);
+    }
+
+    #[tokio::test]
+    async fn test_reset_password() {
+        let (database, auth, uid) = signed_up("jane@example.com").await;
+        let config = PasswordResetConfig::default();
+        let dir = Utils::mail_dir();
+        let mailer = FileMailer::new(MailConfig::default().sender().unwrap(), Some(dir.clone()));
+        assert!(
+            request_password_reset(&database, &mailer, "john@example.com", &config)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            request_password_reset(&database, &mailer, "jane", &config).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(Utils::take_sent_emails(&dir).is_empty());
+
+        request_password_reset(&database, &mailer, "jane@example.com", &config)
+            .await
+            .unwrap()
+            .unwrap();
+        let superseded = Utils::token_in(&Utils::take_sent_emails(&dir)[0]);
+        let reset = request_password_reset(&database, &mailer, " Jane@Example.com", &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reset.user_id, uid);
+        let emails = Utils::take_sent_emails(&dir);
+        assert_eq!(emails.len(), 1);
+        assert!(emails[0].contains("To: jane@example.com"));
+        let token = Utils::token_in(&emails[0]);
+        assert!(matches!(
+            reset_password(&auth, &database, &superseded, "new password").await,
+            Err(AppError::NotFound)
+        ));
+        // a rejected password leaves the token usable
+        assert!(matches!(
+            reset_password(&auth, &database, &token, "short").await,
+            Err(AppError::Validation(_))
+        ));
+
+        let old_session = auth.sign_in("jane@example.com", "password").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let reset = reset_password(&auth, &database, &token, "new password")
+            .await
+            .unwrap();
+        assert!(reset.used_at.is_some());
+        assert_eq!(
+            auth.verify_token(&old_session).await,
+            Err(AuthError::Unauthorized)
+        );
+        assert!(auth
+            .sign_in("jane@example.com", "new password")
+            .await
+            .is_ok());
+        assert!(password_is(&database, &uid, "new password").await);
+        assert!(matches!(
+            reset_password(&auth, &database, &token, "another password").await,
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_reset() {
+        let (database, auth, _) = signed_up("jane@example.com").await;
+        let token = OneTimeToken::generate();
+        database
+            .create_password_reset("jane@example.com", &token.hash, Utc::now())
+            .await
+            .unwrap();
+        assert!(matches!(
+            reset_password(&auth, &database, &token.token, "new password").await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(auth.sign_in("jane@example.com", "password").await.is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+use uuid::Uuid;
+
+use crate::{
+    errors::main::AppError,
+    structs::{invitation::Invitation, user::User},
+    traits::{auth::AuthProvider, user::UserTrait},
+};
+
+// the shortest password Firebase accepts
+pub const MIN_PASSWORD_LENGTH: usize = 6;
+
+/*
+    * Check a new password
+    @param password: &str
+    @return Validation error with the reason
+*/
+pub fn validate_password(password: &str) -> Result<(), AppError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::Validation(format!(
+            "a password has at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/*
+    * Sign a new user up: the identity of the auth provider, then the users row and its User role
+    in one transaction, then the custom claims of the identity.
+    A failing step undoes the previous ones, so the caller sees all of them or none.
+    @param auth: &A, the auth provider
+    @param database: &D, unscoped, nobody is signed in yet
+    @param user: &User, its id is ignored and a new uid drawn
+    @return User, Conflict when the email is already used
+*/
+pub async fn sign_up<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    user: &User,
+) -> Result<User, AppError> {
+    let mut user = User {
+        name: user.name.trim().to_string(),
+        email: Invitation::normalize_email(&user.email).map_err(AppError::Validation)?,
+        ..user.clone()
+    };
+    if user.name.is_empty() {
+        return Err(AppError::Validation("a name is required".to_string()));
+    }
+    validate_password(&user.password)?;
+    user.fill_id(Uuid::new_v4().to_string());
+
+    let uid = auth.create_identity(&user).await?;
+    user.id = Some(uid.clone());
+    let created = match database.create_user(&user).await {
+        Ok(created) => created,
+        Err(e) => return Err(undo(auth, None::<&D>, &uid, e.into()).await),
+    };
+    let synced = match database.get_user_claims(&uid).await {
+        Ok(claims) => auth
+            .set_custom_claims(&uid, &claims)
+            .await
+            .map_err(AppError::from),
+        Err(e) => Err(e.into()),
+    };
+    match synced {
+        Ok(()) => Ok(created),
+        Err(e) => Err(undo(auth, Some(database), &uid, e).await),
+    }
+}
+
+/*
+    * Undo a sign up that failed half way, newest step first
+    @param database: Option<&D>, when the users row was created
+    @param cause: AppError, why the sign up failed
+    @return the cause, or an internal error naming what was left behind
+*/
+async fn undo<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: Option<&D>,
+    uid: &str,
+    cause: AppError,
+) -> AppError {
+    let mut left = Vec::new();
+    if let Some(database) = database {
+        if let Err(e) = database.delete_user(uid).await {
+            left.push(format!("the users row ({})", e));
+        }
+    }
+    if let Err(e) = auth.delete_identity(uid).await {
+        left.push(format!("the identity ({})", e));
+    }
+    if left.is_empty() {
+        cause
+    } else {
+        AppError::internal(format!(
+            "sign up of {} failed with {:?}, left behind {}",
+            uid,
+            cause,
+            left.join(" and ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::main::AuthService,
+        database::{backend::Database, error::DatabaseError},
+        structs::{
+            claims::{Plan, UserClaims},
+            role::Role,
+        },
+        traits::{
+            auth::{AuthClaims, AuthError},
+            identity::IdentityTrait,
+        },
+        utils::Utils,
+    };
+    use chrono::{DateTime, Utc};
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Step {
+        Identity,
+        User,
+        Claims,
+        UndoUser,
+        UndoIdentity,
+    }
+
+    // provider or database failing at the given steps, and behaving otherwise
+    struct FailAt<T> {
+        inner: T,
+        steps: &'static [Step],
+    }
+
+    impl<T> FailAt<T> {
+        fn fails(&self, step: Step) -> bool {
+            self.steps.contains(&step)
+        }
+    }
+
+    impl AuthProvider for FailAt<AuthService> {
+        async fn verify_token(&self, token: &str) -> Result<AuthClaims, AuthError> {
+            self.inner.verify_token(token).await
+        }
+
+        async fn sign_in(&self, email: &str, password: &str) -> Result<String, AuthError> {
+            self.inner.sign_in(email, password).await
+        }
+
+        async fn create_identity(&self, user: &User) -> Result<String, AuthError> {
+            if self.fails(Step::Identity) {
+                return Err(AuthError::Upstream("identity refused".to_string()));
+            }
+            self.inner.create_identity(user).await
+        }
+
+        async fn update_identity(&self, uid: &str, user: &User) -> Result<(), AuthError> {
+            self.inner.update_identity(uid, user).await
+        }
+
+        async fn delete_identity(&self, uid: &str) -> Result<(), AuthError> {
+            if self.fails(Step::UndoIdentity) {
+                return Err(AuthError::Upstream("deletion refused".to_string()));
+            }
+            self.inner.delete_identity(uid).await
+        }
+
+        async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AuthError> {
+            self.inner.check_password(uid, password).await
+        }
+
+        async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AuthError> {
+            self.inner.change_password(uid, new_password).await
+        }
+
+        async fn set_email_verified(&self, uid: &str) -> Result<(), AuthError> {
+            self.inner.set_email_verified(uid).await
+        }
+
+        async fn set_disabled(&self, uid: &str, disabled: bool) -> Result<(), AuthError> {
+            self.inner.set_disabled(uid, disabled).await
+        }
+
+        async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AuthError> {
+            if self.fails(Step::Claims) {
+                return Err(AuthError::Upstream("claims refused".to_string()));
+            }
+            self.inner.set_custom_claims(uid, claims).await
+        }
+    }
+
+    impl UserTrait for FailAt<Database> {
+        async fn get_user_roles(&self, user_uid: &str) -> Result<Vec<Role>, DatabaseError> {
+            self.inner.get_user_roles(user_uid).await
+        }
+
+        async fn save_user_role(&self, user_uid: &str, role: &Role) -> Result<(), DatabaseError> {
+            self.inner.save_user_role(user_uid, role).await
+        }
+
+        async fn remove_user_role(&self, user_uid: &str, role: &Role) -> Result<(), DatabaseError> {
+            self.inner.remove_user_role(user_uid, role).await
+        }
+
+        async fn get_users_by_role(&self, role: &Role) -> Result<Vec<User>, DatabaseError> {
+            self.inner.get_users_by_role(role).await
+        }
+
+        async fn create_user(&self, user: &User) -> Result<User, DatabaseError> {
+            if self.fails(Step::User) {
+                return Err(DatabaseError::Conflict("users_pkey".to_string()));
+            }
+            self.inner.create_user(user).await
+        }
+
+        async fn delete_user(&self, user_uid: &str) -> Result<(), DatabaseError> {
+            if self.fails(Step::UndoUser) {
+                return Err(DatabaseError::NotFound);
+            }
+            self.inner.delete_user(user_uid).await
+        }
+
+        async fn update_user_name(
+            &self,
+            user_name: &str,
+            user_uid: &str,
+        ) -> Result<User, DatabaseError> {
+            self.inner.update_user_name(user_name, user_uid).await
+        }
+
+        async fn update_user_password(
+            &self,
+            user_uid: &str,
+            password: &str,
+        ) -> Result<(), DatabaseError> {
+            self.inner.update_user_password(user_uid, password).await
+        }
+
+        async fn update_user_email(
+            &self,
+            user_uid: &str,
+            email: &str,
+        ) -> Result<User, DatabaseError> {
+            self.inner.update_user_email(user_uid, email).await
+        }
+
+        async fn get_user(&self, user_uid: &str) -> Result<User, DatabaseError> {
+            self.inner.get_user(user_uid).await
+        }
+
+        async fn update_user_plan(
+            &self,
+            user_uid: &str,
+            plan: Plan,
+        ) -> Result<User, DatabaseError> {
+            self.inner.update_user_plan(user_uid, plan).await
+        }
+
+        async fn update_user_status(
+            &self,
+            user_uid: &str,
+            disabled: bool,
+            deleted_at: Option<DateTime<Utc>>,
+            reason: Option<&str>,
+        ) -> Result<User, DatabaseError> {
+            self.inner
+                .update_user_status(user_uid, disabled, deleted_at, reason)
+                .await
+        }
+
+        async fn get_users_deleted_before(
+            &self,
+            before: DateTime<Utc>,
+        ) -> Result<Vec<User>, DatabaseError> {
+            self.inner.get_users_deleted_before(before).await
+        }
+
+        async fn get_user_claims(&self, user_uid: &str) -> Result<UserClaims, DatabaseError> {
+            self.inner.get_user_claims(user_uid).await
+        }
+    }
+
+    fn new_user(email: &str) -> User {
+        User {
+            id: None,
+            name: "Jane".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    /*
+        * Sign a user up with the steps failing
+        @return (result, database, auth) to look at what is left
+    */
+    async fn sign_up_failing_at(
+        steps: &'static [Step],
+        email: &str,
+    ) -> (Result<User, AppError>, Database, AuthService) {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let res = sign_up(
+            &FailAt {
+                inner: auth.clone(),
+                steps,
+            },
+            &FailAt {
+                inner: database.clone(),
+                steps,
+            },
+            &new_user(email),
+        )
+        .await;
+        (res, database, auth)
+    }
+
+    async fn users(database: &Database) -> usize {
+        database.get_users_by_role(&Role::USER).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_sign_up() {
+        let (res, database, auth) = sign_up_failing_at(&[], " Jane@Example.com").await;
+        let user = res.unwrap();
+        let uid = user.id.unwrap();
+        assert_eq!(user.email, "jane@example.com");
+        assert_eq!(
+            database.get_user_roles(&uid).await.unwrap(),
+            vec![Role::USER]
+        );
+        let identity = database.get_identity(&uid).await.unwrap().unwrap();
+        assert_eq!(identity.email, "jane@example.com");
+        assert_eq!(
+            UserClaims::from_token(&identity.custom_claims),
+            Some(database.get_user_claims(&uid).await.unwrap())
+        );
+        assert!(auth.sign_in("jane@example.com", "password").await.is_ok());
+
+        // the email belongs to the first identity, nothing else is created
+        let res = sign_up(&auth, &database, &new_user("jane@example.com")).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))), "{:?}", res);
+        assert_eq!(users(&database).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let mut user = new_user("jane");
+        assert!(matches!(
+            sign_up(&auth, &database, &user).await,
+            Err(AppError::Validation(_))
+        ));
+        user.email = "jane@example.com".to_string();
+        user.password = "12345".to_string();
+        assert!(matches!(
+            sign_up(&auth, &database, &user).await,
+            Err(AppError::Validation(_))
+        ));
+        user.password = "password".to_string();
+        user.name = " ".to_string();
+        assert!(matches!(
+            sign_up(&auth, &database, &user).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_identity_fails() {
+        let (res, database, _) = sign_up_failing_at(&[Step::Identity], "jane@example.com").await;
+        assert!(matches!(res, Err(AppError::Upstream(_))), "{:?}", res);
+        assert_eq!(users(&database).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_user_fails() {
+        let (res, database, auth) = sign_up_failing_at(&[Step::User], "jane@example.com").await;
+        assert!(matches!(res, Err(AppError::Conflict(_))), "{:?}", res);
+        assert_eq!(users(&database).await, 0);
+        // the identity is deleted, the email can sign up again
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        assert!(sign_up(&auth, &database, &new_user("jane@example.com"))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_claims_fail() {
+        let (res, database, auth) = sign_up_failing_at(&[Step::Claims], "jane@example.com").await;
+        assert!(matches!(res, Err(AppError::Upstream(_))), "{:?}", res);
+        assert_eq!(users(&database).await, 0);
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_undo_fails() {
+        let (res, database, auth) =
+            sign_up_failing_at(&[Step::Claims, Step::UndoUser], "jane@example.com").await;
+        match res {
+            Err(AppError::Internal(detail)) => {
+                assert!(detail.contains("left behind the users row"), "{}", detail)
+            }
+            res => panic!("{:?}", res),
+        }
+        // what could be undone still is
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        assert_eq!(users(&database).await, 1);
+
+        let (res, database, auth) =
+            sign_up_failing_at(&[Step::User, Step::UndoIdentity], "john@example.com").await;
+        match res {
+            Err(AppError::Internal(detail)) => {
+                assert!(detail.contains("left behind the identity"), "{}", detail)
+            }
+            res => panic!("{:?}", res),
+        }
+        assert!(auth.sign_in("john@example.com", "password").await.is_ok());
+        assert_eq!(users(&database).await, 0);
+    }
+}
@@ -0,0 +1,293 @@
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{
+    auth::{account::delete_account, main::AuthService},
+    database::backend::Database,
+    errors::main::AppError,
+    structs::user::{User, UserPurgeConfig},
+    traits::{
+        auth::{AuthError, AuthProvider},
+        user::UserTrait,
+    },
+};
+
+// longest reason kept with a suspension or a soft delete
+pub const MAX_REASON_LENGTH: usize = 500;
+
+/*
+    * Check the reason given by an Admin
+    @param reason: &str
+    @return the trimmed reason, Validation error when empty or too long
+*/
+fn validate_reason(reason: &str) -> Result<&str, AppError> {
+    let reason = reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LENGTH {
+        return Err(AppError::Validation(format!(
+            "a reason has 1 to {} characters",
+            MAX_REASON_LENGTH
+        )));
+    }
+    Ok(reason)
+}
+
+/*
+    * Disable or enable the identity of the auth provider
+    @param auth: &A
+    @param uid: &str
+    @param disabled: bool
+    @return (), users seeded straight into the database have no identity
+*/
+async fn set_identity_disabled<A: AuthProvider>(
+    auth: &A,
+    uid: &str,
+    disabled: bool,
+) -> Result<(), AppError> {
+    match auth.set_disabled(uid, disabled).await {
+        Ok(()) | Err(AuthError::NotFound) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    * Suspend a user, the users row first then the identity of the auth provider
+    A suspension stopped half way is finished by calling it again.
+    @param auth: &A
+    @param database: &D
+    @param uid: &str
+    @param reason: &str
+    @return User, NotFound for an unknown user
+*/
+pub async fn suspend_user<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    reason: &str,
+) -> Result<User, AppError> {
+    let reason = validate_reason(reason)?;
+    let user = database.get_user(uid).await?;
+    let user = database
+        .update_user_status(uid, true, user.deleted_at, Some(reason))
+        .await?;
+    set_identity_disabled(auth, uid, true).await?;
+    Ok(user)
+}
+
+/*
+    * Soft delete a user, suspended until purged once the retention period is over
+    Deleting it again keeps the first deletion time.
+    @param auth: &A
+    @param database: &D
+    @param uid: &str
+    @param reason: &str
+    @return User, NotFound for an unknown user
+*/
+pub async fn soft_delete_user<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    reason: &str,
+) -> Result<User, AppError> {
+    let reason = validate_reason(reason)?;
+    let user = database.get_user(uid).await?;
+    let deleted_at = user.deleted_at.unwrap_or_else(Utc::now);
+    let user = database
+        .update_user_status(uid, true, Some(deleted_at), Some(reason))
+        .await?;
+    set_identity_disabled(auth, uid, true).await?;
+    Ok(user)
+}
+
+/*
+    * Lift a suspension or a soft delete
+    @param auth: &A
+    @param database: &D
+    @param uid: &str
+    @return User, NotFound for an unknown user, purged ones included
+*/
+pub async fn reactivate_user<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+) -> Result<User, AppError> {
+    let user = database.update_user_status(uid, false, None, None).await?;
+    set_identity_disabled(auth, uid, false).await?;
+    Ok(user)
+}
+
+/*
+    * Delete for good the users soft deleted before a cutoff, see delete_account
+    A user failing to be deleted is logged and tried again by the next purge.
+    @param auth: &A
+    @param database: &D, unscoped
+    @param before: DateTime<Utc>
+    @return uids of the purged users
+*/
+pub async fn purge_deleted_users<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    before: DateTime<Utc>,
+) -> Result<Vec<String>, AppError> {
+    let mut purged = Vec::new();
+    for user in database.get_users_deleted_before(before).await? {
+        let Some(uid) = user.id else { continue };
+        match delete_account(auth, database, &uid).await {
+            Ok(()) => purged.push(uid),
+            Err(e) => eprintln!("purge of user {} failed: {}", uid, e),
+        }
+    }
+    Ok(purged)
+}
+
+/*
+    * Purge soft deleted users every config.interval, from now on
+    @param auth: AuthService
+    @param database: Database, unscoped
+    @param config: UserPurgeConfig
+    @return JoinHandle of the task, it runs until aborted
+*/
+pub fn spawn_purge(
+    auth: AuthService,
+    database: Database,
+    config: UserPurgeConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            match purge_deleted_users(&auth, &database, config.cutoff(Utc::now())).await {
+                Ok(purged) if !purged.is_empty() => {
+                    println!("{} soft deleted user(s) purged", purged.len())
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("purge of soft deleted users failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::signup::sign_up,
+        database::error::DatabaseError,
+        structs::{claims::Plan, role::Role},
+        traits::identity::IdentityTrait,
+        utils::Utils,
+    };
+    use std::time::Duration;
+
+    fn new_user(email: &str) -> User {
+        User {
+            id: None,
+            name: "Jane".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    async fn signed_up(auth: &AuthService, database: &Database, email: &str) -> String {
+        sign_up(auth, database, &new_user(email))
+            .await
+            .unwrap()
+            .id
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_suspend_and_reactivate() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let uid = signed_up(&auth, &database, "jane@example.com").await;
+        let token = auth.sign_in("jane@example.com", "password").await.unwrap();
+
+        assert!(matches!(
+            suspend_user(&auth, &database, &uid, "  ").await,
+            Err(AppError::Validation(_))
+        ));
+        let user = suspend_user(&auth, &database, &uid, " spam ")
+            .await
+            .unwrap();
+        assert!(user.disabled);
+        assert_eq!(user.deleted_at, None);
+        assert_eq!(user.status_reason.as_deref(), Some("spam"));
+        assert!(database.get_identity(&uid).await.unwrap().unwrap().disabled);
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        assert_eq!(
+            auth.verify_token(&token).await.unwrap_err(),
+            AuthError::Unauthorized
+        );
+
+        let user = reactivate_user(&auth, &database, &uid).await.unwrap();
+        assert!(!user.disabled);
+        assert_eq!(user.status_reason, None);
+        assert!(auth.verify_token(&token).await.is_ok());
+
+        // users without an identity, seeded straight into the database
+        let seeded = database.crate_random_user().await.unwrap().id.unwrap();
+        assert!(suspend_user(&auth, &database, &seeded, "spam")
+            .await
+            .is_ok());
+        assert!(matches!(
+            suspend_user(&auth, &database, "nobody", "spam").await,
+            Err(AppError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_and_purge() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let uid = signed_up(&auth, &database, "jane@example.com").await;
+        let kept = signed_up(&auth, &database, "john@example.com").await;
+        let admin = signed_up(&auth, &database, "jack@example.com").await;
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+
+        let user = soft_delete_user(&auth, &database, &uid, "spam")
+            .await
+            .unwrap();
+        let deleted_at = user.deleted_at.unwrap();
+        assert!(user.disabled);
+        let user = soft_delete_user(&auth, &database, &uid, "fraud")
+            .await
+            .unwrap();
+        assert_eq!(user.deleted_at, Some(deleted_at));
+        assert_eq!(user.status_reason.as_deref(), Some("fraud"));
+        suspend_user(&auth, &database, &kept, "spam").await.unwrap();
+        // the last Admin stays, the purge goes on with the others
+        soft_delete_user(&auth, &database, &admin, "left")
+            .await
+            .unwrap();
+
+        let config = UserPurgeConfig {
+            retention: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        assert!(
+            purge_deleted_users(&auth, &database, config.cutoff(Utc::now()))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let later = Utc::now() + chrono::Duration::hours(2);
+        let purged = purge_deleted_users(&auth, &database, config.cutoff(later))
+            .await
+            .unwrap();
+        assert_eq!(purged, vec![uid.clone()]);
+        assert!(matches!(
+            database.get_user(&uid).await,
+            Err(DatabaseError::NotFound)
+        ));
+        assert!(database.get_identity(&uid).await.unwrap().is_none());
+        assert!(database.get_user(&kept).await.unwrap().disabled);
+        assert!(database.get_user(&admin).await.is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::Utc;
+
+use crate::{
+    errors::main::AppError,
+    structs::{
+        email_verification::{EmailVerification, EmailVerificationConfig},
+        invitation::Invitation,
+        one_time_token::OneTimeToken,
+        user::User,
+    },
+    traits::{
+        auth::{AuthError, AuthProvider},
+        email_verification::EmailVerificationTrait,
+        mailer::Mailer,
+        user::UserTrait,
+    },
+};
+
+/*
+    * Send a verification link to the email of a user, its previous links stop working
+    @param database: &D, unscoped, links are never read by requests
+    @param mailer: &M
+    @param uid: &str
+    @param email: &str, the current email of the user
+    @param config: &EmailVerificationConfig
+    @return EmailVerification, NotFound for an unknown user
+*/
+pub async fn send_email_verification<D: EmailVerificationTrait, M: Mailer>(
+    database: &D,
+    mailer: &M,
+    uid: &str,
+    email: &str,
+    config: &EmailVerificationConfig,
+) -> Result<EmailVerification, AppError> {
+    let token = OneTimeToken::generate();
+    let verification = database
+        .create_email_verification(uid, email, &token.hash, config.expires_at(Utc::now()))
+        .await?;
+    mailer.send(&config.email(email, &token.token)).await?;
+    Ok(verification)
+}
+
+/*
+    * Verify an email with the token of a link, the auth provider is told as well
+    The token is used up first, a failure afterwards needs a new link.
+    @param auth: &A, the auth provider
+    @param database: &D, unscoped, the link may be opened before signing in
+    @param token: &str
+    @return EmailVerification, NotFound for an unknown token,
+    Validation once used, expired or when the email changed since
+*/
+pub async fn verify_email<A: AuthProvider, D: EmailVerificationTrait>(
+    auth: &A,
+    database: &D,
+    token: &str,
+) -> Result<EmailVerification, AppError> {
+    let verification = database
+        .consume_email_verification(&OneTimeToken::hash(token))
+        .await?;
+    match auth.set_email_verified(&verification.user_id).await {
+        // users seeded straight into the database have no identity to update
+        Ok(()) | Err(AuthError::NotFound) => Ok(verification),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    * Change the email of a signed in user, knowing its password
+    The identity and the user take the new email, unverified until its link is followed.
+    @param auth: &A, the auth provider
+    @param database: &D
+    @param uid: &str
+    @param password: &str, checked by the auth provider
+    @param email: &str, normalized here
+    @return User, InvalidCredentials when the password is wrong, Conflict when the email is used
+*/
+pub async fn change_email<A: AuthProvider, D: UserTrait>(
+    auth: &A,
+    database: &D,
+    uid: &str,
+    password: &str,
+    email: &str,
+) -> Result<User, AppError> {
+    let email = Invitation::normalize_email(email).map_err(AppError::Validation)?;
+    if !auth.check_password(uid, password).await? {
+        return Err(AppError::InvalidCredentials);
+    }
+    let user = database.get_user(uid).await?;
+    // the provider updates email, name and password together, the password stays the same
+    let identity = User {
+        email: email.clone(),
+        password: password.to_string(),
+        ..user
+    };
+    auth.update_identity(uid, &identity).await?;
+    Ok(database.update_user_email(uid, &email).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{main::AuthService, signup::sign_up},
+        database::backend::Database,
+        mailer::{file::FileMailer, main::MailConfig},
+        structs::claims::Plan,
+        utils::Utils,
+    };
+
+    fn new_user(email: &str) -> User {
+        User {
+            id: None,
+            name: "Jane".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    async fn signed_up(email: &str) -> (Database, AuthService, String) {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let user = sign_up(&auth, &database, &new_user(email)).await.unwrap();
+        (database, auth, user.id.unwrap())
+    }
+
+    async fn email_verified(auth: &AuthService, email: &str, password: &str) -> bool {
+        let token = auth.sign_in(email, password).await.unwrap();
+        auth.verify_token(&token).await.unwrap().email_verified
+    }
+
+    #[tokio::test]
+    async fn test_verify_email() {
+        let (database, auth, uid) = signed_up("jane@example.com").await;
+        let dir = Utils::mail_dir();
+        let mailer = FileMailer::new(MailConfig::default().sender().unwrap(), Some(dir.clone()));
+        let config = EmailVerificationConfig::default();
+        let send =
+            || send_email_verification(&database, &mailer, &uid, "jane@example.com", &config);
+
+        send().await.unwrap();
+        let superseded = Utils::take_sent_emails(&dir);
+        let verification = send().await.unwrap();
+        assert_eq!(verification.email, "jane@example.com");
+        let sent = Utils::take_sent_emails(&dir);
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!((superseded.len(), sent.len()), (1, 1));
+        assert!(sent[0].contains("To: jane@example.com"));
+        let (superseded, token) = (Utils::token_in(&superseded[0]), Utils::token_in(&sent[0]));
+
+        assert!(matches!(
+            verify_email(&auth, &database, &superseded).await,
+            Err(AppError::NotFound)
+        ));
+        assert!(!database.get_user(&uid).await.unwrap().email_verified);
+        let verified = verify_email(&auth, &database, &token).await.unwrap();
+        assert_eq!(verified.id, verification.id);
+        assert!(database.get_user(&uid).await.unwrap().email_verified);
+        assert!(email_verified(&auth, "jane@example.com", "password").await);
+        assert!(matches!(
+            verify_email(&auth, &database, &token).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            send_email_verification(&database, &mailer, "nobody", "nobody@example.com", &config)
+                .await,
+            Err(AppError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let (database, auth, uid) = signed_up("jane@example.com").await;
+        sign_up(&auth, &database, &new_user("john@example.com"))
+            .await
+            .unwrap();
+        let pending = OneTimeToken::generate();
+        database
+            .create_email_verification(
+                &uid,
+                "jane@example.com",
+                &pending.hash,
+                Utc::now() + chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            change_email(&auth, &database, &uid, "wrong", "jane@example.org").await,
+            Err(AppError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            change_email(&auth, &database, &uid, "password", "jane").await,
+            Err(AppError::Validation(_))
+        ));
+        let user = change_email(&auth, &database, &uid, "password", " Jane@Example.org")
+            .await
+            .unwrap();
+        assert_eq!(user.email, "jane@example.org");
+        assert!(!user.email_verified);
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        assert!(!email_verified(&auth, "jane@example.org", "password").await);
+        // a link sent to the previous email verifies nothing
+        assert!(matches!(
+            verify_email(&auth, &database, &pending.token).await,
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            change_email(&auth, &database, &uid, "password", "john@example.com").await,
+            Err(AppError::Conflict(_))
+        ));
+    }
+}
@@ -0,0 +1,973 @@
+use std::{collections::BTreeMap, env, fmt, net::SocketAddr, path::PathBuf, time::Duration};
+
+use crate::{
+    auth::local::LocalAuthConfig,
+    database::{
+        pool::PoolConfig,
+        tls::{SslMode, TlsConfig},
+    },
+    mailer::{
+        main::{MailConfig, MailTransport},
+        smtp::{SmtpConfig, SmtpTls},
+    },
+    structs::{
+        claims::ClaimsFallback, email_verification::EmailVerificationConfig,
+        impersonation::ImpersonationConfig, invitation::InvitationConfig,
+        password_reset::PasswordResetConfig, user::UserPurgeConfig,
+    },
+};
+
+/*
+    * Every configuration key, with the environment variable it can be read from
+    and its default value when it is optional.
+    The same dotted key is used in the toml file (`[postgres] host = ...`)
+    and on the command line (`--postgres.host=...`).
+*/
+const KEYS: &[(&str, &str, Option<&str>)] = &[
+    (
+        "server.listen_addr",
+        "SERVER_LISTEN_ADDR",
+        Some("127.0.0.1:4000"),
+    ),
+    (
+        "server.cors_origins",
+        "SERVER_CORS_ORIGINS",
+        Some("http://localhost:5173"),
+    ),
+    ("postgres.host", "POSTGRES_HOST", None),
+    ("postgres.port", "POSTGRES_PORT", Some("5432")),
+    ("postgres.user", "POSTGRES_USER", None),
+    ("postgres.password", "POSTGRES_PASSWORD", None),
+    ("postgres.database", "POSTGRES_DATABASE", None),
+    (
+        "postgres.pool_min_size",
+        "POSTGRES_POOL_MIN_SIZE",
+        Some("1"),
+    ),
+    (
+        "postgres.pool_max_size",
+        "POSTGRES_POOL_MAX_SIZE",
+        Some("10"),
+    ),
+    (
+        "postgres.pool_checkout_timeout_ms",
+        "POSTGRES_POOL_CHECKOUT_TIMEOUT_MS",
+        Some("10000"),
+    ),
+    (
+        "postgres.pool_health_check",
+        "POSTGRES_POOL_HEALTH_CHECK",
+        Some("true"),
+    ),
+    ("postgres.sslmode", "POSTGRES_SSLMODE", Some("prefer")),
+    ("postgres.sslrootcert", "POSTGRES_SSLROOTCERT", None),
+    ("postgres.sslcert", "POSTGRES_SSLCERT", None),
+    ("postgres.sslkey", "POSTGRES_SSLKEY", None),
+    ("firebase.service_account", "SERVICE_ACCOUNT", None),
+    ("firebase.api_key", "FIREBASE_API_KEY", None),
+    (
+        "firebase.service_account_email",
+        "FIREBASE_SERVICE_ACCOUNT_EMAIL",
+        None,
+    ),
+    ("firebase.project_id", "FIREBASE_PROJECT_ID", None),
+    (
+        "firebase.emulator_host",
+        "FIREBASE_AUTH_EMULATOR_HOST",
+        None,
+    ),
+    ("auth.provider", "AUTH_PROVIDER", Some("firebase")),
+    ("auth.private_key", "AUTH_PRIVATE_KEY", None),
+    ("auth.public_key", "AUTH_PUBLIC_KEY", None),
+    ("auth.issuer", "AUTH_ISSUER", Some("data_intuitive")),
+    ("auth.token_ttl_secs", "AUTH_TOKEN_TTL_SECS", Some("3600")),
+    (
+        "auth.claims_fallback",
+        "AUTH_CLAIMS_FALLBACK",
+        Some("missing"),
+    ),
+    (
+        "invitations.ttl_hours",
+        "INVITATIONS_TTL_HOURS",
+        Some("168"),
+    ),
+    (
+        "invitations.link",
+        "INVITATIONS_LINK",
+        Some("http://localhost:5173/accept-invitation"),
+    ),
+    (
+        "impersonation.max_minutes",
+        "IMPERSONATION_MAX_MINUTES",
+        Some("60"),
+    ),
+    (
+        "password_resets.ttl_minutes",
+        "PASSWORD_RESETS_TTL_MINUTES",
+        Some("60"),
+    ),
+    (
+        "password_resets.link",
+        "PASSWORD_RESETS_LINK",
+        Some("http://localhost:5173/reset-password"),
+    ),
+    (
+        "email_verifications.ttl_hours",
+        "EMAIL_VERIFICATIONS_TTL_HOURS",
+        Some("24"),
+    ),
+    (
+        "email_verifications.link",
+        "EMAIL_VERIFICATIONS_LINK",
+        Some("http://localhost:5173/verify-email"),
+    ),
+    (
+        "users.deleted_retention_days",
+        "USERS_DELETED_RETENTION_DAYS",
+        Some("30"),
+    ),
+    (
+        "users.purge_interval_minutes",
+        "USERS_PURGE_INTERVAL_MINUTES",
+        Some("60"),
+    ),
+    ("mail.transport", "MAIL_TRANSPORT", Some("stdout")),
+    (
+        "mail.from",
+        "MAIL_FROM",
+        Some("Data Intuitive <no-reply@localhost>"),
+    ),
+    ("mail.dir", "MAIL_DIR", None),
+    ("mail.smtp_host", "MAIL_SMTP_HOST", None),
+    ("mail.smtp_port", "MAIL_SMTP_PORT", Some("587")),
+    ("mail.smtp_user", "MAIL_SMTP_USER", None),
+    ("mail.smtp_password", "MAIL_SMTP_PASSWORD", None),
+    ("mail.smtp_tls", "MAIL_SMTP_TLS", Some("starttls")),
+];
+
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub postgres: PostgresConfig,
+    pub auth: AuthConfig,
+    // when roles are read from the database rather than from the token claims
+    pub claims_fallback: ClaimsFallback,
+    pub invitations: InvitationConfig,
+    pub impersonation: ImpersonationConfig,
+    pub password_resets: PasswordResetConfig,
+    pub email_verifications: EmailVerificationConfig,
+    pub users: UserPurgeConfig,
+    pub mail: MailConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub listen_addr: SocketAddr,
+    pub cors_origins: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PostgresConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    pub pool: PoolConfig,
+    pub tls: TlsConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct FirebaseConfig {
+    // only optional with the emulator
+    pub service_account: Option<String>,
+    pub api_key: String,
+    // issuer of the custom tokens, client_email of the service account unless overridden
+    pub service_account_email: String,
+    pub project_id: String,
+    // host:port of the Firebase Auth emulator, every call goes there when set
+    pub emulator_host: Option<String>,
+}
+
+/*
+ * Auth provider and its settings, only the section of the selected provider is validated
+*/
+#[derive(Clone, Debug)]
+pub enum AuthConfig {
+    Firebase(FirebaseConfig),
+    Local(LocalAuthConfig),
+}
+
+/*
+ * All the problems found while loading the configuration, reported together
+*/
+#[derive(Debug, PartialEq)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/*
+    * Raw configuration values merged from, by increasing priority:
+    the toml file, the environment and the command line
+*/
+#[derive(Default, Debug)]
+pub struct ConfigSource {
+    values: BTreeMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl ConfigSource {
+    /*
+        * Load every source
+        @param args: command line options, `--config <path>` and `--<key>[=]<value>`
+        @return ConfigSource
+    */
+    pub fn load(args: &[String]) -> ConfigSource {
+        let mut source = ConfigSource::default();
+        let mut cli = BTreeMap::new();
+        let mut config_file = env::var("CONFIG_FILE").ok().map(PathBuf::from);
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else {
+                source.errors.push(format!("unexpected argument {}", arg));
+                continue;
+            };
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (option.to_string(), args.next().cloned()),
+            };
+            let Some(value) = value else {
+                source.errors.push(format!("missing value for --{}", key));
+                continue;
+            };
+            if key == "config" {
+                config_file = Some(PathBuf::from(value));
+            } else {
+                cli.insert(key, value);
+            }
+        }
+
+        if let Some(path) = config_file {
+            source.read_file(&path);
+        }
+        for (key, env_key, _) in KEYS {
+            if let Ok(value) = env::var(env_key) {
+                source.values.insert(key.to_string(), value);
+            }
+        }
+        for (key, value) in cli {
+            source.set(&key, value);
+        }
+        source
+    }
+
+    pub fn from_env() -> ConfigSource {
+        ConfigSource::load(&[])
+    }
+
+    fn read_file(&mut self, path: &PathBuf) {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                self.errors
+                    .push(format!("can't read {}: {}", path.display(), e));
+                return;
+            }
+        };
+        match content.parse::<toml::Table>() {
+            Ok(table) => self.flatten("", table),
+            Err(e) => self
+                .errors
+                .push(format!("invalid toml in {}: {}", path.display(), e)),
+        }
+    }
+
+    fn flatten(&mut self, prefix: &str, table: toml::Table) {
+        for (key, value) in table {
+            let key = format!("{}{}", prefix, key);
+            let value = match value {
+                toml::Value::Table(table) => {
+                    self.flatten(&format!("{}.", key), table);
+                    continue;
+                }
+                toml::Value::String(s) => s,
+                toml::Value::Array(values) => values
+                    .iter()
+                    .map(|v| v.as_str().map(String::from).unwrap_or(v.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                value => value.to_string(),
+            };
+            self.set(&key, value);
+        }
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        if KEYS.iter().any(|(k, _, _)| *k == key) {
+            self.values.insert(key.to_string(), value);
+        } else {
+            self.errors
+                .push(format!("unknown configuration key {}", key));
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned().or_else(|| {
+            KEYS.iter()
+                .find(|(k, _, _)| *k == key)
+                .and_then(|(_, _, default)| default.map(String::from))
+        })
+    }
+
+    fn required(&self, key: &str, errors: &mut Vec<String>) -> String {
+        match self.get(key) {
+            Some(value) if !value.is_empty() => value,
+            _ => {
+                let env_key = KEYS
+                    .iter()
+                    .find(|(k, _, _)| *k == key)
+                    .map(|(_, e, _)| *e)
+                    .unwrap_or_default();
+                errors.push(format!("{} is required (or {})", key, env_key));
+                String::new()
+            }
+        }
+    }
+
+    fn parsed<T: std::str::FromStr + Default>(&self, key: &str, errors: &mut Vec<String>) -> T
+    where
+        T::Err: fmt::Display,
+    {
+        let value = self.required(key, errors);
+        if value.is_empty() {
+            return T::default();
+        }
+        value.parse().unwrap_or_else(|e| {
+            errors.push(format!("{}: invalid value {:?}: {}", key, value, e));
+            T::default()
+        })
+    }
+
+    pub fn server(&self) -> Result<ServerConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let listen_addr = self.required("server.listen_addr", &mut errors);
+        let listen_addr = listen_addr.parse().unwrap_or_else(|e| {
+            errors.push(format!(
+                "server.listen_addr: invalid value {:?}: {}",
+                listen_addr, e
+            ));
+            SocketAddr::from(([127, 0, 0, 1], 4000))
+        });
+        let cors_origins: Vec<String> = self
+            .required("server.cors_origins", &mut errors)
+            .split(',')
+            .map(|origin| origin.trim().to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+        for origin in cors_origins.iter() {
+            if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                errors.push(format!("server.cors_origins: invalid origin {:?}", origin));
+            }
+        }
+        check(errors)?;
+        Ok(ServerConfig {
+            listen_addr,
+            cors_origins,
+        })
+    }
+
+    pub fn postgres(&self) -> Result<PostgresConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let pool = PoolConfig {
+            min_size: self.parsed("postgres.pool_min_size", &mut errors),
+            max_size: self.parsed("postgres.pool_max_size", &mut errors),
+            checkout_timeout: Duration::from_millis(
+                self.parsed("postgres.pool_checkout_timeout_ms", &mut errors),
+            ),
+            health_check: self.parsed("postgres.pool_health_check", &mut errors),
+        };
+        if pool.max_size == 0 {
+            errors.push("postgres.pool_max_size must be greater than 0".to_string());
+        }
+        if pool.min_size > pool.max_size {
+            errors.push("postgres.pool_min_size can't be greater than pool_max_size".to_string());
+        }
+
+        let tls = TlsConfig {
+            mode: self.parsed::<SslMode>("postgres.sslmode", &mut errors),
+            root_cert: self.get("postgres.sslrootcert").map(PathBuf::from),
+            client_cert: self.get("postgres.sslcert").map(PathBuf::from),
+            client_key: self.get("postgres.sslkey").map(PathBuf::from),
+        };
+        if let Err(e) = tls.connector() {
+            errors.push(format!("postgres tls: {}", e));
+        }
+
+        let config = PostgresConfig {
+            host: self.required("postgres.host", &mut errors),
+            port: self.parsed("postgres.port", &mut errors),
+            user: self.required("postgres.user", &mut errors),
+            password: self.required("postgres.password", &mut errors),
+            database: self.required("postgres.database", &mut errors),
+            pool,
+            tls,
+        };
+        check(errors)?;
+        Ok(config)
+    }
+
+    pub fn firebase(&self) -> Result<FirebaseConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let emulator_host = self
+            .get("firebase.emulator_host")
+            .filter(|host| !host.is_empty());
+        if let Some(host) = &emulator_host {
+            if let Err(e) = host.parse::<http::uri::Authority>() {
+                errors.push(format!(
+                    "firebase.emulator_host: invalid value {:?}: {}",
+                    host, e
+                ));
+            }
+        }
+
+        // the emulator needs neither credentials nor a real api key
+        let (service_account, api_key) = match emulator_host {
+            Some(_) => (
+                self.get("firebase.service_account")
+                    .filter(|key| !key.is_empty()),
+                self.get("firebase.api_key")
+                    .unwrap_or_else(|| "fake-api-key".to_string()),
+            ),
+            None => (
+                Some(self.required("firebase.service_account", &mut errors))
+                    .filter(|key| !key.is_empty()),
+                self.required("firebase.api_key", &mut errors),
+            ),
+        };
+        let service_account_json = service_account.as_ref().and_then(|key| {
+            serde_json::from_str::<serde_json::Value>(key)
+                .map_err(|e| errors.push(format!("firebase.service_account: invalid json: {}", e)))
+                .ok()
+        });
+        let from_service_account = |field: &str| {
+            service_account_json
+                .as_ref()
+                .and_then(|json| json[field].as_str())
+                .map(String::from)
+        };
+
+        let project_id = self
+            .get("firebase.project_id")
+            .or_else(|| from_service_account("project_id"))
+            .unwrap_or_default();
+        if emulator_host.is_some() && project_id.is_empty() {
+            errors.push(
+                "firebase.project_id is required (or FIREBASE_PROJECT_ID) with the emulator"
+                    .to_string(),
+            );
+        }
+
+        let service_account_email = self
+            .get("firebase.service_account_email")
+            .or_else(|| from_service_account("client_email"))
+            .unwrap_or_else(|| {
+                if emulator_host.is_some() {
+                    "firebase-auth-emulator@example.com".to_string()
+                } else {
+                    // a missing service account is already reported
+                    if service_account.is_some() {
+                        errors.push(
+                        "firebase.service_account_email is required when the service account has no client_email"
+                            .to_string(),
+                        );
+                    }
+                    String::new()
+                }
+            });
+        check(errors)?;
+        Ok(FirebaseConfig {
+            service_account,
+            api_key,
+            service_account_email,
+            project_id,
+            emulator_host,
+        })
+    }
+
+    pub fn local_auth(&self) -> Result<LocalAuthConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let config = LocalAuthConfig {
+            private_key: PathBuf::from(self.required("auth.private_key", &mut errors)),
+            public_key: PathBuf::from(self.required("auth.public_key", &mut errors)),
+            issuer: self.required("auth.issuer", &mut errors),
+            token_ttl: Duration::from_secs(self.parsed("auth.token_ttl_secs", &mut errors)),
+        };
+        // missing paths are already reported
+        if errors.is_empty() {
+            if let Err(e) = config.keys() {
+                errors.push(format!("auth: {}", e));
+            }
+        }
+        check(errors)?;
+        Ok(config)
+    }
+
+    pub fn invitations(&self) -> Result<InvitationConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let ttl_hours: u64 = self.parsed("invitations.ttl_hours", &mut errors);
+        if ttl_hours == 0 && errors.is_empty() {
+            errors.push("invitations.ttl_hours must be greater than 0".to_string());
+        }
+        let link = self.required("invitations.link", &mut errors);
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            errors.push(format!("invitations.link: invalid url {:?}", link));
+        }
+        check(errors)?;
+        Ok(InvitationConfig {
+            ttl: Duration::from_secs(ttl_hours * 3600),
+            link,
+        })
+    }
+
+    pub fn impersonation(&self) -> Result<ImpersonationConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let max_minutes: u32 = self.parsed("impersonation.max_minutes", &mut errors);
+        if max_minutes == 0 && errors.is_empty() {
+            errors.push("impersonation.max_minutes must be greater than 0".to_string());
+        }
+        check(errors)?;
+        Ok(ImpersonationConfig {
+            max_duration: Duration::from_secs(u64::from(max_minutes) * 60),
+        })
+    }
+
+    pub fn password_resets(&self) -> Result<PasswordResetConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let ttl_minutes: u64 = self.parsed("password_resets.ttl_minutes", &mut errors);
+        if ttl_minutes == 0 && errors.is_empty() {
+            errors.push("password_resets.ttl_minutes must be greater than 0".to_string());
+        }
+        let link = self.required("password_resets.link", &mut errors);
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            errors.push(format!("password_resets.link: invalid url {:?}", link));
+        }
+        check(errors)?;
+        Ok(PasswordResetConfig {
+            ttl: Duration::from_secs(ttl_minutes * 60),
+            link,
+        })
+    }
+
+    pub fn email_verifications(&self) -> Result<EmailVerificationConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let ttl_hours: u64 = self.parsed("email_verifications.ttl_hours", &mut errors);
+        if ttl_hours == 0 && errors.is_empty() {
+            errors.push("email_verifications.ttl_hours must be greater than 0".to_string());
+        }
+        let link = self.required("email_verifications.link", &mut errors);
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            errors.push(format!("email_verifications.link: invalid url {:?}", link));
+        }
+        check(errors)?;
+        Ok(EmailVerificationConfig {
+            ttl: Duration::from_secs(ttl_hours * 3600),
+            link,
+        })
+    }
+
+    pub fn users(&self) -> Result<UserPurgeConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        // 0 purges soft deleted users on the next run
+        let retention_days: u64 = self.parsed("users.deleted_retention_days", &mut errors);
+        let interval_minutes: u64 = self.parsed("users.purge_interval_minutes", &mut errors);
+        if interval_minutes == 0 && errors.is_empty() {
+            errors.push("users.purge_interval_minutes must be greater than 0".to_string());
+        }
+        check(errors)?;
+        Ok(UserPurgeConfig {
+            retention: Duration::from_secs(retention_days * 24 * 3600),
+            interval: Duration::from_secs(interval_minutes * 60),
+        })
+    }
+
+    pub fn mail(&self) -> Result<MailConfig, ConfigErrors> {
+        let mut errors = Vec::new();
+        let transport = match self.required("mail.transport", &mut errors).as_str() {
+            "stdout" => MailTransport::Stdout,
+            "file" => MailTransport::File(PathBuf::from(self.required("mail.dir", &mut errors))),
+            "smtp" => MailTransport::Smtp(SmtpConfig {
+                host: self.required("mail.smtp_host", &mut errors),
+                port: self.parsed("mail.smtp_port", &mut errors),
+                user: self.get("mail.smtp_user").filter(|user| !user.is_empty()),
+                password: self
+                    .get("mail.smtp_password")
+                    .filter(|password| !password.is_empty()),
+                tls: self.parsed::<SmtpTls>("mail.smtp_tls", &mut errors),
+            }),
+            transport => {
+                errors.push(format!(
+                    "mail.transport: unknown transport {:?}, expected stdout, file or smtp",
+                    transport
+                ));
+                MailTransport::Stdout
+            }
+        };
+        let config = MailConfig {
+            from: self.required("mail.from", &mut errors),
+            transport,
+        };
+        // missing values are already reported
+        if errors.is_empty() {
+            if let Err(e) = config.validate() {
+                errors.push(format!("mail: {}", e));
+            }
+        }
+        check(errors)?;
+        Ok(config)
+    }
+
+    pub fn auth(&self) -> Result<AuthConfig, ConfigErrors> {
+        match self.required("auth.provider", &mut Vec::new()).as_str() {
+            "firebase" => self.firebase().map(AuthConfig::Firebase),
+            "local" => self.local_auth().map(AuthConfig::Local),
+            provider => Err(ConfigErrors(vec![format!(
+                "auth.provider: unknown provider {:?}, expected firebase or local",
+                provider
+            )])),
+        }
+    }
+}
+
+fn check(errors: Vec<String>) -> Result<(), ConfigErrors> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigErrors(errors))
+    }
+}
+
+impl AppConfig {
+    /*
+        * Load and validate the whole configuration
+        @param args: command line options
+        @return AppConfig, or every error found
+    */
+    pub fn load(args: &[String]) -> Result<AppConfig, ConfigErrors> {
+        AppConfig::from_source(&ConfigSource::load(args))
+    }
+
+    pub fn from_source(source: &ConfigSource) -> Result<AppConfig, ConfigErrors> {
+        let mut errors = source.errors.clone();
+        let server = source.server().map_err(|e| errors.extend(e.0)).ok();
+        let postgres = source.postgres().map_err(|e| errors.extend(e.0)).ok();
+        let auth = source.auth().map_err(|e| errors.extend(e.0)).ok();
+        let claims_fallback = source.parsed("auth.claims_fallback", &mut errors);
+        let invitations = source.invitations().map_err(|e| errors.extend(e.0)).ok();
+        let impersonation = source.impersonation().map_err(|e| errors.extend(e.0)).ok();
+        let password_resets = source
+            .password_resets()
+            .map_err(|e| errors.extend(e.0))
+            .ok();
+        let email_verifications = source
+            .email_verifications()
+            .map_err(|e| errors.extend(e.0))
+            .ok();
+        let users = source.users().map_err(|e| errors.extend(e.0)).ok();
+        let mail = source.mail().map_err(|e| errors.extend(e.0)).ok();
+        match (
+            server,
+            postgres,
+            auth,
+            invitations,
+            impersonation,
+            password_resets,
+            email_verifications,
+            users,
+            mail,
+        ) {
+            (
+                Some(server),
+                Some(postgres),
+                Some(auth),
+                Some(invitations),
+                Some(impersonation),
+                Some(password_resets),
+                Some(email_verifications),
+                Some(users),
+                Some(mail),
+            ) if errors.is_empty() => Ok(AppConfig {
+                server,
+                postgres,
+                auth,
+                claims_fallback,
+                invitations,
+                impersonation,
+                password_resets,
+                email_verifications,
+                users,
+                mail,
+            }),
+            _ => Err(ConfigErrors(errors)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(values: &[(&str, &str)]) -> ConfigSource {
+        let mut source = ConfigSource::default();
+        for (key, value) in values {
+            source.set(key, value.to_string());
+        }
+        source
+    }
+
+    #[test]
+    fn test_defaults() {
+        let source = source(&[
+            ("postgres.host", "localhost"),
+            ("postgres.user", "user"),
+            ("postgres.password", "password"),
+            ("postgres.database", "db"),
+        ]);
+        let server = source.server().unwrap();
+        assert_eq!(server.listen_addr.to_string(), "127.0.0.1:4000");
+        assert_eq!(server.cors_origins, vec!["http://localhost:5173"]);
+        let postgres = source.postgres().unwrap();
+        assert_eq!(postgres.port, 5432);
+        assert_eq!(postgres.pool.max_size, 10);
+        assert_eq!(postgres.tls.mode, SslMode::Prefer);
+        assert_eq!(
+            source.parsed::<ClaimsFallback>("auth.claims_fallback", &mut Vec::new()),
+            ClaimsFallback::Missing
+        );
+        assert_eq!(source.invitations().unwrap(), InvitationConfig::default());
+        assert_eq!(
+            source.impersonation().unwrap().max_duration,
+            Duration::from_secs(3600)
+        );
+        assert_eq!(
+            source.password_resets().unwrap(),
+            PasswordResetConfig::default()
+        );
+        assert_eq!(
+            source.email_verifications().unwrap(),
+            EmailVerificationConfig::default()
+        );
+        assert_eq!(source.users().unwrap(), UserPurgeConfig::default());
+        assert_eq!(source.mail().unwrap(), MailConfig::default());
+    }
+
+    #[test]
+    fn test_all_errors_reported_together() {
+        let source = source(&[
+            ("server.listen_addr", "not an address"),
+            ("postgres.port", "abc"),
+            ("postgres.pool_min_size", "20"),
+            ("postgres.sslmode", "verify-full"),
+            ("auth.claims_fallback", "sometimes"),
+            ("invitations.ttl_hours", "0"),
+            ("invitations.link", "accept-invitation"),
+            ("impersonation.max_minutes", "0"),
+            ("password_resets.ttl_minutes", "0"),
+            ("password_resets.link", "reset-password"),
+            ("email_verifications.link", "verify-email"),
+            ("users.purge_interval_minutes", "0"),
+            ("mail.transport", "file"),
+        ]);
+        let errors = AppConfig::from_source(&source).unwrap_err().0;
+        let expected = [
+            "server.listen_addr",
+            "postgres.host is required",
+            "postgres.user is required",
+            "postgres.password is required",
+            "postgres.database is required",
+            "postgres.port",
+            "pool_min_size can't be greater",
+            "verify-full needs",
+            "firebase.service_account is required",
+            "firebase.api_key is required",
+            "unknown claims fallback sometimes",
+            "invitations.ttl_hours must be greater than 0",
+            "invitations.link: invalid url",
+            "impersonation.max_minutes must be greater than 0",
+            "password_resets.ttl_minutes must be greater than 0",
+            "password_resets.link: invalid url",
+            "email_verifications.link: invalid url",
+            "users.purge_interval_minutes must be greater than 0",
+            "mail.dir is required",
+        ];
+        for expected in expected {
+            assert!(
+                errors.iter().any(|e| e.contains(expected)),
+                "{} not in {:?}",
+                expected,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_and_cli_overrides() {
+        let path = env::temp_dir().join(format!("{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+            [server]
+            listen_addr = "0.0.0.0:8080"
+            cors_origins = ["https://app.example.com", "https://admin.example.com"]
+            "#,
+        )
+        .unwrap();
+        let args = [
+            "--config".to_string(),
+            path.display().to_string(),
+            "--server.listen_addr=0.0.0.0:9090".to_string(),
+        ];
+        let source = ConfigSource::load(&args);
+        std::fs::remove_file(path).unwrap();
+
+        let server = source.server().unwrap();
+        assert_eq!(server.listen_addr.to_string(), "0.0.0.0:9090");
+        assert_eq!(server.cors_origins.len(), 2);
+    }
+
+    #[test]
+    fn test_issuer_from_service_account() {
+        let source = source(&[
+            (
+                "firebase.service_account",
+                r#"{"client_email": "admin@project.iam.gserviceaccount.com"}"#,
+            ),
+            ("firebase.api_key", "key"),
+        ]);
+        let firebase = source.firebase().unwrap();
+        assert_eq!(
+            firebase.service_account_email,
+            "admin@project.iam.gserviceaccount.com"
+        );
+    }
+
+    #[test]
+    fn test_firebase_emulator() {
+        let source = source(&[
+            ("firebase.emulator_host", "127.0.0.1:9099"),
+            ("firebase.project_id", "demo-project"),
+        ]);
+        let firebase = source.firebase().unwrap();
+        assert_eq!(firebase.emulator_host.as_deref(), Some("127.0.0.1:9099"));
+        assert_eq!(firebase.service_account, None);
+        assert_eq!(firebase.api_key, "fake-api-key");
+
+        let errors = self::source(&[("firebase.emulator_host", "not a host/")])
+            .firebase()
+            .unwrap_err()
+            .0;
+        assert!(errors.iter().any(|e| e.contains("firebase.emulator_host")));
+        assert!(errors.iter().any(|e| e.contains("firebase.project_id")));
+        assert!(!errors.iter().any(|e| e.contains("service_account")));
+    }
+
+    #[test]
+    fn test_local_auth_provider() {
+        let source = source(&[
+            ("auth.provider", "local"),
+            ("auth.private_key", "testdata/auth/private.pem"),
+            ("auth.public_key", "testdata/auth/public.pem"),
+        ]);
+        match source.auth().unwrap() {
+            AuthConfig::Local(local) => {
+                assert_eq!(local.issuer, "data_intuitive");
+                assert_eq!(local.token_ttl, Duration::from_secs(3600));
+            }
+            AuthConfig::Firebase(_) => panic!("expected the local provider"),
+        }
+
+        let source = self::source(&[
+            ("auth.provider", "local"),
+            ("auth.private_key", "testdata/auth/missing.pem"),
+            ("auth.public_key", "testdata/auth/public.pem"),
+        ]);
+        let errors = source.auth().unwrap_err().0;
+        assert!(errors[0].contains("can't read testdata/auth/missing.pem"));
+
+        let errors = self::source(&[("auth.provider", "ldap")])
+            .auth()
+            .unwrap_err()
+            .0;
+        assert!(errors[0].contains("unknown provider"));
+    }
+
+    #[test]
+    fn test_mail() {
+        let source = source(&[
+            ("mail.transport", "smtp"),
+            ("mail.smtp_host", "smtp.example.com"),
+            ("mail.smtp_user", "jane"),
+            ("mail.smtp_password", "password"),
+        ]);
+        match source.mail().unwrap().transport {
+            MailTransport::Smtp(smtp) => {
+                assert_eq!(smtp.port, 587);
+                assert_eq!(smtp.tls, SmtpTls::StartTls);
+                assert_eq!(smtp.user.as_deref(), Some("jane"));
+            }
+            transport => panic!("expected smtp, got {:?}", transport),
+        }
+
+        let errors = self::source(&[
+            ("mail.transport", "smtp"),
+            ("mail.smtp_tls", "ssl"),
+            ("mail.from", "nobody"),
+        ])
+        .mail()
+        .unwrap_err()
+        .0;
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("mail.smtp_host is required")));
+        assert!(errors.iter().any(|e| e.contains("unknown smtp tls ssl")));
+
+        let errors = self::source(&[
+            ("mail.transport", "smtp"),
+            ("mail.smtp_host", "smtp.example.com"),
+            ("mail.smtp_user", "jane"),
+        ])
+        .mail()
+        .unwrap_err()
+        .0;
+        assert!(errors[0].contains("user and password must be set together"));
+        let errors = self::source(&[("mail.from", "nobody")])
+            .mail()
+            .unwrap_err()
+            .0;
+        assert!(errors[0].contains("invalid sender"));
+        let errors = self::source(&[("mail.transport", "pigeon")])
+            .mail()
+            .unwrap_err()
+            .0;
+        assert!(errors[0].contains("unknown transport"));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let source = ConfigSource::load(&["--postgres.hots=localhost".to_string()]);
+        assert!(source
+            .errors
+            .iter()
+            .any(|e| e.contains("unknown configuration key postgres.hots")));
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use tokio::sync::OnceCell;
+
+use uuid::Uuid;
+
+use crate::{
+    contexts::user_uid::UserUID,
+    structs::api_key::ApiKey,
+    structs::impersonation::{Impersonation, ImpersonationClaim},
+    structs::role::Role,
+    structs::user::User,
+    traits::auth::AuthClaims,
+};
+
+/*
+    * Identity of the caller, scoped to a single graphql request
+    The guards fill it lazily (uid -> user -> roles) and the resolvers read it back,
+    so it must be inserted per request and never as schema data.
+    Clones share the same cells, the database of the request reads them back to scope its rows.
+*/
+#[derive(Clone, Default)]
+pub struct Identity(Arc<IdentityCells>);
+
+#[derive(Default)]
+struct IdentityCells {
+    verified: OnceCell<(UserUID, AuthClaims)>,
+    user: OnceCell<User>,
+    roles: OnceCell<Vec<Role>>,
+    permissions: OnceCell<Vec<String>>,
+}
+
+impl Identity {
+    /*
+        * Create an identity from claims verified elsewhere, i.e when a websocket connects
+        @param claims: AuthClaims
+        @return Identity
+    */
+    pub fn from_claims(claims: AuthClaims) -> Identity {
+        Identity(Arc::new(IdentityCells {
+            verified: OnceCell::new_with(Some((UserUID(claims.uid.clone()), claims))),
+            ..Default::default()
+        }))
+    }
+
+    /*
+        * Create an identity for an already verified uid
+        @param uid: &str
+        @return Identity
+    */
+    #[cfg(test)]
+    pub fn verified(uid: &str) -> Identity {
+        Identity::from_claims(AuthClaims {
+            uid: uid.to_string(),
+            ..Default::default()
+        })
+    }
+
+    pub fn uid(&self) -> Option<&UserUID> {
+        self.0.verified.get().map(|(uid, _)| uid)
+    }
+
+    pub fn claims(&self) -> Option<&AuthClaims> {
+        self.0.verified.get().map(|(_, claims)| claims)
+    }
+
+    /*
+        * Id of the api key the request is authenticated with, None for tokens of the auth provider
+        @return Option<Uuid>
+    */
+    pub fn api_key_id(&self) -> Option<Uuid> {
+        self.claims()?
+            .claims
+            .get(ApiKey::CLAIM)?
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+    }
+
+    /*
+        * Impersonation the request is made in, its uid is then the impersonated one
+        @return None unless an Admin impersonates
+    */
+    pub fn impersonation(&self) -> Option<ImpersonationClaim> {
+        let claim = self.claims()?.claims.get(Impersonation::CLAIM)?;
+        serde_json::from_value(claim.clone()).ok()
+    }
+
+    pub fn user(&self) -> Option<&User> {
+        self.0.user.get()
+    }
+
+    pub fn roles(&self) -> Option<&Vec<Role>> {
+        self.0.roles.get()
+    }
+
+    pub fn permissions(&self) -> Option<&Vec<String>> {
+        self.0.permissions.get()
+    }
+
+    /*
+        * Set the roles without loading them, i.e from the claims of the token
+        A no-op when they are already known.
+        @param roles: Vec<Role>
+    */
+    pub fn set_roles(&self, roles: Vec<Role>) {
+        let _ = self.0.roles.set(roles);
+    }
+
+    pub async fn get_or_verify<F, Fut, E>(&self, verify: F) -> Result<&AuthClaims, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<AuthClaims, E>>,
+    {
+        let (_, claims) = self
+            .0
+            .verified
+            .get_or_try_init(|| async {
+                let claims = verify().await?;
+                Ok((UserUID(claims.uid.clone()), claims))
+            })
+            .await?;
+        Ok(claims)
+    }
+
+    pub async fn get_or_load_user<F, Fut, E>(&self, load: F) -> Result<&User, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<User, E>>,
+    {
+        self.0.user.get_or_try_init(load).await
+    }
+
+    pub async fn get_or_load_roles<F, Fut, E>(&self, load: F) -> Result<&Vec<Role>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<Role>, E>>,
+    {
+        self.0.roles.get_or_try_init(load).await
+    }
+
+    pub async fn get_or_load_permissions<F, Fut, E>(&self, load: F) -> Result<&Vec<String>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<String>, E>>,
+    {
+        self.0.permissions.get_or_try_init(load).await
+    }
+}
@@ -0,0 +1,11 @@
+use uuid::Uuid;
+
+// header selecting the impersonation session a request is made in
+pub const IMPERSONATION_HEADER: &str = "x-impersonate";
+
+/*
+    * Impersonation session asked for by the X-Impersonate header, AuthTokenGuard checks it
+    belongs to the signed in Admin and is still active before acting as its target
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImpersonationId(pub Uuid);
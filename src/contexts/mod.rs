@@ -1,2 +1,5 @@
+pub mod identity;
+pub mod impersonation;
+pub mod organization;
 pub mod token;
 pub mod user_uid;
@@ -0,0 +1,35 @@
+use async_graphql::{Context, ErrorExtensions, Result};
+use uuid::Uuid;
+
+use crate::errors::main::AppError;
+
+// header selecting the active organization of a request
+pub const ORGANIZATION_HEADER: &str = "x-organization-id";
+
+/*
+    * Organization selected for the whole request, by the X-Organization-Id header
+    or the organizationId of the connection_init payload of a websocket
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrganizationId(pub Uuid);
+
+impl OrganizationId {
+    /*
+        * Organization a field runs in, its organizationId argument wins over the request one
+        @param ctx: &Context<'_>
+        @param argument: Option<Uuid>, the organizationId argument of the field
+        @return None when neither selects one
+    */
+    pub fn active(ctx: &Context<'_>, argument: Option<Uuid>) -> Option<Uuid> {
+        argument.or_else(|| ctx.data_opt::<OrganizationId>().map(|id| id.0))
+    }
+
+    /*
+        * Same as OrganizationId::active for fields that only make sense inside an organization
+        @return Uuid, VALIDATION_FAILED when no organization is selected
+    */
+    pub fn required(ctx: &Context<'_>, argument: Option<Uuid>) -> Result<Uuid> {
+        OrganizationId::active(ctx, argument)
+            .ok_or_else(|| AppError::Validation("an organization is required".to_string()).extend())
+    }
+}
@@ -0,0 +1,121 @@
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::{api_key::ApiKey, role::Role};
+use crate::traits::api_key::ApiKeyTrait;
+
+fn api_key_from_row(row: &Row) -> ApiKey {
+    ApiKey {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        scopes: row.get("scopes"),
+        key_hash: row.get("key_hash"),
+        created_by: row.get("created_by"),
+        last_used_at: row.get("last_used_at"),
+        revoked_at: row.get("revoked_at"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+impl PostGreClient {
+    /*
+        * Update an api key that isn't revoked, Expired when there is one but it is
+        @param query: UPDATE ... WHERE id = $1 AND revoked_at IS NULL RETURNING *
+    */
+    async fn update_active_api_key(
+        &self,
+        query: &str,
+        params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+    ) -> Result<ApiKey, Error> {
+        let mut client = self.connection().await?;
+        match client.query_opt(query, params).await? {
+            Some(row) => Ok(api_key_from_row(&row)),
+            None => {
+                let exists = client
+                    .query_opt("SELECT 1 FROM api_keys WHERE id = $1", &[params[0]])
+                    .await?;
+                Err(exists.map_or(Error::NotFound, |_| Error::Expired))
+            }
+        }
+    }
+}
+
+impl ApiKeyTrait for PostGreClient {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<ApiKey, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_one(
+                "INSERT INTO api_keys (id, user_id, name, scopes, key_hash, created_by) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+                &[
+                    &api_key.id,
+                    &api_key.user_id,
+                    &api_key.name,
+                    &api_key.scopes,
+                    &api_key.key_hash,
+                    &api_key.created_by,
+                ],
+            )
+            .await?;
+        Ok(api_key_from_row(&row))
+    }
+
+    async fn get_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM api_keys WHERE id = $1", &[id])
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(api_key_from_row(&row))
+    }
+
+    async fn get_user_api_keys(&self, user_uid: &str) -> Result<Vec<ApiKey>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at, id",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(api_key_from_row).collect())
+    }
+
+    async fn update_api_key(
+        &self,
+        id: &Uuid,
+        name: Option<&str>,
+        scopes: Option<&[Role]>,
+    ) -> Result<ApiKey, Error> {
+        self.update_active_api_key(
+            "UPDATE api_keys SET name = coalesce($2, name), scopes = coalesce($3, scopes), updated_at = now() WHERE id = $1 AND revoked_at IS NULL RETURNING *",
+            &[id, &name, &scopes],
+        )
+        .await
+    }
+
+    async fn revoke_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        self.update_active_api_key(
+            "UPDATE api_keys SET revoked_at = now(), updated_at = now() WHERE id = $1 AND revoked_at IS NULL RETURNING *",
+            &[id],
+        )
+        .await
+    }
+
+    async fn touch_api_key(&self, id: &Uuid) -> Result<(), Error> {
+        // a key used by a busy job doesn't write on every request
+        self.connection()
+            .await?
+            .execute(
+                "UPDATE api_keys SET last_used_at = now() WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < now() - interval '1 minute')",
+                &[id],
+            )
+            .await?;
+        Ok(())
+    }
+}
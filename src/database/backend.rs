@@ -0,0 +1,624 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{error::DatabaseError as Error, main::PostGreClient, memory::MemoryClient};
+use crate::{
+    contexts::identity::Identity,
+    structs::{
+        api_key::ApiKey,
+        claims::{Plan, UserClaims},
+        email_verification::EmailVerification,
+        identity::LocalIdentity,
+        impersonation::{Impersonation, ImpersonationEvent},
+        invitation::Invitation,
+        organization::{Membership, Organization},
+        password_reset::PasswordReset,
+        permission::Permission,
+        role::{Role, RoleDefinition},
+        user::User,
+    },
+    traits::{
+        api_key::ApiKeyTrait, email_verification::EmailVerificationTrait, identity::IdentityTrait,
+        impersonation::ImpersonationTrait, invitation::InvitationTrait,
+        organization::OrganizationTrait, password_reset::PasswordResetTrait,
+        permission::PermissionTrait, role::RoleTrait, user::UserTrait,
+    },
+};
+
+/*
+    * Storage backend handed to the resolvers and guards
+    Postgres in production, memory in tests that don't exercise sql.
+*/
+#[derive(Clone)]
+pub enum Database {
+    Postgres(PostGreClient),
+    Memory(MemoryClient),
+}
+
+impl Database {
+    /*
+        * Database handed to the resolvers of a request, its rows are restricted by the
+        row level security policies to those the identity may see. Memory has no such policies.
+        @param identity: Identity of the request
+        @return Database
+    */
+    pub fn scoped(&self, identity: Identity) -> Database {
+        match self {
+            Database::Postgres(client) => Database::Postgres(client.scoped(identity)),
+            Database::Memory(client) => Database::Memory(client.clone()),
+        }
+    }
+
+    /*
+        * Database seeing every row, for changes a request makes on behalf of the server,
+        i.e granting the role of an invitation
+        @return Database
+    */
+    pub fn unscoped(&self) -> Database {
+        match self {
+            Database::Postgres(client) => Database::Postgres(client.unscoped()),
+            Database::Memory(client) => Database::Memory(client.clone()),
+        }
+    }
+}
+
+impl UserTrait for Database {
+    async fn get_user_roles(&self, user_uid: &str) -> Result<Vec<Role>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_roles(user_uid).await,
+            Database::Memory(client) => client.get_user_roles(user_uid).await,
+        }
+    }
+
+    async fn save_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.save_user_role(user_uid, role).await,
+            Database::Memory(client) => client.save_user_role(user_uid, role).await,
+        }
+    }
+
+    async fn remove_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.remove_user_role(user_uid, role).await,
+            Database::Memory(client) => client.remove_user_role(user_uid, role).await,
+        }
+    }
+
+    async fn get_users_by_role(&self, role: &Role) -> Result<Vec<User>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_users_by_role(role).await,
+            Database::Memory(client) => client.get_users_by_role(role).await,
+        }
+    }
+
+    async fn create_user(&self, user: &User) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => client.create_user(user).await,
+            Database::Memory(client) => client.create_user(user).await,
+        }
+    }
+
+    async fn delete_user(&self, user_uid: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.delete_user(user_uid).await,
+            Database::Memory(client) => client.delete_user(user_uid).await,
+        }
+    }
+
+    async fn update_user_name(&self, user_name: &str, user_uid: &str) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => client.update_user_name(user_name, user_uid).await,
+            Database::Memory(client) => client.update_user_name(user_name, user_uid).await,
+        }
+    }
+
+    async fn update_user_password(&self, user_uid: &str, password: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.update_user_password(user_uid, password).await,
+            Database::Memory(client) => client.update_user_password(user_uid, password).await,
+        }
+    }
+
+    async fn update_user_email(&self, user_uid: &str, email: &str) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => client.update_user_email(user_uid, email).await,
+            Database::Memory(client) => client.update_user_email(user_uid, email).await,
+        }
+    }
+
+    async fn get_user(&self, user_uid: &str) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user(user_uid).await,
+            Database::Memory(client) => client.get_user(user_uid).await,
+        }
+    }
+
+    async fn update_user_plan(&self, user_uid: &str, plan: Plan) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => client.update_user_plan(user_uid, plan).await,
+            Database::Memory(client) => client.update_user_plan(user_uid, plan).await,
+        }
+    }
+
+    async fn update_user_status(
+        &self,
+        user_uid: &str,
+        disabled: bool,
+        deleted_at: Option<DateTime<Utc>>,
+        reason: Option<&str>,
+    ) -> Result<User, Error> {
+        match self {
+            Database::Postgres(client) => {
+                client
+                    .update_user_status(user_uid, disabled, deleted_at, reason)
+                    .await
+            }
+            Database::Memory(client) => {
+                client
+                    .update_user_status(user_uid, disabled, deleted_at, reason)
+                    .await
+            }
+        }
+    }
+
+    async fn get_users_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<User>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_users_deleted_before(before).await,
+            Database::Memory(client) => client.get_users_deleted_before(before).await,
+        }
+    }
+
+    async fn get_user_claims(&self, user_uid: &str) -> Result<UserClaims, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_claims(user_uid).await,
+            Database::Memory(client) => client.get_user_claims(user_uid).await,
+        }
+    }
+}
+
+impl IdentityTrait for Database {
+    async fn create_identity(&self, identity: &LocalIdentity) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.create_identity(identity).await,
+            Database::Memory(client) => client.create_identity(identity).await,
+        }
+    }
+
+    async fn get_identity(&self, uid: &str) -> Result<Option<LocalIdentity>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_identity(uid).await,
+            Database::Memory(client) => client.get_identity(uid).await,
+        }
+    }
+
+    async fn get_identity_by_email(&self, email: &str) -> Result<Option<LocalIdentity>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_identity_by_email(email).await,
+            Database::Memory(client) => client.get_identity_by_email(email).await,
+        }
+    }
+
+    async fn update_identity(&self, identity: &LocalIdentity) -> Result<bool, Error> {
+        match self {
+            Database::Postgres(client) => client.update_identity(identity).await,
+            Database::Memory(client) => client.update_identity(identity).await,
+        }
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<bool, Error> {
+        match self {
+            Database::Postgres(client) => client.delete_identity(uid).await,
+            Database::Memory(client) => client.delete_identity(uid).await,
+        }
+    }
+}
+
+impl PermissionTrait for Database {
+    async fn get_permissions(&self) -> Result<Vec<Permission>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_permissions().await,
+            Database::Memory(client) => client.get_permissions().await,
+        }
+    }
+
+    async fn create_permission(&self, name: &str, description: &str) -> Result<Permission, Error> {
+        match self {
+            Database::Postgres(client) => client.create_permission(name, description).await,
+            Database::Memory(client) => client.create_permission(name, description).await,
+        }
+    }
+
+    async fn delete_permission(&self, name: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.delete_permission(name).await,
+            Database::Memory(client) => client.delete_permission(name).await,
+        }
+    }
+
+    async fn get_role_permissions(&self, role: &Role) -> Result<Vec<String>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_role_permissions(role).await,
+            Database::Memory(client) => client.get_role_permissions(role).await,
+        }
+    }
+
+    async fn grant_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.grant_permission(role, permission).await,
+            Database::Memory(client) => client.grant_permission(role, permission).await,
+        }
+    }
+
+    async fn revoke_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.revoke_permission(role, permission).await,
+            Database::Memory(client) => client.revoke_permission(role, permission).await,
+        }
+    }
+
+    async fn get_user_permissions(&self, user_uid: &str) -> Result<Vec<String>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_permissions(user_uid).await,
+            Database::Memory(client) => client.get_user_permissions(user_uid).await,
+        }
+    }
+}
+
+impl RoleTrait for Database {
+    async fn get_role_definitions(&self) -> Result<Vec<RoleDefinition>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_role_definitions().await,
+            Database::Memory(client) => client.get_role_definitions().await,
+        }
+    }
+
+    async fn create_role(
+        &self,
+        name: &Role,
+        description: &str,
+        parent: Option<&Role>,
+    ) -> Result<RoleDefinition, Error> {
+        match self {
+            Database::Postgres(client) => client.create_role(name, description, parent).await,
+            Database::Memory(client) => client.create_role(name, description, parent).await,
+        }
+    }
+
+    async fn rename_role(&self, name: &Role, new_name: &Role) -> Result<RoleDefinition, Error> {
+        match self {
+            Database::Postgres(client) => client.rename_role(name, new_name).await,
+            Database::Memory(client) => client.rename_role(name, new_name).await,
+        }
+    }
+
+    async fn delete_role(&self, name: &Role) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.delete_role(name).await,
+            Database::Memory(client) => client.delete_role(name).await,
+        }
+    }
+}
+
+impl OrganizationTrait for Database {
+    async fn create_organization(
+        &self,
+        name: &str,
+        owner_uid: &str,
+    ) -> Result<Organization, Error> {
+        match self {
+            Database::Postgres(client) => client.create_organization(name, owner_uid).await,
+            Database::Memory(client) => client.create_organization(name, owner_uid).await,
+        }
+    }
+
+    async fn get_organization(&self, id: &Uuid) -> Result<Organization, Error> {
+        match self {
+            Database::Postgres(client) => client.get_organization(id).await,
+            Database::Memory(client) => client.get_organization(id).await,
+        }
+    }
+
+    async fn get_user_organizations(&self, user_uid: &str) -> Result<Vec<Organization>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_organizations(user_uid).await,
+            Database::Memory(client) => client.get_user_organizations(user_uid).await,
+        }
+    }
+
+    async fn rename_organization(&self, id: &Uuid, name: &str) -> Result<Organization, Error> {
+        match self {
+            Database::Postgres(client) => client.rename_organization(id, name).await,
+            Database::Memory(client) => client.rename_organization(id, name).await,
+        }
+    }
+
+    async fn delete_organization(&self, id: &Uuid) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.delete_organization(id).await,
+            Database::Memory(client) => client.delete_organization(id).await,
+        }
+    }
+
+    async fn get_members(&self, id: &Uuid) -> Result<Vec<Membership>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_members(id).await,
+            Database::Memory(client) => client.get_members(id).await,
+        }
+    }
+
+    async fn get_membership(&self, id: &Uuid, user_uid: &str) -> Result<Option<Membership>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_membership(id, user_uid).await,
+            Database::Memory(client) => client.get_membership(id, user_uid).await,
+        }
+    }
+
+    async fn save_membership(
+        &self,
+        id: &Uuid,
+        user_uid: &str,
+        role: &Role,
+    ) -> Result<Membership, Error> {
+        match self {
+            Database::Postgres(client) => client.save_membership(id, user_uid, role).await,
+            Database::Memory(client) => client.save_membership(id, user_uid, role).await,
+        }
+    }
+
+    async fn remove_membership(&self, id: &Uuid, user_uid: &str) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.remove_membership(id, user_uid).await,
+            Database::Memory(client) => client.remove_membership(id, user_uid).await,
+        }
+    }
+}
+
+impl InvitationTrait for Database {
+    async fn create_invitation(&self, invitation: &Invitation) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.create_invitation(invitation).await,
+            Database::Memory(client) => client.create_invitation(invitation).await,
+        }
+    }
+
+    async fn get_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.get_invitation(id).await,
+            Database::Memory(client) => client.get_invitation(id).await,
+        }
+    }
+
+    async fn get_invitation_by_token(&self, token_hash: &str) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.get_invitation_by_token(token_hash).await,
+            Database::Memory(client) => client.get_invitation_by_token(token_hash).await,
+        }
+    }
+
+    async fn get_open_invitations(
+        &self,
+        organization_id: Option<&Uuid>,
+    ) -> Result<Vec<Invitation>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_open_invitations(organization_id).await,
+            Database::Memory(client) => client.get_open_invitations(organization_id).await,
+        }
+    }
+
+    async fn get_user_invitations(&self, user_uid: &str) -> Result<Vec<Invitation>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_invitations(user_uid).await,
+            Database::Memory(client) => client.get_user_invitations(user_uid).await,
+        }
+    }
+
+    async fn renew_invitation(
+        &self,
+        id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.renew_invitation(id, token_hash, expires_at).await,
+            Database::Memory(client) => client.renew_invitation(id, token_hash, expires_at).await,
+        }
+    }
+
+    async fn revoke_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.revoke_invitation(id).await,
+            Database::Memory(client) => client.revoke_invitation(id).await,
+        }
+    }
+
+    async fn accept_invitation(&self, token_hash: &str, user: &User) -> Result<Invitation, Error> {
+        match self {
+            Database::Postgres(client) => client.accept_invitation(token_hash, user).await,
+            Database::Memory(client) => client.accept_invitation(token_hash, user).await,
+        }
+    }
+}
+
+impl ApiKeyTrait for Database {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<ApiKey, Error> {
+        match self {
+            Database::Postgres(client) => client.create_api_key(api_key).await,
+            Database::Memory(client) => client.create_api_key(api_key).await,
+        }
+    }
+
+    async fn get_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        match self {
+            Database::Postgres(client) => client.get_api_key(id).await,
+            Database::Memory(client) => client.get_api_key(id).await,
+        }
+    }
+
+    async fn get_user_api_keys(&self, user_uid: &str) -> Result<Vec<ApiKey>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_api_keys(user_uid).await,
+            Database::Memory(client) => client.get_user_api_keys(user_uid).await,
+        }
+    }
+
+    async fn update_api_key(
+        &self,
+        id: &Uuid,
+        name: Option<&str>,
+        scopes: Option<&[Role]>,
+    ) -> Result<ApiKey, Error> {
+        match self {
+            Database::Postgres(client) => client.update_api_key(id, name, scopes).await,
+            Database::Memory(client) => client.update_api_key(id, name, scopes).await,
+        }
+    }
+
+    async fn revoke_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        match self {
+            Database::Postgres(client) => client.revoke_api_key(id).await,
+            Database::Memory(client) => client.revoke_api_key(id).await,
+        }
+    }
+
+    async fn touch_api_key(&self, id: &Uuid) -> Result<(), Error> {
+        match self {
+            Database::Postgres(client) => client.touch_api_key(id).await,
+            Database::Memory(client) => client.touch_api_key(id).await,
+        }
+    }
+}
+
+impl ImpersonationTrait for Database {
+    async fn create_impersonation(
+        &self,
+        impersonation: &Impersonation,
+    ) -> Result<Impersonation, Error> {
+        match self {
+            Database::Postgres(client) => client.create_impersonation(impersonation).await,
+            Database::Memory(client) => client.create_impersonation(impersonation).await,
+        }
+    }
+
+    async fn get_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        match self {
+            Database::Postgres(client) => client.get_impersonation(id).await,
+            Database::Memory(client) => client.get_impersonation(id).await,
+        }
+    }
+
+    async fn get_impersonations(
+        &self,
+        user_uid: Option<&str>,
+    ) -> Result<Vec<Impersonation>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_impersonations(user_uid).await,
+            Database::Memory(client) => client.get_impersonations(user_uid).await,
+        }
+    }
+
+    async fn end_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        match self {
+            Database::Postgres(client) => client.end_impersonation(id).await,
+            Database::Memory(client) => client.end_impersonation(id).await,
+        }
+    }
+
+    async fn record_impersonation_event(
+        &self,
+        event: &ImpersonationEvent,
+    ) -> Result<ImpersonationEvent, Error> {
+        match self {
+            Database::Postgres(client) => client.record_impersonation_event(event).await,
+            Database::Memory(client) => client.record_impersonation_event(event).await,
+        }
+    }
+
+    async fn get_impersonation_events(
+        &self,
+        impersonation_id: &Uuid,
+    ) -> Result<Vec<ImpersonationEvent>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_impersonation_events(impersonation_id).await,
+            Database::Memory(client) => client.get_impersonation_events(impersonation_id).await,
+        }
+    }
+}
+
+impl PasswordResetTrait for Database {
+    async fn create_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PasswordReset, Error> {
+        match self {
+            Database::Postgres(client) => {
+                client
+                    .create_password_reset(email, token_hash, expires_at)
+                    .await
+            }
+            Database::Memory(client) => {
+                client
+                    .create_password_reset(email, token_hash, expires_at)
+                    .await
+            }
+        }
+    }
+
+    async fn consume_password_reset(&self, token_hash: &str) -> Result<PasswordReset, Error> {
+        match self {
+            Database::Postgres(client) => client.consume_password_reset(token_hash).await,
+            Database::Memory(client) => client.consume_password_reset(token_hash).await,
+        }
+    }
+
+    async fn get_user_password_resets(&self, user_uid: &str) -> Result<Vec<PasswordReset>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_password_resets(user_uid).await,
+            Database::Memory(client) => client.get_user_password_resets(user_uid).await,
+        }
+    }
+}
+
+impl EmailVerificationTrait for Database {
+    async fn create_email_verification(
+        &self,
+        user_uid: &str,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailVerification, Error> {
+        match self {
+            Database::Postgres(client) => {
+                client
+                    .create_email_verification(user_uid, email, token_hash, expires_at)
+                    .await
+            }
+            Database::Memory(client) => {
+                client
+                    .create_email_verification(user_uid, email, token_hash, expires_at)
+                    .await
+            }
+        }
+    }
+
+    async fn consume_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerification, Error> {
+        match self {
+            Database::Postgres(client) => client.consume_email_verification(token_hash).await,
+            Database::Memory(client) => client.consume_email_verification(token_hash).await,
+        }
+    }
+
+    async fn get_user_email_verifications(
+        &self,
+        user_uid: &str,
+    ) -> Result<Vec<EmailVerification>, Error> {
+        match self {
+            Database::Postgres(client) => client.get_user_email_verifications(user_uid).await,
+            Database::Memory(client) => client.get_user_email_verifications(user_uid).await,
+        }
+    }
+}
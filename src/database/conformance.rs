@@ -0,0 +1,1515 @@
+/*
+    * Behaviour every backend must share, run against PostGreClient and MemoryClient.
+    Each check works on fresh uids so it doesn't depend on what else is stored.
+*/
+use chrono::Utc;
+
+use super::error::DatabaseError;
+use crate::{
+    structs::{
+        api_key::ApiKey,
+        claims::{Plan, UserClaims},
+        email_verification::EmailVerification,
+        identity::LocalIdentity,
+        impersonation::{Impersonation, ImpersonationEvent},
+        invitation::Invitation,
+        one_time_token::OneTimeToken,
+        password_reset::PasswordReset,
+        role::Role,
+        user::User,
+    },
+    traits::{
+        api_key::ApiKeyTrait, email_verification::EmailVerificationTrait, identity::IdentityTrait,
+        impersonation::ImpersonationTrait, invitation::InvitationTrait,
+        organization::OrganizationTrait, password_reset::PasswordResetTrait,
+        permission::PermissionTrait, role::RoleTrait, user::UserTrait,
+    },
+};
+
+pub async fn run<
+    T: UserTrait
+        + IdentityTrait
+        + PermissionTrait
+        + RoleTrait
+        + OrganizationTrait
+        + InvitationTrait
+        + ApiKeyTrait
+        + ImpersonationTrait
+        + PasswordResetTrait
+        + EmailVerificationTrait,
+>(
+    backend: &T,
+) {
+    create_and_get_user(backend).await;
+    duplicate_user(backend).await;
+    missing_user(backend).await;
+    update_user_name(backend).await;
+    delete_user(backend).await;
+    update_user_password(backend).await;
+    update_user_email(backend).await;
+    user_status(backend).await;
+    roles(backend).await;
+    last_admin(backend).await;
+    identities(backend).await;
+    permissions(backend).await;
+    role_catalogue(backend).await;
+    claims(backend).await;
+    organizations(backend).await;
+    invitations(backend).await;
+    api_keys(backend).await;
+    impersonations(backend).await;
+    password_resets(backend).await;
+    email_verifications(backend).await;
+    user_data(backend).await;
+    delete_last_admin(backend).await;
+}
+
+fn new_uid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+async fn create_and_get_user<T: UserTrait>(backend: &T) {
+    let uid = new_uid();
+    let user = User {
+        id: Some(uid.clone()),
+        name: "conformance".to_string(),
+        email: format!("{}@example.com", uid),
+        password: "password".to_string(),
+        plan: Plan::Free,
+        email_verified: false,
+        disabled: false,
+        deleted_at: None,
+        status_reason: None,
+        created_at: Some(Utc::now()),
+        updated_at: Some(Utc::now()),
+    };
+    let created = backend.create_user(&user).await.unwrap();
+    assert_eq!(created.id, user.id);
+    assert_eq!(created.name, user.name);
+    assert_eq!(created.email, user.email);
+    // passwords are never stored in clear
+    assert_ne!(created.password, user.password);
+    assert!(bcrypt::verify(&user.password, &created.password).unwrap());
+
+    let fetched = backend.get_user(&uid).await.unwrap();
+    assert_eq!(fetched.email, user.email);
+    assert_eq!(
+        fetched.created_at.unwrap().timestamp(),
+        user.created_at.unwrap().timestamp()
+    );
+}
+
+async fn duplicate_user<T: UserTrait>(backend: &T) {
+    let user = backend.crate_random_user().await.unwrap();
+    let res = backend.create_test_user(&user.id.unwrap()).await;
+    assert!(matches!(res, Err(DatabaseError::Conflict(_))), "{:?}", res);
+
+    // emails aren't unique in users
+    let mut other = backend.crate_random_user().await.unwrap();
+    other.id = Some(new_uid());
+    other.email = user.email;
+    assert!(backend.create_user(&other).await.is_ok());
+
+    other.id = None;
+    assert!(matches!(
+        backend.create_user(&other).await,
+        Err(DatabaseError::Invalid(_))
+    ));
+}
+
+async fn missing_user<T: UserTrait>(backend: &T) {
+    let uid = new_uid();
+    assert!(matches!(
+        backend.get_user(&uid).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.update_user_name("name", &uid).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.save_user_role(&uid, &Role::ADMIN).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(backend.get_user_roles(&uid).await.unwrap().is_empty());
+}
+
+async fn update_user_name<T: UserTrait>(backend: &T) {
+    let user = backend.crate_random_user().await.unwrap();
+    let uid = user.id.unwrap();
+    let updated = backend.update_user_name("new name", &uid).await.unwrap();
+    assert_eq!(updated.name, "new name");
+    assert_eq!(updated.email, user.email);
+    assert_eq!(backend.get_user(&uid).await.unwrap().name, "new name");
+}
+
+async fn delete_user<T: UserTrait + OrganizationTrait + ApiKeyTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    let other = backend.crate_random_user().await.unwrap().id.unwrap();
+    let organization = backend.create_organization("Acme", &other).await.unwrap();
+    backend
+        .save_membership(&organization.id, &uid, &Role::USER)
+        .await
+        .unwrap();
+    let mut api_key = new_api_key(&uid, "etl", vec![Role::USER]);
+    backend.create_api_key(&api_key).await.unwrap();
+    // created by the user for another one, i.e a service account
+    api_key = new_api_key(&other, "svc", vec![Role::USER]);
+    api_key.created_by = Some(uid.clone());
+    backend.create_api_key(&api_key).await.unwrap();
+
+    backend.delete_user(&uid).await.unwrap();
+    assert!(matches!(
+        backend.get_user(&uid).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(backend.get_user_roles(&uid).await.unwrap().is_empty());
+    assert!(matches!(
+        backend.get_user_claims(&uid).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(backend.get_user_api_keys(&uid).await.unwrap().is_empty());
+    assert_eq!(
+        backend.get_api_key(&api_key.id).await.unwrap().created_by,
+        None
+    );
+    let members = backend.get_members(&organization.id).await.unwrap();
+    assert!(!members.iter().any(|member| member.user_id == uid));
+    assert!(matches!(
+        backend.delete_user(&uid).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn update_user_password<T: UserTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend
+        .update_user_password(&uid, "new password")
+        .await
+        .unwrap();
+    let stored = backend.get_user(&uid).await.unwrap();
+    assert!(bcrypt::verify("new password", &stored.password).unwrap());
+    assert!(matches!(
+        backend
+            .update_user_password(&new_uid(), "new password")
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn update_user_email<T: UserTrait>(backend: &T) {
+    let user = backend.crate_random_user().await.unwrap();
+    let uid = user.id.unwrap();
+    assert!(!user.email_verified);
+    let email = format!("{}@example.org", new_uid());
+    let updated = backend.update_user_email(&uid, &email).await.unwrap();
+    assert_eq!(updated.email, email);
+    assert_eq!(updated.name, user.name);
+    assert_eq!(backend.get_user(&uid).await.unwrap().email, email);
+    assert!(matches!(
+        backend.update_user_email(&new_uid(), &email).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn user_status<T: UserTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    let user = backend.get_user(&uid).await.unwrap();
+    assert!(!user.disabled);
+    assert_eq!(user.deleted_at, None);
+    assert_eq!(user.status_reason, None);
+
+    let suspended = backend
+        .update_user_status(&uid, true, None, Some("spam"))
+        .await
+        .unwrap();
+    assert!(suspended.disabled);
+    assert_eq!(suspended.status_reason.as_deref(), Some("spam"));
+    assert_eq!(backend.get_user(&uid).await.unwrap(), suspended);
+
+    // deleted long ago, or recently
+    let now = Utc::now();
+    let old = now - chrono::Duration::days(400);
+    backend
+        .update_user_status(&uid, true, Some(old), Some("left"))
+        .await
+        .unwrap();
+    let recent = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend
+        .update_user_status(&recent, true, Some(now), None)
+        .await
+        .unwrap();
+    let due: Vec<String> = backend
+        .get_users_deleted_before(now - chrono::Duration::days(1))
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|user| user.id)
+        .collect();
+    assert!(due.contains(&uid));
+    assert!(!due.contains(&recent));
+    let user = backend.get_user(&uid).await.unwrap();
+    assert_eq!(
+        user.deleted_at.map(|t| t.timestamp()),
+        Some(old.timestamp())
+    );
+
+    let reactivated = backend
+        .update_user_status(&uid, false, None, None)
+        .await
+        .unwrap();
+    assert!(!reactivated.disabled);
+    assert_eq!(reactivated.deleted_at, None);
+    assert_eq!(reactivated.status_reason, None);
+    assert!(matches!(
+        backend
+            .update_user_status(&new_uid(), true, None, None)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn roles<T: UserTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    assert_eq!(
+        backend.get_user_roles(&uid).await.unwrap(),
+        vec![Role::USER]
+    );
+    backend.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+    assert_eq!(
+        backend.get_user_roles(&uid).await.unwrap(),
+        vec![Role::USER, Role::ADMIN]
+    );
+    // a role is held once
+    assert!(matches!(
+        backend.save_user_role(&uid, &Role::ADMIN).await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    assert!(backend
+        .get_users_by_role(&Role::ADMIN)
+        .await
+        .unwrap()
+        .iter()
+        .any(|user| user.id.as_deref() == Some(uid.as_str())));
+
+    backend.remove_user_role(&uid, &Role::USER).await.unwrap();
+    assert_eq!(
+        backend.get_user_roles(&uid).await.unwrap(),
+        vec![Role::ADMIN]
+    );
+    assert!(matches!(
+        backend.remove_user_role(&uid, &Role::MANAGER).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn last_admin<T: UserTrait + RoleTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+    // leave uid as the only Admin, whatever other checks stored
+    for admin in backend.get_users_by_role(&Role::ADMIN).await.unwrap() {
+        let other = admin.id.unwrap();
+        if other != uid {
+            backend
+                .remove_user_role(&other, &Role::ADMIN)
+                .await
+                .unwrap();
+        }
+    }
+    assert!(matches!(
+        backend.remove_user_role(&uid, &Role::ADMIN).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+
+    let next = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend.save_user_role(&next, &Role::ADMIN).await.unwrap();
+    // suspended or soft deleted Admins can't act as one
+    for deleted_at in [None, Some(Utc::now())] {
+        backend
+            .update_user_status(&next, true, deleted_at, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            backend.remove_user_role(&uid, &Role::ADMIN).await,
+            Err(DatabaseError::LastAdmin)
+        ));
+    }
+    backend
+        .update_user_status(&next, false, None, None)
+        .await
+        .unwrap();
+    backend.remove_user_role(&uid, &Role::ADMIN).await.unwrap();
+    let admins = backend.get_users_by_role(&Role::ADMIN).await.unwrap();
+    assert_eq!(admins.len(), 1);
+    assert_eq!(admins[0].id, Some(next.clone()));
+
+    // a custom child of Admin counts as an Admin
+    let owner = Role::parse(&format!("Owner-{}", &uid[..8])).unwrap();
+    backend
+        .create_role(&owner, "", Some(&Role::ADMIN))
+        .await
+        .unwrap();
+    backend.save_user_role(&uid, &owner).await.unwrap();
+    backend.remove_user_role(&next, &Role::ADMIN).await.unwrap();
+    assert!(matches!(
+        backend.remove_user_role(&uid, &owner).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    assert!(matches!(
+        backend.delete_user(&uid).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    backend.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+    backend.remove_user_role(&uid, &owner).await.unwrap();
+    backend.delete_role(&owner).await.unwrap();
+}
+
+async fn identities<T: IdentityTrait>(backend: &T) {
+    let uid = new_uid();
+    let mut identity = LocalIdentity {
+        uid: uid.clone(),
+        email: format!("{}@example.com", uid),
+        password: "hash".to_string(),
+        display_name: Some("conformance".to_string()),
+        email_verified: false,
+        disabled: false,
+        custom_claims: serde_json::Map::new(),
+        sessions_valid_after: None,
+        created_at: None,
+        updated_at: None,
+    };
+    backend.create_identity(&identity).await.unwrap();
+    let stored = backend.get_identity(&uid).await.unwrap().unwrap();
+    assert_eq!(stored.email, identity.email);
+    assert!(stored.created_at.is_some());
+    assert_eq!(
+        backend
+            .get_identity_by_email(&identity.email)
+            .await
+            .unwrap()
+            .map(|i| i.uid),
+        Some(uid.clone())
+    );
+
+    // uid and email are both unique
+    let res = backend.create_identity(&identity).await;
+    assert!(matches!(res, Err(DatabaseError::Conflict(_))), "{:?}", res);
+    let other = LocalIdentity {
+        uid: new_uid(),
+        ..identity.clone()
+    };
+    let res = backend.create_identity(&other).await;
+    assert!(matches!(res, Err(DatabaseError::Conflict(_))), "{:?}", res);
+
+    let other = LocalIdentity {
+        uid: new_uid(),
+        email: format!("{}@example.com", new_uid()),
+        ..identity.clone()
+    };
+    backend.create_identity(&other).await.unwrap();
+    identity.email = other.email.clone();
+    let res = backend.update_identity(&identity).await;
+    assert!(matches!(res, Err(DatabaseError::Conflict(_))), "{:?}", res);
+
+    identity.email = format!("{}@example.com", new_uid());
+    identity.disabled = true;
+    identity.sessions_valid_after = Some(Utc::now());
+    identity.custom_claims = UserClaims {
+        roles: vec![Role::ADMIN],
+        plan: Plan::Premium,
+        claims_version: 2,
+    }
+    .to_map();
+    assert!(backend.update_identity(&identity).await.unwrap());
+    let stored = backend.get_identity(&uid).await.unwrap().unwrap();
+    assert_eq!(stored.email, identity.email);
+    assert!(stored.disabled);
+    assert_eq!(
+        stored.sessions_valid_after.map(|t| t.timestamp()),
+        identity.sessions_valid_after.map(|t| t.timestamp())
+    );
+    assert_eq!(stored.custom_claims, identity.custom_claims);
+
+    assert!(backend.delete_identity(&uid).await.unwrap());
+    assert!(!backend.delete_identity(&uid).await.unwrap());
+    assert!(!backend.update_identity(&identity).await.unwrap());
+    assert_eq!(backend.get_identity(&uid).await.unwrap(), None);
+}
+
+async fn permissions<T: UserTrait + PermissionTrait>(backend: &T) {
+    // permissions are global, the name is unique to this run
+    let name = format!("conformance:{}", new_uid().replace('-', "_"));
+    let created = backend
+        .create_permission(&name, "checked by the conformance suite")
+        .await
+        .unwrap();
+    assert_eq!(created.name, name);
+    assert!(matches!(
+        backend.create_permission(&name, "again").await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    assert!(backend
+        .get_permissions()
+        .await
+        .unwrap()
+        .iter()
+        .any(|p| p.name == name));
+
+    assert!(matches!(
+        backend
+            .grant_permission(&Role::MANAGER, "conformance:unknown")
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    backend
+        .grant_permission(&Role::MANAGER, &name)
+        .await
+        .unwrap();
+    assert!(matches!(
+        backend.grant_permission(&Role::MANAGER, &name).await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    assert!(backend
+        .get_role_permissions(&Role::MANAGER)
+        .await
+        .unwrap()
+        .contains(&name));
+
+    // granted to Manager, so to Admin as well but not to User
+    let user = backend.crate_random_user().await.unwrap().id.unwrap();
+    let manager = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend
+        .save_user_role(&manager, &Role::MANAGER)
+        .await
+        .unwrap();
+    let admin = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+    assert!(!backend
+        .get_user_permissions(&user)
+        .await
+        .unwrap()
+        .contains(&name));
+    for uid in [&manager, &admin] {
+        let permissions = backend.get_user_permissions(uid).await.unwrap();
+        assert!(permissions.contains(&name));
+        let mut sorted = permissions.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(permissions, sorted);
+    }
+
+    backend
+        .revoke_permission(&Role::MANAGER, &name)
+        .await
+        .unwrap();
+    assert!(matches!(
+        backend.revoke_permission(&Role::MANAGER, &name).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(!backend
+        .get_user_permissions(&admin)
+        .await
+        .unwrap()
+        .contains(&name));
+
+    // deleting a permission drops its grants
+    backend.grant_permission(&Role::USER, &name).await.unwrap();
+    backend.delete_permission(&name).await.unwrap();
+    assert!(!backend
+        .get_role_permissions(&Role::USER)
+        .await
+        .unwrap()
+        .contains(&name));
+    assert!(matches!(
+        backend.delete_permission(&name).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn role_catalogue<T: UserTrait + PermissionTrait + RoleTrait>(backend: &T) {
+    // roles are global, the names are unique to this run
+    let suffix = new_uid();
+    let analyst = Role::parse(&format!("analyst-{}", suffix)).unwrap();
+    let senior = Role::parse(&format!("senior-{}", suffix)).unwrap();
+    let renamed = Role::parse(&format!("renamed-{}", suffix)).unwrap();
+
+    let created = backend
+        .create_role(
+            &analyst,
+            "checked by the conformance suite",
+            Some(&Role::USER),
+        )
+        .await
+        .unwrap();
+    assert_eq!(created.name, analyst);
+    assert_eq!(created.parent, Some(Role::USER));
+    assert!(!created.built_in);
+    assert!(matches!(
+        backend.create_role(&analyst, "again", None).await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    assert!(matches!(
+        backend
+            .create_role(
+                &senior,
+                "",
+                Some(&Role::parse("conformance-unknown").unwrap())
+            )
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    backend
+        .create_role(&senior, "", Some(&analyst))
+        .await
+        .unwrap();
+
+    // a role outside the catalogue can't be granted
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    assert!(matches!(
+        backend
+            .save_user_role(&uid, &Role::parse("conformance-unknown").unwrap())
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    backend.save_user_role(&uid, &senior).await.unwrap();
+    let name = format!("conformance:{}", suffix.replace('-', "_"));
+    backend.create_permission(&name, "").await.unwrap();
+    backend.grant_permission(&analyst, &name).await.unwrap();
+    // inherited through the parent chain
+    assert!(backend
+        .get_user_permissions(&uid)
+        .await
+        .unwrap()
+        .contains(&name));
+
+    // the rename reaches assignments, permissions and children
+    assert!(matches!(
+        backend.rename_role(&analyst, &senior).await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    let definition = backend.rename_role(&analyst, &renamed).await.unwrap();
+    assert_eq!(definition.name, renamed);
+    assert_eq!(
+        backend.get_role_permissions(&renamed).await.unwrap(),
+        vec![name.clone()]
+    );
+    let definitions = backend.get_role_definitions().await.unwrap();
+    assert!(!definitions.iter().any(|d| d.name == analyst));
+    assert_eq!(
+        definitions
+            .iter()
+            .find(|d| d.name == senior)
+            .and_then(|d| d.parent.clone()),
+        Some(renamed.clone())
+    );
+    assert!(matches!(
+        backend.rename_role(&analyst, &renamed).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // a parent goes after its children, the assignments go with the role
+    assert!(matches!(
+        backend.delete_role(&renamed).await,
+        Err(DatabaseError::RoleInUse(_))
+    ));
+    backend.delete_role(&senior).await.unwrap();
+    assert_eq!(
+        backend.get_user_roles(&uid).await.unwrap(),
+        vec![Role::USER]
+    );
+    backend.delete_role(&renamed).await.unwrap();
+    assert!(!backend
+        .get_user_permissions(&uid)
+        .await
+        .unwrap()
+        .contains(&name));
+    assert!(matches!(
+        backend.delete_role(&renamed).await,
+        Err(DatabaseError::NotFound)
+    ));
+    backend.delete_permission(&name).await.unwrap();
+
+    for role in Role::BUILT_IN {
+        assert!(matches!(
+            backend.rename_role(&role, &renamed).await,
+            Err(DatabaseError::BuiltInRole(_))
+        ));
+        assert!(matches!(
+            backend.delete_role(&role).await,
+            Err(DatabaseError::BuiltInRole(_))
+        ));
+    }
+}
+
+async fn claims<T: UserTrait + RoleTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    let created = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(created.roles, vec![Role::USER]);
+    assert_eq!(created.plan, Plan::Free);
+
+    // every change of roles or plan bumps the version
+    backend.save_user_role(&uid, &Role::MANAGER).await.unwrap();
+    let granted = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(granted.roles, vec![Role::USER, Role::MANAGER]);
+    assert!(granted.claims_version > created.claims_version);
+
+    let user = backend.update_user_plan(&uid, Plan::Premium).await.unwrap();
+    assert_eq!(user.plan, Plan::Premium);
+    assert_eq!(backend.get_user(&uid).await.unwrap().plan, Plan::Premium);
+    let upgraded = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(upgraded.plan, Plan::Premium);
+    assert!(upgraded.claims_version > granted.claims_version);
+
+    backend
+        .remove_user_role(&uid, &Role::MANAGER)
+        .await
+        .unwrap();
+    let revoked = backend.get_user_claims(&uid).await.unwrap();
+    assert!(revoked.claims_version > upgraded.claims_version);
+
+    // so does a change to a custom role held by the user
+    let custom = Role::parse(&format!("claims-{}", new_uid())).unwrap();
+    backend.create_role(&custom, "", None).await.unwrap();
+    backend.save_user_role(&uid, &custom).await.unwrap();
+    let before = backend.get_user_claims(&uid).await.unwrap();
+    backend.delete_role(&custom).await.unwrap();
+    let deleted = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(deleted.roles, vec![Role::USER]);
+    assert!(deleted.claims_version > before.claims_version);
+
+    assert!(matches!(
+        backend.get_user_claims(&new_uid()).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.update_user_plan(&new_uid(), Plan::Premium).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn organizations<T: UserTrait + RoleTrait + OrganizationTrait>(backend: &T) {
+    let owner = backend.crate_random_user().await.unwrap().id.unwrap();
+    let member = backend.crate_random_user().await.unwrap().id.unwrap();
+    let organization = backend
+        .create_organization("Conformance", &owner)
+        .await
+        .unwrap();
+    assert_eq!(organization.name, "Conformance");
+    assert_eq!(
+        backend.get_organization(&organization.id).await.unwrap(),
+        organization
+    );
+    assert!(matches!(
+        backend.create_organization("Nobody's", &new_uid()).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // the creator is its first Admin
+    let membership = backend
+        .get_membership(&organization.id, &owner)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(membership.role, Role::ADMIN);
+    assert_eq!(
+        backend
+            .get_membership(&organization.id, &member)
+            .await
+            .unwrap(),
+        None
+    );
+
+    let added = backend
+        .save_membership(&organization.id, &member, &Role::USER)
+        .await
+        .unwrap();
+    assert_eq!(added.role, Role::USER);
+    let changed = backend
+        .save_membership(&organization.id, &member, &Role::MANAGER)
+        .await
+        .unwrap();
+    assert_eq!(changed.role, Role::MANAGER);
+    assert_eq!(changed.created_at, added.created_at);
+    let members = backend.get_members(&organization.id).await.unwrap();
+    assert_eq!(
+        members
+            .iter()
+            .map(|m| (m.user_id.clone(), m.role.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (owner.clone(), Role::ADMIN),
+            (member.clone(), Role::MANAGER)
+        ]
+    );
+    for missing in [
+        backend
+            .save_membership(&uuid::Uuid::new_v4(), &member, &Role::USER)
+            .await,
+        backend
+            .save_membership(&organization.id, &new_uid(), &Role::USER)
+            .await,
+        backend
+            .save_membership(&organization.id, &member, &Role::parse("Nobody").unwrap())
+            .await,
+    ] {
+        assert!(
+            matches!(missing, Err(DatabaseError::NotFound)),
+            "{:?}",
+            missing
+        );
+    }
+
+    // the last Admin can be neither demoted nor removed, until there is another one
+    assert!(matches!(
+        backend
+            .save_membership(&organization.id, &owner, &Role::USER)
+            .await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    assert!(matches!(
+        backend.remove_membership(&organization.id, &owner).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    backend
+        .save_membership(&organization.id, &member, &Role::ADMIN)
+        .await
+        .unwrap();
+    backend
+        .remove_membership(&organization.id, &owner)
+        .await
+        .unwrap();
+    assert!(matches!(
+        backend.remove_membership(&organization.id, &owner).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // members of a deleted custom role fall back to User
+    let role = Role::parse(&format!("Org-{}", &new_uid()[..8])).unwrap();
+    backend
+        .create_role(&role, "", Some(&Role::USER))
+        .await
+        .unwrap();
+    backend
+        .save_membership(&organization.id, &owner, &role)
+        .await
+        .unwrap();
+    backend.delete_role(&role).await.unwrap();
+    let membership = backend
+        .get_membership(&organization.id, &owner)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(membership.role, Role::USER);
+
+    let renamed = backend
+        .rename_organization(&organization.id, "Renamed")
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "Renamed");
+    assert_eq!(
+        backend.get_user_organizations(&member).await.unwrap(),
+        vec![renamed]
+    );
+    assert!(matches!(
+        backend
+            .rename_organization(&uuid::Uuid::new_v4(), "Nothing")
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    backend.delete_organization(&organization.id).await.unwrap();
+    assert!(backend
+        .get_user_organizations(&member)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        backend.get_organization(&organization.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.delete_organization(&organization.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+fn new_invitation(
+    email: &str,
+    role: Role,
+    organization_id: Option<uuid::Uuid>,
+) -> (Invitation, String) {
+    let token = OneTimeToken::generate();
+    let invitation = Invitation {
+        id: uuid::Uuid::new_v4(),
+        email: email.to_string(),
+        role,
+        organization_id,
+        token_hash: token.hash,
+        invited_by: None,
+        expires_at: Utc::now() + chrono::Duration::hours(1),
+        accepted_at: None,
+        accepted_by: None,
+        revoked_at: None,
+        created_at: None,
+        updated_at: None,
+    };
+    (invitation, token.token)
+}
+
+async fn invitations<T: UserTrait + OrganizationTrait + InvitationTrait>(backend: &T) {
+    let email = format!("{}@example.com", new_uid());
+    let (invitation, token) = new_invitation(&email, Role::MANAGER, None);
+    let created = backend.create_invitation(&invitation).await.unwrap();
+    assert_eq!(created.email, email);
+    assert!(created.is_open());
+    assert_eq!(
+        backend
+            .get_invitation_by_token(&OneTimeToken::hash(&token))
+            .await
+            .unwrap()
+            .id,
+        invitation.id
+    );
+    // a single open invitation per email, whatever its case
+    let (again, _) = new_invitation(&email.to_uppercase(), Role::USER, None);
+    assert!(matches!(
+        backend.create_invitation(&again).await,
+        Err(DatabaseError::Conflict(_))
+    ));
+    let (unknown_role, _) = new_invitation(
+        &format!("{}@example.com", new_uid()),
+        Role::parse("conformance-unknown").unwrap(),
+        None,
+    );
+    assert!(matches!(
+        backend.create_invitation(&unknown_role).await,
+        Err(DatabaseError::NotFound)
+    ));
+    let open = backend.get_open_invitations(None).await.unwrap();
+    assert!(open.iter().any(|other| other.id == invitation.id));
+
+    // a new token replaces the previous one
+    let renewed_token = OneTimeToken::generate();
+    let renewed = backend
+        .renew_invitation(
+            &invitation.id,
+            &renewed_token.hash,
+            Utc::now() + chrono::Duration::hours(2),
+        )
+        .await
+        .unwrap();
+    assert!(renewed.expires_at > created.expires_at);
+    assert!(matches!(
+        backend
+            .get_invitation_by_token(&OneTimeToken::hash(&token))
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // accepting creates the user with both roles, once
+    let uid = new_uid();
+    let user = User {
+        id: Some(uid.clone()),
+        name: "invited".to_string(),
+        email: "ignored@example.com".to_string(),
+        password: "password".to_string(),
+        plan: Plan::Free,
+        email_verified: false,
+        disabled: false,
+        deleted_at: None,
+        status_reason: None,
+        created_at: None,
+        updated_at: None,
+    };
+    let accepted = backend
+        .accept_invitation(&renewed_token.hash, &user)
+        .await
+        .unwrap();
+    assert_eq!(accepted.accepted_by, Some(uid.clone()));
+    assert!(accepted.accepted_at.is_some());
+    let stored = backend.get_user(&uid).await.unwrap();
+    assert_eq!(stored.email, email);
+    assert!(bcrypt::verify("password", &stored.password).unwrap());
+    let claims = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(claims.roles, vec![Role::USER, Role::MANAGER]);
+    assert!(matches!(
+        backend.accept_invitation(&renewed_token.hash, &user).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(matches!(
+        backend.revoke_invitation(&invitation.id).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(!backend
+        .get_open_invitations(None)
+        .await
+        .unwrap()
+        .iter()
+        .any(|other| other.id == invitation.id));
+
+    // an existing user joins an organization with the invited role
+    let owner = backend.crate_random_user().await.unwrap().id.unwrap();
+    let organization = backend
+        .create_organization("Invitations", &owner)
+        .await
+        .unwrap();
+    let (to_organization, token) = new_invitation(&email, Role::MANAGER, Some(organization.id));
+    backend.create_invitation(&to_organization).await.unwrap();
+    assert_eq!(
+        backend
+            .get_open_invitations(Some(&organization.id))
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    backend
+        .accept_invitation(&OneTimeToken::hash(&token), &user)
+        .await
+        .unwrap();
+    let membership = backend
+        .get_membership(&organization.id, &uid)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(membership.role, Role::MANAGER);
+    let joined = backend.get_user_claims(&uid).await.unwrap();
+    assert_eq!(joined.roles, claims.roles);
+    assert!(joined.claims_version > claims.claims_version);
+
+    // revoked and expired invitations can't be accepted
+    let other = User {
+        id: Some(new_uid()),
+        ..user.clone()
+    };
+    let other_email = format!("{}@example.com", new_uid());
+    let (revoked, token) = new_invitation(&other_email, Role::USER, Some(organization.id));
+    backend.create_invitation(&revoked).await.unwrap();
+    let revoked = backend.revoke_invitation(&revoked.id).await.unwrap();
+    assert!(revoked.revoked_at.is_some());
+    assert!(matches!(
+        backend
+            .accept_invitation(&OneTimeToken::hash(&token), &other)
+            .await,
+        Err(DatabaseError::Expired)
+    ));
+    let (mut expired, token) = new_invitation(&other_email, Role::USER, Some(organization.id));
+    expired.expires_at = Utc::now() - chrono::Duration::seconds(1);
+    backend.create_invitation(&expired).await.unwrap();
+    assert!(matches!(
+        backend
+            .accept_invitation(&OneTimeToken::hash(&token), &other)
+            .await,
+        Err(DatabaseError::Expired)
+    ));
+    // nothing was created along the way
+    assert!(matches!(
+        backend.get_user(other.id.as_deref().unwrap()).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.accept_invitation("unknown", &other).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        backend.revoke_invitation(&uuid::Uuid::new_v4()).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // deleting the organization deletes its invitations
+    backend.delete_organization(&organization.id).await.unwrap();
+    assert!(matches!(
+        backend.get_invitation(&expired.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+fn new_api_key(uid: &str, name: &str, scopes: Vec<Role>) -> ApiKey {
+    ApiKey {
+        id: uuid::Uuid::new_v4(),
+        user_id: uid.to_string(),
+        name: name.to_string(),
+        scopes,
+        key_hash: "hash".to_string(),
+        created_by: Some(uid.to_string()),
+        last_used_at: None,
+        revoked_at: None,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+async fn api_keys<T: UserTrait + RoleTrait + ApiKeyTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    let first = new_api_key(&uid, "etl", vec![Role::USER]);
+    let created = backend.create_api_key(&first).await.unwrap();
+    assert_eq!(created.id, first.id);
+    assert_eq!(created.key_hash, first.key_hash);
+    assert!(created.last_used_at.is_none());
+    assert!(matches!(
+        backend
+            .create_api_key(&new_api_key(&new_uid(), "etl", vec![Role::USER]))
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    let second = backend
+        .create_api_key(&new_api_key(&uid, "reports", vec![Role::USER]))
+        .await
+        .unwrap();
+    let listed: Vec<_> = backend
+        .get_user_api_keys(&uid)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|api_key| api_key.id)
+        .collect();
+    assert_eq!(listed, vec![first.id, second.id]);
+
+    let updated = backend
+        .update_api_key(&first.id, Some("nightly etl"), None)
+        .await
+        .unwrap();
+    assert_eq!(updated.name, "nightly etl");
+    assert_eq!(updated.scopes, vec![Role::USER]);
+    let updated = backend
+        .update_api_key(&first.id, None, Some(&[Role::USER, Role::MANAGER]))
+        .await
+        .unwrap();
+    assert_eq!(updated.name, "nightly etl");
+    assert_eq!(updated.scopes, vec![Role::USER, Role::MANAGER]);
+    assert!(matches!(
+        backend
+            .update_api_key(&uuid::Uuid::new_v4(), Some("name"), None)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    backend.touch_api_key(&first.id).await.unwrap();
+    let touched = backend.get_api_key(&first.id).await.unwrap();
+    assert!(touched.last_used_at.is_some());
+    // throttled, a second use within the minute isn't written
+    backend.touch_api_key(&first.id).await.unwrap();
+    assert_eq!(
+        backend.get_api_key(&first.id).await.unwrap().last_used_at,
+        touched.last_used_at
+    );
+
+    let revoked = backend.revoke_api_key(&second.id).await.unwrap();
+    assert!(revoked.revoked_at.is_some());
+    assert!(matches!(
+        backend.revoke_api_key(&second.id).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(matches!(
+        backend
+            .update_api_key(&second.id, Some("again"), None)
+            .await,
+        Err(DatabaseError::Expired)
+    ));
+
+    // scopes follow the catalogue
+    let suffix = new_uid();
+    let auditor = Role::parse(&format!("auditor-{}", suffix)).unwrap();
+    let renamed = Role::parse(&format!("renamed-auditor-{}", suffix)).unwrap();
+    backend
+        .create_role(&auditor, "", Some(&Role::USER))
+        .await
+        .unwrap();
+    backend
+        .update_api_key(&first.id, None, Some(&[Role::USER, auditor.clone()]))
+        .await
+        .unwrap();
+    backend.rename_role(&auditor, &renamed).await.unwrap();
+    assert_eq!(
+        backend.get_api_key(&first.id).await.unwrap().scopes,
+        vec![Role::USER, renamed.clone()]
+    );
+    backend.delete_role(&renamed).await.unwrap();
+    assert_eq!(
+        backend.get_api_key(&first.id).await.unwrap().scopes,
+        vec![Role::USER]
+    );
+}
+
+fn new_impersonation(admin_uid: &str, target_uid: &str) -> Impersonation {
+    Impersonation {
+        id: uuid::Uuid::new_v4(),
+        admin_uid: admin_uid.to_string(),
+        target_uid: target_uid.to_string(),
+        reason: "checked by the conformance suite".to_string(),
+        read_only: true,
+        expires_at: Utc::now() + chrono::Duration::minutes(10),
+        ended_at: None,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+fn new_impersonation_event(impersonation: &Impersonation, blocked: bool) -> ImpersonationEvent {
+    ImpersonationEvent {
+        id: uuid::Uuid::new_v4(),
+        impersonation_id: impersonation.id,
+        real_uid: impersonation.admin_uid.clone(),
+        effective_uid: impersonation.target_uid.clone(),
+        operation_type: "query".to_string(),
+        operation_name: Some("Dashboard".to_string()),
+        blocked,
+        created_at: None,
+    }
+}
+
+async fn impersonations<T: UserTrait + ImpersonationTrait>(backend: &T) {
+    let admin = backend.crate_random_user().await.unwrap().id.unwrap();
+    let target = backend.crate_random_user().await.unwrap().id.unwrap();
+    let first = backend
+        .create_impersonation(&new_impersonation(&admin, &target))
+        .await
+        .unwrap();
+    assert!(first.is_active(Utc::now()));
+    assert!(first.created_at.is_some());
+    assert!(matches!(
+        backend
+            .create_impersonation(&new_impersonation(&admin, &new_uid()))
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    let second = backend
+        .create_impersonation(&new_impersonation(&target, &admin))
+        .await
+        .unwrap();
+    assert_eq!(
+        backend
+            .get_impersonation(&first.id)
+            .await
+            .unwrap()
+            .target_uid,
+        target
+    );
+
+    // newest first, either side of a session matches the uid
+    let listed: Vec<_> = backend
+        .get_impersonations(Some(&admin))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|impersonation| impersonation.id)
+        .collect();
+    assert_eq!(listed, vec![second.id, first.id]);
+    let all = backend.get_impersonations(None).await.unwrap();
+    assert!(all.iter().any(|impersonation| impersonation.id == first.id));
+
+    let recorded = backend
+        .record_impersonation_event(&new_impersonation_event(&first, false))
+        .await
+        .unwrap();
+    assert!(recorded.created_at.is_some());
+    backend
+        .record_impersonation_event(&new_impersonation_event(&first, true))
+        .await
+        .unwrap();
+    let events = backend.get_impersonation_events(&first.id).await.unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events
+        .iter()
+        .all(|event| event.real_uid == admin && event.effective_uid == target));
+    assert_eq!(events.iter().filter(|event| event.blocked).count(), 1);
+    assert!(backend
+        .get_impersonation_events(&second.id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        backend
+            .record_impersonation_event(&new_impersonation_event(
+                &new_impersonation(&admin, &target),
+                false
+            ))
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    let ended = backend.end_impersonation(&first.id).await.unwrap();
+    assert!(!ended.is_active(Utc::now()));
+    assert!(matches!(
+        backend.end_impersonation(&first.id).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(matches!(
+        backend.end_impersonation(&uuid::Uuid::new_v4()).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn password_resets<T: UserTrait + PasswordResetTrait>(backend: &T) {
+    let user = backend.crate_random_user().await.unwrap();
+    let uid = user.id.unwrap();
+    let expires_at = Utc::now() + chrono::Duration::hours(1);
+    let create = |token: &OneTimeToken, expires_at| {
+        let (email, hash) = (&user.email, token.hash.clone());
+        async move {
+            backend
+                .create_password_reset(email, &hash, expires_at)
+                .await
+        }
+    };
+
+    let superseded = OneTimeToken::generate();
+    create(&superseded, expires_at).await.unwrap();
+    let token = OneTimeToken::generate();
+    let reset = create(&token, expires_at).await.unwrap();
+    assert_eq!(reset.user_id, uid);
+    assert!(reset.is_pending(Utc::now()));
+    assert!(reset.created_at.is_some());
+    // only the latest reset of a user works
+    assert!(matches!(
+        backend.consume_password_reset(&superseded.hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+    let used: PasswordReset = backend.consume_password_reset(&token.hash).await.unwrap();
+    assert_eq!(used.id, reset.id);
+    assert!(used.used_at.is_some());
+    assert!(matches!(
+        backend.consume_password_reset(&token.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+    // a used one is kept when another is requested
+    create(&OneTimeToken::generate(), expires_at).await.unwrap();
+    assert!(matches!(
+        backend.consume_password_reset(&token.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+
+    let expired = OneTimeToken::generate();
+    create(&expired, Utc::now()).await.unwrap();
+    assert!(matches!(
+        backend.consume_password_reset(&expired.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(matches!(
+        backend
+            .create_password_reset(
+                &format!("{}@example.com", new_uid()),
+                &OneTimeToken::generate().hash,
+                expires_at
+            )
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // emails are matched whatever their case, with the user gone its resets are
+    let pending = OneTimeToken::generate();
+    backend
+        .create_password_reset(&user.email.to_lowercase(), &pending.hash, expires_at)
+        .await
+        .unwrap();
+    backend.delete_user(&uid).await.unwrap();
+    assert!(matches!(
+        backend.consume_password_reset(&pending.hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn email_verifications<T: UserTrait + EmailVerificationTrait>(backend: &T) {
+    let user = backend.crate_random_user().await.unwrap();
+    let uid = user.id.unwrap();
+    let expires_at = Utc::now() + chrono::Duration::hours(1);
+    let create = |token: &OneTimeToken, email: &str, expires_at| {
+        let (uid, email, hash) = (uid.clone(), email.to_string(), token.hash.clone());
+        async move {
+            backend
+                .create_email_verification(&uid, &email, &hash, expires_at)
+                .await
+        }
+    };
+
+    let superseded = OneTimeToken::generate();
+    create(&superseded, &user.email, expires_at).await.unwrap();
+    let token = OneTimeToken::generate();
+    let verification = create(&token, &user.email, expires_at).await.unwrap();
+    assert_eq!(verification.user_id, uid);
+    assert_eq!(verification.email, user.email);
+    assert!(verification.is_pending(Utc::now()));
+    assert!(verification.created_at.is_some());
+    // only the latest link of a user works
+    assert!(matches!(
+        backend.consume_email_verification(&superseded.hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(!backend.get_user(&uid).await.unwrap().email_verified);
+    let used: EmailVerification = backend
+        .consume_email_verification(&token.hash)
+        .await
+        .unwrap();
+    assert_eq!(used.id, verification.id);
+    assert!(used.used_at.is_some());
+    assert!(backend.get_user(&uid).await.unwrap().email_verified);
+    assert!(matches!(
+        backend.consume_email_verification(&token.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+
+    let expired = OneTimeToken::generate();
+    create(&expired, &user.email, Utc::now()).await.unwrap();
+    assert!(matches!(
+        backend.consume_email_verification(&expired.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(matches!(
+        backend
+            .create_email_verification(
+                &new_uid(),
+                &user.email,
+                &OneTimeToken::generate().hash,
+                expires_at
+            )
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // a link sent before the email changed verifies neither email
+    let previous = OneTimeToken::generate();
+    create(&previous, &user.email.to_uppercase(), expires_at)
+        .await
+        .unwrap();
+    let email = format!("{}@example.org", new_uid());
+    let updated = backend.update_user_email(&uid, &email).await.unwrap();
+    assert!(!updated.email_verified);
+    assert!(matches!(
+        backend.consume_email_verification(&previous.hash).await,
+        Err(DatabaseError::Expired)
+    ));
+    assert!(!backend.get_user(&uid).await.unwrap().email_verified);
+
+    // with the user gone its links are
+    let pending = OneTimeToken::generate();
+    create(&pending, &email, expires_at).await.unwrap();
+    backend.delete_user(&uid).await.unwrap();
+    assert!(matches!(
+        backend.consume_email_verification(&pending.hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+async fn user_data<
+    T: UserTrait + OrganizationTrait + InvitationTrait + PasswordResetTrait + EmailVerificationTrait,
+>(
+    backend: &T,
+) {
+    let user = backend.crate_random_user().await.unwrap();
+    let uid = user.id.unwrap();
+    let other = backend.crate_random_user().await.unwrap().id.unwrap();
+    let expires_at = Utc::now() + chrono::Duration::hours(1);
+    assert!(backend.get_user_invitations(&uid).await.unwrap().is_empty());
+    assert!(backend
+        .get_user_password_resets(&uid)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(backend
+        .get_user_email_verifications(&uid)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let (mut sent, _) = new_invitation(&format!("{}@example.com", new_uid()), Role::USER, None);
+    sent.invited_by = Some(uid.clone());
+    backend.create_invitation(&sent).await.unwrap();
+    let (mut received, _) = new_invitation(&user.email.to_uppercase(), Role::USER, None);
+    received.invited_by = Some(other.clone());
+    backend.create_invitation(&received).await.unwrap();
+    let (unrelated, _) = new_invitation(&format!("{}@example.com", new_uid()), Role::USER, None);
+    backend.create_invitation(&unrelated).await.unwrap();
+    let invitations = backend.get_user_invitations(&uid).await.unwrap();
+    let ids: Vec<_> = invitations.iter().map(|invitation| invitation.id).collect();
+    assert_eq!(ids, vec![sent.id, received.id]);
+    assert!(matches!(
+        backend.get_user_invitations(&new_uid()).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    for _ in 0..2 {
+        backend
+            .create_password_reset(
+                &user.email.to_lowercase(),
+                &OneTimeToken::generate().hash,
+                expires_at,
+            )
+            .await
+            .unwrap();
+        backend
+            .create_email_verification(
+                &uid,
+                &user.email,
+                &OneTimeToken::generate().hash,
+                expires_at,
+            )
+            .await
+            .unwrap();
+    }
+    // pending ones are superseded
+    assert_eq!(
+        backend.get_user_password_resets(&uid).await.unwrap().len(),
+        1
+    );
+    let verifications = backend.get_user_email_verifications(&uid).await.unwrap();
+    assert_eq!(verifications.len(), 1);
+    assert_eq!(verifications[0].user_id, uid);
+
+    // what was sent to the user goes with it, what it sent stays without it
+    backend.delete_user(&uid).await.unwrap();
+    assert!(matches!(
+        backend.get_invitation(&received.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert_eq!(
+        backend.get_invitation(&sent.id).await.unwrap().invited_by,
+        None
+    );
+    assert!(backend.get_invitation(&unrelated.id).await.is_ok());
+    assert!(backend
+        .get_user_password_resets(&uid)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(backend
+        .get_user_email_verifications(&uid)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn delete_last_admin<T: UserTrait + OrganizationTrait>(backend: &T) {
+    let uid = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+    // leave uid as the only Admin, whatever other checks stored
+    for admin in backend.get_users_by_role(&Role::ADMIN).await.unwrap() {
+        let other = admin.id.unwrap();
+        if other != uid {
+            backend
+                .remove_user_role(&other, &Role::ADMIN)
+                .await
+                .unwrap();
+        }
+    }
+    assert!(matches!(
+        backend.delete_user(&uid).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    let next = backend.crate_random_user().await.unwrap().id.unwrap();
+    backend.save_user_role(&next, &Role::ADMIN).await.unwrap();
+
+    // the only Admin of an organization stays too
+    let organization = backend.create_organization("Acme", &uid).await.unwrap();
+    assert!(matches!(
+        backend.delete_user(&uid).await,
+        Err(DatabaseError::LastAdmin)
+    ));
+    backend
+        .save_membership(&organization.id, &next, &Role::ADMIN)
+        .await
+        .unwrap();
+    backend.delete_user(&uid).await.unwrap();
+    let members = backend.get_members(&organization.id).await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].user_id, next);
+}
@@ -0,0 +1,196 @@
+use bb8::PooledConnection;
+use tokio_postgres::{types::ToSql, Error, Row, ToStatement, Transaction};
+
+use super::pool::PostgresManager;
+use crate::{contexts::identity::Identity, structs::role::RoleCatalogue};
+
+// role without BYPASSRLS taken by the transactions of a request, created by the migrations
+pub const REQUEST_ROLE: &str = "data_intuitive_request";
+
+/*
+    * Settings read back by the row level security policies, see app_uid() and app_is_admin()
+    They are transaction local, so they can't outlive the statement they scope on a pooled connection.
+*/
+const SET_SCOPE: &str = "SELECT set_config('app.uid', $1, true), set_config('app.roles', $2, true), set_config('role', $3, true)";
+
+/*
+    * Uid and global roles of a request at the time a connection is checked out,
+    an anonymous request has none and sees no rows
+    The roles are expanded through the catalogue, app_is_admin() only knows the built in names.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowScope {
+    pub uid: String,
+    // json array of role names, implied ones included
+    pub roles: String,
+}
+
+impl RowScope {
+    /*
+        * Scope of an identity, the roles are those already known to the request
+        A custom child of Admin is an Admin to the policies too, as it is to RoleGuard::at_least.
+        @param identity: &Identity
+        @param catalogue: &RoleCatalogue
+        @return RowScope
+    */
+    pub fn of(identity: &Identity, catalogue: &RoleCatalogue) -> RowScope {
+        let roles = identity
+            .roles()
+            .map(|roles| catalogue.implied(roles))
+            .unwrap_or_default();
+        let roles: Vec<&str> = roles.iter().map(|role| role.name()).collect();
+        RowScope {
+            uid: identity.uid().map(|uid| uid.0.clone()).unwrap_or_default(),
+            roles: serde_json::to_string(&roles).unwrap_or_else(|_| "[]".to_string()),
+        }
+    }
+}
+
+/*
+    * Connection checked out of the pool
+    Unscoped connections belong to the server itself and see every row. Each statement of a scoped one
+    runs in its own transaction, after switching to REQUEST_ROLE with the uid and roles of the request.
+*/
+pub struct Connection<'a> {
+    client: PooledConnection<'a, PostgresManager>,
+    scope: Option<RowScope>,
+}
+
+impl<'a> Connection<'a> {
+    pub fn new(client: PooledConnection<'a, PostgresManager>, scope: Option<RowScope>) -> Self {
+        Connection { client, scope }
+    }
+
+    /*
+        * Start a transaction, scoped to the request if the connection is
+        @return Transaction, rolled back on drop
+    */
+    pub async fn transaction(&mut self) -> Result<Transaction<'_>, Error> {
+        let transaction = self.client.transaction().await?;
+        if let Some(scope) = &self.scope {
+            transaction
+                .execute(SET_SCOPE, &[&scope.uid, &scope.roles, &REQUEST_ROLE])
+                .await?;
+        }
+        Ok(transaction)
+    }
+
+    pub async fn query<T>(
+        &mut self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        if self.scope.is_none() {
+            return self.client.query(statement, params).await;
+        }
+        let transaction = self.transaction().await?;
+        let rows = transaction.query(statement, params).await?;
+        transaction.commit().await?;
+        Ok(rows)
+    }
+
+    pub async fn query_one<T>(
+        &mut self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        if self.scope.is_none() {
+            return self.client.query_one(statement, params).await;
+        }
+        let transaction = self.transaction().await?;
+        let row = transaction.query_one(statement, params).await?;
+        transaction.commit().await?;
+        Ok(row)
+    }
+
+    pub async fn query_opt<T>(
+        &mut self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        if self.scope.is_none() {
+            return self.client.query_opt(statement, params).await;
+        }
+        let transaction = self.transaction().await?;
+        let row = transaction.query_opt(statement, params).await?;
+        transaction.commit().await?;
+        Ok(row)
+    }
+
+    pub async fn execute<T>(
+        &mut self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        if self.scope.is_none() {
+            return self.client.execute(statement, params).await;
+        }
+        let transaction = self.transaction().await?;
+        let count = transaction.execute(statement, params).await?;
+        transaction.commit().await?;
+        Ok(count)
+    }
+
+    pub async fn batch_execute(&mut self, query: &str) -> Result<(), Error> {
+        if self.scope.is_none() {
+            return self.client.batch_execute(query).await;
+        }
+        let transaction = self.transaction().await?;
+        transaction.batch_execute(query).await?;
+        transaction.commit().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        structs::role::{Role, RoleDefinition},
+        traits::auth::AuthClaims,
+    };
+
+    #[test]
+    fn test_row_scope() {
+        let mut definitions = RoleDefinition::defaults();
+        definitions.push(RoleDefinition {
+            name: Role::parse("Owner").unwrap(),
+            description: String::new(),
+            parent: Some(Role::ADMIN),
+            built_in: false,
+            created_at: None,
+            updated_at: None,
+        });
+        let catalogue = RoleCatalogue::new(definitions);
+        let anonymous = RowScope::of(&Identity::default(), &catalogue);
+        assert_eq!(anonymous.uid, "");
+        assert_eq!(anonymous.roles, "[]");
+
+        let identity = Identity::from_claims(AuthClaims {
+            uid: "uid".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(RowScope::of(&identity, &catalogue).roles, "[]");
+        identity.set_roles(vec![Role::USER, Role::MANAGER]);
+        let scope = RowScope::of(&identity, &catalogue);
+        assert_eq!(scope.uid, "uid");
+        assert_eq!(scope.roles, r#"["Manager","User"]"#);
+
+        // a custom child of Admin implies it
+        let owner = Identity::verified("owner");
+        owner.set_roles(vec![Role::parse("Owner").unwrap()]);
+        let scope = RowScope::of(&owner, &catalogue);
+        assert_eq!(scope.roles, r#"["Admin","Manager","Owner","User"]"#);
+    }
+}
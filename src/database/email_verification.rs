@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::email_verification::EmailVerification;
+use crate::traits::email_verification::EmailVerificationTrait;
+
+fn email_verification_from_row(row: &Row) -> EmailVerification {
+    EmailVerification {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        email: row.get("email"),
+        token_hash: row.get("token_hash"),
+        expires_at: row.get("expires_at"),
+        used_at: row.get("used_at"),
+        created_at: Some(row.get("created_at")),
+    }
+}
+
+impl EmailVerificationTrait for PostGreClient {
+    async fn create_email_verification(
+        &self,
+        user_uid: &str,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailVerification, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .query_opt("SELECT 1 FROM users WHERE id = $1", &[&user_uid])
+            .await?
+            .ok_or(Error::NotFound)?;
+        transaction
+            .execute(
+                "DELETE FROM email_verifications WHERE user_id = $1 AND used_at IS NULL",
+                &[&user_uid],
+            )
+            .await?;
+        let row = transaction
+            .query_one(
+                "INSERT INTO email_verifications (id, user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+                &[&Uuid::new_v4(), &user_uid, &email, &token_hash, &expires_at],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(email_verification_from_row(&row))
+    }
+
+    async fn consume_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerification, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        let Some(row) = transaction
+            .query_opt(
+                "UPDATE email_verifications SET used_at = now() WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now() RETURNING *",
+                &[&token_hash],
+            )
+            .await?
+        else {
+            let exists = transaction
+                .query_opt(
+                    "SELECT 1 FROM email_verifications WHERE token_hash = $1",
+                    &[&token_hash],
+                )
+                .await?;
+            return Err(exists.map_or(Error::NotFound, |_| Error::Expired));
+        };
+        let verification = email_verification_from_row(&row);
+        // a link sent to a previous email proves nothing about the current one, rolled back on drop
+        let verified = transaction
+            .execute(
+                "UPDATE users SET email_verified = true, updated_at = now() WHERE id = $1 AND lower(email) = lower($2)",
+                &[&verification.user_id, &verification.email],
+            )
+            .await?;
+        if verified == 0 {
+            return Err(Error::Expired);
+        }
+        transaction.commit().await?;
+        Ok(verification)
+    }
+
+    async fn get_user_email_verifications(
+        &self,
+        user_uid: &str,
+    ) -> Result<Vec<EmailVerification>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM email_verifications WHERE user_id = $1 ORDER BY created_at, id",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(email_verification_from_row).collect())
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use bb8::RunError;
+use tokio_postgres::error::SqlState;
+
+/*
+    * Errors shared by every backend
+    NotFound and Conflict carry the same meaning whether the data lives in postgres or in memory.
+*/
+#[derive(Debug)]
+pub enum DatabaseError {
+    Postgres(tokio_postgres::Error),
+    PoolTimedOut,
+    NotFound,
+    // name of the violated constraint
+    Conflict(String),
+    // input the backend can't store, i.e a user without id
+    Invalid(String),
+    Password(bcrypt::BcryptError),
+    // revoking the role would leave nobody able to administrate
+    LastAdmin,
+    // built-in roles can't be renamed or deleted
+    BuiltInRole(String),
+    // the role is the parent of another one
+    RoleInUse(String),
+    // a single-use token, i.e of an invitation, that expired, was already used or was revoked
+    Expired,
+    // the row level security policies deny the change to the request
+    Forbidden,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Postgres(e) => write!(f, "{}", e),
+            DatabaseError::PoolTimedOut => write!(f, "timed out waiting for a database connection"),
+            DatabaseError::NotFound => write!(f, "not found"),
+            DatabaseError::Conflict(constraint) => write!(f, "conflict on {}", constraint),
+            DatabaseError::Invalid(e) => write!(f, "invalid input: {}", e),
+            DatabaseError::Password(e) => write!(f, "can't hash password: {}", e),
+            DatabaseError::LastAdmin => write!(f, "the last Admin can't be revoked"),
+            DatabaseError::BuiltInRole(role) => write!(f, "{} is a built-in role", role),
+            DatabaseError::RoleInUse(role) => {
+                write!(f, "{} is the parent of another role", role)
+            }
+            DatabaseError::Expired => write!(f, "expired, already used or revoked"),
+            DatabaseError::Forbidden => write!(f, "denied by row level security"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<tokio_postgres::Error> for DatabaseError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        match e.code() {
+            Some(&SqlState::UNIQUE_VIOLATION) => DatabaseError::Conflict(
+                e.as_db_error()
+                    .and_then(|e| e.constraint())
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            // rows referencing a missing parent, i.e a role for an unknown user
+            Some(&SqlState::FOREIGN_KEY_VIOLATION) => DatabaseError::NotFound,
+            Some(&SqlState::INSUFFICIENT_PRIVILEGE) => DatabaseError::Forbidden,
+            _ => DatabaseError::Postgres(e),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for DatabaseError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        DatabaseError::Password(e)
+    }
+}
+
+impl From<RunError<tokio_postgres::Error>> for DatabaseError {
+    fn from(e: RunError<tokio_postgres::Error>) -> Self {
+        match e {
+            RunError::User(e) => DatabaseError::from(e),
+            RunError::TimedOut => DatabaseError::PoolTimedOut,
+        }
+    }
+}
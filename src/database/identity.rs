@@ -0,0 +1,75 @@
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::identity::LocalIdentity;
+use crate::traits::identity::IdentityTrait;
+use tokio_postgres::Row;
+
+fn identity_from_row(row: &Row) -> LocalIdentity {
+    LocalIdentity {
+        uid: row.get("uid"),
+        email: row.get("email"),
+        password: row.get("password"),
+        display_name: row.get("display_name"),
+        email_verified: row.get("email_verified"),
+        disabled: row.get("disabled"),
+        custom_claims: match row.get("custom_claims") {
+            serde_json::Value::Object(claims) => claims,
+            _ => serde_json::Map::new(),
+        },
+        sessions_valid_after: row.get("sessions_valid_after"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+impl IdentityTrait for PostGreClient {
+    async fn create_identity(&self, identity: &LocalIdentity) -> Result<(), Error> {
+        self.connection()
+            .await?
+            .execute(
+                "INSERT INTO local_identities (uid, email, password, display_name, email_verified, disabled, custom_claims, sessions_valid_after) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[&identity.uid, &identity.email, &identity.password, &identity.display_name, &identity.email_verified, &identity.disabled, &serde_json::Value::Object(identity.custom_claims.clone()), &identity.sessions_valid_after],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_identity(&self, uid: &str) -> Result<Option<LocalIdentity>, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM local_identities WHERE uid = $1", &[&uid])
+            .await?;
+        Ok(row.as_ref().map(identity_from_row))
+    }
+
+    async fn get_identity_by_email(&self, email: &str) -> Result<Option<LocalIdentity>, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM local_identities WHERE email = $1", &[&email])
+            .await?;
+        Ok(row.as_ref().map(identity_from_row))
+    }
+
+    async fn update_identity(&self, identity: &LocalIdentity) -> Result<bool, Error> {
+        let updated = self
+            .connection()
+            .await?
+            .execute(
+                "UPDATE local_identities SET email = $2, password = $3, display_name = $4, email_verified = $5, disabled = $6, custom_claims = $7, sessions_valid_after = $8, updated_at = now() WHERE uid = $1",
+                &[&identity.uid, &identity.email, &identity.password, &identity.display_name, &identity.email_verified, &identity.disabled, &serde_json::Value::Object(identity.custom_claims.clone()), &identity.sessions_valid_after],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<bool, Error> {
+        let deleted = self
+            .connection()
+            .await?
+            .execute("DELETE FROM local_identities WHERE uid = $1", &[&uid])
+            .await?;
+        Ok(deleted == 1)
+    }
+}
@@ -0,0 +1,142 @@
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::impersonation::{Impersonation, ImpersonationEvent};
+use crate::traits::impersonation::ImpersonationTrait;
+
+fn impersonation_from_row(row: &Row) -> Impersonation {
+    Impersonation {
+        id: row.get("id"),
+        admin_uid: row.get("admin_uid"),
+        target_uid: row.get("target_uid"),
+        reason: row.get("reason"),
+        read_only: row.get("read_only"),
+        expires_at: row.get("expires_at"),
+        ended_at: row.get("ended_at"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+fn impersonation_event_from_row(row: &Row) -> ImpersonationEvent {
+    ImpersonationEvent {
+        id: row.get("id"),
+        impersonation_id: row.get("impersonation_id"),
+        real_uid: row.get("real_uid"),
+        effective_uid: row.get("effective_uid"),
+        operation_type: row.get("operation_type"),
+        operation_name: row.get("operation_name"),
+        blocked: row.get("blocked"),
+        created_at: Some(row.get("created_at")),
+    }
+}
+
+impl ImpersonationTrait for PostGreClient {
+    async fn create_impersonation(
+        &self,
+        impersonation: &Impersonation,
+    ) -> Result<Impersonation, Error> {
+        // no foreign key on target_uid, the user is checked on insert only
+        let row = self
+            .connection()
+            .await?
+            .query_opt(
+                "INSERT INTO impersonations (id, admin_uid, target_uid, reason, read_only, expires_at) SELECT $1::uuid, $2::text, $3::text, $4::text, $5::boolean, $6::timestamptz WHERE EXISTS (SELECT 1 FROM users WHERE id = $3) RETURNING *",
+                &[
+                    &impersonation.id,
+                    &impersonation.admin_uid,
+                    &impersonation.target_uid,
+                    &impersonation.reason,
+                    &impersonation.read_only,
+                    &impersonation.expires_at,
+                ],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(impersonation_from_row(&row))
+    }
+
+    async fn get_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM impersonations WHERE id = $1", &[id])
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(impersonation_from_row(&row))
+    }
+
+    async fn get_impersonations(
+        &self,
+        user_uid: Option<&str>,
+    ) -> Result<Vec<Impersonation>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM impersonations WHERE $1::text IS NULL OR admin_uid = $1 OR target_uid = $1 ORDER BY created_at DESC, id",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(impersonation_from_row).collect())
+    }
+
+    async fn end_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        let mut client = self.connection().await?;
+        match client
+            .query_opt(
+                "UPDATE impersonations SET ended_at = now(), updated_at = now() WHERE id = $1 AND ended_at IS NULL RETURNING *",
+                &[id],
+            )
+            .await?
+        {
+            Some(row) => Ok(impersonation_from_row(&row)),
+            None => {
+                let exists = client
+                    .query_opt("SELECT 1 FROM impersonations WHERE id = $1", &[id])
+                    .await?;
+                Err(exists.map_or(Error::NotFound, |_| Error::Expired))
+            }
+        }
+    }
+
+    async fn record_impersonation_event(
+        &self,
+        event: &ImpersonationEvent,
+    ) -> Result<ImpersonationEvent, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_one(
+                "INSERT INTO impersonation_events (id, impersonation_id, real_uid, effective_uid, operation_type, operation_name, blocked) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+                &[
+                    &event.id,
+                    &event.impersonation_id,
+                    &event.real_uid,
+                    &event.effective_uid,
+                    &event.operation_type,
+                    &event.operation_name,
+                    &event.blocked,
+                ],
+            )
+            .await?;
+        Ok(impersonation_event_from_row(&row))
+    }
+
+    async fn get_impersonation_events(
+        &self,
+        impersonation_id: &Uuid,
+    ) -> Result<Vec<ImpersonationEvent>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM impersonation_events WHERE impersonation_id = $1 ORDER BY created_at, id",
+                &[impersonation_id],
+            )
+            .await?;
+        Ok(rows.iter().map(impersonation_event_from_row).collect())
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::invitation::{Invitation, InvitationStatus};
+use crate::structs::role::Role;
+use crate::structs::user::{hash_password, User};
+use crate::traits::invitation::InvitationTrait;
+
+fn invitation_from_row(row: &Row) -> Invitation {
+    Invitation {
+        id: row.get("id"),
+        email: row.get("email"),
+        role: row.get("role"),
+        organization_id: row.get("organization_id"),
+        token_hash: row.get("token_hash"),
+        invited_by: row.get("invited_by"),
+        expires_at: row.get("expires_at"),
+        accepted_at: row.get("accepted_at"),
+        accepted_by: row.get("accepted_by"),
+        revoked_at: row.get("revoked_at"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+impl PostGreClient {
+    /*
+        * Update an open invitation, Expired when there is one but it is closed
+        @param query: UPDATE ... WHERE id = $1 AND accepted_at IS NULL AND revoked_at IS NULL RETURNING *
+    */
+    async fn update_open_invitation(
+        &self,
+        query: &str,
+        params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+    ) -> Result<Invitation, Error> {
+        let mut client = self.connection().await?;
+        match client.query_opt(query, params).await? {
+            Some(row) => Ok(invitation_from_row(&row)),
+            None => {
+                let exists = client
+                    .query_opt("SELECT 1 FROM invitations WHERE id = $1", &[params[0]])
+                    .await?;
+                Err(exists.map_or(Error::NotFound, |_| Error::Expired))
+            }
+        }
+    }
+}
+
+impl InvitationTrait for PostGreClient {
+    async fn create_invitation(&self, invitation: &Invitation) -> Result<Invitation, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_one(
+                "INSERT INTO invitations (id, email, role, organization_id, token_hash, invited_by, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+                &[
+                    &invitation.id,
+                    &invitation.email,
+                    &invitation.role,
+                    &invitation.organization_id,
+                    &invitation.token_hash,
+                    &invitation.invited_by,
+                    &invitation.expires_at,
+                ],
+            )
+            .await?;
+        Ok(invitation_from_row(&row))
+    }
+
+    async fn get_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM invitations WHERE id = $1", &[id])
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(invitation_from_row(&row))
+    }
+
+    async fn get_invitation_by_token(&self, token_hash: &str) -> Result<Invitation, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt(
+                "SELECT * FROM invitations WHERE token_hash = $1",
+                &[&token_hash],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(invitation_from_row(&row))
+    }
+
+    async fn get_open_invitations(
+        &self,
+        organization_id: Option<&Uuid>,
+    ) -> Result<Vec<Invitation>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM invitations WHERE organization_id IS NOT DISTINCT FROM $1 AND accepted_at IS NULL AND revoked_at IS NULL ORDER BY created_at, id",
+                &[&organization_id],
+            )
+            .await?;
+        Ok(rows.iter().map(invitation_from_row).collect())
+    }
+
+    async fn get_user_invitations(&self, user_uid: &str) -> Result<Vec<Invitation>, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        let email: String = transaction
+            .query_opt("SELECT email FROM users WHERE id = $1", &[&user_uid])
+            .await?
+            .ok_or(Error::NotFound)?
+            .get("email");
+        let rows = transaction
+            .query(
+                "SELECT * FROM invitations WHERE invited_by = $1 OR accepted_by = $1 OR lower(email) = lower($2) ORDER BY created_at, id",
+                &[&user_uid, &email],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(rows.iter().map(invitation_from_row).collect())
+    }
+
+    async fn renew_invitation(
+        &self,
+        id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invitation, Error> {
+        self.update_open_invitation(
+            "UPDATE invitations SET token_hash = $2, expires_at = $3, updated_at = now() WHERE id = $1 AND accepted_at IS NULL AND revoked_at IS NULL RETURNING *",
+            &[id, &token_hash, &expires_at],
+        )
+        .await
+    }
+
+    async fn revoke_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        self.update_open_invitation(
+            "UPDATE invitations SET revoked_at = now(), updated_at = now() WHERE id = $1 AND accepted_at IS NULL AND revoked_at IS NULL RETURNING *",
+            &[id],
+        )
+        .await
+    }
+
+    async fn accept_invitation(&self, token_hash: &str, user: &User) -> Result<Invitation, Error> {
+        let uid = user
+            .id
+            .as_deref()
+            .ok_or_else(|| Error::Invalid("a user id is required".to_string()))?;
+        let password = hash_password(&user.password)?;
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        // locked until commit, the same token can't be accepted twice concurrently
+        let invitation = transaction
+            .query_opt(
+                "SELECT * FROM invitations WHERE token_hash = $1 FOR UPDATE",
+                &[&token_hash],
+            )
+            .await?
+            .map(|row| invitation_from_row(&row))
+            .ok_or(Error::NotFound)?;
+        if invitation.status(Utc::now()) != InvitationStatus::Pending {
+            return Err(Error::Expired);
+        }
+
+        let created = transaction
+            .execute(
+                "INSERT INTO users (id, name, email, password, plan) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO NOTHING",
+                &[&uid, &user.name, &invitation.email, &password, &user.plan],
+            )
+            .await?;
+        if created == 1 {
+            transaction
+                .execute(
+                    "INSERT INTO roles (firebase_uid, role) VALUES ($1, $2)",
+                    &[&uid, &Role::USER],
+                )
+                .await?;
+        }
+        // members and holders keep what they already have
+        match &invitation.organization_id {
+            Some(organization_id) => {
+                transaction
+                    .execute(
+                        "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                        &[organization_id, &uid, &invitation.role],
+                    )
+                    .await?;
+            }
+            None => {
+                transaction
+                    .execute(
+                        "INSERT INTO roles (firebase_uid, role) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                        &[&uid, &invitation.role],
+                    )
+                    .await?;
+            }
+        }
+        transaction
+            .execute(
+                "UPDATE users SET claims_version = claims_version + 1 WHERE id = $1",
+                &[&uid],
+            )
+            .await?;
+        let row = transaction
+            .query_one(
+                "UPDATE invitations SET accepted_at = now(), accepted_by = $2, updated_at = now() WHERE id = $1 RETURNING *",
+                &[&invitation.id, &uid],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(invitation_from_row(&row))
+    }
+}
@@ -1,80 +1,208 @@
-use std::env;
-use std::sync::Arc;
-use tokio_postgres::{Error, NoTls};
+use bb8::Pool;
+
+use super::{
+    connection::{Connection, RowScope},
+    error::DatabaseError,
+    migrations::MIGRATIONS,
+    pool::{LogErrorSink, PoolConfig, PostgresManager},
+    role::role_definition_from_row,
+};
+use crate::{
+    config::main::PostgresConfig,
+    contexts::identity::Identity,
+    structs::{diagnostics::PoolStatistics, role::RoleCatalogue},
+};
 
 #[derive(Clone)]
 pub struct PostGreClient {
-    pub client: Arc<tokio_postgres::Client>,
+    pub pool: Pool<PostgresManager>,
+    pool_config: PoolConfig,
+    // identity of the request the client was handed to, None for the server itself
+    scope: Option<Identity>,
 }
 
 impl PostGreClient {
-    pub async fn new() -> PostGreClient {
-        dotenv::dotenv().ok();
-        let host = env::var("POSTGRES_HOST").expect("POSTGRES_HOST must be set");
-        let user = env::var("POSTGRES_USER").expect("POSTGRES_USER must be set");
-        let password = env::var("POSTGRES_PASSWORD").expect("POSTGRES_PASSWORD must be set");
-        let dbname = env::var("POSTGRES_DATABASE").expect("POSTGRES_DATABASE must be set");
-        let port = env::var("POSTGRES_PORT").expect("POSTGRES_PORT must be set");
+    /*
+        * Create a client backed by a connection pool
+        @param config: &PostgresConfig, validated when the configuration is loaded
+        @return PostGreClient
+    */
+    pub async fn new(config: &PostgresConfig) -> PostGreClient {
+        let pool_config = config.pool.clone();
+        let mut postgres_config = tokio_postgres::Config::new();
+        postgres_config
+            .host(&config.host)
+            .port(config.port)
+            .user(&config.user)
+            .password(&config.password)
+            .dbname(&config.database)
+            .ssl_mode(config.tls.postgres_ssl_mode());
+        let tls = config
+            .tls
+            .connector()
+            .expect("Invalid postgres tls configuration");
 
-        let config = format!(
-            "host={} user={} password={} dbname={}  port={}",
-            host, user, password, dbname, port
-        );
-        let (client, connection) = tokio_postgres::connect(&config, NoTls).await.unwrap();
+        // connections are opened lazily, the pool keeps retrying until the database is reachable
+        let pool = Pool::builder()
+            .min_idle(pool_config.min_size)
+            .max_size(pool_config.max_size)
+            .connection_timeout(pool_config.checkout_timeout)
+            .test_on_check_out(pool_config.health_check)
+            .retry_connection(true)
+            .error_sink(Box::new(LogErrorSink))
+            .build_unchecked(PostgresManager::new(postgres_config, tls));
 
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
         PostGreClient {
-            client: Arc::new(client),
+            pool,
+            pool_config,
+            scope: None,
         }
     }
 
     /*
-     * Create tables if not exist
-     */
-    pub async fn create_tables_if_not_exist(&mut self) -> Result<(), Error> {
-        self.client
-            .batch_execute(
-                "CREATE TABLE IF NOT EXISTS users (
-                    id TEXT PRIMARY KEY UNIQUE NOT NULL,
-                    name TEXT NOT NULL,
-                    email TEXT NOT NULL,
-                    password TEXT NOT NULL,
-                    created_at TIMESTAMPTZ NOT NULL default now(),
-                    updated_at TIMESTAMPTZ NOT NULL default now()
-                );",
-            )
-            .await?;
+        * Same pool, with the rows restricted to what the identity may see
+        @param identity: Identity of the request, read whenever a connection is checked out
+        @return PostGreClient
+    */
+    pub fn scoped(&self, identity: Identity) -> PostGreClient {
+        PostGreClient {
+            scope: Some(identity),
+            ..self.clone()
+        }
+    }
 
-        self.client
-            .batch_execute(
-                "
-            CREATE TYPE ROLE AS ENUM ('User', 'Manager', 'Admin');
-            CREATE TABLE IF NOT EXISTS roles (
-                id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
-                role ROLE NOT NULL default 'User',
-                firebase_uid TEXT not null references users(id) on delete cascade,
-                created_at TIMESTAMPTZ NOT NULL default now(),
-                updated_at TIMESTAMPTZ NOT NULL default now()
-            );",
-            )
-            .await?;
-        Ok(())
+    /*
+        * Same pool, seeing every row, for the few changes a request makes on behalf of the server
+        @return PostGreClient
+    */
+    pub fn unscoped(&self) -> PostGreClient {
+        PostGreClient {
+            scope: None,
+            ..self.clone()
+        }
+    }
+
+    /*
+        * Check a connection out of the pool
+        @return Connection, scoped to the request if the client is, given back to the pool on drop
+    */
+    pub async fn connection(&self) -> Result<Connection<'_>, DatabaseError> {
+        let client = self.pool.get().await?;
+        let scope = match &self.scope {
+            Some(identity) => {
+                // read before the scope is set, the roles of the request are expanded with it
+                let definitions = match identity.roles() {
+                    Some(roles) if !roles.is_empty() => client
+                        .query("SELECT * FROM role_definitions", &[])
+                        .await?
+                        .iter()
+                        .map(role_definition_from_row)
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                Some(RowScope::of(identity, &RoleCatalogue::new(definitions)))
+            }
+            None => None,
+        };
+        Ok(Connection::new(client, scope))
+    }
+
+    /*
+        * Pool statistics for diagnostics
+        @return PoolStatistics
+    */
+    pub fn statistics(&self) -> PoolStatistics {
+        let state = self.pool.state();
+        PoolStatistics {
+            min_size: self.pool_config.min_size,
+            max_size: self.pool_config.max_size,
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            checkouts_direct: state.statistics.get_direct,
+            checkouts_waited: state.statistics.get_waited,
+            checkouts_timed_out: state.statistics.get_timed_out,
+            checkout_wait_time_ms: state.statistics.get_wait_time.as_millis() as u64,
+            connections_created: state.statistics.connections_created,
+            connections_closed_broken: state.statistics.connections_closed_broken,
+            connections_closed_invalid: state.statistics.connections_closed_invalid,
+        }
     }
 
-    pub async fn drop_tables(&mut self) -> Result<(), Error> {
-        self.client
-            .batch_execute(
-                "
-                DROP TABLE IF EXISTS roles;
-                DROP TABLE IF EXISTS users;
-                DROP TYPE IF EXISTS ROLE;
-                ",
-            )
+    /*
+     * Run every down script and forget about applied migrations,
+     * used to reset the database in tests
+     */
+    pub async fn drop_tables(&self) -> Result<(), DatabaseError> {
+        let mut client = self.connection().await?;
+        for migration in MIGRATIONS.iter().rev() {
+            client.batch_execute(migration.down).await?;
+        }
+        client
+            .batch_execute("DROP TABLE IF EXISTS schema_migrations;")
             .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reconnects_after_connection_killed() {
+        let mut config = Utils::postgres_config();
+        config.pool = PoolConfig {
+            min_size: 1,
+            max_size: 1,
+            ..Default::default()
+        };
+        let client = PostGreClient::new(&config).await;
+        let pid: i32 = client
+            .connection()
+            .await
+            .unwrap()
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+
+        let killer = PostGreClient::new(&Utils::postgres_config()).await;
+        killer
+            .connection()
+            .await
+            .unwrap()
+            .execute("SELECT pg_terminate_backend($1)", &[&pid])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let new_pid: i32 = client
+            .connection()
+            .await
+            .unwrap()
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_ne!(pid, new_pid);
+        assert!(client.statistics().connections_created >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_timeout() {
+        let mut config = Utils::postgres_config();
+        config.pool = PoolConfig {
+            min_size: 0,
+            max_size: 1,
+            checkout_timeout: Duration::from_millis(200),
+            health_check: true,
+        };
+        let client = PostGreClient::new(&config).await;
+        let _held = client.connection().await.unwrap();
+        let res = client.connection().await;
+        assert!(matches!(res, Err(DatabaseError::PoolTimedOut)));
+        assert_eq!(client.statistics().checkouts_timed_out, 1);
+    }
+}
@@ -0,0 +1,1358 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use crate::{
+    structs::{
+        api_key::ApiKey,
+        claims::{Plan, UserClaims},
+        email_verification::EmailVerification,
+        identity::LocalIdentity,
+        impersonation::{Impersonation, ImpersonationEvent},
+        invitation::{Invitation, InvitationStatus},
+        organization::{Membership, Organization},
+        password_reset::PasswordReset,
+        permission::{Permission, DEFAULT_PERMISSIONS, DEFAULT_ROLE_PERMISSIONS},
+        role::{Role, RoleCatalogue, RoleDefinition},
+        user::{hash_password, User},
+    },
+    traits::{
+        api_key::ApiKeyTrait, email_verification::EmailVerificationTrait, identity::IdentityTrait,
+        impersonation::ImpersonationTrait, invitation::InvitationTrait,
+        organization::OrganizationTrait, password_reset::PasswordResetTrait,
+        permission::PermissionTrait, role::RoleTrait, user::UserTrait,
+    },
+};
+
+struct MemoryState {
+    users: BTreeMap<String, User>,
+    claims_versions: BTreeMap<String, i64>,
+    // (user uid, role), in insertion order like the roles table
+    roles: Vec<(String, Role)>,
+    identities: BTreeMap<String, LocalIdentity>,
+    permissions: BTreeMap<String, Permission>,
+    role_permissions: Vec<(Role, String)>,
+    role_definitions: BTreeMap<Role, RoleDefinition>,
+    organizations: BTreeMap<Uuid, Organization>,
+    // in insertion order like organization_members
+    memberships: Vec<Membership>,
+    invitations: BTreeMap<Uuid, Invitation>,
+    api_keys: BTreeMap<Uuid, ApiKey>,
+    impersonations: BTreeMap<Uuid, Impersonation>,
+    // in insertion order like impersonation_events
+    impersonation_events: Vec<ImpersonationEvent>,
+    password_resets: Vec<PasswordReset>,
+    email_verifications: Vec<EmailVerification>,
+}
+
+// seeded like the migrations seed postgres
+impl Default for MemoryState {
+    fn default() -> Self {
+        let now = Utc::now();
+        MemoryState {
+            users: BTreeMap::new(),
+            claims_versions: BTreeMap::new(),
+            roles: Vec::new(),
+            identities: BTreeMap::new(),
+            permissions: DEFAULT_PERMISSIONS
+                .iter()
+                .map(|(name, description)| {
+                    let permission = Permission {
+                        name: name.to_string(),
+                        description: description.to_string(),
+                        created_at: Some(now),
+                    };
+                    (name.to_string(), permission)
+                })
+                .collect(),
+            role_permissions: DEFAULT_ROLE_PERMISSIONS
+                .iter()
+                .map(|(role, name)| (role.clone(), name.to_string()))
+                .collect(),
+            role_definitions: RoleDefinition::defaults()
+                .into_iter()
+                .map(|definition| (definition.name.clone(), definition))
+                .collect(),
+            organizations: BTreeMap::new(),
+            memberships: Vec::new(),
+            invitations: BTreeMap::new(),
+            api_keys: BTreeMap::new(),
+            impersonations: BTreeMap::new(),
+            impersonation_events: Vec::new(),
+            password_resets: Vec::new(),
+            email_verifications: Vec::new(),
+        }
+    }
+}
+
+/*
+    * Backend keeping everything in memory, with the same uniqueness and not-found
+    semantics as PostGreClient. Clones share the same data.
+*/
+#[derive(Clone, Default)]
+pub struct MemoryClient {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryState {
+    fn bump_claims_version(&mut self, user_uid: &str) {
+        *self
+            .claims_versions
+            .entry(user_uid.to_string())
+            .or_default() += 1;
+    }
+
+    fn bump_holders(&mut self, role: &Role) {
+        let holders: Vec<String> = self
+            .roles
+            .iter()
+            .filter(|(_, held)| held == role)
+            .map(|(uid, _)| uid.clone())
+            .collect();
+        for uid in holders {
+            self.bump_claims_version(&uid);
+        }
+    }
+
+    // like the postgres backend, another active user keeps a role implying Admin,
+    // role is None when the user is deleted
+    fn ensure_other_global_admin(&self, user_uid: &str, role: Option<&Role>) -> Result<(), Error> {
+        let catalogue = RoleCatalogue::new(self.role_definitions.values().cloned().collect());
+        let removed =
+            |uid: &String, held: &Role| uid == user_uid && role.is_none_or(|role| role == held);
+        let active = |uid: &String| {
+            self.users
+                .get(uid)
+                .is_some_and(|user| !user.disabled && user.deleted_at.is_none())
+        };
+        let mut admins = self
+            .roles
+            .iter()
+            .filter(|(_, held)| catalogue.implies(held, &Role::ADMIN));
+        if admins.clone().any(|(uid, held)| removed(uid, held))
+            && !admins.any(|(uid, held)| !removed(uid, held) && active(uid))
+        {
+            return Err(Error::LastAdmin);
+        }
+        Ok(())
+    }
+
+    // like the postgres backend, the last Admin of an organization stays
+    fn ensure_other_admin(&self, id: &Uuid, user_uid: &str) -> Result<(), Error> {
+        let admins: Vec<&Membership> = self
+            .memberships
+            .iter()
+            .filter(|m| m.organization_id == *id && m.role == Role::ADMIN)
+            .collect();
+        if admins.len() == 1 && admins[0].user_id == user_uid {
+            return Err(Error::LastAdmin);
+        }
+        Ok(())
+    }
+
+    fn open_invitation(&mut self, id: &Uuid) -> Result<&mut Invitation, Error> {
+        let invitation = self.invitations.get_mut(id).ok_or(Error::NotFound)?;
+        if !invitation.is_open() {
+            return Err(Error::Expired);
+        }
+        Ok(invitation)
+    }
+
+    fn active_api_key(&mut self, id: &Uuid) -> Result<&mut ApiKey, Error> {
+        let api_key = self.api_keys.get_mut(id).ok_or(Error::NotFound)?;
+        if api_key.revoked_at.is_some() {
+            return Err(Error::Expired);
+        }
+        Ok(api_key)
+    }
+}
+
+impl MemoryClient {
+    pub fn new() -> MemoryClient {
+        MemoryClient::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        // a panic while holding the lock can't leave the maps half updated
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl UserTrait for MemoryClient {
+    async fn get_user_roles(&self, user_uid: &str) -> Result<Vec<Role>, Error> {
+        Ok(self
+            .state()
+            .roles
+            .iter()
+            .filter(|(uid, _)| uid == user_uid)
+            .map(|(_, role)| role.clone())
+            .collect())
+    }
+
+    async fn save_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        let mut state = self.state();
+        if !state.users.contains_key(user_uid) || !state.role_definitions.contains_key(role) {
+            return Err(Error::NotFound);
+        }
+        if state
+            .roles
+            .iter()
+            .any(|(uid, held)| uid == user_uid && held == role)
+        {
+            return Err(Error::Conflict("roles_firebase_uid_role_key".to_string()));
+        }
+        state.roles.push((user_uid.to_string(), role.clone()));
+        state.bump_claims_version(user_uid);
+        Ok(())
+    }
+
+    async fn remove_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        let mut state = self.state();
+        let position = state
+            .roles
+            .iter()
+            .position(|(uid, held)| uid == user_uid && held == role)
+            .ok_or(Error::NotFound)?;
+        state.ensure_other_global_admin(user_uid, Some(role))?;
+        state.roles.remove(position);
+        state.bump_claims_version(user_uid);
+        Ok(())
+    }
+
+    async fn get_users_by_role(&self, role: &Role) -> Result<Vec<User>, Error> {
+        let state = self.state();
+        let mut users: Vec<User> = state
+            .roles
+            .iter()
+            .filter(|(_, held)| held == role)
+            .filter_map(|(uid, _)| state.users.get(uid).cloned())
+            .collect();
+        users.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(users)
+    }
+
+    async fn create_user(&self, user: &User) -> Result<User, Error> {
+        let mut state = self.state();
+        let uid = user
+            .id
+            .clone()
+            .ok_or_else(|| Error::Invalid("a user id is required".to_string()))?;
+        if state.users.contains_key(&uid) {
+            return Err(Error::Conflict("users_pkey".to_string()));
+        }
+        let now = Utc::now();
+        let user = User {
+            id: Some(uid.clone()),
+            password: hash_password(&user.password)?,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: user.created_at.or(Some(now)),
+            updated_at: user.updated_at.or(Some(now)),
+            ..user.clone()
+        };
+        state.users.insert(uid.clone(), user.clone());
+        state.roles.push((uid.clone(), Role::USER));
+        state.bump_claims_version(&uid);
+        Ok(user)
+    }
+
+    async fn delete_user(&self, user_uid: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        let email = state
+            .users
+            .get(user_uid)
+            .ok_or(Error::NotFound)?
+            .email
+            .to_lowercase();
+        state.ensure_other_global_admin(user_uid, None)?;
+        for membership in state
+            .memberships
+            .iter()
+            .filter(|m| m.user_id == user_uid && m.role == Role::ADMIN)
+        {
+            state.ensure_other_admin(&membership.organization_id, user_uid)?;
+        }
+        state.users.remove(user_uid);
+        state.invitations.retain(|_, invitation| {
+            invitation.accepted_by.as_deref() != Some(user_uid)
+                && invitation.email.to_lowercase() != email
+        });
+        // like the foreign keys of postgres, cascaded or set null
+        state.claims_versions.remove(user_uid);
+        state.roles.retain(|(uid, _)| uid != user_uid);
+        state.memberships.retain(|m| m.user_id != user_uid);
+        state
+            .password_resets
+            .retain(|reset| reset.user_id != user_uid);
+        state
+            .email_verifications
+            .retain(|verification| verification.user_id != user_uid);
+        state
+            .api_keys
+            .retain(|_, api_key| api_key.user_id != user_uid);
+        for api_key in state.api_keys.values_mut() {
+            if api_key.created_by.as_deref() == Some(user_uid) {
+                api_key.created_by = None;
+            }
+        }
+        for invitation in state.invitations.values_mut() {
+            if invitation.invited_by.as_deref() == Some(user_uid) {
+                invitation.invited_by = None;
+            }
+            if invitation.accepted_by.as_deref() == Some(user_uid) {
+                invitation.accepted_by = None;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_user_name(&self, user_name: &str, user_uid: &str) -> Result<User, Error> {
+        let mut state = self.state();
+        let user = state.users.get_mut(user_uid).ok_or(Error::NotFound)?;
+        user.name = user_name.to_string();
+        Ok(user.clone())
+    }
+
+    async fn update_user_password(&self, user_uid: &str, password: &str) -> Result<(), Error> {
+        let password = hash_password(password)?;
+        let mut state = self.state();
+        let user = state.users.get_mut(user_uid).ok_or(Error::NotFound)?;
+        user.password = password;
+        user.updated_at = Some(Utc::now());
+        Ok(())
+    }
+
+    async fn update_user_email(&self, user_uid: &str, email: &str) -> Result<User, Error> {
+        let mut state = self.state();
+        let user = state.users.get_mut(user_uid).ok_or(Error::NotFound)?;
+        user.email = email.to_string();
+        user.email_verified = false;
+        user.updated_at = Some(Utc::now());
+        Ok(user.clone())
+    }
+
+    async fn get_user(&self, user_uid: &str) -> Result<User, Error> {
+        self.state()
+            .users
+            .get(user_uid)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn update_user_plan(&self, user_uid: &str, plan: Plan) -> Result<User, Error> {
+        let mut state = self.state();
+        let user = state.users.get_mut(user_uid).ok_or(Error::NotFound)?;
+        user.plan = plan;
+        user.updated_at = Some(Utc::now());
+        let user = user.clone();
+        state.bump_claims_version(user_uid);
+        Ok(user)
+    }
+
+    async fn update_user_status(
+        &self,
+        user_uid: &str,
+        disabled: bool,
+        deleted_at: Option<DateTime<Utc>>,
+        reason: Option<&str>,
+    ) -> Result<User, Error> {
+        let mut state = self.state();
+        let user = state.users.get_mut(user_uid).ok_or(Error::NotFound)?;
+        user.disabled = disabled;
+        user.deleted_at = deleted_at;
+        user.status_reason = reason.map(str::to_string);
+        user.updated_at = Some(Utc::now());
+        Ok(user.clone())
+    }
+
+    async fn get_users_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<User>, Error> {
+        let mut users: Vec<User> = self
+            .state()
+            .users
+            .values()
+            .filter(|user| {
+                user.deleted_at
+                    .is_some_and(|deleted_at| deleted_at < before)
+            })
+            .cloned()
+            .collect();
+        users.sort_by(|a, b| (a.deleted_at, &a.id).cmp(&(b.deleted_at, &b.id)));
+        Ok(users)
+    }
+
+    async fn get_user_claims(&self, user_uid: &str) -> Result<UserClaims, Error> {
+        let state = self.state();
+        let user = state.users.get(user_uid).ok_or(Error::NotFound)?;
+        Ok(UserClaims {
+            roles: state
+                .roles
+                .iter()
+                .filter(|(uid, _)| uid == user_uid)
+                .map(|(_, role)| role.clone())
+                .collect(),
+            plan: user.plan,
+            claims_version: state
+                .claims_versions
+                .get(user_uid)
+                .copied()
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl IdentityTrait for MemoryClient {
+    async fn create_identity(&self, identity: &LocalIdentity) -> Result<(), Error> {
+        let mut state = self.state();
+        if state.identities.contains_key(&identity.uid) {
+            return Err(Error::Conflict("local_identities_pkey".to_string()));
+        }
+        if state
+            .identities
+            .values()
+            .any(|other| other.email == identity.email)
+        {
+            return Err(Error::Conflict("local_identities_email_key".to_string()));
+        }
+        let now = Utc::now();
+        let identity = LocalIdentity {
+            created_at: Some(now),
+            updated_at: Some(now),
+            ..identity.clone()
+        };
+        state.identities.insert(identity.uid.clone(), identity);
+        Ok(())
+    }
+
+    async fn get_identity(&self, uid: &str) -> Result<Option<LocalIdentity>, Error> {
+        Ok(self.state().identities.get(uid).cloned())
+    }
+
+    async fn get_identity_by_email(&self, email: &str) -> Result<Option<LocalIdentity>, Error> {
+        Ok(self
+            .state()
+            .identities
+            .values()
+            .find(|identity| identity.email == email)
+            .cloned())
+    }
+
+    async fn update_identity(&self, identity: &LocalIdentity) -> Result<bool, Error> {
+        let mut state = self.state();
+        if state
+            .identities
+            .values()
+            .any(|other| other.email == identity.email && other.uid != identity.uid)
+        {
+            return Err(Error::Conflict("local_identities_email_key".to_string()));
+        }
+        let Some(current) = state.identities.get_mut(&identity.uid) else {
+            return Ok(false);
+        };
+        *current = LocalIdentity {
+            created_at: current.created_at,
+            updated_at: Some(Utc::now()),
+            ..identity.clone()
+        };
+        Ok(true)
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<bool, Error> {
+        Ok(self.state().identities.remove(uid).is_some())
+    }
+}
+
+impl PermissionTrait for MemoryClient {
+    async fn get_permissions(&self) -> Result<Vec<Permission>, Error> {
+        Ok(self.state().permissions.values().cloned().collect())
+    }
+
+    async fn create_permission(&self, name: &str, description: &str) -> Result<Permission, Error> {
+        let mut state = self.state();
+        if state.permissions.contains_key(name) {
+            return Err(Error::Conflict("permissions_pkey".to_string()));
+        }
+        let permission = Permission {
+            name: name.to_string(),
+            description: description.to_string(),
+            created_at: Some(Utc::now()),
+        };
+        state
+            .permissions
+            .insert(name.to_string(), permission.clone());
+        Ok(permission)
+    }
+
+    async fn delete_permission(&self, name: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        state.permissions.remove(name).ok_or(Error::NotFound)?;
+        state
+            .role_permissions
+            .retain(|(_, permission)| permission != name);
+        Ok(())
+    }
+
+    async fn get_role_permissions(&self, role: &Role) -> Result<Vec<String>, Error> {
+        let mut permissions: Vec<String> = self
+            .state()
+            .role_permissions
+            .iter()
+            .filter(|(granted, _)| granted == role)
+            .map(|(_, permission)| permission.clone())
+            .collect();
+        permissions.sort();
+        Ok(permissions)
+    }
+
+    async fn grant_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        if !state.permissions.contains_key(permission) || !state.role_definitions.contains_key(role)
+        {
+            return Err(Error::NotFound);
+        }
+        if state
+            .role_permissions
+            .iter()
+            .any(|(granted, name)| granted == role && name == permission)
+        {
+            return Err(Error::Conflict("role_permissions_pkey".to_string()));
+        }
+        state
+            .role_permissions
+            .push((role.clone(), permission.to_string()));
+        Ok(())
+    }
+
+    async fn revoke_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        let position = state
+            .role_permissions
+            .iter()
+            .position(|(granted, name)| granted == role && name == permission)
+            .ok_or(Error::NotFound)?;
+        state.role_permissions.remove(position);
+        Ok(())
+    }
+
+    async fn get_user_permissions(&self, user_uid: &str) -> Result<Vec<String>, Error> {
+        let state = self.state();
+        let held: Vec<Role> = state
+            .roles
+            .iter()
+            .filter(|(uid, _)| uid == user_uid)
+            .map(|(_, role)| role.clone())
+            .collect();
+        let implied =
+            RoleCatalogue::new(state.role_definitions.values().cloned().collect()).implied(&held);
+        let mut permissions: Vec<String> = state
+            .role_permissions
+            .iter()
+            .filter(|(granted, _)| implied.contains(granted))
+            .map(|(_, permission)| permission.clone())
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+        Ok(permissions)
+    }
+}
+
+impl RoleTrait for MemoryClient {
+    async fn get_role_definitions(&self) -> Result<Vec<RoleDefinition>, Error> {
+        Ok(self.state().role_definitions.values().cloned().collect())
+    }
+
+    async fn create_role(
+        &self,
+        name: &Role,
+        description: &str,
+        parent: Option<&Role>,
+    ) -> Result<RoleDefinition, Error> {
+        let mut state = self.state();
+        if state.role_definitions.contains_key(name) {
+            return Err(Error::Conflict("role_definitions_pkey".to_string()));
+        }
+        if parent.is_some_and(|parent| !state.role_definitions.contains_key(parent)) {
+            return Err(Error::NotFound);
+        }
+        let now = Utc::now();
+        let definition = RoleDefinition {
+            name: name.clone(),
+            description: description.to_string(),
+            parent: parent.cloned(),
+            built_in: false,
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        state
+            .role_definitions
+            .insert(name.clone(), definition.clone());
+        Ok(definition)
+    }
+
+    async fn rename_role(&self, name: &Role, new_name: &Role) -> Result<RoleDefinition, Error> {
+        if name.is_built_in() {
+            return Err(Error::BuiltInRole(name.to_string()));
+        }
+        let mut state = self.state();
+        if !state.role_definitions.contains_key(name) {
+            return Err(Error::NotFound);
+        }
+        if state.role_definitions.contains_key(new_name) {
+            return Err(Error::Conflict("role_definitions_pkey".to_string()));
+        }
+        let mut definition = state.role_definitions.remove(name).ok_or(Error::NotFound)?;
+        definition.name = new_name.clone();
+        definition.updated_at = Some(Utc::now());
+        state
+            .role_definitions
+            .insert(new_name.clone(), definition.clone());
+        state.bump_holders(name);
+        // like on update cascade
+        for other in state.role_definitions.values_mut() {
+            if other.parent.as_ref() == Some(name) {
+                other.parent = Some(new_name.clone());
+            }
+        }
+        for membership in state.memberships.iter_mut() {
+            if membership.role == *name {
+                membership.role = new_name.clone();
+            }
+        }
+        for invitation in state.invitations.values_mut() {
+            if invitation.role == *name {
+                invitation.role = new_name.clone();
+            }
+        }
+        for api_key in state.api_keys.values_mut() {
+            for scope in api_key.scopes.iter_mut() {
+                if scope == name {
+                    *scope = new_name.clone();
+                }
+            }
+        }
+        for (_, role) in state.roles.iter_mut() {
+            if role == name {
+                *role = new_name.clone();
+            }
+        }
+        for (role, _) in state.role_permissions.iter_mut() {
+            if role == name {
+                *role = new_name.clone();
+            }
+        }
+        Ok(definition)
+    }
+
+    async fn delete_role(&self, name: &Role) -> Result<(), Error> {
+        if name.is_built_in() {
+            return Err(Error::BuiltInRole(name.to_string()));
+        }
+        let mut state = self.state();
+        if state
+            .role_definitions
+            .values()
+            .any(|other| other.parent.as_ref() == Some(name))
+        {
+            return Err(Error::RoleInUse(name.to_string()));
+        }
+        state.role_definitions.remove(name).ok_or(Error::NotFound)?;
+        state.bump_holders(name);
+        // like on delete cascade
+        state.roles.retain(|(_, role)| role != name);
+        state.role_permissions.retain(|(role, _)| role != name);
+        state
+            .invitations
+            .retain(|_, invitation| invitation.role != *name);
+        for api_key in state.api_keys.values_mut() {
+            api_key.scopes.retain(|scope| scope != name);
+        }
+        // like on delete set default
+        for membership in state.memberships.iter_mut() {
+            if membership.role == *name {
+                membership.role = Role::USER;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OrganizationTrait for MemoryClient {
+    async fn create_organization(
+        &self,
+        name: &str,
+        owner_uid: &str,
+    ) -> Result<Organization, Error> {
+        let mut state = self.state();
+        if !state.users.contains_key(owner_uid) {
+            return Err(Error::NotFound);
+        }
+        let now = Utc::now();
+        let organization = Organization {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        state
+            .organizations
+            .insert(organization.id, organization.clone());
+        state.memberships.push(Membership {
+            organization_id: organization.id,
+            user_id: owner_uid.to_string(),
+            role: Role::ADMIN,
+            created_at: Some(now),
+            updated_at: Some(now),
+        });
+        Ok(organization)
+    }
+
+    async fn get_organization(&self, id: &Uuid) -> Result<Organization, Error> {
+        self.state()
+            .organizations
+            .get(id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_user_organizations(&self, user_uid: &str) -> Result<Vec<Organization>, Error> {
+        let state = self.state();
+        let mut organizations: Vec<Organization> = state
+            .memberships
+            .iter()
+            .filter(|m| m.user_id == user_uid)
+            .filter_map(|m| state.organizations.get(&m.organization_id).cloned())
+            .collect();
+        organizations.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        Ok(organizations)
+    }
+
+    async fn rename_organization(&self, id: &Uuid, name: &str) -> Result<Organization, Error> {
+        let mut state = self.state();
+        let organization = state.organizations.get_mut(id).ok_or(Error::NotFound)?;
+        organization.name = name.to_string();
+        organization.updated_at = Some(Utc::now());
+        Ok(organization.clone())
+    }
+
+    async fn delete_organization(&self, id: &Uuid) -> Result<(), Error> {
+        let mut state = self.state();
+        state.organizations.remove(id).ok_or(Error::NotFound)?;
+        // like on delete cascade
+        state.memberships.retain(|m| m.organization_id != *id);
+        state
+            .invitations
+            .retain(|_, invitation| invitation.organization_id != Some(*id));
+        Ok(())
+    }
+
+    async fn get_members(&self, id: &Uuid) -> Result<Vec<Membership>, Error> {
+        let mut members: Vec<Membership> = self
+            .state()
+            .memberships
+            .iter()
+            .filter(|m| m.organization_id == *id)
+            .cloned()
+            .collect();
+        members.sort_by(|a, b| (a.created_at, &a.user_id).cmp(&(b.created_at, &b.user_id)));
+        Ok(members)
+    }
+
+    async fn get_membership(&self, id: &Uuid, user_uid: &str) -> Result<Option<Membership>, Error> {
+        Ok(self
+            .state()
+            .memberships
+            .iter()
+            .find(|m| m.organization_id == *id && m.user_id == user_uid)
+            .cloned())
+    }
+
+    async fn save_membership(
+        &self,
+        id: &Uuid,
+        user_uid: &str,
+        role: &Role,
+    ) -> Result<Membership, Error> {
+        let mut state = self.state();
+        if !state.organizations.contains_key(id)
+            || !state.users.contains_key(user_uid)
+            || !state.role_definitions.contains_key(role)
+        {
+            return Err(Error::NotFound);
+        }
+        if *role != Role::ADMIN {
+            state.ensure_other_admin(id, user_uid)?;
+        }
+        let now = Utc::now();
+        let position = state
+            .memberships
+            .iter()
+            .position(|m| m.organization_id == *id && m.user_id == user_uid);
+        let membership = match position {
+            Some(position) => {
+                let membership = &mut state.memberships[position];
+                membership.role = role.clone();
+                membership.updated_at = Some(now);
+                membership.clone()
+            }
+            None => {
+                let membership = Membership {
+                    organization_id: *id,
+                    user_id: user_uid.to_string(),
+                    role: role.clone(),
+                    created_at: Some(now),
+                    updated_at: Some(now),
+                };
+                state.memberships.push(membership.clone());
+                membership
+            }
+        };
+        Ok(membership)
+    }
+
+    async fn remove_membership(&self, id: &Uuid, user_uid: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        state.ensure_other_admin(id, user_uid)?;
+        let position = state
+            .memberships
+            .iter()
+            .position(|m| m.organization_id == *id && m.user_id == user_uid)
+            .ok_or(Error::NotFound)?;
+        state.memberships.remove(position);
+        Ok(())
+    }
+}
+
+impl InvitationTrait for MemoryClient {
+    async fn create_invitation(&self, invitation: &Invitation) -> Result<Invitation, Error> {
+        let mut state = self.state();
+        if !state.role_definitions.contains_key(&invitation.role)
+            || invitation
+                .organization_id
+                .is_some_and(|id| !state.organizations.contains_key(&id))
+        {
+            return Err(Error::NotFound);
+        }
+        if state.invitations.contains_key(&invitation.id)
+            || state
+                .invitations
+                .values()
+                .any(|other| other.token_hash == invitation.token_hash)
+        {
+            return Err(Error::Conflict("invitations_pkey".to_string()));
+        }
+        if state.invitations.values().any(|other| {
+            other.is_open()
+                && other.email.to_lowercase() == invitation.email.to_lowercase()
+                && other.organization_id == invitation.organization_id
+        }) {
+            return Err(Error::Conflict("invitations_pending_key".to_string()));
+        }
+        let now = Utc::now();
+        let invitation = Invitation {
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+            ..invitation.clone()
+        };
+        state.invitations.insert(invitation.id, invitation.clone());
+        Ok(invitation)
+    }
+
+    async fn get_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        self.state()
+            .invitations
+            .get(id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_invitation_by_token(&self, token_hash: &str) -> Result<Invitation, Error> {
+        self.state()
+            .invitations
+            .values()
+            .find(|invitation| invitation.token_hash == token_hash)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_open_invitations(
+        &self,
+        organization_id: Option<&Uuid>,
+    ) -> Result<Vec<Invitation>, Error> {
+        let mut invitations: Vec<Invitation> = self
+            .state()
+            .invitations
+            .values()
+            .filter(|invitation| {
+                invitation.is_open() && invitation.organization_id.as_ref() == organization_id
+            })
+            .cloned()
+            .collect();
+        invitations.sort_by_key(|invitation| (invitation.created_at, invitation.id));
+        Ok(invitations)
+    }
+
+    async fn get_user_invitations(&self, user_uid: &str) -> Result<Vec<Invitation>, Error> {
+        let state = self.state();
+        let email = state
+            .users
+            .get(user_uid)
+            .ok_or(Error::NotFound)?
+            .email
+            .to_lowercase();
+        let mut invitations: Vec<Invitation> = state
+            .invitations
+            .values()
+            .filter(|invitation| {
+                invitation.invited_by.as_deref() == Some(user_uid)
+                    || invitation.accepted_by.as_deref() == Some(user_uid)
+                    || invitation.email.to_lowercase() == email
+            })
+            .cloned()
+            .collect();
+        invitations.sort_by_key(|invitation| (invitation.created_at, invitation.id));
+        Ok(invitations)
+    }
+
+    async fn renew_invitation(
+        &self,
+        id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invitation, Error> {
+        let mut state = self.state();
+        let invitation = state.open_invitation(id)?;
+        invitation.token_hash = token_hash.to_string();
+        invitation.expires_at = expires_at;
+        invitation.updated_at = Some(Utc::now());
+        Ok(invitation.clone())
+    }
+
+    async fn revoke_invitation(&self, id: &Uuid) -> Result<Invitation, Error> {
+        let mut state = self.state();
+        let invitation = state.open_invitation(id)?;
+        let now = Utc::now();
+        invitation.revoked_at = Some(now);
+        invitation.updated_at = Some(now);
+        Ok(invitation.clone())
+    }
+
+    async fn accept_invitation(&self, token_hash: &str, user: &User) -> Result<Invitation, Error> {
+        let uid = user
+            .id
+            .clone()
+            .ok_or_else(|| Error::Invalid("a user id is required".to_string()))?;
+        let password = hash_password(&user.password)?;
+        let mut state = self.state();
+        let now = Utc::now();
+        let invitation = state
+            .invitations
+            .values()
+            .find(|invitation| invitation.token_hash == token_hash)
+            .cloned()
+            .ok_or(Error::NotFound)?;
+        if invitation.status(now) != InvitationStatus::Pending {
+            return Err(Error::Expired);
+        }
+
+        // every change happens under the lock, like the transaction of the postgres backend
+        if !state.users.contains_key(&uid) {
+            let created = User {
+                id: Some(uid.clone()),
+                email: invitation.email.clone(),
+                password,
+                created_at: Some(now),
+                updated_at: Some(now),
+                ..user.clone()
+            };
+            state.users.insert(uid.clone(), created);
+            state.roles.push((uid.clone(), Role::USER));
+        }
+        match invitation.organization_id {
+            Some(organization_id) => {
+                if !state
+                    .memberships
+                    .iter()
+                    .any(|m| m.organization_id == organization_id && m.user_id == uid)
+                {
+                    state.memberships.push(Membership {
+                        organization_id,
+                        user_id: uid.clone(),
+                        role: invitation.role.clone(),
+                        created_at: Some(now),
+                        updated_at: Some(now),
+                    });
+                }
+            }
+            None => {
+                if !state
+                    .roles
+                    .iter()
+                    .any(|(held_by, role)| *held_by == uid && *role == invitation.role)
+                {
+                    state.roles.push((uid.clone(), invitation.role.clone()));
+                }
+            }
+        }
+        state.bump_claims_version(&uid);
+        let invitation = state
+            .invitations
+            .get_mut(&invitation.id)
+            .ok_or(Error::NotFound)?;
+        invitation.accepted_at = Some(now);
+        invitation.accepted_by = Some(uid);
+        invitation.updated_at = Some(now);
+        Ok(invitation.clone())
+    }
+}
+
+impl ApiKeyTrait for MemoryClient {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<ApiKey, Error> {
+        let mut state = self.state();
+        if !state.users.contains_key(&api_key.user_id) {
+            return Err(Error::NotFound);
+        }
+        if state.api_keys.contains_key(&api_key.id) {
+            return Err(Error::Conflict("api_keys_pkey".to_string()));
+        }
+        let now = Utc::now();
+        let api_key = ApiKey {
+            last_used_at: None,
+            revoked_at: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+            ..api_key.clone()
+        };
+        state.api_keys.insert(api_key.id, api_key.clone());
+        Ok(api_key)
+    }
+
+    async fn get_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        self.state()
+            .api_keys
+            .get(id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_user_api_keys(&self, user_uid: &str) -> Result<Vec<ApiKey>, Error> {
+        let mut api_keys: Vec<ApiKey> = self
+            .state()
+            .api_keys
+            .values()
+            .filter(|api_key| api_key.user_id == user_uid)
+            .cloned()
+            .collect();
+        api_keys.sort_by_key(|api_key| (api_key.created_at, api_key.id));
+        Ok(api_keys)
+    }
+
+    async fn update_api_key(
+        &self,
+        id: &Uuid,
+        name: Option<&str>,
+        scopes: Option<&[Role]>,
+    ) -> Result<ApiKey, Error> {
+        let mut state = self.state();
+        let api_key = state.active_api_key(id)?;
+        if let Some(name) = name {
+            api_key.name = name.to_string();
+        }
+        if let Some(scopes) = scopes {
+            api_key.scopes = scopes.to_vec();
+        }
+        api_key.updated_at = Some(Utc::now());
+        Ok(api_key.clone())
+    }
+
+    async fn revoke_api_key(&self, id: &Uuid) -> Result<ApiKey, Error> {
+        let mut state = self.state();
+        let api_key = state.active_api_key(id)?;
+        let now = Utc::now();
+        api_key.revoked_at = Some(now);
+        api_key.updated_at = Some(now);
+        Ok(api_key.clone())
+    }
+
+    async fn touch_api_key(&self, id: &Uuid) -> Result<(), Error> {
+        let mut state = self.state();
+        let api_key = state.api_keys.get_mut(id).ok_or(Error::NotFound)?;
+        let now = Utc::now();
+        if api_key
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at < now - chrono::Duration::minutes(1))
+        {
+            api_key.last_used_at = Some(now);
+        }
+        Ok(())
+    }
+}
+
+impl ImpersonationTrait for MemoryClient {
+    async fn create_impersonation(
+        &self,
+        impersonation: &Impersonation,
+    ) -> Result<Impersonation, Error> {
+        let mut state = self.state();
+        if !state.users.contains_key(&impersonation.target_uid) {
+            return Err(Error::NotFound);
+        }
+        if state.impersonations.contains_key(&impersonation.id) {
+            return Err(Error::Conflict("impersonations_pkey".to_string()));
+        }
+        let now = Utc::now();
+        let impersonation = Impersonation {
+            ended_at: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+            ..impersonation.clone()
+        };
+        state
+            .impersonations
+            .insert(impersonation.id, impersonation.clone());
+        Ok(impersonation)
+    }
+
+    async fn get_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        self.state()
+            .impersonations
+            .get(id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_impersonations(
+        &self,
+        user_uid: Option<&str>,
+    ) -> Result<Vec<Impersonation>, Error> {
+        let mut impersonations: Vec<Impersonation> = self
+            .state()
+            .impersonations
+            .values()
+            .filter(|impersonation| {
+                user_uid.is_none_or(|uid| {
+                    impersonation.admin_uid == uid || impersonation.target_uid == uid
+                })
+            })
+            .cloned()
+            .collect();
+        impersonations.sort_by_key(|impersonation| {
+            (
+                std::cmp::Reverse(impersonation.created_at),
+                impersonation.id,
+            )
+        });
+        Ok(impersonations)
+    }
+
+    async fn end_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error> {
+        let mut state = self.state();
+        let impersonation = state.impersonations.get_mut(id).ok_or(Error::NotFound)?;
+        if impersonation.ended_at.is_some() {
+            return Err(Error::Expired);
+        }
+        let now = Utc::now();
+        impersonation.ended_at = Some(now);
+        impersonation.updated_at = Some(now);
+        Ok(impersonation.clone())
+    }
+
+    async fn record_impersonation_event(
+        &self,
+        event: &ImpersonationEvent,
+    ) -> Result<ImpersonationEvent, Error> {
+        let mut state = self.state();
+        if !state.impersonations.contains_key(&event.impersonation_id) {
+            return Err(Error::NotFound);
+        }
+        let event = ImpersonationEvent {
+            created_at: Some(Utc::now()),
+            ..event.clone()
+        };
+        state.impersonation_events.push(event.clone());
+        Ok(event)
+    }
+
+    async fn get_impersonation_events(
+        &self,
+        impersonation_id: &Uuid,
+    ) -> Result<Vec<ImpersonationEvent>, Error> {
+        Ok(self
+            .state()
+            .impersonation_events
+            .iter()
+            .filter(|event| event.impersonation_id == *impersonation_id)
+            .cloned()
+            .collect())
+    }
+}
+
+impl PasswordResetTrait for MemoryClient {
+    async fn create_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PasswordReset, Error> {
+        let mut state = self.state();
+        let user_id = state
+            .users
+            .values()
+            .filter(|user| user.email.to_lowercase() == email)
+            .min_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)))
+            .and_then(|user| user.id.clone())
+            .ok_or(Error::NotFound)?;
+        if state
+            .password_resets
+            .iter()
+            .any(|reset| reset.token_hash == token_hash)
+        {
+            return Err(Error::Conflict(
+                "password_resets_token_hash_key".to_string(),
+            ));
+        }
+        state
+            .password_resets
+            .retain(|reset| reset.user_id != user_id || reset.used_at.is_some());
+        let reset = PasswordReset {
+            id: Uuid::new_v4(),
+            user_id,
+            token_hash: token_hash.to_string(),
+            expires_at,
+            used_at: None,
+            created_at: Some(Utc::now()),
+        };
+        state.password_resets.push(reset.clone());
+        Ok(reset)
+    }
+
+    async fn consume_password_reset(&self, token_hash: &str) -> Result<PasswordReset, Error> {
+        let mut state = self.state();
+        let reset = state
+            .password_resets
+            .iter_mut()
+            .find(|reset| reset.token_hash == token_hash)
+            .ok_or(Error::NotFound)?;
+        let now = Utc::now();
+        if !reset.is_pending(now) {
+            return Err(Error::Expired);
+        }
+        reset.used_at = Some(now);
+        Ok(reset.clone())
+    }
+
+    async fn get_user_password_resets(&self, user_uid: &str) -> Result<Vec<PasswordReset>, Error> {
+        let mut resets: Vec<PasswordReset> = self
+            .state()
+            .password_resets
+            .iter()
+            .filter(|reset| reset.user_id == user_uid)
+            .cloned()
+            .collect();
+        resets.sort_by_key(|reset| (reset.created_at, reset.id));
+        Ok(resets)
+    }
+}
+
+impl EmailVerificationTrait for MemoryClient {
+    async fn create_email_verification(
+        &self,
+        user_uid: &str,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailVerification, Error> {
+        let mut state = self.state();
+        if !state.users.contains_key(user_uid) {
+            return Err(Error::NotFound);
+        }
+        if state
+            .email_verifications
+            .iter()
+            .any(|verification| verification.token_hash == token_hash)
+        {
+            return Err(Error::Conflict(
+                "email_verifications_token_hash_key".to_string(),
+            ));
+        }
+        state.email_verifications.retain(|verification| {
+            verification.user_id != user_uid || verification.used_at.is_some()
+        });
+        let verification = EmailVerification {
+            id: Uuid::new_v4(),
+            user_id: user_uid.to_string(),
+            email: email.to_string(),
+            token_hash: token_hash.to_string(),
+            expires_at,
+            used_at: None,
+            created_at: Some(Utc::now()),
+        };
+        state.email_verifications.push(verification.clone());
+        Ok(verification)
+    }
+
+    async fn consume_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerification, Error> {
+        let mut state = self.state();
+        let now = Utc::now();
+        let verification = state
+            .email_verifications
+            .iter()
+            .find(|verification| verification.token_hash == token_hash)
+            .cloned()
+            .ok_or(Error::NotFound)?;
+        if !verification.is_pending(now) {
+            return Err(Error::Expired);
+        }
+        let user = state
+            .users
+            .get_mut(&verification.user_id)
+            .filter(|user| user.email.to_lowercase() == verification.email.to_lowercase())
+            .ok_or(Error::Expired)?;
+        user.email_verified = true;
+        user.updated_at = Some(now);
+        let verification = state
+            .email_verifications
+            .iter_mut()
+            .find(|verification| verification.token_hash == token_hash)
+            .ok_or(Error::NotFound)?;
+        verification.used_at = Some(now);
+        Ok(verification.clone())
+    }
+
+    async fn get_user_email_verifications(
+        &self,
+        user_uid: &str,
+    ) -> Result<Vec<EmailVerification>, Error> {
+        let mut verifications: Vec<EmailVerification> = self
+            .state()
+            .email_verifications
+            .iter()
+            .filter(|verification| verification.user_id == user_uid)
+            .cloned()
+            .collect();
+        verifications.sort_by_key(|verification| (verification.created_at, verification.id));
+        Ok(verifications)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::conformance;
+
+    #[tokio::test]
+    async fn test_conformance() {
+        conformance::run(&MemoryClient::new()).await;
+    }
+}
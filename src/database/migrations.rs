@@ -0,0 +1,348 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use tokio_postgres::Client;
+
+use super::{error::DatabaseError, main::PostGreClient};
+
+/*
+    * Key of the postgres advisory lock held while migrating,
+    so two replicas booting at the same time don't migrate concurrently
+*/
+const MIGRATION_LOCK_ID: i64 = 0x6461_7461_5f69_6e74;
+
+macro_rules! migration {
+    ($version:literal, $file:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $file, "_", $name, ".up.sql")),
+            down: include_str!(concat!("../../migrations/", $file, "_", $name, ".down.sql")),
+        }
+    };
+}
+
+/*
+    * Ordered list of migrations, append only
+    A migration that has been applied somewhere must never be edited,
+    its checksum is validated on every run.
+*/
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001", "create_users_and_roles"),
+    migration!(2, "0002", "create_local_identities"),
+    migration!(3, "0003", "unique_user_roles"),
+    migration!(4, "0004", "create_permissions"),
+    migration!(5, "0005", "create_role_catalogue"),
+    migration!(6, "0006", "user_claims"),
+    migration!(7, "0007", "create_organizations"),
+    migration!(8, "0008", "create_invitations"),
+    migration!(9, "0009", "row_level_security"),
+    migration!(10, "0010", "create_api_keys"),
+    migration!(11, "0011", "create_impersonations"),
+    migration!(12, "0012", "create_password_resets"),
+    migration!(13, "0013", "create_email_verifications"),
+    migration!(14, "0014", "add_users_status"),
+    migration!(15, "0015", "secure_remaining_tables"),
+];
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.up.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(DatabaseError),
+    ChecksumMismatch { version: i64, name: String },
+    UnknownVersion(i64),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "database error: {}", e),
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "migration {} ({}) was modified after being applied",
+                version, name
+            ),
+            MigrationError::UnknownVersion(version) => write!(
+                f,
+                "database has migration {} applied which this binary doesn't know",
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<DatabaseError> for MigrationError {
+    fn from(e: DatabaseError) -> Self {
+        MigrationError::Database(e)
+    }
+}
+
+impl From<tokio_postgres::Error> for MigrationError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        MigrationError::Database(DatabaseError::Postgres(e))
+    }
+}
+
+impl PostGreClient {
+    /*
+        * Apply every pending migration up to target (all of them if None)
+        @param target: Option<i64>
+        @return versions applied
+    */
+    pub async fn migrate_up(&self, target: Option<i64>) -> Result<Vec<i64>, MigrationError> {
+        // the advisory lock is held by the session, so everything runs on one connection,
+        // straight from the pool as the owner of the tables
+        let mut client = self.pool.get().await.map_err(DatabaseError::from)?;
+        lock(&client).await?;
+        let result = up(&mut client, target).await;
+        unlock(&client).await?;
+        result
+    }
+
+    /*
+        * Revert the last applied migrations
+        @param steps: number of migrations to revert
+        @return versions reverted
+    */
+    pub async fn migrate_down(&self, steps: usize) -> Result<Vec<i64>, MigrationError> {
+        let mut client = self.pool.get().await.map_err(DatabaseError::from)?;
+        lock(&client).await?;
+        let result = down(&mut client, steps).await;
+        unlock(&client).await?;
+        result
+    }
+
+    /*
+        * List the migrations recorded in schema_migrations
+        @return Vec<AppliedMigration>
+    */
+    pub async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, MigrationError> {
+        let client = self.pool.get().await.map_err(DatabaseError::from)?;
+        applied_migrations(&client).await
+    }
+}
+
+async fn up(client: &mut Client, target: Option<i64>) -> Result<Vec<i64>, MigrationError> {
+    let applied = validate_applied_migrations(client).await?;
+    let mut done = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+        if target.is_some_and(|target| migration.version > target) {
+            break;
+        }
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.up).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+                &[&migration.version, &migration.name, &migration.checksum()],
+            )
+            .await?;
+        transaction.commit().await?;
+        println!(
+            "migration {} ({}) applied",
+            migration.version, migration.name
+        );
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+async fn down(client: &mut Client, steps: usize) -> Result<Vec<i64>, MigrationError> {
+    let applied = validate_applied_migrations(client).await?;
+    let mut done = Vec::new();
+    for applied in applied.iter().rev().take(steps) {
+        let migration = find_migration(applied.version)?;
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.down).await?;
+        transaction
+            .execute(
+                "DELETE FROM schema_migrations WHERE version = $1",
+                &[&migration.version],
+            )
+            .await?;
+        transaction.commit().await?;
+        println!(
+            "migration {} ({}) reverted",
+            migration.version, migration.name
+        );
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+async fn applied_migrations(client: &Client) -> Result<Vec<AppliedMigration>, MigrationError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL default now()
+            );",
+        )
+        .await?;
+    let rows = client
+        .query(
+            "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version",
+            &[],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get(0),
+            name: row.get(1),
+            checksum: row.get(2),
+            applied_at: row.get(3),
+        })
+        .collect())
+}
+
+async fn validate_applied_migrations(
+    client: &Client,
+) -> Result<Vec<AppliedMigration>, MigrationError> {
+    let applied = applied_migrations(client).await?;
+    for applied in applied.iter() {
+        let migration = find_migration(applied.version)?;
+        if migration.checksum() != applied.checksum {
+            return Err(MigrationError::ChecksumMismatch {
+                version: applied.version,
+                name: applied.name.clone(),
+            });
+        }
+    }
+    Ok(applied)
+}
+
+fn find_migration(version: i64) -> Result<&'static Migration, MigrationError> {
+    MIGRATIONS
+        .iter()
+        .find(|m| m.version == version)
+        .ok_or(MigrationError::UnknownVersion(version))
+}
+
+async fn lock(client: &Client) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_ID])
+        .await
+        .map(|_| ())
+}
+
+async fn unlock(client: &Client) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_ID])
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_up_and_down() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.drop_tables().await.unwrap();
+        let applied = client.migrate_up(None).await.unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        // second run is a no-op
+        assert!(client.migrate_up(None).await.unwrap().is_empty());
+
+        let reverted = client.migrate_down(MIGRATIONS.len()).await.unwrap();
+        assert_eq!(reverted.len(), MIGRATIONS.len());
+        assert!(client.applied_migrations().await.unwrap().is_empty());
+        client.migrate_up(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_tables_on_fresh_database() {
+        let mut config = Utils::postgres_config();
+        let server = PostGreClient::new(&config).await;
+        config.database = format!("fresh_{}", uuid::Uuid::new_v4().simple());
+        server
+            .connection()
+            .await
+            .unwrap()
+            .batch_execute(&format!("CREATE DATABASE {}", config.database))
+            .await
+            .unwrap();
+
+        // nothing to drop yet, every down script must cope with it
+        let client = PostGreClient::new(&config).await;
+        client.drop_tables().await.unwrap();
+        assert_eq!(
+            client.migrate_up(None).await.unwrap().len(),
+            MIGRATIONS.len()
+        );
+        client.drop_tables().await.unwrap();
+        drop(client);
+
+        server
+            .connection()
+            .await
+            .unwrap()
+            .batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", config.database))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.drop_tables().await.unwrap();
+        client.migrate_up(None).await.unwrap();
+        client
+            .connection()
+            .await
+            .unwrap()
+            .execute(
+                "UPDATE schema_migrations SET checksum = 'tampered' WHERE version = 1",
+                &[],
+            )
+            .await
+            .unwrap();
+        let res = client.migrate_up(None).await;
+        assert!(matches!(
+            res,
+            Err(MigrationError::ChecksumMismatch { version: 1, .. })
+        ));
+    }
+}
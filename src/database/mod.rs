@@ -1,2 +1,20 @@
+pub mod api_key;
+pub mod backend;
+#[cfg(test)]
+mod conformance;
+pub mod connection;
+pub mod email_verification;
+pub mod error;
+pub mod identity;
+pub mod impersonation;
+pub mod invitation;
 pub mod main;
+pub mod memory;
+pub mod migrations;
+pub mod organization;
+pub mod password_reset;
+pub mod permission;
+pub mod pool;
+pub mod role;
+pub mod tls;
 pub mod user;
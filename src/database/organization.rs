@@ -0,0 +1,186 @@
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::{
+    organization::{Membership, Organization},
+    role::Role,
+};
+use crate::traits::organization::OrganizationTrait;
+use tokio_postgres::{Row, Transaction};
+use uuid::Uuid;
+
+fn organization_from_row(row: &Row) -> Organization {
+    Organization {
+        id: row.get("id"),
+        name: row.get("name"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+fn membership_from_row(row: &Row) -> Membership {
+    Membership {
+        organization_id: row.get("organization_id"),
+        user_id: row.get("user_id"),
+        role: row.get("role"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+/*
+    * Fail with LastAdmin when user_uid is the only Admin of the organization
+    The Admin rows stay locked until commit, two demotions can't both see another Admin left.
+*/
+pub(super) async fn ensure_other_admin(
+    transaction: &Transaction<'_>,
+    id: &Uuid,
+    user_uid: &str,
+) -> Result<(), Error> {
+    let admins = transaction
+        .query(
+            "SELECT user_id FROM organization_members WHERE organization_id = $1 AND role = 'Admin' FOR UPDATE",
+            &[id],
+        )
+        .await?;
+    if admins.len() == 1 && admins[0].get::<_, &str>(0) == user_uid {
+        return Err(Error::LastAdmin);
+    }
+    Ok(())
+}
+
+impl OrganizationTrait for PostGreClient {
+    async fn create_organization(
+        &self,
+        name: &str,
+        owner_uid: &str,
+    ) -> Result<Organization, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        let row = transaction
+            .query_one(
+                "INSERT INTO organizations (name) VALUES ($1) RETURNING *",
+                &[&name],
+            )
+            .await?;
+        let organization = organization_from_row(&row);
+        transaction
+            .execute(
+                "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)",
+                &[&organization.id, &owner_uid, &Role::ADMIN],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(organization)
+    }
+
+    async fn get_organization(&self, id: &Uuid) -> Result<Organization, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM organizations WHERE id = $1", &[id])
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(organization_from_row(&row))
+    }
+
+    async fn get_user_organizations(&self, user_uid: &str) -> Result<Vec<Organization>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT organizations.* FROM organizations JOIN organization_members ON organization_members.organization_id = organizations.id WHERE organization_members.user_id = $1 ORDER BY organizations.name, organizations.id",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(organization_from_row).collect())
+    }
+
+    async fn rename_organization(&self, id: &Uuid, name: &str) -> Result<Organization, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt(
+                "UPDATE organizations SET name = $2, updated_at = now() WHERE id = $1 RETURNING *",
+                &[id, &name],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(organization_from_row(&row))
+    }
+
+    async fn delete_organization(&self, id: &Uuid) -> Result<(), Error> {
+        let deleted = self
+            .connection()
+            .await?
+            .execute("DELETE FROM organizations WHERE id = $1", &[id])
+            .await?;
+        match deleted {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_members(&self, id: &Uuid) -> Result<Vec<Membership>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM organization_members WHERE organization_id = $1 ORDER BY created_at, user_id",
+                &[id],
+            )
+            .await?;
+        Ok(rows.iter().map(membership_from_row).collect())
+    }
+
+    async fn get_membership(&self, id: &Uuid, user_uid: &str) -> Result<Option<Membership>, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_opt(
+                "SELECT * FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+                &[id, &user_uid],
+            )
+            .await?;
+        Ok(row.as_ref().map(membership_from_row))
+    }
+
+    async fn save_membership(
+        &self,
+        id: &Uuid,
+        user_uid: &str,
+        role: &Role,
+    ) -> Result<Membership, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        if *role != Role::ADMIN {
+            ensure_other_admin(&transaction, id, user_uid).await?;
+        }
+        let row = transaction
+            .query_one(
+                "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)
+                ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role, updated_at = now()
+                RETURNING *",
+                &[id, &user_uid, role],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(membership_from_row(&row))
+    }
+
+    async fn remove_membership(&self, id: &Uuid, user_uid: &str) -> Result<(), Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        ensure_other_admin(&transaction, id, user_uid).await?;
+        let removed = transaction
+            .execute(
+                "DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+                &[id, &user_uid],
+            )
+            .await?;
+        transaction.commit().await?;
+        match removed {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::password_reset::PasswordReset;
+use crate::traits::password_reset::PasswordResetTrait;
+
+fn password_reset_from_row(row: &Row) -> PasswordReset {
+    PasswordReset {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        token_hash: row.get("token_hash"),
+        expires_at: row.get("expires_at"),
+        used_at: row.get("used_at"),
+        created_at: Some(row.get("created_at")),
+    }
+}
+
+impl PasswordResetTrait for PostGreClient {
+    async fn create_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PasswordReset, Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        // emails aren't unique in users, the oldest user signed up with it
+        let user_id: String = transaction
+            .query_opt(
+                "SELECT id FROM users WHERE lower(email) = $1 ORDER BY created_at, id LIMIT 1",
+                &[&email],
+            )
+            .await?
+            .ok_or(Error::NotFound)?
+            .get("id");
+        transaction
+            .execute(
+                "DELETE FROM password_resets WHERE user_id = $1 AND used_at IS NULL",
+                &[&user_id],
+            )
+            .await?;
+        let row = transaction
+            .query_one(
+                "INSERT INTO password_resets (id, user_id, token_hash, expires_at) VALUES ($1, $2, $3, $4) RETURNING *",
+                &[&Uuid::new_v4(), &user_id, &token_hash, &expires_at],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(password_reset_from_row(&row))
+    }
+
+    async fn consume_password_reset(&self, token_hash: &str) -> Result<PasswordReset, Error> {
+        let mut client = self.connection().await?;
+        match client
+            .query_opt(
+                "UPDATE password_resets SET used_at = now() WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now() RETURNING *",
+                &[&token_hash],
+            )
+            .await?
+        {
+            Some(row) => Ok(password_reset_from_row(&row)),
+            None => {
+                let exists = client
+                    .query_opt(
+                        "SELECT 1 FROM password_resets WHERE token_hash = $1",
+                        &[&token_hash],
+                    )
+                    .await?;
+                Err(exists.map_or(Error::NotFound, |_| Error::Expired))
+            }
+        }
+    }
+
+    async fn get_user_password_resets(&self, user_uid: &str) -> Result<Vec<PasswordReset>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM password_resets WHERE user_id = $1 ORDER BY created_at, id",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(password_reset_from_row).collect())
+    }
+}
@@ -0,0 +1,144 @@
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::permission::Permission;
+use crate::structs::role::Role;
+use crate::traits::permission::PermissionTrait;
+use tokio_postgres::Row;
+
+fn permission_from_row(row: &Row) -> Permission {
+    Permission {
+        name: row.get("name"),
+        description: row.get("description"),
+        created_at: Some(row.get("created_at")),
+    }
+}
+
+impl PermissionTrait for PostGreClient {
+    async fn get_permissions(&self) -> Result<Vec<Permission>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query("SELECT * FROM permissions ORDER BY name", &[])
+            .await?;
+        Ok(rows.iter().map(permission_from_row).collect())
+    }
+
+    async fn create_permission(&self, name: &str, description: &str) -> Result<Permission, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_one(
+                "INSERT INTO permissions (name, description) VALUES ($1, $2) RETURNING *",
+                &[&name, &description],
+            )
+            .await?;
+        Ok(permission_from_row(&row))
+    }
+
+    async fn delete_permission(&self, name: &str) -> Result<(), Error> {
+        let deleted = self
+            .connection()
+            .await?
+            .execute("DELETE FROM permissions WHERE name = $1", &[&name])
+            .await?;
+        match deleted {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_role_permissions(&self, role: &Role) -> Result<Vec<String>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT permission FROM role_permissions WHERE role = $1 ORDER BY permission",
+                &[role],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn grant_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        self.connection()
+            .await?
+            .execute(
+                "INSERT INTO role_permissions (role, permission) VALUES ($1, $2)",
+                &[role, &permission],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_permission(&self, role: &Role, permission: &str) -> Result<(), Error> {
+        let deleted = self
+            .connection()
+            .await?
+            .execute(
+                "DELETE FROM role_permissions WHERE role = $1 AND permission = $2",
+                &[role, &permission],
+            )
+            .await?;
+        match deleted {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_user_permissions(&self, user_uid: &str) -> Result<Vec<String>, Error> {
+        // held roles and their ancestors through the catalogue
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "WITH RECURSIVE implied(name) AS (
+                    SELECT role FROM roles WHERE firebase_uid = $1
+                    UNION
+                    SELECT role_definitions.parent FROM role_definitions
+                    JOIN implied ON role_definitions.name = implied.name
+                    WHERE role_definitions.parent IS NOT NULL
+                )
+                SELECT DISTINCT role_permissions.permission FROM role_permissions
+                JOIN implied ON role_permissions.role = implied.name
+                ORDER BY role_permissions.permission",
+                &[&user_uid],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::permission::{DEFAULT_PERMISSIONS, DEFAULT_ROLE_PERMISSIONS};
+    use crate::utils::Utils;
+
+    #[tokio::test]
+    async fn test_migration_seeds_the_defaults() {
+        let client = Utils::generate_testing_database().await.unwrap();
+        let names: Vec<String> = client
+            .get_permissions()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        let mut expected: Vec<String> = DEFAULT_PERMISSIONS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        for role in Role::BUILT_IN {
+            let mut expected: Vec<String> = DEFAULT_ROLE_PERMISSIONS
+                .iter()
+                .filter(|(granted, _)| *granted == role)
+                .map(|(_, name)| name.to_string())
+                .collect();
+            expected.sort();
+            assert_eq!(client.get_role_permissions(&role).await.unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use bb8::{ErrorSink, ManageConnection};
+use tokio_postgres::{Client, Config, Error};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+/*
+    * Pool settings, see postgres.pool_* in the configuration
+    Reconnecting is done by the pool itself: broken connections are dropped on return,
+    and new ones are retried with an exponential backoff bounded by the checkout timeout.
+*/
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub min_size: u32,
+    pub max_size: u32,
+    pub checkout_timeout: Duration,
+    pub health_check: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            min_size: 1,
+            max_size: 10,
+            checkout_timeout: Duration::from_secs(10),
+            health_check: true,
+        }
+    }
+}
+
+pub struct PostgresManager {
+    config: Config,
+    tls: MakeRustlsConnect,
+}
+
+impl PostgresManager {
+    pub fn new(config: Config, tls: MakeRustlsConnect) -> PostgresManager {
+        PostgresManager { config, tls }
+    }
+}
+
+impl ManageConnection for PostgresManager {
+    type Connection = Client;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<Client, Error> {
+        let (client, connection) = self.config.connect(self.tls.clone()).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!("connection error: {}", e);
+            }
+        });
+        Ok(client)
+    }
+
+    async fn is_valid(&self, client: &mut Client) -> Result<(), Error> {
+        client.simple_query("").await.map(|_| ())
+    }
+
+    fn has_broken(&self, client: &mut Client) -> bool {
+        client.is_closed()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogErrorSink;
+
+impl ErrorSink<Error> for LogErrorSink {
+    fn sink(&self, e: Error) {
+        eprintln!("connection error: {}", e);
+    }
+
+    fn boxed_clone(&self) -> Box<dyn ErrorSink<Error>> {
+        Box::new(*self)
+    }
+}
@@ -0,0 +1,138 @@
+use super::error::DatabaseError as Error;
+use super::main::PostGreClient;
+use crate::structs::role::{Role, RoleDefinition};
+use crate::traits::role::RoleTrait;
+use tokio_postgres::Row;
+
+pub(super) fn role_definition_from_row(row: &Row) -> RoleDefinition {
+    RoleDefinition {
+        name: row.get("name"),
+        description: row.get("description"),
+        parent: row.get("parent"),
+        built_in: row.get("built_in"),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+impl RoleTrait for PostGreClient {
+    async fn get_role_definitions(&self) -> Result<Vec<RoleDefinition>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query("SELECT * FROM role_definitions ORDER BY name", &[])
+            .await?;
+        Ok(rows.iter().map(role_definition_from_row).collect())
+    }
+
+    async fn create_role(
+        &self,
+        name: &Role,
+        description: &str,
+        parent: Option<&Role>,
+    ) -> Result<RoleDefinition, Error> {
+        let row = self
+            .connection()
+            .await?
+            .query_one(
+                "INSERT INTO role_definitions (name, description, parent) VALUES ($1, $2, $3) RETURNING *",
+                &[name, &description, &parent],
+            )
+            .await?;
+        Ok(role_definition_from_row(&row))
+    }
+
+    async fn rename_role(&self, name: &Role, new_name: &Role) -> Result<RoleDefinition, Error> {
+        if name.is_built_in() {
+            return Err(Error::BuiltInRole(name.to_string()));
+        }
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        // assignments, permissions and children follow through on update cascade
+        let row = transaction
+            .query_opt(
+                "UPDATE role_definitions SET name = $2, updated_at = now() WHERE name = $1 AND NOT built_in RETURNING *",
+                &[name, new_name],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        // the claims of the holders name the old role
+        transaction
+            .execute(
+                "UPDATE users SET claims_version = claims_version + 1 WHERE id IN (SELECT firebase_uid FROM roles WHERE role = $1)",
+                &[new_name],
+            )
+            .await?;
+        // scopes of api keys aren't foreign keys
+        transaction
+            .execute(
+                "UPDATE api_keys SET scopes = array_replace(scopes, $1, $2) WHERE $1 = ANY(scopes)",
+                &[name, new_name],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(role_definition_from_row(&row))
+    }
+
+    async fn delete_role(&self, name: &Role) -> Result<(), Error> {
+        if name.is_built_in() {
+            return Err(Error::BuiltInRole(name.to_string()));
+        }
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        // children are locked so none is added under the role while it goes away
+        let children = transaction
+            .query(
+                "SELECT name FROM role_definitions WHERE parent = $1 FOR UPDATE",
+                &[name],
+            )
+            .await?;
+        if !children.is_empty() {
+            return Err(Error::RoleInUse(name.to_string()));
+        }
+        transaction
+            .execute(
+                "UPDATE users SET claims_version = claims_version + 1 WHERE id IN (SELECT firebase_uid FROM roles WHERE role = $1)",
+                &[name],
+            )
+            .await?;
+        transaction
+            .execute(
+                "UPDATE api_keys SET scopes = array_remove(scopes, $1) WHERE $1 = ANY(scopes)",
+                &[name],
+            )
+            .await?;
+        let deleted = transaction
+            .execute(
+                "DELETE FROM role_definitions WHERE name = $1 AND NOT built_in",
+                &[name],
+            )
+            .await?;
+        transaction.commit().await?;
+        match deleted {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+
+    #[tokio::test]
+    async fn test_migration_seeds_the_built_in_roles() {
+        let client = Utils::generate_testing_database().await.unwrap();
+        let definitions = client.get_role_definitions().await.unwrap();
+        let mut expected = RoleDefinition::defaults();
+        expected.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(definitions.len(), expected.len());
+        for (definition, expected) in definitions.iter().zip(expected) {
+            assert_eq!(definition.name, expected.name);
+            assert_eq!(definition.description, expected.description);
+            assert_eq!(definition.parent, expected.parent);
+            assert!(definition.built_in);
+        }
+    }
+}
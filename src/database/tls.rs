@@ -0,0 +1,395 @@
+use std::{fmt, fs::File, io::BufReader, path::PathBuf, str::FromStr, sync::Arc};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use tokio_postgres::config::SslMode as PostgresSslMode;
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+/*
+    * Same meaning as libpq's sslmode
+    prefer and require encrypt the connection without checking the server certificate,
+    verify-full checks the chain against the CA bundle and the host name.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SslMode {
+    Disable,
+    #[default]
+    Prefer,
+    Require,
+    VerifyFull,
+}
+
+impl FromStr for SslMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(SslMode::Disable),
+            "prefer" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            _ => Err(format!(
+                "unknown sslmode {}, expected one of disable, prefer, require, verify-full",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    pub mode: SslMode,
+    pub root_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(PathBuf, std::io::Error),
+    Rustls(rustls::Error),
+    MissingRootCert,
+    MissingClientKey,
+    NoPrivateKey(PathBuf),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            TlsError::Rustls(e) => write!(f, "invalid tls configuration: {}", e),
+            TlsError::MissingRootCert => {
+                write!(f, "sslmode verify-full needs postgres.sslrootcert")
+            }
+            TlsError::MissingClientKey => {
+                write!(
+                    f,
+                    "postgres.sslcert and postgres.sslkey must be set together"
+                )
+            }
+            TlsError::NoPrivateKey(path) => write!(f, "no private key in {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rustls(e)
+    }
+}
+
+impl TlsConfig {
+    pub fn postgres_ssl_mode(&self) -> PostgresSslMode {
+        match self.mode {
+            SslMode::Disable => PostgresSslMode::Disable,
+            SslMode::Prefer => PostgresSslMode::Prefer,
+            SslMode::Require | SslMode::VerifyFull => PostgresSslMode::Require,
+        }
+    }
+
+    /*
+        * Build the rustls connector handed to tokio_postgres
+        @return Result<MakeRustlsConnect, TlsError>
+    */
+    pub fn connector(&self) -> Result<MakeRustlsConnect, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match self.mode {
+            SslMode::VerifyFull => {
+                let path = self.root_cert.as_ref().ok_or(TlsError::MissingRootCert)?;
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(path)? {
+                    roots.add(cert)?;
+                }
+                builder.with_root_certificates(roots)
+            }
+            _ => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoCertificateVerification(provider))),
+        };
+
+        let config = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                builder.with_client_auth_cert(read_certs(cert)?, read_private_key(key)?)?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err(TlsError::MissingClientKey),
+        };
+        Ok(MakeRustlsConnect::new(config))
+    }
+}
+
+fn read_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Io(path.clone(), e))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Io(path.clone(), e))
+}
+
+fn read_private_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Io(path.clone(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Io(path.clone(), e))?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.clone()))
+}
+
+/*
+    * Verifier used by prefer and require, the handshake signatures are still checked
+    but the certificate itself is trusted blindly
+*/
+#[derive(Debug)]
+struct NoCertificateVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::main::PostgresConfig,
+        database::{error::DatabaseError, main::PostGreClient},
+        utils::Utils,
+    };
+    use std::{process::Command, time::Duration};
+
+    #[test]
+    fn test_parse_ssl_mode() {
+        assert_eq!("disable".parse(), Ok(SslMode::Disable));
+        assert_eq!("prefer".parse(), Ok(SslMode::Prefer));
+        assert_eq!("require".parse(), Ok(SslMode::Require));
+        assert_eq!("verify-full".parse(), Ok(SslMode::VerifyFull));
+        assert!("verify-ca".parse::<SslMode>().is_err());
+    }
+
+    #[test]
+    fn test_verify_full_needs_root_cert() {
+        let config = TlsConfig {
+            mode: SslMode::VerifyFull,
+            ..Default::default()
+        };
+        assert!(matches!(config.connector(), Err(TlsError::MissingRootCert)));
+    }
+
+    // a postgres cluster started with ssl = on for a single test, stopped and removed on drop
+    struct SslPostgres {
+        dir: PathBuf,
+        port: u16,
+        as_postgres: bool,
+    }
+
+    impl SslPostgres {
+        /*
+            * Issue a CA and a certificate it signs for localhost, then start postgres with them
+            Postgres refuses to run as root, it is then run as the postgres user.
+            @return SslPostgres
+        */
+        fn start() -> SslPostgres {
+            use std::os::unix::fs::MetadataExt;
+
+            let dir = std::env::temp_dir().join(format!("ssl-postgres-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let as_postgres = std::fs::metadata("/proc/self").unwrap().uid() == 0;
+            let postgres = SslPostgres {
+                dir,
+                port,
+                as_postgres,
+            };
+
+            for ca in ["ca", "other-ca"] {
+                postgres.openssl(&format!(
+                    "req -x509 -newkey rsa:2048 -nodes -days 1 -keyout {0}.key -out {0}.crt -subj /CN={0}",
+                    ca
+                ));
+            }
+            postgres.openssl(
+                "req -newkey rsa:2048 -nodes -keyout server.key -out server.csr -subj /CN=localhost",
+            );
+            std::fs::write(
+                postgres.path("server.ext"),
+                "subjectAltName = DNS:localhost, IP:127.0.0.1\nextendedKeyUsage = serverAuth\n",
+            )
+            .unwrap();
+            postgres.openssl(
+                "x509 -req -in server.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 1 -extfile server.ext -out server.crt",
+            );
+
+            if as_postgres {
+                run(Command::new("chown")
+                    .args(["-R", "postgres"])
+                    .arg(&postgres.dir));
+            }
+            let data = postgres.path("data");
+            run(postgres
+                .pg_command("initdb")
+                .args(["-U", "postgres", "--auth=trust", "-D"])
+                .arg(&data));
+            run(postgres.pg_command("pg_ctl").arg("-D").arg(&data).args([
+                "-w",
+                "-l",
+                postgres.path("postgres.log").to_str().unwrap(),
+                "-o",
+                &format!(
+                    "-p {} -k {} -c listen_addresses=localhost -c ssl=on -c ssl_cert_file={} -c ssl_key_file={}",
+                    port,
+                    postgres.dir.display(),
+                    postgres.path("server.crt").display(),
+                    postgres.path("server.key").display()
+                ),
+                "start",
+            ]));
+            postgres
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.join(name)
+        }
+
+        fn openssl(&self, args: &str) {
+            run(Command::new("openssl")
+                .args(args.split_whitespace())
+                .current_dir(&self.dir));
+        }
+
+        fn pg_command(&self, program: &str) -> Command {
+            if self.as_postgres {
+                let mut command = Command::new("runuser");
+                command.args(["-u", "postgres", "--", program]);
+                command
+            } else {
+                Command::new(program)
+            }
+        }
+
+        /*
+            * Configuration connecting to this cluster
+            @param mode: SslMode
+            @param root_cert: Option<&str>, file name of the CA bundle
+            @return PostgresConfig
+        */
+        fn config(&self, mode: SslMode, root_cert: Option<&str>) -> PostgresConfig {
+            let mut config = Utils::postgres_config();
+            config.host = "localhost".to_string();
+            config.port = self.port;
+            config.user = "postgres".to_string();
+            config.database = "postgres".to_string();
+            config.pool.checkout_timeout = Duration::from_secs(3);
+            config.tls = TlsConfig {
+                mode,
+                root_cert: root_cert.map(|name| self.path(name)),
+                ..Default::default()
+            };
+            config
+        }
+    }
+
+    impl Drop for SslPostgres {
+        fn drop(&mut self) {
+            let _ = self
+                .pg_command("pg_ctl")
+                .arg("-D")
+                .arg(self.path("data"))
+                .args(["-m", "immediate", "stop"])
+                .output();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn run(command: &mut Command) {
+        let output = command.output().unwrap();
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    async fn ssl_in_use(config: &PostgresConfig) -> Result<bool, DatabaseError> {
+        let client = PostGreClient::new(config).await;
+        let row = client
+            .connection()
+            .await?
+            .query_one(
+                "SELECT ssl FROM pg_stat_ssl WHERE pid = pg_backend_pid()",
+                &[],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_self_signed_certificate() {
+        let postgres = SslPostgres::start();
+
+        assert!(ssl_in_use(&postgres.config(SslMode::Require, None))
+            .await
+            .unwrap());
+        assert!(
+            ssl_in_use(&postgres.config(SslMode::VerifyFull, Some("ca.crt")))
+                .await
+                .unwrap()
+        );
+        // signed by another CA, the handshake fails and the pool gives up
+        assert!(
+            ssl_in_use(&postgres.config(SslMode::VerifyFull, Some("other-ca.crt")))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,13 +1,84 @@
+use super::error::DatabaseError as Error;
 use super::main::PostGreClient;
-use crate::enums::role::Role;
-use crate::structs::user::User;
+use super::organization::ensure_other_admin;
+use super::role::role_definition_from_row;
+use crate::structs::claims::{Plan, UserClaims};
+use crate::structs::role::{Role, RoleCatalogue, RoleDefinition};
+use crate::structs::user::{hash_password, User};
 use crate::traits::user::UserTrait;
-use tokio_postgres::Error;
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Row, Transaction};
+
+fn user_from_row(row: &Row) -> User {
+    User {
+        id: row.get(0),
+        name: row.get(1),
+        email: row.get(2),
+        password: row.get(3),
+        plan: row.get("plan"),
+        email_verified: row.get("email_verified"),
+        disabled: row.get("disabled"),
+        deleted_at: row.get("deleted_at"),
+        status_reason: row.get("status_reason"),
+        created_at: Some(row.get(4)),
+        updated_at: Some(row.get(5)),
+    }
+}
+
+/*
+    * Fail with LastAdmin when user_uid holds a role that implies Admin and no other active user does,
+    suspended and soft deleted Admins can't act as one
+    The rows of those roles stay locked until commit, two revocations can't both see another Admin left.
+    @param role: Option<&Role>, the role taken from the user, None when the user is deleted
+*/
+async fn ensure_other_global_admin(
+    transaction: &Transaction<'_>,
+    user_uid: &str,
+    role: Option<&Role>,
+) -> Result<(), Error> {
+    let definitions: Vec<RoleDefinition> = transaction
+        .query("SELECT * FROM role_definitions", &[])
+        .await?
+        .iter()
+        .map(role_definition_from_row)
+        .collect();
+    let names: Vec<Role> = definitions.iter().map(|d| d.name.clone()).collect();
+    let catalogue = RoleCatalogue::new(definitions);
+    let admin_roles: Vec<&str> = names
+        .iter()
+        .filter(|name| catalogue.implies(name, &Role::ADMIN))
+        .map(Role::name)
+        .collect();
+    if role.is_some_and(|role| !admin_roles.contains(&role.name())) {
+        return Ok(());
+    }
+    let admins = transaction
+        .query(
+            "SELECT roles.firebase_uid, roles.role, NOT users.disabled AND users.deleted_at IS NULL AS active
+            FROM roles JOIN users ON users.id = roles.firebase_uid WHERE roles.role = ANY($1)
+            FOR UPDATE OF roles",
+            &[&admin_roles],
+        )
+        .await?;
+    let removed = |row: &Row| {
+        row.get::<_, &str>("firebase_uid") == user_uid
+            && role.is_none_or(|role| role.name() == row.get::<_, &str>("role"))
+    };
+    if admins.iter().any(removed)
+        && !admins
+            .iter()
+            .any(|row| !removed(row) && row.get::<_, bool>("active"))
+    {
+        return Err(Error::LastAdmin);
+    }
+    Ok(())
+}
 
 impl UserTrait for PostGreClient {
-    async fn get_user_roles<'a>(&self, user_uid: &'a str) -> Result<Vec<Role>, Error> {
+    async fn get_user_roles(&self, user_uid: &str) -> Result<Vec<Role>, Error> {
         let rows = self
-            .client
+            .connection()
+            .await?
             .query(
                 "SELECT role FROM roles WHERE firebase_uid = $1",
                 &[&user_uid],
@@ -20,124 +91,258 @@ impl UserTrait for PostGreClient {
         Ok(roles)
     }
 
-    async fn save_user_role<'a>(&self, user_uid: &'a str, role: &'a Role) -> Result<(), Error> {
-        self.client
+    async fn save_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        self.connection()
+            .await?
             .execute(
-                "INSERT INTO roles (firebase_uid, role) VALUES ($1, $2)",
+                "WITH inserted AS (INSERT INTO roles (firebase_uid, role) VALUES ($1, $2) RETURNING firebase_uid)
+                UPDATE users SET claims_version = claims_version + 1 WHERE id IN (SELECT firebase_uid FROM inserted)",
                 &[&user_uid, &role],
             )
             .await?;
         Ok(())
     }
 
-    async fn create_user<'a>(&self, user: &'a User) -> Result<User, Error> {
-        let password = bcrypt::hash(&user.password, 12).unwrap();
-        let query = self
-            .client
+    async fn remove_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        ensure_other_global_admin(&transaction, user_uid, Some(role)).await?;
+        let removed = transaction
+            .execute(
+                "DELETE FROM roles WHERE firebase_uid = $1 AND role = $2",
+                &[&user_uid, role],
+            )
+            .await?;
+        transaction
+            .execute(
+                "UPDATE users SET claims_version = claims_version + 1 WHERE id = $1",
+                &[&user_uid],
+            )
+            .await?;
+        transaction.commit().await?;
+        match removed {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_users_by_role(&self, role: &Role) -> Result<Vec<User>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT users.* FROM users JOIN roles ON roles.firebase_uid = users.id WHERE roles.role = $1 ORDER BY users.created_at, users.id",
+                &[role],
+            )
+            .await?;
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    async fn create_user(&self, user: &User) -> Result<User, Error> {
+        let uid = user
+            .id
+            .as_deref()
+            .ok_or_else(|| Error::Invalid("a user id is required".to_string()))?;
+        let password = hash_password(&user.password)?;
+        let now = chrono::Utc::now();
+        let mut client = self.connection().await?;
+        // a user without its User role would be signed up half way, rolled back on drop
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                "INSERT INTO users (id, name, email, password, created_at, updated_at, plan) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[&uid, &user.name, &user.email, &password, &user.created_at.unwrap_or(now), &user.updated_at.unwrap_or(now), &user.plan],
+            )
+            .await?;
+        transaction
+            .execute(
+                "INSERT INTO roles (firebase_uid, role) VALUES ($1, $2)",
+                &[&uid, &Role::USER],
+            )
+            .await?;
+        let query = transaction
             .query_one(
-                "INSERT INTO users (id, name, email, password, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
-                &[&user.id, &user.name, &user.email, &password, &user.created_at.unwrap(), &user.updated_at.unwrap()],
+                "UPDATE users SET claims_version = claims_version + 1 WHERE id = $1 RETURNING *",
+                &[&uid],
             )
             .await?;
-        let role = Role::User;
-        self.save_user_role(&user.clone().id.unwrap(), &role)
+        transaction.commit().await?;
+        Ok(user_from_row(&query))
+    }
+
+    async fn delete_user(&self, user_uid: &str) -> Result<(), Error> {
+        let mut client = self.connection().await?;
+        let transaction = client.transaction().await?;
+        let email: String = transaction
+            .query_opt(
+                "SELECT email FROM users WHERE id = $1 FOR UPDATE",
+                &[&user_uid],
+            )
+            .await?
+            .ok_or(Error::NotFound)?
+            .get("email");
+        ensure_other_global_admin(&transaction, user_uid, None).await?;
+        let organizations = transaction
+            .query(
+                "SELECT organization_id FROM organization_members WHERE user_id = $1 AND role = 'Admin'",
+                &[&user_uid],
+            )
             .await?;
-        let user = User {
-            id: query.get(0),
-            name: query.get(1),
-            email: query.get(2),
-            password: query.get(3),
-            created_at: Some(query.get(4)),
-            updated_at: Some(query.get(5)),
-        };
-        Ok(user)
+        for organization in organizations {
+            ensure_other_admin(&transaction, &organization.get("organization_id"), user_uid)
+                .await?;
+        }
+        // the foreign keys only set the invitations accepted by the user to null, they carry its email
+        transaction
+            .execute(
+                "DELETE FROM invitations WHERE accepted_by = $1 OR lower(email) = lower($2)",
+                &[&user_uid, &email],
+            )
+            .await?;
+        transaction
+            .execute("DELETE FROM users WHERE id = $1", &[&user_uid])
+            .await?;
+        transaction.commit().await?;
+        Ok(())
     }
 
-    async fn update_user_name<'a>(
-        &self,
-        user_name: &'a str,
-        user_uid: &'a str,
-    ) -> Result<User, Error> {
+    async fn update_user_name(&self, user_name: &str, user_uid: &str) -> Result<User, Error> {
         let query = self
-            .client
-            .query_one(
+            .connection()
+            .await?
+            .query_opt(
                 "UPDATE users SET name = $1 WHERE id = $2 RETURNING *",
                 &[&user_name, &user_uid],
             )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(user_from_row(&query))
+    }
+
+    async fn update_user_password(&self, user_uid: &str, password: &str) -> Result<(), Error> {
+        let password = hash_password(password)?;
+        let updated = self
+            .connection()
+            .await?
+            .execute(
+                "UPDATE users SET password = $2, updated_at = now() WHERE id = $1",
+                &[&user_uid, &password],
+            )
             .await?;
-        let user = User {
-            id: query.get(0),
-            name: query.get(1),
-            email: query.get(2),
-            password: query.get(3),
-            created_at: Some(query.get(4)),
-            updated_at: Some(query.get(5)),
-        };
-        Ok(user)
+        if updated == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
     }
 
-    async fn get_user<'a>(&self, user_uid: &'a str) -> Result<User, Error> {
+    async fn update_user_email(&self, user_uid: &str, email: &str) -> Result<User, Error> {
         let query = self
-            .client
-            .query_one("SELECT * FROM users WHERE id = $1", &[&user_uid])
-            .await?;
-        let user = User {
-            id: query.get(0),
-            name: query.get(1),
-            email: query.get(2),
-            password: query.get(3),
-            created_at: Some(query.get(4)),
-            updated_at: Some(query.get(5)),
-        };
-        Ok(user)
+            .connection()
+            .await?
+            .query_opt(
+                "UPDATE users SET email = $2, email_verified = false, updated_at = now() WHERE id = $1 RETURNING *",
+                &[&user_uid, &email],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(user_from_row(&query))
     }
 
-    #[cfg(test)]
-    async fn crate_random_user<'a>(&self) -> Result<User, Error> {
-        use chrono::Utc;
+    async fn get_user(&self, user_uid: &str) -> Result<User, Error> {
+        let query = self
+            .connection()
+            .await?
+            .query_opt("SELECT * FROM users WHERE id = $1", &[&user_uid])
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(user_from_row(&query))
+    }
 
-        let random_string = uuid::Uuid::new_v4().to_string();
-        let email = format!("{}@gmail.com", random_string);
-        let user = User {
-            id: Some(random_string.clone()),
-            name: "test".to_string(),
-            email,
-            password: random_string.clone(),
-            created_at: Some(Utc::now()),
-            updated_at: Some(Utc::now()),
-        };
-        self.create_user(&user).await
+    async fn update_user_plan(&self, user_uid: &str, plan: Plan) -> Result<User, Error> {
+        let query = self
+            .connection()
+            .await?
+            .query_opt(
+                "UPDATE users SET plan = $2, claims_version = claims_version + 1, updated_at = now() WHERE id = $1 RETURNING *",
+                &[&user_uid, &plan],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(user_from_row(&query))
     }
 
-    #[cfg(test)]
-    async fn create_test_user<'a>(&self, uuid: &'a str) -> Result<User, Error> {
-        use chrono::Utc;
-        let email = format!("{}@gmail.com", uuid);
-        let user = User {
-            id: Some(uuid.to_string()),
-            name: "test".to_string(),
-            email,
-            password: uuid.to_string(),
-            created_at: Some(Utc::now()),
-            updated_at: Some(Utc::now()),
-        };
-        self.create_user(&user).await
+    async fn update_user_status(
+        &self,
+        user_uid: &str,
+        disabled: bool,
+        deleted_at: Option<DateTime<Utc>>,
+        reason: Option<&str>,
+    ) -> Result<User, Error> {
+        let query = self
+            .connection()
+            .await?
+            .query_opt(
+                "UPDATE users SET disabled = $2, deleted_at = $3, status_reason = $4, updated_at = now() WHERE id = $1 RETURNING *",
+                &[&user_uid, &disabled, &deleted_at, &reason],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(user_from_row(&query))
+    }
+
+    async fn get_users_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<User>, Error> {
+        let rows = self
+            .connection()
+            .await?
+            .query(
+                "SELECT * FROM users WHERE deleted_at < $1 ORDER BY deleted_at, id",
+                &[&before],
+            )
+            .await?;
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    async fn get_user_claims(&self, user_uid: &str) -> Result<UserClaims, Error> {
+        // the version is read first, roles changed in between make the claims look stale, never fresh
+        let row = self
+            .connection()
+            .await?
+            .query_opt(
+                "SELECT plan, claims_version FROM users WHERE id = $1",
+                &[&user_uid],
+            )
+            .await?
+            .ok_or(Error::NotFound)?;
+        Ok(UserClaims {
+            roles: self.get_user_roles(user_uid).await?,
+            plan: row.get("plan"),
+            claims_version: row.get("claims_version"),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::enums::role::Role;
+    use crate::database::conformance;
+    use crate::structs::role::Role;
     use crate::structs::user::User;
     use crate::traits::user::UserTrait;
+    use crate::utils::Utils;
     use chrono::Utc;
 
+    #[tokio::test]
+    async fn test_conformance() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        conformance::run(&client).await;
+    }
+
     #[tokio::test]
     async fn test_create_user() {
-        let mut _client = PostGreClient::new().await;
-        let _db_dropeed = _client.drop_tables().await.unwrap();
-        let _db_created = _client.create_tables_if_not_exist().await.unwrap();
+        let _client = PostGreClient::new(&Utils::postgres_config()).await;
+        _client.drop_tables().await.unwrap();
+        _client.migrate_up(None).await.unwrap();
         let random_string = uuid::Uuid::new_v4().to_string();
         let email = format!("{}@gmail.com", random_string);
         let user = User {
@@ -147,6 +352,11 @@ mod tests {
             created_at: Some(Utc::now()),
             updated_at: Some(Utc::now()),
             password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
         };
         let user_created = _client.create_user(&user).await.unwrap();
         assert_eq!(user.name, user.name);
@@ -163,9 +373,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut _client = PostGreClient::new().await;
-        let _db_dropeed = _client.drop_tables().await.unwrap();
-        let _db_created = _client.create_tables_if_not_exist().await.unwrap();
+        let _client = PostGreClient::new(&Utils::postgres_config()).await;
+        _client.drop_tables().await.unwrap();
+        _client.migrate_up(None).await.unwrap();
         let user = _client.crate_random_user().await.unwrap();
         let user_getted = _client.get_user(&user.id.unwrap()).await.unwrap();
         assert_eq!(user.name, user_getted.name);
@@ -182,25 +392,62 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_user_roles() {
-        let mut _client = PostGreClient::new().await;
-        let _db_dropeed = _client.drop_tables().await.unwrap();
-        let _db_created = _client.create_tables_if_not_exist().await.unwrap();
+        let _client = PostGreClient::new(&Utils::postgres_config()).await;
+        _client.drop_tables().await.unwrap();
+        _client.migrate_up(None).await.unwrap();
         let user = _client.crate_random_user().await.unwrap();
         let roles = _client.get_user_roles(&user.id.unwrap()).await.unwrap();
         assert_eq!(roles.len(), 1);
-        assert_eq!(roles[0], Role::User);
+        assert_eq!(roles[0], Role::USER);
     }
 
     #[tokio::test]
     async fn test_update_user_name() {
-        let mut _client = PostGreClient::new().await;
-        let _db_dropeed = _client.drop_tables().await.unwrap();
-        let _db_created = _client.create_tables_if_not_exist().await.unwrap();
+        let _client = PostGreClient::new(&Utils::postgres_config()).await;
+        _client.drop_tables().await.unwrap();
+        _client.migrate_up(None).await.unwrap();
         let user = _client.crate_random_user().await.unwrap();
         let user = _client
-            .update_user_name(&"new name", &user.id.unwrap())
+            .update_user_name("new name", &user.id.unwrap())
             .await
             .unwrap();
         assert_eq!(user.name, "new name");
     }
+
+    #[tokio::test]
+    async fn test_create_user_is_atomic() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        let uid = uuid::Uuid::new_v4();
+        // the User role of this uid only can't be stored
+        let function = format!("fail_role_{}", uid.simple());
+        client
+            .connection()
+            .await
+            .unwrap()
+            .batch_execute(&format!(
+                "CREATE FUNCTION {function}() RETURNS trigger LANGUAGE plpgsql AS $$ BEGIN RAISE EXCEPTION 'role refused'; END $$;
+                CREATE TRIGGER {function} BEFORE INSERT ON roles FOR EACH ROW WHEN (NEW.firebase_uid = '{uid}') EXECUTE FUNCTION {function}();"
+            ))
+            .await
+            .unwrap();
+
+        let res = client.create_test_user(&uid.to_string()).await;
+        client
+            .connection()
+            .await
+            .unwrap()
+            .batch_execute(&format!(
+                "DROP TRIGGER {function} ON roles; DROP FUNCTION {function}();"
+            ))
+            .await
+            .unwrap();
+        assert!(res.is_err());
+        // the users row went with the role
+        assert!(matches!(
+            client.get_user(&uid.to_string()).await,
+            Err(Error::NotFound)
+        ));
+        assert!(client.create_test_user(&uid.to_string()).await.is_ok());
+    }
 }
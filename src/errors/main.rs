@@ -0,0 +1,198 @@
+use std::fmt;
+
+use async_graphql::{Error, ErrorExtensions};
+
+use crate::{
+    database::error::DatabaseError,
+    traits::{auth::AuthError, mailer::MailError},
+};
+
+/*
+    * Errors returned to graphql clients
+    Each variant has a stable `extensions.code`, clients match on it rather than on the message.
+    Upstream and Internal carry details which are logged server side and never sent.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum AppError {
+    Unauthorized,
+    InvalidCredentials,
+    // see VerifiedEmailGuard
+    EmailNotVerified,
+    // see UserExistGuard, suspended or soft deleted by an Admin
+    AccountSuspended,
+    AccountDeleted,
+    Forbidden,
+    NotFound,
+    // message shown to the client
+    Validation(String),
+    Conflict(String),
+    // details only logged
+    Upstream(String),
+    Internal(String),
+}
+
+impl AppError {
+    /*
+        * Code sent in the extensions of the graphql error
+        @return &'static str
+    */
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Unauthorized => "UNAUTHENTICATED",
+            AppError::InvalidCredentials => "INVALID_CREDENTIALS",
+            AppError::EmailNotVerified => "EMAIL_NOT_VERIFIED",
+            AppError::AccountSuspended => "ACCOUNT_SUSPENDED",
+            AppError::AccountDeleted => "ACCOUNT_DELETED",
+            AppError::Forbidden => "FORBIDDEN",
+            AppError::NotFound => "NOT_FOUND",
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::Upstream(_) => "UPSTREAM_ERROR",
+            AppError::Internal(_) => "INTERNAL_SERVER_ERROR",
+        }
+    }
+
+    pub fn upstream(e: impl fmt::Display) -> AppError {
+        AppError::Upstream(e.to_string())
+    }
+
+    pub fn internal(e: impl fmt::Display) -> AppError {
+        AppError::Internal(e.to_string())
+    }
+}
+
+// only what a client may see, details of Upstream and Internal are left out
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Unauthorized => write!(f, "Auth::Unauthorized"),
+            AppError::InvalidCredentials => write!(f, "Auth::InvalidCredentials"),
+            AppError::EmailNotVerified => write!(f, "Auth::EmailNotVerified"),
+            AppError::AccountSuspended => write!(f, "Auth::AccountSuspended"),
+            AppError::AccountDeleted => write!(f, "Auth::AccountDeleted"),
+            AppError::Forbidden => write!(f, "Role::Unauthorized"),
+            AppError::NotFound => write!(f, "NotFound"),
+            AppError::Validation(message) => write!(f, "Validation: {}", message),
+            AppError::Conflict(message) => write!(f, "Conflict: {}", message),
+            AppError::Upstream(_) => write!(f, "Upstream::Unavailable"),
+            AppError::Internal(_) => write!(f, "Internal::Error"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ErrorExtensions for AppError {
+    fn extend(&self) -> Error {
+        if let AppError::Upstream(detail) | AppError::Internal(detail) = self {
+            eprintln!("{}: {}", self.code(), detail);
+        }
+        Error::new(self.to_string()).extend_with(|_, e| e.set("code", self.code()))
+    }
+}
+
+impl From<DatabaseError> for AppError {
+    fn from(e: DatabaseError) -> Self {
+        match e {
+            DatabaseError::NotFound => AppError::NotFound,
+            // constraint names are a detail of the schema
+            DatabaseError::Conflict(_) => AppError::Conflict("already exists".to_string()),
+            DatabaseError::Invalid(e) => AppError::Validation(e),
+            DatabaseError::Expired => AppError::Validation(e.to_string()),
+            DatabaseError::Forbidden => AppError::Forbidden,
+            DatabaseError::LastAdmin
+            | DatabaseError::BuiltInRole(_)
+            | DatabaseError::RoleInUse(_) => AppError::Conflict(e.to_string()),
+            e => AppError::internal(e),
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthorized => AppError::Unauthorized,
+            AuthError::InvalidCredentials => AppError::InvalidCredentials,
+            AuthError::NotFound => AppError::NotFound,
+            AuthError::Conflict(email) => AppError::Conflict(format!("{} is already used", email)),
+            AuthError::Upstream(e) => AppError::Upstream(e),
+        }
+    }
+}
+
+impl From<MailError> for AppError {
+    fn from(e: MailError) -> Self {
+        match e {
+            MailError::Invalid(e) => AppError::Validation(e),
+            MailError::Transport(e) => AppError::Upstream(e),
+        }
+    }
+}
+
+/*
+    * Convert the error of a result into a graphql error with its code
+    i.e `database.get_user(uid).await.app_err()?`
+*/
+pub trait AppResultExt<T> {
+    fn app_err(self) -> Result<T, Error>;
+}
+
+impl<T, E: Into<AppError>> AppResultExt<T> for Result<T, E> {
+    fn app_err(self) -> Result<T, Error> {
+        self.map_err(|e| e.into().extend())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::Value;
+
+    fn code(e: &Error) -> Option<Value> {
+        e.extensions.as_ref().and_then(|e| e.get("code")).cloned()
+    }
+
+    #[test]
+    fn test_codes_are_in_extensions() {
+        let errors = [
+            (AppError::Unauthorized, "UNAUTHENTICATED"),
+            (AppError::InvalidCredentials, "INVALID_CREDENTIALS"),
+            (AppError::EmailNotVerified, "EMAIL_NOT_VERIFIED"),
+            (AppError::AccountSuspended, "ACCOUNT_SUSPENDED"),
+            (AppError::AccountDeleted, "ACCOUNT_DELETED"),
+            (AppError::Forbidden, "FORBIDDEN"),
+            (AppError::NotFound, "NOT_FOUND"),
+            (AppError::Validation("bad".to_string()), "VALIDATION_FAILED"),
+            (AppError::Conflict("taken".to_string()), "CONFLICT"),
+            (AppError::upstream("firebase down"), "UPSTREAM_ERROR"),
+            (AppError::internal("boom"), "INTERNAL_SERVER_ERROR"),
+        ];
+        for (error, expected) in errors {
+            let e = error.extend();
+            assert_eq!(e.message, error.to_string());
+            assert_eq!(code(&e), Some(Value::from(expected)));
+        }
+    }
+
+    #[test]
+    fn test_internals_are_not_exposed() {
+        let e = AppError::internal("relation \"users\" does not exist").extend();
+        assert_eq!(e.message, "Internal::Error");
+        let e = AppError::upstream("EMAIL_EXISTS at https://identitytoolkit").extend();
+        assert_eq!(e.message, "Upstream::Unavailable");
+        let e = AppError::from(DatabaseError::Conflict("users_pkey".to_string())).extend();
+        assert!(!e.message.contains("users_pkey"));
+        assert_eq!(
+            AppError::from(DatabaseError::PoolTimedOut).extend().message,
+            "Internal::Error"
+        );
+    }
+
+    #[test]
+    fn test_app_err() {
+        let res: Result<(), AuthError> = Err(AuthError::InvalidCredentials);
+        let e = res.app_err().unwrap_err();
+        assert_eq!(e.message, "Auth::InvalidCredentials");
+        assert_eq!(code(&e), Some(Value::from("INVALID_CREDENTIALS")));
+    }
+}
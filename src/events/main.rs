@@ -0,0 +1,104 @@
+use async_graphql::futures_util::{stream, Stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::structs::{claims::UserClaims, role::Role, user::User};
+
+// events kept for subscribers lagging behind, older ones are dropped
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UserEvent {
+    ProfileChanged(User),
+    RolesChanged { uid: String, roles: Vec<Role> },
+    // pushed to the auth provider, tokens issued before carry stale claims
+    ClaimsChanged { uid: String, claims: UserClaims },
+}
+
+impl UserEvent {
+    pub fn uid(&self) -> Option<&str> {
+        match self {
+            UserEvent::ProfileChanged(user) => user.id.as_deref(),
+            UserEvent::RolesChanged { uid, .. } | UserEvent::ClaimsChanged { uid, .. } => Some(uid),
+        }
+    }
+}
+
+/*
+    * In-process publish/subscribe of user events, feeding the graphql subscriptions
+    Events published by one server instance are only seen by its own subscribers.
+*/
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<UserEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus { sender }
+    }
+
+    /*
+        * Publish an event, a no-op when nobody is subscribed
+        @param event: UserEvent
+    */
+    pub fn publish(&self, event: UserEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /*
+        * Events of one user, published after this call
+        @param uid: &str
+        @return Stream<UserEvent>
+    */
+    pub fn subscribe(&self, uid: &str) -> impl Stream<Item = UserEvent> {
+        let receiver = self.sender.subscribe();
+        stream::unfold(
+            (receiver, uid.to_string()),
+            |(mut receiver, uid)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) if event.uid() == Some(uid.as_str()) => {
+                            return Some((event, (receiver, uid)))
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn test_subscribe_filters_by_uid() {
+        let events = EventBus::new();
+        let mut stream = Box::pin(events.subscribe("me"));
+        events.publish(UserEvent::RolesChanged {
+            uid: "someone else".to_string(),
+            roles: vec![Role::ADMIN],
+        });
+        events.publish(UserEvent::RolesChanged {
+            uid: "me".to_string(),
+            roles: vec![Role::USER],
+        });
+        assert_eq!(
+            stream.next().await,
+            Some(UserEvent::RolesChanged {
+                uid: "me".to_string(),
+                roles: vec![Role::USER]
+            })
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute},
+    to_value, Response,
+};
+
+use crate::contexts::identity::Identity;
+
+/*
+    * Tell clients a response was made while impersonating, so they can show a banner
+    The response gets an `impersonation` extension, i.e
+    {"id": "...", "realUid": "...", "effectiveUid": "...", "readOnly": true, "expiresAt": 1700000000}
+*/
+pub struct ImpersonationNotice;
+
+impl ExtensionFactory for ImpersonationNotice {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ImpersonationNoticeExtension)
+    }
+}
+
+struct ImpersonationNoticeExtension;
+
+#[async_trait::async_trait]
+impl Extension for ImpersonationNoticeExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let response = next.run(ctx, operation_name).await;
+        // the guards verified the identity while executing
+        let claim = ctx
+            .data_opt::<Identity>()
+            .and_then(|identity| identity.impersonation());
+        match claim.and_then(|claim| to_value(claim).ok()) {
+            Some(claim) => response.extension("impersonation", claim),
+            None => response,
+        }
+    }
+}
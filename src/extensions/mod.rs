@@ -0,0 +1,2 @@
+pub mod impersonation;
+pub mod row_security;
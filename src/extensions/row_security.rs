@@ -0,0 +1,352 @@
+use std::{any::TypeId, sync::Arc};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest},
+    Request, ServerResult,
+};
+
+use crate::{contexts::identity::Identity, database::backend::Database};
+
+/*
+    * Hand every request a database scoped to its identity, see Database::scoped
+    The resolvers and guards keep reading ctx.data::<Database>(), which now finds the scoped one
+    first, so postgres only returns the rows the uid and roles of the request may see.
+*/
+pub struct RowSecurity;
+
+impl ExtensionFactory for RowSecurity {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RowSecurityExtension)
+    }
+}
+
+struct RowSecurityExtension;
+
+#[async_trait::async_trait]
+impl Extension for RowSecurityExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        // set per http request, or per websocket connection
+        let identity = request
+            .data
+            .get(&TypeId::of::<Identity>())
+            .and_then(|data| data.downcast_ref::<Identity>())
+            .or_else(|| ctx.data_opt::<Identity>())
+            .cloned()
+            .unwrap_or_default();
+        let request = match ctx.data_opt::<Database>() {
+            Some(database) => {
+                let database = database.scoped(identity.clone());
+                request.data(identity).data(database)
+            }
+            None => request,
+        };
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::error::DatabaseError,
+        database::main::PostGreClient,
+        errors::main::AppResultExt,
+        structs::{
+            claims::Plan, identity::LocalIdentity, invitation::Invitation, role::Role, user::User,
+        },
+        traits::{
+            identity::IdentityTrait, invitation::InvitationTrait, organization::OrganizationTrait,
+            role::RoleTrait, user::UserTrait,
+        },
+        utils::Utils,
+    };
+    use async_graphql::*;
+
+    struct Query;
+
+    // resolvers with the bugs row level security is there to contain
+    #[Object]
+    impl Query {
+        // forgets to filter on the uid of the request
+        async fn user_ids<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<String>> {
+            let Database::Postgres(client) = ctx.data::<Database>()? else {
+                unreachable!()
+            };
+            let rows = client
+                .connection()
+                .await
+                .app_err()?
+                .query("SELECT id FROM users ORDER BY id", &[])
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+            Ok(rows.iter().map(|row| row.get("id")).collect())
+        }
+
+        // trusts its argument without any guard
+        async fn user_name<'ctx>(&self, ctx: &Context<'ctx>, uid: String) -> Result<String> {
+            let user = ctx.data::<Database>()?.get_user(&uid).await.app_err()?;
+            Ok(user.name)
+        }
+
+        // grants whatever it is asked to
+        async fn grant<'ctx>(&self, ctx: &Context<'ctx>, uid: String, role: Role) -> Result<bool> {
+            ctx.data::<Database>()?
+                .save_user_role(&uid, &role)
+                .await
+                .app_err()?;
+            Ok(true)
+        }
+    }
+
+    fn code(response: &Response) -> Option<Value> {
+        response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code").cloned())
+    }
+
+    #[tokio::test]
+    async fn test_resolver_bugs_cannot_reach_other_users() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        let database = Database::Postgres(client);
+        let alice = database.crate_random_user().await.unwrap().id.unwrap();
+        let bob = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .extension(RowSecurity)
+            .finish();
+        let execute =
+            |query: String, identity: Identity| schema.execute(Request::new(query).data(identity));
+
+        let res = execute("{ userIds }".to_string(), Identity::verified(&alice)).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(res.data, value!({"userIds": [alice.clone()]}));
+        let res = execute(
+            format!(r#"{{ userName(uid: "{}") }}"#, bob),
+            Identity::verified(&alice),
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+        let res = execute(
+            format!(r#"{{ userName(uid: "{}") }}"#, alice),
+            Identity::verified(&alice),
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+
+        // nor can a user grant itself more than the base role
+        let res = execute(
+            format!(r#"{{ grant(uid: "{}", role: "Admin") }}"#, alice),
+            Identity::verified(&alice),
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        assert_eq!(
+            database.get_user_roles(&alice).await.unwrap(),
+            vec![Role::USER]
+        );
+
+        // anonymous requests see nothing
+        let res = execute("{ userIds }".to_string(), Identity::default()).await;
+        assert_eq!(res.data, value!({"userIds": []}));
+
+        // Admins, as loaded by RoleGuard or read from the token, see everyone
+        let admin = Identity::verified(&alice);
+        admin.set_roles(vec![Role::USER, Role::ADMIN]);
+        let res = execute("{ userIds }".to_string(), admin).await;
+        let ids = res.data.into_json().unwrap()["userIds"].clone();
+        let ids: Vec<String> = serde_json::from_value(ids).unwrap();
+        assert!(ids.contains(&alice) && ids.contains(&bob));
+    }
+
+    #[tokio::test]
+    async fn test_custom_child_of_admin_is_admin() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        let database = Database::Postgres(client);
+        let alice = database.crate_random_user().await.unwrap().id.unwrap();
+        let bob = database.crate_random_user().await.unwrap().id.unwrap();
+        let owner = Role::parse(&format!("Owner-{}", &alice[..8])).unwrap();
+        database
+            .create_role(&owner, "above Admin", Some(&Role::ADMIN))
+            .await
+            .unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .extension(RowSecurity)
+            .finish();
+
+        // RoleGuard::at_least(Role::ADMIN) lets it through, so do the policies
+        let identity = Identity::verified(&alice);
+        identity.set_roles(vec![Role::USER, owner.clone()]);
+        let res = schema
+            .execute(Request::new("{ userIds }").data(identity))
+            .await;
+        assert_eq!(res.errors.first(), None);
+        let ids = res.data.into_json().unwrap()["userIds"].clone();
+        let ids: Vec<String> = serde_json::from_value(ids).unwrap();
+        assert!(ids.contains(&alice) && ids.contains(&bob));
+
+        database.delete_role(&owner).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_identities_are_for_the_server_only() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        let database = Database::Postgres(client);
+        let alice = database.crate_random_user().await.unwrap().id.unwrap();
+        let bob = uuid::Uuid::new_v4().to_string();
+        database
+            .create_identity(&LocalIdentity {
+                uid: bob.clone(),
+                email: format!("{}@example.com", bob),
+                password: "hash".to_string(),
+                display_name: None,
+                email_verified: true,
+                disabled: false,
+                custom_claims: serde_json::Map::new(),
+                sessions_valid_after: None,
+                created_at: None,
+                updated_at: None,
+            })
+            .await
+            .unwrap();
+
+        let scoped = database.scoped(Identity::verified(&alice));
+        assert_eq!(scoped.get_identity(&bob).await.unwrap(), None);
+        assert_eq!(
+            scoped
+                .get_identity_by_email(&format!("{}@example.com", bob))
+                .await
+                .unwrap(),
+            None
+        );
+        // the server still reads it
+        assert!(database.get_identity(&bob).await.unwrap().is_some());
+        database.delete_identity(&bob).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_organizations_are_for_their_members() {
+        let client = PostGreClient::new(&Utils::postgres_config()).await;
+        client.migrate_up(None).await.unwrap();
+        let database = Database::Postgres(client);
+        let alice = database.crate_random_user().await.unwrap().id.unwrap();
+        let bob = database.crate_random_user().await.unwrap().id.unwrap();
+        let organization = database.create_organization("Acme", &bob).await.unwrap();
+        let alice_scoped = database.scoped(Identity::verified(&alice));
+        let bob_scoped = database.scoped(Identity::verified(&bob));
+
+        assert!(matches!(
+            alice_scoped.get_organization(&organization.id).await,
+            Err(DatabaseError::NotFound)
+        ));
+        assert!(alice_scoped
+            .get_members(&organization.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            bob_scoped.get_organization(&organization.id).await.unwrap(),
+            organization
+        );
+        assert_eq!(
+            bob_scoped
+                .get_members(&organization.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // nor can an outsider invite into it, or read its invitations
+        let invitation = Invitation {
+            id: uuid::Uuid::new_v4(),
+            email: format!("{}@example.com", alice),
+            role: Role::USER,
+            organization_id: Some(organization.id),
+            token_hash: uuid::Uuid::new_v4().to_string(),
+            invited_by: Some(alice.clone()),
+            expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        assert!(alice_scoped.create_invitation(&invitation).await.is_err());
+        let invitation = Invitation {
+            invited_by: Some(bob.clone()),
+            ..invitation
+        };
+        bob_scoped.create_invitation(&invitation).await.unwrap();
+        assert!(alice_scoped
+            .get_open_invitations(Some(&organization.id))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            bob_scoped
+                .get_open_invitations(Some(&organization.id))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        database
+            .delete_organization(&organization.id)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scope_ends_with_the_statement() {
+        let mut config = Utils::postgres_config();
+        config.pool.min_size = 1;
+        config.pool.max_size = 1;
+        let client = PostGreClient::new(&config).await;
+        client.migrate_up(None).await.unwrap();
+        let uid = uuid::Uuid::new_v4().to_string();
+        let scoped = Database::Postgres(client.clone()).scoped(Identity::verified(&uid));
+
+        // a user signs itself up and renames itself
+        let user = User {
+            id: Some(uid.clone()),
+            name: "scoped".to_string(),
+            email: format!("{}@example.com", uid),
+            password: "password".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        };
+        scoped.create_user(&user).await.unwrap();
+        scoped.update_user_name("renamed", &uid).await.unwrap();
+        assert_eq!(scoped.get_user_roles(&uid).await.unwrap(), vec![Role::USER]);
+
+        // the single pooled connection is the server's again
+        let row = client
+            .connection()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT current_user::text AS role, coalesce(current_setting('app.uid', true), '') AS uid",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, String>("role"), config.user);
+        assert_eq!(row.get::<_, String>("uid"), "");
+    }
+}
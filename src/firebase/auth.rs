@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+use super::main::Firebase;
+use crate::{
+    errors::main::AppError,
+    structs::{claims::UserClaims, user::User},
+    traits::auth::{AuthClaims, AuthError, AuthProvider},
+};
+
+#[derive(Serialize)]
+struct SignInPayload<'a> {
+    email: &'a str,
+    password: &'a str,
+    #[serde(rename = "returnSecureToken")]
+    return_secure_token: bool,
+}
+
+#[derive(Deserialize)]
+struct SignInResponse {
+    #[serde(rename = "idToken")]
+    id_token: String,
+}
+
+fn upstream(e: impl std::fmt::Display) -> AuthError {
+    AuthError::Upstream(e.to_string())
+}
+
+// keep the details of the admin sdk errors, AppError only displays what clients may see
+fn from_app_error(e: AppError) -> AuthError {
+    match e {
+        AppError::Unauthorized => AuthError::Unauthorized,
+        AppError::InvalidCredentials => AuthError::InvalidCredentials,
+        AppError::NotFound => AuthError::NotFound,
+        AppError::Conflict(e) => AuthError::Conflict(e),
+        AppError::Upstream(e) | AppError::Internal(e) => AuthError::Upstream(e),
+        e => AuthError::Upstream(e.to_string()),
+    }
+}
+
+impl AuthProvider for Firebase {
+    async fn verify_token(&self, token: &str) -> Result<AuthClaims, AuthError> {
+        let token = self.verify(token).await?;
+        Ok(AuthClaims {
+            uid: token.critical_claims.sub,
+            email: token.all_claims["email"].as_str().map(String::from),
+            email_verified: token.all_claims["email_verified"].as_bool() == Some(true),
+            expires_at: token.critical_claims.exp.unix_timestamp(),
+            claims: token.all_claims.into_iter().collect(),
+        })
+    }
+
+    async fn sign_in(&self, email: &str, password: &str) -> Result<String, AuthError> {
+        let payload = SignInPayload {
+            email,
+            password,
+            return_secure_token: true,
+        };
+        let resp = reqwest::Client::new()
+            .post(self.accounts_url("signInWithPassword"))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&payload).map_err(upstream)?)
+            .send()
+            .await
+            .map_err(upstream)?;
+        // firebase answers 400 for an unknown email as well as a wrong password
+        if resp.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let body = resp
+            .error_for_status()
+            .map_err(upstream)?
+            .text()
+            .await
+            .map_err(upstream)?;
+        let body: SignInResponse = serde_json::from_str(&body).map_err(upstream)?;
+        Ok(body.id_token)
+    }
+
+    async fn create_identity(&self, user: &User) -> Result<String, AuthError> {
+        self.create_user(user).await.map_err(from_app_error)
+    }
+
+    async fn update_identity(&self, uid: &str, user: &User) -> Result<(), AuthError> {
+        self.update_user(uid, user).await.map_err(from_app_error)
+    }
+
+    async fn delete_identity(&self, uid: &str) -> Result<(), AuthError> {
+        self.remove_user(uid).await.map_err(from_app_error)
+    }
+
+    async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AuthError> {
+        Firebase::check_password(self, uid, password)
+            .await
+            .map_err(from_app_error)
+    }
+
+    async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AuthError> {
+        Firebase::change_password(self, uid, new_password)
+            .await
+            .map_err(from_app_error)
+    }
+
+    async fn set_email_verified(&self, uid: &str) -> Result<(), AuthError> {
+        self.update_email_is_verified(uid)
+            .await
+            .map_err(from_app_error)
+    }
+
+    async fn set_disabled(&self, uid: &str, disabled: bool) -> Result<(), AuthError> {
+        self.update_disabled(uid, disabled)
+            .await
+            .map_err(from_app_error)
+    }
+
+    async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AuthError> {
+        Firebase::set_custom_claims(self, uid, claims)
+            .await
+            .map_err(from_app_error)
+    }
+}
@@ -1,16 +1,32 @@
-use crate::structs::user::User;
-use async_graphql::*;
+use crate::{
+    config::main::FirebaseConfig,
+    errors::main::AppError,
+    structs::{claims::UserClaims, user::User},
+    traits::auth::{AuthError, AuthProvider},
+};
+use ::http::uri::Authority;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use gcp_auth::AuthenticationManager;
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use rs_firebase_admin_sdk::{
     auth::{
-        token::TokenVerifier, AttributeOp, FirebaseAuthService, NewUser, UserIdentifiers,
+        token::{jwt::JWToken, TokenVerifier},
+        AttributeOp, Claims as FirebaseClaims, FirebaseAuthService, NewUser, UserIdentifiers,
         UserUpdate,
     },
-    App, CustomServiceAccount, LiveAuthAdmin,
+    credentials::emulator::EmulatorCredentials,
+    App, CustomServiceAccount, EmulatorAuthAdmin, LiveAuthAdmin,
 };
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+
+// how long the revocation state of a user is trusted before asking firebase again
+const REVOCATION_CACHE_TTL: Duration = Duration::from_secs(60);
 
 #[derive(Serialize)]
 struct Payload {
@@ -33,112 +49,188 @@ struct Claims {
     iat: i64,
     exp: i64,
     uid: String,
-    claims: CustomClaims,
+    claims: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct CustomClaims {
-    premium_account: bool,
+enum FirebaseApp {
+    Live(App<AuthenticationManager>),
+    // Firebase Auth emulator, admin calls need no credentials and id tokens are unsigned
+    Emulator {
+        app: App<EmulatorCredentials>,
+        host: Authority,
+        project_id: String,
+    },
+}
+
+/*
+    * Run $body with $client bound to the admin client of the live project or of the emulator,
+    both implement FirebaseAuthService
+*/
+macro_rules! with_auth_admin {
+    ($firebase:expr, $client:ident => $body:expr) => {
+        match &$firebase.app {
+            FirebaseApp::Live(app) => {
+                let $client: LiveAuthAdmin = app.auth();
+                $body
+            }
+            FirebaseApp::Emulator { app, host, .. } => {
+                let $client: EmulatorAuthAdmin = app.auth(host.clone());
+                $body
+            }
+        }
+    };
+}
+
+// what an id token is checked against once its signature is, see Firebase::check_revoked
+#[derive(Clone, Copy, Debug)]
+struct Revocation {
+    // tokensValidAfterTime of the account, in seconds
+    valid_since: Option<i64>,
+    disabled: bool,
+    fetched_at: Instant,
 }
 
 pub struct Firebase {
-    app: App<AuthenticationManager>,
-    verify_custom_url: String,
-    service_account: CustomServiceAccount,
+    app: FirebaseApp,
+    revocations: Mutex<HashMap<String, Revocation>>,
+    api_key: String,
+    // identity toolkit REST api, proxied by the emulator under its own host
+    identity_toolkit_url: String,
+    service_account: Option<CustomServiceAccount>,
+    service_account_email: String,
 }
 
 impl Firebase {
     /*
-        * Create a new Firebase instance
+        * Create a new Firebase instance, backed by the emulator when config.emulator_host is set
+        @param config: &FirebaseConfig, validated when the configuration is loaded
         @return Firebase
     */
-    pub async fn new() -> Firebase {
-        dotenv::dotenv().ok();
-        let key: String =
-            env::var("SERVICE_ACCOUNT").expect("Firebase Service account key must be set");
-        let service_account = CustomServiceAccount::from_json(&key).unwrap();
-        let _service_account = CustomServiceAccount::from_json(&key).unwrap();
-        let app = App::live(service_account.into()).await.unwrap();
-        let api_key = env::var("FIREBASE_API_KEY").expect("Firebase API key must be set");
+    pub async fn new(config: &FirebaseConfig) -> Firebase {
+        let service_account = config
+            .service_account
+            .as_ref()
+            .map(|key| CustomServiceAccount::from_json(key).unwrap());
+        let (app, identity_toolkit_url) = match &config.emulator_host {
+            Some(host) => (
+                FirebaseApp::Emulator {
+                    app: App::emulated(config.project_id.clone()),
+                    host: host.parse().expect("Invalid firebase emulator host"),
+                    project_id: config.project_id.clone(),
+                },
+                format!("http://{}/identitytoolkit.googleapis.com", host),
+            ),
+            None => {
+                let key = config
+                    .service_account
+                    .as_ref()
+                    .expect("A service account is required outside of the emulator");
+                let credentials = CustomServiceAccount::from_json(key).unwrap();
+                (
+                    FirebaseApp::Live(App::live(credentials.into()).await.unwrap()),
+                    "https://identitytoolkit.googleapis.com".to_string(),
+                )
+            }
+        };
         Firebase {
             app,
-            verify_custom_url: format!("https://www.googleapis.com/identitytoolkit/v3/relyingparty/verifyCustomToken?key={}", api_key),
-            service_account:_service_account,
+            revocations: Mutex::new(HashMap::new()),
+            api_key: config.api_key.clone(),
+            identity_toolkit_url,
+            service_account,
+            service_account_email: config.service_account_email.clone(),
         }
     }
 
+    /*
+        * Url of an identity toolkit accounts endpoint
+        @param method: i.e signInWithPassword
+        @return String
+    */
+    pub(crate) fn accounts_url(&self, method: &str) -> String {
+        format!(
+            "{}/v1/accounts:{}?key={}",
+            self.identity_toolkit_url, method, self.api_key
+        )
+    }
+
     /*
         * Create a user
-        @param user: &UserInput
-        @return Result<String, reqwest::Error>
+        @param user: &UserInput, user.id is used as uid
+        @return Result<String, AppError>
     */
-    pub async fn create_user(&self, user: &User) -> Result<String, reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
+    pub async fn create_user(&self, user: &User) -> Result<String, AppError> {
+        let uid = user
+            .id
+            .clone()
+            .ok_or_else(|| AppError::Validation("a user id is required".to_string()))?;
         let new_user = NewUser {
             email: Some(user.email.clone()),
             password: Some(user.password.clone()),
-            uid: Some(user.id.clone().unwrap()),
+            uid: Some(uid),
         };
-        let user = client
+        let user = with_auth_admin!(self, client => client
             .create_user(new_user)
             .await
-            .expect("Error creating user");
+            .map_err(AppError::upstream)?);
         Ok(user.uid)
     }
 
     /*
         * Remove a user
         @param uid: &str
-        @return Result<(), reqwest::Error>
+        @return Result<(), AppError>
     */
-    pub async fn remove_user(&self, uid: &str) -> Result<(), reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
-        client
+    pub async fn remove_user(&self, uid: &str) -> Result<(), AppError> {
+        with_auth_admin!(self, client => client
             .delete_user(uid.to_string())
             .await
-            .expect("Error deleting user");
+            .map_err(AppError::upstream)?);
+        self.forget_revocation(uid);
         Ok(())
     }
 
     /*
         * Remove all users
-        @return Result<(), reqwest::Error>
+        @return Result<(), AppError>
     */
-    pub async fn delete_all_users(&self) -> Result<(), reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
-        let users_list = client
-            .list_users(1000, None)
-            .await
-            .expect("Error listing users");
-        let users = users_list.unwrap().users;
-        for user in users.iter() {
-            client
-                .delete_user(user.uid.clone())
+    pub async fn delete_all_users(&self) -> Result<(), AppError> {
+        with_auth_admin!(self, client => {
+            let users_list = client
+                .list_users(1000, None)
                 .await
-                .expect("Error deleting user");
-        }
+                .map_err(AppError::upstream)?;
+            // None when the project has no user at all
+            let users = users_list.map(|list| list.users).unwrap_or_default();
+            for user in users.iter() {
+                client
+                    .delete_user(user.uid.clone())
+                    .await
+                    .map_err(AppError::upstream)?;
+            }
+        });
+        self.revocations.lock().unwrap().clear();
 
         Ok(())
     }
 
     /*
         * Create a custom token for a user
+        The emulator accepts unsigned tokens, they are used when no service account is configured.
         @param uid: &str
-        @param is_premium_account: bool
-        @return Result<String>
+        @param claims: &UserClaims, copied into the id tokens exchanged for it
+        @return Result<String, AppError>
     */
-    pub async fn create_custom_token(&self, uid: &str, is_premium_account: bool) -> Result<String> {
+    pub async fn create_custom_token(
+        &self,
+        uid: &str,
+        claims: &UserClaims,
+    ) -> Result<String, AppError> {
         let now_seconds = chrono::Utc::now().timestamp();
         let one_hour_from_now = now_seconds + 3600;
 
-        let iss = format!(
-            "{}.iam.gserviceaccount.com",
-            "firebase-adminsdk-yc1ep@exchange-dev-afae1"
-        );
-        let sub = format!(
-            "{}.iam.gserviceaccount.com",
-            "firebase-adminsdk-yc1ep@exchange-dev-afae1"
-        );
+        let iss = self.service_account_email.clone();
+        let sub = self.service_account_email.clone();
 
         let claims = Claims {
             iss,
@@ -147,18 +239,17 @@ impl Firebase {
             iat: now_seconds,
             exp: one_hour_from_now,  // Maximum expiration time is one hour
             uid: uid.to_string(),
-            claims: CustomClaims {
-                premium_account: is_premium_account,
-            }
+            claims: claims.to_map(),
         };
 
-        let key =
-            EncodingKey::from_rsa_pem(self.service_account.private_key_pem().as_bytes()).unwrap();
+        let Some(service_account) = &self.service_account else {
+            return unsigned_token(&claims);
+        };
+        let key = EncodingKey::from_rsa_pem(service_account.private_key_pem().as_bytes())
+            .map_err(AppError::internal)?;
 
         // Encode the token
-        let token =
-            encode(&Header::new(Algorithm::RS256), &claims, &key).expect("Failed to encode token");
-        Ok(token)
+        encode(&Header::new(Algorithm::RS256), &claims, &key).map_err(AppError::internal)
     }
 
     /*
@@ -166,24 +257,43 @@ impl Firebase {
         * update user in firebase
         @param uid: &str
         @param user: &UserInput
-        @return Result<(), reqwest::Error>
+        @return Result<(), AppError>
     */
-    pub async fn update_user(&self, uid: &str, user: &User) -> Result<(), reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
+    pub async fn update_user(&self, uid: &str, user: &User) -> Result<(), AppError> {
         // let phone_formatted = format!("+33{}", user.clone().phone.replace(" ", ""));
         let display_name = AttributeOp::Change(user.name.clone());
-        client
-            .update_user(
-                UserUpdate::builder(uid.to_string())
-                    .email(user.email.clone())
-                    .disabled(false)
-                    .email_verified(false)
-                    .password(user.password.clone())
-                    .display_name(display_name)
-                    .build(),
-            )
+        let update = UserUpdate::builder(uid.to_string())
+            .email(user.email.clone())
+            .email_verified(false)
+            .password(user.password.clone())
+            .display_name(display_name)
+            .build();
+        with_auth_admin!(self, client => client
+            .update_user(update)
+            .await
+            .map_err(AppError::upstream)?);
+        Ok(())
+    }
+
+    /*
+        * Replace the custom claims of a user, id tokens issued afterwards carry them
+        @param uid: &str
+        @param claims: &UserClaims
+        @return Result<(), AppError>
+    */
+    pub async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AppError> {
+        let update = UserUpdate::builder(uid.to_string())
+            .custom_claims(FirebaseClaims::from(
+                claims
+                    .to_map()
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>(),
+            ))
+            .build();
+        with_auth_admin!(self, client => client
+            .update_user(update)
             .await
-            .expect("Failed to update user");
+            .map_err(AppError::upstream)?);
         Ok(())
     }
 
@@ -192,7 +302,7 @@ impl Firebase {
         @param custom_token: custom token to verify
         @return: id token if custom token is valid, error otherwise
     */
-    pub async fn get_id_token(&self, custom_token: &str) -> Result<String, reqwest::Error> {
+    pub async fn get_id_token(&self, custom_token: &str) -> Result<String, AppError> {
         let payload = Payload {
             token: custom_token.to_string(),
             return_secure_token: true,
@@ -200,72 +310,209 @@ impl Firebase {
 
         let client = reqwest::Client::new();
         let resp = client
-            .post(&self.verify_custom_url)
+            .post(self.accounts_url("signInWithCustomToken"))
             .header("Content-Type", "application/json")
-            .body(serde_json::to_string(&payload).unwrap())
+            .body(serde_json::to_string(&payload).map_err(AppError::internal)?)
             .send()
-            .await?
+            .await
+            .map_err(AppError::upstream)?
+            .error_for_status()
+            .map_err(AppError::upstream)?
             .text()
-            .await?
-            .to_string();
+            .await
+            .map_err(AppError::upstream)?;
 
-        let resp_body: RespBody = serde_json::from_str(&resp).unwrap();
+        let resp_body: RespBody = serde_json::from_str(&resp).map_err(AppError::upstream)?;
 
         Ok(resp_body.id_token)
     }
 
+    /*
+        * Verify an id token, the emulator issues unsigned tokens so only their claims are checked
+        Tokens of a disabled or deleted user, or signed in before its tokensValidAfterTime, are revoked.
+        @param id_token: id token to verify
+        @return: JWToken if the token is valid
+    */
+    pub(crate) async fn verify(&self, id_token: &str) -> Result<JWToken, AuthError> {
+        let token = self.verify_signature(id_token).await?;
+        self.check_revoked(&token).await?;
+        Ok(token)
+    }
+
+    /*
+        * Verify the signature and the claims of an id token
+        @param id_token: id token to verify
+        @return: JWToken if the token is valid
+    */
+    async fn verify_signature(&self, id_token: &str) -> Result<JWToken, AuthError> {
+        match &self.app {
+            FirebaseApp::Live(app) => app
+                .id_token_verifier()
+                .await
+                .map_err(|e| AuthError::Upstream(e.to_string()))?
+                .verify_token(id_token)
+                .await
+                .map_err(|_| AuthError::Unauthorized),
+            FirebaseApp::Emulator {
+                app, project_id, ..
+            } => {
+                let token = app
+                    .id_token_verifier()
+                    .verify_token(id_token)
+                    .await
+                    .map_err(|_| AuthError::Unauthorized)?;
+                let claims = &token.critical_claims;
+                if claims.aud != *project_id
+                    || claims.iss != format!("https://securetoken.google.com/{}", project_id)
+                    || claims.exp.unix_timestamp() < chrono::Utc::now().timestamp()
+                    || claims.sub.is_empty()
+                {
+                    return Err(AuthError::Unauthorized);
+                }
+                Ok(token)
+            }
+        }
+    }
+
+    /*
+        * Check an id token against the account it was issued for, as LocalAuth does with
+        sessions_valid_after. The account is looked up at most once per REVOCATION_CACHE_TTL.
+        @param token: &JWToken, verified
+        @return: (), Unauthorized when revoked
+    */
+    async fn check_revoked(&self, token: &JWToken) -> Result<(), AuthError> {
+        let uid = &token.critical_claims.sub;
+        let cached = self
+            .revocations
+            .lock()
+            .unwrap()
+            .get(uid)
+            .copied()
+            .filter(|r| r.fetched_at.elapsed() < REVOCATION_CACHE_TTL);
+        let revocation = match cached {
+            Some(revocation) => revocation,
+            None => {
+                let user_identifier = UserIdentifiers::builder().with_uid(uid.clone()).build();
+                let user = with_auth_admin!(self, client => client
+                    .get_user(user_identifier)
+                    .await
+                    .map_err(|e| AuthError::Upstream(e.to_string()))?)
+                .ok_or(AuthError::Unauthorized)?;
+                let revocation = Revocation {
+                    valid_since: user
+                        .valid_since
+                        .map(|t| OffsetDateTime::from(t).unix_timestamp()),
+                    disabled: user.disabled == Some(true),
+                    fetched_at: Instant::now(),
+                };
+                self.remember_revocation(uid, revocation);
+                revocation
+            }
+        };
+        let auth_time = token.critical_claims.auth_time.unix_timestamp();
+        if revocation.disabled
+            || revocation
+                .valid_since
+                .is_some_and(|valid_since| auth_time < valid_since)
+        {
+            return Err(AuthError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /*
+        * Remember the revocation state of a user, the stale ones are dropped on the way
+        so the cache only holds the users seen within REVOCATION_CACHE_TTL
+        @param uid: user id
+        @param revocation: Revocation, just fetched
+    */
+    fn remember_revocation(&self, uid: &str, revocation: Revocation) {
+        let mut revocations = self.revocations.lock().unwrap();
+        revocations.retain(|_, cached| cached.fetched_at.elapsed() < REVOCATION_CACHE_TTL);
+        revocations.insert(uid.to_string(), revocation);
+    }
+
+    /*
+        * Forget the revocation state of a user after changing it, the next token is checked afresh
+        @param uid: user id
+    */
+    fn forget_revocation(&self, uid: &str) {
+        self.revocations.lock().unwrap().remove(uid);
+    }
+
     /*
         * Verify id token
         @param id_token: id token to verify
         @return: user id if token is valid, error otherwise
     */
-    pub async fn verify_id_token(&self, id_token: &str) -> Result<String> {
-        let token_verifier = self.app.id_token_verifier().await.unwrap();
-        match token_verifier.verify_token(id_token).await {
+    pub async fn verify_id_token(&self, id_token: &str) -> Result<String, AppError> {
+        match self.verify(id_token).await {
             Ok(token) => {
                 let user_id = token.critical_claims.sub;
-                return Ok(user_id);
+                Ok(user_id)
             }
-            Err(_) => return Err(Error::new("Unauthorized")),
+            Err(AuthError::Upstream(e)) => Err(AppError::Upstream(e)),
+            Err(_) => Err(AppError::Unauthorized),
         }
     }
 
     /*
-        * Update email is verified
+        * Mark the email of a user verified, id tokens issued afterwards say so
         @param uid: user id
-        @return: true if email is verified, false otherwise
+        @return: ()
     */
-    pub async fn update_email_is_verified(&self, uid: &str) -> Result<(), reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
-        client
-            .update_user(
-                UserUpdate::builder(uid.to_string())
-                    .email_verified(true)
-                    .build(),
-            )
+    pub async fn update_email_is_verified(&self, uid: &str) -> Result<(), AppError> {
+        let update = UserUpdate::builder(uid.to_string())
+            .email_verified(true)
+            .build();
+        with_auth_admin!(self, client => client
+            .update_user(update)
             .await
-            .expect("Failed to update user");
+            .map_err(AppError::upstream)?);
         Ok(())
     }
 
     /*
-        * Check if password is valid
+        * Disable or enable a user, a disabled user can't sign in nor refresh its id tokens
+        @param uid: user id
+        @param disabled: bool
+        @return: ()
+    */
+    pub async fn update_disabled(&self, uid: &str, disabled: bool) -> Result<(), AppError> {
+        let update = UserUpdate::builder(uid.to_string())
+            .disabled(disabled)
+            .build();
+        with_auth_admin!(self, client => client
+            .update_user(update)
+            .await
+            .map_err(AppError::upstream)?);
+        self.forget_revocation(uid);
+        Ok(())
+    }
+
+    /*
+        * Check the password of a user by signing in with it
+        The admin api hands out the scrypt hash of firebase, which can't be checked here.
         @param uid: user id
         @param password: password to check
         @return: true if password is valid, false otherwise
     */
-    pub async fn check_password(&self, uid: &str, password: &str) -> Result<bool, reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
+    pub async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AppError> {
         let user_identifier = UserIdentifiers::builder().with_uid(uid.to_string()).build();
-        let user = client
+        let user = with_auth_admin!(self, client => client
             .get_user(user_identifier)
             .await
-            .expect("Failed to create custom token");
-        let user = user.unwrap();
-        let user_password = user.password_hash;
-        let is_valid =
-            bcrypt::verify(password, &user_password.unwrap()).expect("Failed to verify password");
-        Ok(is_valid)
+            .map_err(AppError::upstream)?)
+        .ok_or(AppError::NotFound)?;
+        // users created without a password have none to check
+        let Some(email) = user.email else {
+            return Ok(false);
+        };
+        match AuthProvider::sign_in(self, &email, password).await {
+            Ok(_) => Ok(true),
+            Err(AuthError::InvalidCredentials) => Ok(false),
+            Err(e) => Err(AppError::Upstream(e.to_string())),
+        }
     }
 
     /*
@@ -273,56 +520,225 @@ impl Firebase {
         @param uid: user id
         @param new_password: new password
 
-        Change the password of a user
+        Change the password of a user, firebase revokes its refresh tokens along with it
         @return: ()
-        @throws: reqwest::Error
+        @throws: AppError
     */
-    pub async fn change_password(
-        &self,
-        uid: &str,
-        new_password: &str,
-    ) -> Result<(), reqwest::Error> {
-        let client: LiveAuthAdmin = self.app.auth();
-        client
-            .update_user(
-                UserUpdate::builder(uid.to_string())
-                    .password(new_password.to_string())
-                    .build(),
-            )
+    pub async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AppError> {
+        let update = UserUpdate::builder(uid.to_string())
+            .password(new_password.to_string())
+            .build();
+        with_auth_admin!(self, client => client
+            .update_user(update)
             .await
-            .expect("Failed to update user");
+            .map_err(AppError::upstream)?);
+        self.forget_revocation(uid);
         Ok(())
     }
 }
 
+/*
+ * JWT with the "none" algorithm, only ever accepted by the emulator
+*/
+fn unsigned_token<T: Serialize>(claims: &T) -> Result<String, AppError> {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).map_err(AppError::internal)?);
+    Ok(format!("{}.{}.", header, payload))
+}
+
 #[cfg(test)]
 mod tests {
 
     use chrono::Utc;
     use fake::Fake;
     // using `faker` module with locales
-    use crate::structs::user::User;
+    use crate::structs::{claims::Plan, role::Role, user::User};
     use fake::faker::internet::en::*;
     use fake::faker::name::raw::*;
     use fake::faker::number::raw::*;
     use fake::locales::*;
 
     use super::*;
+    use crate::traits::auth::AuthProvider;
+    use crate::utils::Utils;
+
+    // no emulator needs to be running, these tests never reach it
+    async fn emulated() -> Firebase {
+        Firebase::new(&FirebaseConfig {
+            service_account: None,
+            api_key: "fake-api-key".to_string(),
+            service_account_email: "firebase-auth-emulator@example.com".to_string(),
+            project_id: "demo-project".to_string(),
+            emulator_host: Some("127.0.0.1:9099".to_string()),
+        })
+        .await
+    }
+
+    fn emulator_id_token(project_id: &str, uid: &str, expires_in: i64) -> String {
+        let now = Utc::now().timestamp();
+        unsigned_token(&serde_json::json!({
+            "iss": format!("https://securetoken.google.com/{}", project_id),
+            "aud": project_id,
+            "sub": uid,
+            "iat": now,
+            "auth_time": now,
+            "exp": now + expires_in,
+            "email": "emulated@example.com",
+            "email_verified": true,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_emulator_urls() {
+        let firebase = emulated().await;
+        assert_eq!(
+            firebase.accounts_url("signInWithCustomToken"),
+            "http://127.0.0.1:9099/identitytoolkit.googleapis.com/v1/accounts:signInWithCustomToken?key=fake-api-key"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emulator_unsigned_custom_token() {
+        let firebase = emulated().await;
+        let claims = UserClaims {
+            roles: vec![Role::USER, Role::MANAGER],
+            plan: Plan::Premium,
+            claims_version: 4,
+        };
+        let token = firebase.create_custom_token("uid", &claims).await.unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[2], "");
+        let header = URL_SAFE_NO_PAD.decode(parts[0]).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&header).unwrap()["alg"],
+            "none"
+        );
+        let payload = URL_SAFE_NO_PAD.decode(parts[1]).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&payload).unwrap()["claims"],
+            serde_json::json!({"roles": ["User", "Manager"], "plan": "premium", "claims_version": 4})
+        );
+    }
+
+    // stands for the account firebase would answer, the emulator isn't reached
+    fn cache_revocation(firebase: &Firebase, uid: &str, valid_since: Option<i64>, disabled: bool) {
+        firebase.remember_revocation(
+            uid,
+            Revocation {
+                valid_since,
+                disabled,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emulator_verifies_unsigned_id_token() {
+        let firebase = emulated().await;
+        cache_revocation(&firebase, "emulated-uid", None, false);
+        let claims = firebase
+            .verify_token(&emulator_id_token("demo-project", "emulated-uid", 3600))
+            .await
+            .unwrap();
+        assert_eq!(claims.uid, "emulated-uid");
+        assert_eq!(claims.email.as_deref(), Some("emulated@example.com"));
+        assert!(claims.email_verified);
+
+        for token in [
+            emulator_id_token("demo-project", "emulated-uid", -10),
+            emulator_id_token("other-project", "emulated-uid", 3600),
+            "not a token".to_string(),
+        ] {
+            assert_eq!(
+                firebase.verify_token(&token).await,
+                Err(AuthError::Unauthorized)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoked_id_token() {
+        let firebase = emulated().await;
+        let token = emulator_id_token("demo-project", "emulated-uid", 3600);
+        let now = Utc::now().timestamp();
+
+        // signed in before the tokens were revoked, by a password change for instance
+        cache_revocation(&firebase, "emulated-uid", Some(now + 10), false);
+        assert_eq!(
+            firebase.verify_token(&token).await,
+            Err(AuthError::Unauthorized)
+        );
+        cache_revocation(&firebase, "emulated-uid", Some(now - 10), false);
+        assert!(firebase.verify_token(&token).await.is_ok());
+        cache_revocation(&firebase, "emulated-uid", Some(now - 10), true);
+        assert_eq!(
+            firebase.verify_token(&token).await,
+            Err(AuthError::Unauthorized)
+        );
+
+        // a stale state is looked up again, the emulator isn't running
+        firebase
+            .revocations
+            .lock()
+            .unwrap()
+            .get_mut("emulated-uid")
+            .unwrap()
+            .fetched_at -= REVOCATION_CACHE_TTL;
+        assert!(matches!(
+            firebase.verify_token(&token).await,
+            Err(AuthError::Upstream(_))
+        ));
+        firebase.forget_revocation("emulated-uid");
+        assert!(firebase.revocations.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_revocations_are_evicted() {
+        let firebase = emulated().await;
+        for uid in ["stale", "fresh"] {
+            cache_revocation(&firebase, uid, None, false);
+        }
+        firebase
+            .revocations
+            .lock()
+            .unwrap()
+            .get_mut("stale")
+            .unwrap()
+            .fetched_at -= REVOCATION_CACHE_TTL;
+
+        // users seen once don't stay in memory for good
+        cache_revocation(&firebase, "new", None, false);
+        let mut cached: Vec<String> = firebase
+            .revocations
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        cached.sort();
+        assert_eq!(cached, vec!["fresh", "new"]);
+    }
 
     #[tokio::test]
     async fn test_create_custom_token() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
         let uid = "2323ZA2424test";
-        let token = firebase.create_custom_token(uid, false);
-        assert!(token.await.is_ok());
+        let token = firebase
+            .create_custom_token(uid, &UserClaims::default())
+            .await;
+        assert!(token.is_ok());
         //assert_eq!(token.await.unwrap(), "test")
     }
 
     #[tokio::test]
     async fn test_get_id_token() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
         let uid = "test";
-        let token = firebase.create_custom_token(uid, false).await;
+        let token = firebase
+            .create_custom_token(uid, &UserClaims::default())
+            .await;
         assert!(token.is_ok());
         let res = firebase.get_id_token(&token.unwrap()).await;
         assert!(res.is_ok())
@@ -330,9 +746,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_verify_id_token() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
         let uid = "423423test";
-        let token = firebase.create_custom_token(uid, false).await;
+        let token = firebase
+            .create_custom_token(uid, &UserClaims::default())
+            .await;
         let id_token_res = firebase.get_id_token(&token.unwrap()).await;
         assert!(id_token_res.is_ok());
         let res = firebase.verify_id_token(&id_token_res.unwrap()).await;
@@ -341,9 +759,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_update_user() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
         let uid = Digit(EN).fake::<String>();
-        let custom_token = firebase.create_custom_token(&uid, false).await;
+        let custom_token = firebase
+            .create_custom_token(&uid, &UserClaims::default())
+            .await;
         firebase
             .get_id_token(&custom_token.unwrap())
             .await
@@ -354,6 +774,11 @@ mod tests {
             name: Name(EN).fake(),
             email: SafeEmail().fake(),
             password: "11794581oooooo&".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
             created_at: Some(Utc::now()),
             updated_at: Some(Utc::now()),
         };
@@ -363,13 +788,18 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_user() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
 
         let user = User {
             id: Some(Digit(EN).fake::<String>()),
             name: Name(EN).fake(),
             email: SafeEmail().fake(),
             password: "11794581oooooo&".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
             created_at: Some(Utc::now()),
             updated_at: Some(Utc::now()),
         };
@@ -379,13 +809,18 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove_user() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
 
         let user = User {
             id: Some(Digit(EN).fake::<String>()),
             name: Name(EN).fake(),
             email: SafeEmail().fake(),
             password: "11794581oooooo&".to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
             created_at: Some(Utc::now()),
             updated_at: Some(Utc::now()),
         };
@@ -396,9 +831,38 @@ mod tests {
         assert!(res.is_ok())
     }
 
+    #[tokio::test]
+    async fn test_set_custom_claims() {
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
+        let user = User {
+            id: Some(Digit(EN).fake::<String>()),
+            name: Name(EN).fake(),
+            email: SafeEmail().fake(),
+            password: "11794581oooooo&".to_string(),
+            plan: Plan::Premium,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: Some(Utc::now()),
+            updated_at: Some(Utc::now()),
+        };
+        let uid = firebase.create_user(&user).await.unwrap();
+        let claims = UserClaims {
+            roles: vec![Role::USER, Role::ADMIN],
+            plan: Plan::Premium,
+            claims_version: 2,
+        };
+        firebase.set_custom_claims(&uid, &claims).await.unwrap();
+        let token = firebase.sign_in(&user.email, &user.password).await.unwrap();
+        let verified = firebase.verify_token(&token).await.unwrap();
+        assert_eq!(UserClaims::from_token(&verified.claims), Some(claims));
+        firebase.remove_user(&uid).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_delete_all_users() {
-        let firebase = Firebase::new().await;
+        let firebase = Firebase::new(&Utils::firebase_config()).await;
         let res = firebase.delete_all_users().await;
         assert!(res.is_ok())
     }
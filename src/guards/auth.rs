@@ -1,38 +1,349 @@
-use std::sync::Arc;
-
-use async_graphql::*;
-use tokio::sync::Mutex;
+use async_graphql::{parser::types::OperationType, *};
+use chrono::Utc;
+use uuid::Uuid;
 
 use crate::{
-    contexts::{token::Token, user_uid::UserUID},
-    firebase::main::Firebase,
+    auth::main::AuthService,
+    contexts::{identity::Identity, impersonation::ImpersonationId, token::Token},
+    database::{backend::Database, error::DatabaseError},
+    errors::main::{AppError, AppResultExt},
+    structs::{
+        api_key::ApiKey,
+        claims::{ClaimsFallback, UserClaims},
+        impersonation::{Impersonation, ImpersonationClaim, ImpersonationEvent},
+        role::{Role, RoleCatalogue},
+    },
+    traits::{
+        api_key::ApiKeyTrait,
+        auth::{AuthClaims, AuthProvider},
+        impersonation::ImpersonationTrait,
+        role::RoleTrait,
+        user::UserTrait,
+    },
 };
 
+// response header set when the token carries stale claims, clients should refresh it
+pub const TOKEN_REFRESH_HEADER: &str = "x-token-refresh";
+
 pub struct AuthTokenGuard;
 
-impl AuthTokenGuard {}
+impl AuthTokenGuard {
+    /*
+        * Verify an api key, its claims carry the roles it grants at the time of the request
+        Keys are read without row level security, nobody is signed in yet.
+        Keys of suspended or soft deleted users are refused.
+        @param ctx: &Context<'_>
+        @param key: &str, dik_...
+        @return AuthClaims of the user of the key
+    */
+    async fn verify_api_key(ctx: &Context<'_>, key: &str) -> Result<AuthClaims> {
+        let (id, secret) = ApiKey::parse(key).ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?.unscoped();
+        let api_key = match database.get_api_key(&id).await {
+            Ok(api_key) => api_key,
+            Err(DatabaseError::NotFound) => return Err(AppError::Unauthorized.extend()),
+            Err(e) => return Err(e).app_err(),
+        };
+        if api_key.revoked_at.is_some()
+            || !bcrypt::verify(secret, &api_key.key_hash).unwrap_or(false)
+        {
+            return Err(AppError::Unauthorized.extend());
+        }
+        // keys of suspended users stop working with them
+        database
+            .get_user(&api_key.user_id)
+            .await
+            .app_err()?
+            .ensure_active()
+            .map_err(|e| e.extend())?;
+        let claims = database.get_user_claims(&api_key.user_id).await.app_err()?;
+        let catalogue = RoleCatalogue::new(database.get_role_definitions().await.app_err()?);
+        let claims = UserClaims {
+            roles: api_key.granted_roles(&claims.roles, &catalogue),
+            ..claims
+        };
+        database.touch_api_key(&id).await.app_err()?;
+
+        let mut claims = claims.to_map();
+        claims.insert(ApiKey::CLAIM.to_string(), id.to_string().into());
+        Ok(AuthClaims {
+            uid: api_key.user_id,
+            email: None,
+            email_verified: false,
+            // keys last until revoked
+            expires_at: i64::MAX,
+            claims,
+        })
+    }
+
+    /*
+        * Act as the target of an impersonation session, every request is recorded, blocked ones included
+        The session must belong to the signed in user, who must still be a global Admin.
+        @param ctx: &Context<'_>
+        @param real: AuthClaims of the Admin, already verified
+        @param id: &Uuid, of the session
+        @return AuthClaims of the target, carrying an ImpersonationClaim
+    */
+    async fn impersonate(ctx: &Context<'_>, real: AuthClaims, id: &Uuid) -> Result<AuthClaims> {
+        // keys act for machine clients, never for support staff
+        if real.claims.contains_key(ApiKey::CLAIM) {
+            return Err(AppError::Forbidden.extend());
+        }
+        let database = ctx.data::<Database>()?.unscoped();
+        let impersonation = match database.get_impersonation(id).await {
+            Ok(impersonation) => impersonation,
+            Err(DatabaseError::NotFound) => return Err(AppError::Forbidden.extend()),
+            Err(e) => return Err(e).app_err(),
+        };
+        if impersonation.admin_uid != real.uid || !impersonation.is_active(Utc::now()) {
+            return Err(AppError::Forbidden.extend());
+        }
+        let roles = database.get_user_roles(&real.uid).await.app_err()?;
+        if !roles.contains(&Role::ADMIN) {
+            return Err(AppError::Forbidden.extend());
+        }
+        // a suspended Admin loses the sessions it opened
+        database
+            .get_user(&real.uid)
+            .await
+            .app_err()?
+            .ensure_active()
+            .map_err(|e| e.extend())?;
+
+        let operation = &ctx.query_env.operation.node;
+        let blocked = impersonation.read_only && operation.ty == OperationType::Mutation;
+        database
+            .record_impersonation_event(&ImpersonationEvent {
+                id: Uuid::new_v4(),
+                impersonation_id: impersonation.id,
+                real_uid: real.uid.clone(),
+                effective_uid: impersonation.target_uid.clone(),
+                operation_type: operation.ty.to_string(),
+                operation_name: ctx.query_env.operation_name.clone(),
+                blocked,
+                created_at: None,
+            })
+            .await
+            .app_err()?;
+        if blocked {
+            return Err(AppError::Forbidden.extend());
+        }
+
+        let expires_at = real.expires_at.min(impersonation.expires_at.timestamp());
+        let claim = ImpersonationClaim {
+            id: impersonation.id,
+            real_uid: real.uid,
+            effective_uid: impersonation.target_uid.clone(),
+            read_only: impersonation.read_only,
+            expires_at,
+        };
+        let mut claims = database
+            .get_user_claims(&impersonation.target_uid)
+            .await
+            .app_err()?
+            .to_map();
+        claims.insert(
+            Impersonation::CLAIM.to_string(),
+            serde_json::to_value(claim).map_err(|e| AppError::internal(e).extend())?,
+        );
+        Ok(AuthClaims {
+            uid: impersonation.target_uid,
+            email: None,
+            email_verified: false,
+            expires_at,
+            claims,
+        })
+    }
+
+    /*
+        * Refuse suspended and soft deleted users, whatever their token or websocket says
+        Users who haven't signed up yet pass. The user is kept for UserExistGuard.
+        @param ctx: &Context<'_>
+        @param identity: &Identity, already verified
+    */
+    async fn ensure_active(ctx: &Context<'_>, identity: &Identity) -> Result<()> {
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+        match identity
+            .get_or_load_user(|| async { database.get_user(&uid.0).await })
+            .await
+        {
+            Ok(user) => user.ensure_active().map_err(|e| e.extend()),
+            Err(DatabaseError::NotFound) => Ok(()),
+            Err(e) => Err(e).app_err(),
+        }
+    }
+
+    /*
+        * Fill the roles of the identity from the claims of its token
+        Left unset when the database must be read instead, RoleGuard then loads them.
+        @param ctx: &Context<'_>
+        @param identity: &Identity, already verified
+    */
+    async fn resolve_roles(ctx: &Context<'_>, identity: &Identity) -> Result<()> {
+        let Some(claims) = identity.claims() else {
+            return Ok(());
+        };
+        if identity.roles().is_some() {
+            return Ok(());
+        }
+        // read from the database a moment ago, never widened by the fallback
+        if identity.api_key_id().is_some() || identity.impersonation().is_some() {
+            let roles = UserClaims::from_token(&claims.claims)
+                .map(|claims| claims.roles)
+                .unwrap_or_default();
+            identity.set_roles(roles);
+            return Ok(());
+        }
+        let fallback = ctx
+            .data_opt::<ClaimsFallback>()
+            .copied()
+            .unwrap_or_default();
+        match (UserClaims::from_token(&claims.claims), fallback) {
+            (Some(token), ClaimsFallback::Stale) => {
+                let current = match ctx.data::<Database>()?.get_user_claims(&claims.uid).await {
+                    Ok(current) => current,
+                    // not signed up yet, nothing newer than the token
+                    Err(DatabaseError::NotFound) => token.clone(),
+                    Err(e) => return Err(e).app_err(),
+                };
+                if current.claims_version > token.claims_version {
+                    ctx.insert_http_header(TOKEN_REFRESH_HEADER, "true");
+                    identity.set_roles(current.roles);
+                } else {
+                    identity.set_roles(token.roles);
+                }
+            }
+            (Some(token), _) => identity.set_roles(token.roles),
+            (None, ClaimsFallback::Never) => identity.set_roles(Vec::new()),
+            (None, _) => {}
+        }
+        Ok(())
+    }
+}
 
 impl Guard for AuthTokenGuard {
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        let token = ctx
-            .data::<Token>()
-            .map_err(|_| Error::new("Auth::Unauthorized"))?;
-        let token = token.clone();
-        let firebase = ctx.data::<Firebase>()?;
-        let user_uid = ctx.data::<Arc<Mutex<UserUID>>>()?;
-
-        let token = token.0;
-        if token.is_empty() {
-            return Err(Error::new("Auth::Unauthorized"));
-        }
-
-        let token = &token.replace("Bearer ", "");
-        match firebase.verify_id_token(token).await {
-            Ok(uid) => {
-                let _ = user_uid.lock().await.update(uid);
-                return Ok(());
-            }
-            Err(_) => return Err(Error::new("Auth::Unauthorized")),
+        let identity = ctx
+            .data::<Identity>()
+            .map_err(|_| AppError::Unauthorized.extend())?;
+
+        identity
+            .get_or_verify(|| async {
+                let token = ctx
+                    .data::<Token>()
+                    .map_err(|_| AppError::Unauthorized.extend())?;
+                let token = token.0.replace("Bearer ", "");
+                if token.is_empty() {
+                    return Err(AppError::Unauthorized.extend());
+                }
+                let claims = if token.starts_with(ApiKey::PREFIX) {
+                    Self::verify_api_key(ctx, &token).await?
+                } else {
+                    let auth = ctx.data::<AuthService>()?;
+                    auth.verify_token(&token).await.app_err()?
+                };
+                match ctx.data_opt::<ImpersonationId>() {
+                    Some(impersonation) => Self::impersonate(ctx, claims, &impersonation.0).await,
+                    None => Ok(claims),
+                }
+            })
+            .await?;
+        Self::ensure_active(ctx, identity).await?;
+        Self::resolve_roles(ctx, identity).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        guards::role::RoleGuard, structs::role::Role, traits::identity::IdentityTrait, utils::Utils,
+    };
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+        async fn admin_only(&self) -> bool {
+            true
         }
     }
+
+    async fn admin_only(
+        database: &Database,
+        auth: &AuthService,
+        fallback: ClaimsFallback,
+        token: &str,
+    ) -> Response {
+        Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .data(auth.clone())
+            .data(fallback)
+            .finish()
+            .execute(
+                Request::new("query { adminOnly }")
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", token))),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_roles_from_token_claims() {
+        let uid = uuid::Uuid::new_v4().to_string();
+        let (database, unsynced) = Utils::generate_testing_config(&uid).await.unwrap();
+        database.create_test_user(&uid).await.unwrap();
+        database.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+        // keeps the Admin role revocable
+        let other = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&other, &Role::ADMIN).await.unwrap();
+        let local = Utils::local_auth(database.clone());
+        let auth = AuthService::Local(local.clone());
+        let sync = || async {
+            let claims = database.get_user_claims(&uid).await.unwrap();
+            auth.set_custom_claims(&uid, &claims).await.unwrap();
+            let identity = database.get_identity(&uid).await.unwrap().unwrap();
+            local.issue_token(&identity).unwrap()
+        };
+
+        // a token issued before any sync carries no role
+        let res = admin_only(&database, &auth, ClaimsFallback::Missing, &unsynced).await;
+        assert_eq!(res.errors.first(), None);
+        let res = admin_only(&database, &auth, ClaimsFallback::Never, &unsynced).await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+
+        let synced = sync().await;
+        for fallback in [
+            ClaimsFallback::Never,
+            ClaimsFallback::Missing,
+            ClaimsFallback::Stale,
+        ] {
+            let res = admin_only(&database, &auth, fallback, &synced).await;
+            assert_eq!(res.errors.first(), None, "{:?}", fallback);
+            assert!(res.http_headers.get(TOKEN_REFRESH_HEADER).is_none());
+        }
+
+        // the token still says Admin, only the stale check notices
+        database.remove_user_role(&uid, &Role::ADMIN).await.unwrap();
+        let res = admin_only(&database, &auth, ClaimsFallback::Missing, &synced).await;
+        assert_eq!(res.errors.first(), None);
+        let res = admin_only(&database, &auth, ClaimsFallback::Stale, &synced).await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+        assert_eq!(
+            res.http_headers
+                .get(TOKEN_REFRESH_HEADER)
+                .and_then(|v| v.to_str().ok()),
+            Some("true")
+        );
+
+        let refreshed = sync().await;
+        let res = admin_only(&database, &auth, ClaimsFallback::Missing, &refreshed).await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+        let res = admin_only(&database, &auth, ClaimsFallback::Stale, &refreshed).await;
+        assert!(res.http_headers.get(TOKEN_REFRESH_HEADER).is_none());
+    }
 }
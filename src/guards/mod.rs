@@ -1,3 +1,4 @@
 pub mod auth;
+pub mod permission;
 pub mod role;
 pub mod user;
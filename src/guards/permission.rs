@@ -0,0 +1,192 @@
+use async_graphql::*;
+
+use crate::{
+    contexts::identity::Identity,
+    database::backend::Database,
+    errors::main::{AppError, AppResultExt},
+    structs::role::RoleCatalogue,
+    traits::{permission::PermissionTrait, role::RoleTrait},
+};
+
+/*
+    * Permissions of an identity, loaded once per request
+    Those granted to its roles when they are known, an api key or an impersonation narrows them,
+    else those granted to the roles of the user in the database.
+    @param identity: &Identity, verified
+    @param database: &Database
+    @return &Vec<String>, sorted
+*/
+pub async fn identity_permissions<'a>(
+    identity: &'a Identity,
+    database: &Database,
+) -> Result<&'a Vec<String>> {
+    let uid = identity
+        .uid()
+        .ok_or_else(|| AppError::Unauthorized.extend())?;
+    identity
+        .get_or_load_permissions(|| async {
+            let Some(roles) = identity.roles() else {
+                return database.get_user_permissions(&uid.0).await.app_err();
+            };
+            let catalogue = RoleCatalogue::new(database.get_role_definitions().await.app_err()?);
+            let mut permissions = Vec::new();
+            for role in catalogue.implied(roles) {
+                permissions.extend(database.get_role_permissions(&role).await.app_err()?);
+            }
+            permissions.sort();
+            permissions.dedup();
+            Ok(permissions)
+        })
+        .await
+}
+
+/*
+    * Let through users whose roles grant a permission, i.e PermissionGuard::new("user:update")
+    The mapping lives in the database and is edited at runtime by Admins.
+*/
+pub struct PermissionGuard {
+    permission: &'static str,
+}
+
+impl PermissionGuard {
+    pub fn new(permission: &'static str) -> Self {
+        Self { permission }
+    }
+}
+
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let permissions =
+            identity_permissions(ctx.data::<Identity>()?, ctx.data::<Database>()?).await?;
+
+        if permissions.iter().any(|p| p == self.permission) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.extend())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        contexts::token::Token,
+        guards::auth::AuthTokenGuard,
+        structs::{api_key::ApiKey, role::Role, user::hash_password},
+        traits::{api_key::ApiKeyTrait, permission::PermissionTrait, user::UserTrait},
+        utils::Utils,
+    };
+    use uuid::Uuid;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        #[graphql(guard = "AuthTokenGuard.and(PermissionGuard::new(\"dataset:write\"))")]
+        async fn write_dataset(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "AuthTokenGuard.and(PermissionGuard::new(\"role:write\"))")]
+        async fn write_roles(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_permission_guard() {
+        let database = Utils::memory_database();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let manager = database.crate_random_user().await.unwrap().id.unwrap();
+        database
+            .save_user_role(&manager, &Role::MANAGER)
+            .await
+            .unwrap();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let execute = |uid: &str| {
+            schema.execute(Request::new("query { writeDataset }").data(Identity::verified(uid)))
+        };
+
+        // seeded on Manager, inherited by Admin
+        assert_eq!(execute(&manager).await.errors.first(), None);
+        assert_eq!(execute(&admin).await.errors.first(), None);
+        let res = execute(&user).await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("FORBIDDEN"))
+        );
+
+        // the mapping is read on every request
+        database
+            .revoke_permission(&Role::MANAGER, "dataset:write")
+            .await
+            .unwrap();
+        assert!(execute(&admin).await.errors[0].message == "Role::Unauthorized");
+        database
+            .grant_permission(&Role::USER, "dataset:write")
+            .await
+            .unwrap();
+        assert_eq!(execute(&user).await.errors.first(), None);
+    }
+
+    #[tokio::test]
+    async fn test_permissions_of_a_narrowed_api_key() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let key_with = |scopes: Vec<Role>| {
+            let database = database.clone();
+            let admin = admin.clone();
+            async move {
+                let id = Uuid::new_v4();
+                let (key, secret) = ApiKey::generate(&id);
+                database
+                    .create_api_key(&ApiKey {
+                        id,
+                        user_id: admin.clone(),
+                        name: "etl".to_string(),
+                        scopes,
+                        key_hash: hash_password(&secret).unwrap(),
+                        created_by: Some(admin),
+                        last_used_at: None,
+                        revoked_at: None,
+                        created_at: None,
+                        updated_at: None,
+                    })
+                    .await
+                    .unwrap();
+                key
+            }
+        };
+        let execute = |query: &'static str, key: String| {
+            schema.execute(
+                Request::new(query)
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", key))),
+            )
+        };
+
+        // the key of an Admin narrowed to Manager has the permissions of a Manager only
+        let narrowed = key_with(vec![Role::MANAGER]).await;
+        let res = execute("query { writeRoles }", narrowed.clone()).await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("FORBIDDEN"))
+        );
+        let res = execute("query { writeDataset }", narrowed).await;
+        assert_eq!(res.errors.first(), None);
+
+        let full = key_with(vec![Role::ADMIN]).await;
+        let res = execute("query { writeRoles }", full).await;
+        assert_eq!(res.errors.first(), None);
+    }
+}
@@ -1,38 +1,464 @@
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
-
 use async_graphql::*;
+use uuid::Uuid;
 
 use crate::{
-    contexts::user_uid::UserUID, database::main::PostGreClient, enums::role::Role,
-    traits::user::UserTrait,
+    contexts::{identity::Identity, organization::OrganizationId},
+    database::backend::Database,
+    errors::main::{AppError, AppResultExt},
+    structs::role::{Role, RoleCatalogue},
+    traits::{organization::OrganizationTrait, role::RoleTrait, user::UserTrait},
 };
 
+#[derive(Clone, Debug, PartialEq)]
+enum RoleRequirement {
+    // the role itself, the hierarchy is ignored
+    Exact(Role),
+    // the role or any role above it
+    AtLeast(Role),
+    // one of the roles, exactly
+    AnyOf(Vec<Role>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RoleScope {
+    // the role held in this organization or the one of the request, the global roles when none is selected
+    Active(Option<Uuid>),
+    // the role held in this organization, or in the one of the request, one must be selected
+    Organization(Option<Uuid>),
+    // the global roles, whatever the organization of the request, unless one of the above is opted in
+    Global,
+}
+
 pub struct RoleGuard {
-    roles: Role,
+    requirement: RoleRequirement,
+    scope: RoleScope,
 }
 
 impl RoleGuard {
+    /*
+        * Require exactly this role, same as RoleGuard::exact
+        @param roles: Role
+    */
     pub fn new(roles: Role) -> Self {
-        Self { roles }
+        Self::exact(roles)
+    }
+
+    /*
+        * Require this very role, an Admin doesn't pass RoleGuard::exact(Role::MANAGER)
+        @param role: Role
+    */
+    pub fn exact(role: Role) -> Self {
+        Self {
+            requirement: RoleRequirement::Exact(role),
+            scope: RoleScope::Global,
+        }
+    }
+
+    /*
+        * Require this role or a role implying it, i.e an Admin passes RoleGuard::at_least(Role::MANAGER)
+        @param role: Role
+    */
+    pub fn at_least(role: Role) -> Self {
+        Self {
+            requirement: RoleRequirement::AtLeast(role),
+            scope: RoleScope::Global,
+        }
+    }
+
+    /*
+        * Require one of the roles, each compared exactly
+        @param roles: &[Role]
+    */
+    pub fn any_of(roles: &[Role]) -> Self {
+        Self {
+            requirement: RoleRequirement::AnyOf(roles.to_vec()),
+            scope: RoleScope::Global,
+        }
+    }
+
+    /*
+        * Check the role held in an organization, i.e
+        RoleGuard::at_least(Role::ADMIN).organization(organization_id) with the organizationId argument of the field
+        @param organization: Option<Uuid>, None for the organization of the request
+    */
+    pub fn organization(self, organization: Option<Uuid>) -> Self {
+        Self {
+            scope: RoleScope::Organization(organization),
+            ..self
+        }
+    }
+
+    /*
+     * Check the role held in the organization of the request, the global roles when none is selected
+     */
+    pub fn active(self) -> Self {
+        self.active_organization(None)
+    }
+
+    /*
+        * Same as RoleGuard::active, with the organizationId argument of the field winning over the request
+        @param organization: Option<Uuid>, None for the organization of the request
+    */
+    pub fn active_organization(self, organization: Option<Uuid>) -> Self {
+        Self {
+            scope: RoleScope::Active(organization),
+            ..self
+        }
+    }
+
+    /*
+        * Former name of RoleGuard::organization
+        @param organization: Option<Uuid>, None for the organization of the request
+    */
+    pub fn in_organization(self, organization: Option<Uuid>) -> Self {
+        self.organization(organization)
+    }
+
+    /*
+        * Former name of RoleGuard::active_organization
+        @param organization: Option<Uuid>, None for the organization of the request
+    */
+    pub fn in_active_organization(self, organization: Option<Uuid>) -> Self {
+        self.active_organization(organization)
+    }
+
+    /*
+     * Check the global roles, the default since organization scopes are opted in
+     */
+    pub fn global(self) -> Self {
+        Self {
+            scope: RoleScope::Global,
+            ..self
+        }
+    }
+
+    /*
+        * Whether a user holding roles is let through
+        @param roles: &[Role]
+        @param catalogue: &RoleCatalogue, resolves the hierarchy for RoleGuard::at_least
+        @return bool
+    */
+    pub fn allows(&self, roles: &[Role], catalogue: &RoleCatalogue) -> bool {
+        match &self.requirement {
+            RoleRequirement::Exact(role) => roles.contains(role),
+            RoleRequirement::AtLeast(role) => {
+                roles.iter().any(|held| catalogue.implies(held, role))
+            }
+            RoleRequirement::AnyOf(required) => required.iter().any(|role| roles.contains(role)),
+        }
     }
 }
 
 impl Guard for RoleGuard {
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        let _useruid = ctx.data::<Arc<Mutex<UserUID>>>()?;
-        let f = _useruid.as_ref().lock().await;
-        let database = ctx
-            .data::<Arc<RwLock<PostGreClient>>>()
-            .unwrap()
-            .read()
-            .await;
-        let roles: Vec<Role> = database.get_user_roles(&f.0).await?;
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+        let organization = match self.scope {
+            RoleScope::Active(argument) => OrganizationId::active(ctx, argument),
+            RoleScope::Organization(argument) => Some(OrganizationId::required(ctx, argument)?),
+            RoleScope::Global => None,
+        };
+        let roles = match organization {
+            // a member holds a single role in an organization, outsiders none
+            Some(organization) => database
+                .get_membership(&organization, &uid.0)
+                .await
+                .app_err()?
+                .map(|membership| vec![membership.role])
+                .unwrap_or_default(),
+            None => identity
+                .get_or_load_roles(|| async { database.get_user_roles(&uid.0).await.app_err() })
+                .await?
+                .clone(),
+        };
+        let catalogue = RoleCatalogue::new(database.get_role_definitions().await.app_err()?);
 
-        if roles.contains(&self.roles) {
+        if self.allows(&roles, &catalogue) {
             Ok(())
         } else {
-            Err(Error::new("Role::Unauthorized"))
+            Err(AppError::Forbidden.extend())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{structs::role::RoleDefinition, utils::Utils};
+
+    const ROLES: [Role; 3] = Role::BUILT_IN;
+
+    fn catalogue() -> RoleCatalogue {
+        RoleCatalogue::new(RoleDefinition::defaults())
+    }
+
+    // every subset of the three roles, the empty one included
+    fn role_sets() -> Vec<Vec<Role>> {
+        (0..1 << ROLES.len())
+            .map(|mask| {
+                ROLES
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, role)| role.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact() {
+        let catalogue = catalogue();
+        for held in role_sets() {
+            for role in ROLES {
+                assert_eq!(
+                    RoleGuard::exact(role.clone()).allows(&held, &catalogue),
+                    held.contains(&role),
+                    "exact({}) with {:?}",
+                    role,
+                    held
+                );
+                assert_eq!(
+                    RoleGuard::new(role.clone()).allows(&held, &catalogue),
+                    RoleGuard::exact(role).allows(&held, &catalogue)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_at_least() {
+        let catalogue = catalogue();
+        let rank = |role: &Role| ROLES.iter().position(|built_in| built_in == role);
+        for held in role_sets() {
+            let highest = held.iter().filter_map(rank).max();
+            for role in ROLES {
+                assert_eq!(
+                    RoleGuard::at_least(role.clone()).allows(&held, &catalogue),
+                    highest.is_some_and(|highest| Some(highest) >= rank(&role)),
+                    "at_least({}) with {:?}",
+                    role,
+                    held
+                );
+            }
         }
+        // the motivating case, an Admin without a Manager row
+        assert!(RoleGuard::at_least(Role::MANAGER).allows(&[Role::ADMIN], &catalogue));
+        assert!(!RoleGuard::exact(Role::MANAGER).allows(&[Role::ADMIN], &catalogue));
+    }
+
+    #[test]
+    fn test_former_names() {
+        let id = Some(Uuid::new_v4());
+        let guard = || RoleGuard::at_least(Role::MANAGER);
+        assert_eq!(
+            guard().in_organization(id).scope,
+            guard().organization(id).scope
+        );
+        assert_eq!(
+            guard().in_active_organization(id).scope,
+            guard().active_organization(id).scope
+        );
+        assert_eq!(guard().active().global().scope, RoleScope::Global);
+    }
+
+    #[test]
+    fn test_any_of() {
+        let catalogue = catalogue();
+        for held in role_sets() {
+            for required in role_sets() {
+                assert_eq!(
+                    RoleGuard::any_of(&required).allows(&held, &catalogue),
+                    required.iter().any(|role| held.contains(role)),
+                    "any_of({:?}) with {:?}",
+                    required,
+                    held
+                );
+            }
+        }
+        // nothing required by any_of means nobody is let through
+        assert!(!RoleGuard::any_of(&[]).allows(&ROLES, &catalogue));
+    }
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        #[graphql(guard = "RoleGuard::exact(Role::MANAGER)")]
+        async fn exact_manager(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::at_least(Role::MANAGER)")]
+        async fn at_least_manager(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::any_of(&[Role::MANAGER, Role::ADMIN])")]
+        async fn manager_or_admin(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::exact(Role::MANAGER).or(RoleGuard::exact(Role::ADMIN))")]
+        async fn manager_or_admin_composed(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::at_least(Role::USER).and(RoleGuard::exact(Role::ADMIN))")]
+        async fn user_and_admin(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::at_least(Role::ADMIN)")]
+        async fn platform_admin(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::at_least(Role::MANAGER).active()")]
+        async fn active_manager(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "RoleGuard::at_least(Role::MANAGER).organization(organization_id)")]
+        async fn organization_manager(&self, organization_id: Option<Uuid>) -> bool {
+            organization_id.is_some()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_guard_composition() {
+        let database = Utils::memory_database();
+        let catalogue = catalogue();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let fields = [
+            "exactManager",
+            "atLeastManager",
+            "managerOrAdmin",
+            "managerOrAdminComposed",
+            "userAndAdmin",
+        ];
+
+        // users always hold User, they are created with it
+        for extra in role_sets()
+            .into_iter()
+            .filter(|set| !set.contains(&Role::USER))
+        {
+            let uid = database.crate_random_user().await.unwrap().id.unwrap();
+            for role in extra.iter() {
+                database.save_user_role(&uid, role).await.unwrap();
+            }
+            let mut held = extra.clone();
+            held.push(Role::USER);
+
+            for field in fields {
+                let expected = match field {
+                    "exactManager" => RoleGuard::exact(Role::MANAGER).allows(&held, &catalogue),
+                    "atLeastManager" => {
+                        RoleGuard::at_least(Role::MANAGER).allows(&held, &catalogue)
+                    }
+                    "managerOrAdmin" | "managerOrAdminComposed" => {
+                        held.contains(&Role::MANAGER) || held.contains(&Role::ADMIN)
+                    }
+                    _ => held.contains(&Role::ADMIN),
+                };
+                let res = schema
+                    .execute(
+                        Request::new(format!("query {{ {} }}", field))
+                            .data(Identity::verified(&uid)),
+                    )
+                    .await;
+                if expected {
+                    assert_eq!(res.errors.first(), None, "{} with {:?}", field, held);
+                } else {
+                    assert_eq!(
+                        res.errors[0].message, "Role::Unauthorized",
+                        "{} with {:?}",
+                        field, held
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_organization_scope() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let manager = database.crate_random_user().await.unwrap().id.unwrap();
+        let acme = database
+            .create_organization("Acme", &admin)
+            .await
+            .unwrap()
+            .id;
+        let globex = database
+            .create_organization("Globex", &manager)
+            .await
+            .unwrap()
+            .id;
+        database
+            .save_membership(&acme, &manager, &Role::MANAGER)
+            .await
+            .unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let execute = |query: String, uid: &str, organization: Option<Uuid>| {
+            let mut request = Request::new(query).data(Identity::verified(uid));
+            if let Some(organization) = organization {
+                request = request.data(OrganizationId(organization));
+            }
+            schema.execute(request)
+        };
+        let allowed = |res: &Response| res.errors.is_empty();
+
+        // guards check the global roles unless told otherwise
+        let query = || "query { atLeastManager }".to_string();
+        assert!(allowed(&execute(query(), &admin, Some(globex)).await));
+        assert!(!allowed(&execute(query(), &manager, Some(acme)).await));
+
+        // active() replaces them with the role held in the organization of the request
+        let query = || "query { activeManager }".to_string();
+        assert!(allowed(&execute(query(), &admin, None).await));
+        assert!(!allowed(&execute(query(), &manager, None).await));
+        assert!(allowed(&execute(query(), &manager, Some(acme)).await));
+        assert!(allowed(&execute(query(), &admin, Some(acme)).await));
+        // a global Admin is nobody in an organization it isn't a member of
+        assert!(!allowed(&execute(query(), &admin, Some(globex)).await));
+
+        // an Admin of an organization is nobody on the platform
+        let query = || "query { platformAdmin }".to_string();
+        assert!(allowed(&execute(query(), &admin, Some(globex)).await));
+        assert!(!allowed(&execute(query(), &manager, Some(globex)).await));
+        assert!(!allowed(&execute(query(), &manager, None).await));
+
+        // the argument wins over the organization of the request
+        let query = |organization: Uuid| {
+            format!(
+                r#"query {{ organizationManager(organizationId: "{}") }}"#,
+                organization
+            )
+        };
+        assert!(allowed(&execute(query(globex), &manager, Some(acme)).await));
+        assert!(!allowed(&execute(query(globex), &admin, Some(acme)).await));
+        assert!(allowed(&execute(query(acme), &admin, Some(globex)).await));
+        // an organization must be selected one way or the other
+        let res = execute("query { organizationManager }".to_string(), &admin, None).await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("VALIDATION_FAILED"))
+        );
+        let res = execute(
+            "query { organizationManager }".to_string(),
+            &manager,
+            Some(acme),
+        )
+        .await;
+        assert_eq!(res.data, value!({"organizationManager": false}));
     }
 }
@@ -1,26 +1,161 @@
-use crate::{contexts::user_uid::UserUID, database, structs::user::User, traits::user::UserTrait};
+use crate::{
+    contexts::{identity::Identity, organization::OrganizationId},
+    database::backend::Database,
+    errors::main::{AppError, AppResultExt},
+    traits::{organization::OrganizationTrait, user::UserTrait},
+};
 use async_graphql::*;
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
 
+/*
+    * Let through users stored in the database, neither suspended nor soft deleted,
+    members only when the request selects an organization
+*/
 pub struct UserExistGuard;
 
 impl UserExistGuard {}
 
 impl Guard for UserExistGuard {
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        let _useruid = ctx.data::<Arc<Mutex<UserUID>>>()?;
-        let f = _useruid.as_ref().lock().await;
-        let database = ctx
-            .data::<Arc<RwLock<database::main::PostGreClient>>>()
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+
+        identity
+            .get_or_load_user(|| async { database.get_user(&uid.0).await.app_err() })
+            .await?
+            .ensure_active()
+            .map_err(|e| e.extend())?;
+        if let Some(organization) = OrganizationId::active(ctx, None) {
+            database
+                .get_membership(&organization, &uid.0)
+                .await
+                .app_err()?
+                .ok_or_else(|| AppError::Forbidden.extend())?;
+        }
+        Ok(())
+    }
+}
+
+/*
+    * Let through users who verified their email, see Mutation::verify_email
+    The user is read from the database, a token issued before the link was followed still works.
+*/
+pub struct VerifiedEmailGuard;
+
+impl Guard for VerifiedEmailGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+
+        let user = identity
+            .get_or_load_user(|| async { database.get_user(&uid.0).await.app_err() })
+            .await?;
+        if user.email_verified {
+            Ok(())
+        } else {
+            Err(AppError::EmailNotVerified.extend())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        structs::one_time_token::OneTimeToken, traits::email_verification::EmailVerificationTrait,
+        utils::Utils,
+    };
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        #[graphql(guard = "VerifiedEmailGuard")]
+        async fn verified_only(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "UserExistGuard")]
+        async fn users_only(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_exist_guard() {
+        let database = Utils::memory_database();
+        let uid = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let execute = |uid: &str| {
+            schema.execute(Request::new("query { usersOnly }").data(Identity::verified(uid)))
+        };
+        let code = |res: Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code").cloned())
+        };
+
+        assert_eq!(execute(&uid).await.errors.first(), None);
+        assert_eq!(code(execute("nobody").await), Some(value!("NOT_FOUND")));
+
+        database
+            .update_user_status(&uid, true, None, Some("spam"))
+            .await
+            .unwrap();
+        assert_eq!(code(execute(&uid).await), Some(value!("ACCOUNT_SUSPENDED")));
+        database
+            .update_user_status(&uid, true, Some(chrono::Utc::now()), Some("spam"))
+            .await
+            .unwrap();
+        assert_eq!(code(execute(&uid).await), Some(value!("ACCOUNT_DELETED")));
+        database
+            .update_user_status(&uid, false, None, None)
+            .await
             .unwrap();
-        let user = database.as_ref().read().await.get_user(&f.0).await?;
+        assert_eq!(execute(&uid).await.errors.first(), None);
+    }
+
+    #[tokio::test]
+    async fn test_verified_email_guard() {
+        let database = Utils::memory_database();
+        let user = database.crate_random_user().await.unwrap();
+        let uid = user.id.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database.clone())
+            .finish();
+        let execute = || {
+            schema.execute(Request::new("query { verifiedOnly }").data(Identity::verified(&uid)))
+        };
 
-        let _ = ctx
-            .data_unchecked::<Arc<Mutex<Option<User>>>>()
-            .lock()
+        let res = execute().await;
+        assert_eq!(res.errors[0].message, "Auth::EmailNotVerified");
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("EMAIL_NOT_VERIFIED"))
+        );
+
+        let token = OneTimeToken::generate();
+        database
+            .create_email_verification(
+                &uid,
+                &user.email,
+                &token.hash,
+                chrono::Utc::now() + chrono::Duration::hours(1),
+            )
             .await
-            .insert(user);
-        Ok(())
+            .unwrap();
+        database
+            .consume_email_verification(&token.hash)
+            .await
+            .unwrap();
+        assert_eq!(execute().await.errors.first(), None);
     }
 }
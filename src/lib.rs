@@ -1,62 +1,110 @@
-mod contexts;
-mod database;
-mod enums;
-mod firebase;
-mod guards;
-mod mutations;
-mod queries;
-mod structs;
-mod traits;
+pub mod auth;
+pub mod config;
+pub mod contexts;
+pub mod database;
+pub mod errors;
+pub mod events;
+pub mod extensions;
+pub mod firebase;
+pub mod guards;
+pub mod mailer;
+pub mod mutations;
+pub mod queries;
+pub mod structs;
+pub mod subscriptions;
+pub mod traits;
 mod utils;
 
-use std::sync::Arc;
+use std::{pin::pin, sync::Arc, time::Duration};
 
-use database::main::PostGreClient;
+use auth::{local::LocalAuth, main::AuthService};
+use config::main::{AppConfig, AuthConfig, PostgresConfig};
+use database::{backend::Database, main::PostGreClient};
+use errors::main::AppError;
+use events::main::EventBus;
+use extensions::{impersonation::ImpersonationNotice, row_security::RowSecurity};
 use firebase::main::Firebase;
+use mailer::main::MailerService;
 use mutations::main::Mutation;
 use queries::main::Query;
-use reqwest::Method;
 use serde::Deserialize;
-use structs::user::User;
-use tokio::sync::{Mutex, RwLock};
+use subscriptions::main::Subscription;
+use tokio::sync::oneshot;
 
-use contexts::{token::Token, user_uid::UserUID};
+use contexts::{
+    identity::Identity,
+    impersonation::{ImpersonationId, IMPERSONATION_HEADER},
+    organization::{OrganizationId, ORGANIZATION_HEADER},
+    token::Token,
+};
+use traits::auth::AuthProvider;
 
 use async_graphql::{
-    http::{playground_source, GraphQLPlaygroundConfig, ALL_WEBSOCKET_PROTOCOLS},
-    EmptySubscription, Schema,
+    futures_util::{future, SinkExt, StreamExt},
+    http::{playground_source, GraphQLPlaygroundConfig, WsMessage, ALL_WEBSOCKET_PROTOCOLS},
+    ErrorExtensions, Pos, Schema,
 };
-use async_graphql_poem::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use async_graphql_poem::{GraphQLProtocol, GraphQLRequest, GraphQLResponse};
 
 use poem::{
     get, handler,
-    http::HeaderMap,
+    http::{HeaderMap, Method},
     listener::TcpListener,
     middleware::Cors,
-    web::{websocket::WebSocket, Data, Html},
-    EndpointExt, IntoResponse, Route, Server,
+    web::{
+        websocket::{Message, WebSocket, WebSocketStream},
+        Data, Html,
+    },
+    Endpoint, EndpointExt, IntoResponse, Route, Server,
 };
 
 // App Schema
-pub type AppSchema = Schema<Query, Mutation, EmptySubscription>;
+pub type AppSchema = Schema<Query, Mutation, Subscription>;
+
+// close code sent when the token of a websocket connection expires
+pub const TOKEN_EXPIRED_CLOSE_CODE: u16 = 4401;
 
+/*
+    * Verify the token sent in the connection_init payload of a websocket
+    @param auth: AuthService
+    @param expires_at: receives the expiry of the token once verified
+    @param value: payload, i.e {"token": "...", "organizationId": "..."}, the organization is optional
+    @return connection data holding the token, the verified identity and the organization
+*/
 pub async fn on_connection_init(
+    auth: AuthService,
+    expires_at: oneshot::Sender<i64>,
     value: serde_json::Value,
 ) -> async_graphql::Result<async_graphql::Data> {
     #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
     struct Payload {
         token: String,
+        organization_id: Option<uuid::Uuid>,
     }
 
-    // Coerce the connection params into our `Payload` struct so we can
-    // validate the token exists in the headers.
-    if let Ok(payload) = serde_json::from_value::<Payload>(value) {
-        let mut data = async_graphql::Data::default();
-        data.insert(Token(payload.token));
-        Ok(data)
-    } else {
-        Err("Token is required".into())
+    let Ok(payload) = serde_json::from_value::<Payload>(value) else {
+        return Err("Token is required".into());
+    };
+    let token = payload
+        .token
+        .strip_prefix("Bearer ")
+        .unwrap_or(&payload.token)
+        .to_string();
+    let claims = auth
+        .verify_token(&token)
+        .await
+        .map_err(|_| AppError::Unauthorized.extend())?;
+    // the receiver is gone only if the connection already closed
+    let _ = expires_at.send(claims.expires_at);
+
+    let mut data = async_graphql::Data::default();
+    data.insert(Token(token));
+    data.insert(Identity::from_claims(claims));
+    if let Some(organization_id) = payload.organization_id {
+        data.insert(OrganizationId(organization_id));
     }
+    Ok(data)
 }
 
 /*
@@ -69,24 +117,63 @@ pub async fn on_connection_init(
 fn get_token_from_headers(headers: &HeaderMap) -> Option<Token> {
     let auth_header = headers.get("Authorization")?;
     let auth_header = auth_header.to_str().ok()?;
-    let auth_header = auth_header.split(" ").collect::<Vec<_>>();
+    let auth_header = auth_header.split(' ').collect::<Vec<_>>();
+
+    match auth_header.len() {
+        0 | 1 => None,
+        _ => Some(Token(auth_header[1].to_string())),
+    }
+}
 
-    let token = match auth_header.len() {
-        0 => None,
-        1 => None,
-        _ => Some(auth_header[1].to_string()),
+/*
+    * Organization selected by the X-Organization-Id header
+    @return None without the header, an error when it isn't a uuid
+*/
+fn get_organization_from_headers(headers: &HeaderMap) -> Result<Option<OrganizationId>, AppError> {
+    let Some(value) = headers.get(ORGANIZATION_HEADER) else {
+        return Ok(None);
     };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| uuid::Uuid::parse_str(value.trim()).ok())
+        .map(|id| Some(OrganizationId(id)))
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "{} must be an organization id",
+                ORGANIZATION_HEADER
+            ))
+        })
+}
 
-    if token.is_none() {
-        None
-    } else {
-        Some(Token(token.unwrap()))
-    }
+/*
+    * Impersonation session selected by the X-Impersonate header
+    @return None without the header, an error when it isn't a uuid
+*/
+fn get_impersonation_from_headers(
+    headers: &HeaderMap,
+) -> Result<Option<ImpersonationId>, AppError> {
+    let Some(value) = headers.get(IMPERSONATION_HEADER) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| uuid::Uuid::parse_str(value.trim()).ok())
+        .map(|id| Some(ImpersonationId(id)))
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "{} must be an impersonation id",
+                IMPERSONATION_HEADER
+            ))
+        })
 }
 
 #[handler]
 async fn graphiql() -> impl IntoResponse {
-    Html(playground_source(GraphQLPlaygroundConfig::new("/")))
+    Html(playground_source(
+        GraphQLPlaygroundConfig::new("/").subscription_endpoint("/ws"),
+    ))
 }
 
 #[handler]
@@ -95,10 +182,29 @@ async fn index(
     headers: &HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    let mut req = req.0;
+    // identity is request scoped, guards fill it once the token is verified
+    let mut req = req.0.data(Identity::default());
     if let Some(token) = get_token_from_headers(headers) {
         req = req.data(token);
     }
+    match get_organization_from_headers(headers) {
+        Ok(Some(organization)) => req = req.data(organization),
+        Ok(None) => {}
+        // running the request outside of the organization asked for would be worse than failing
+        Err(e) => {
+            let error = e.extend().into_server_error(Pos::default());
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
+    }
+    match get_impersonation_from_headers(headers) {
+        Ok(Some(impersonation)) => req = req.data(impersonation),
+        Ok(None) => {}
+        // same, acting as the Admin instead of the impersonated user would mislead support
+        Err(e) => {
+            let error = e.extend().into_server_error(Pos::default());
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
+    }
 
     schema.execute(req).await.into()
 }
@@ -106,69 +212,424 @@ async fn index(
 #[handler]
 async fn ws(
     schema: Data<&AppSchema>,
+    auth: Data<&AuthService>,
     protocol: GraphQLProtocol,
     websocket: WebSocket,
 ) -> impl IntoResponse {
     let schema = schema.0.clone();
+    let auth = auth.0.clone();
     websocket
         .protocols(ALL_WEBSOCKET_PROTOCOLS)
-        .on_upgrade(move |stream| {
-            GraphQLWebSocket::new(stream, schema, protocol)
-                // connection params are used to extract the token in this fn
-                .on_connection_init(on_connection_init)
-                .serve()
-        })
+        .on_upgrade(move |stream| serve_websocket(stream, schema, auth, protocol))
 }
 
-pub async fn launch_server() -> Result<(), std::io::Error> {
-    dotenv::dotenv().ok();
-    // database for graphql consumption
-    let database = PostGreClient::new().await;
-    let database_arc_rw = Arc::new(RwLock::new(database));
+/*
+    * Same loop as GraphQLWebSocket::serve, plus a close frame
+    sent as soon as the token given in connection_init expires
+*/
+async fn serve_websocket(
+    stream: WebSocketStream,
+    schema: AppSchema,
+    auth: AuthService,
+    protocol: GraphQLProtocol,
+) {
+    let (mut sink, stream) = stream.split();
+    let incoming = stream
+        .take_while(|res| future::ready(res.is_ok()))
+        .map(Result::unwrap)
+        .filter(|msg| future::ready(msg.is_text() || msg.is_binary()))
+        .map(Message::into_bytes);
 
-    database_arc_rw
-        .write()
-        .await
-        .drop_tables()
-        .await
-        .expect("Error dropping tables");
-    let create_tables = database_arc_rw
-        .write()
-        .await
-        .create_tables_if_not_exist()
-        .await;
-    match create_tables {
-        Ok(_) => (),
-        Err(e) => println!("Error creating tables: {:?}", e),
+    let (expires_at_tx, expires_at_rx) = oneshot::channel();
+    let mut outgoing = pin!(
+        async_graphql::http::WebSocket::new(schema, incoming, protocol.0)
+            .on_connection_init(move |value| on_connection_init(auth, expires_at_tx, value))
+            .map(|msg| match msg {
+                WsMessage::Text(text) => Message::text(text),
+                WsMessage::Close(code, status) => Message::close_with(code, status),
+            })
+    );
+    let mut expired = pin!(async move {
+        match expires_at_rx.await {
+            Ok(expires_at) => {
+                let left = expires_at - chrono::Utc::now().timestamp();
+                tokio::time::sleep(Duration::from_secs(left.max(0) as u64)).await;
+            }
+            // connection_init never succeeded, the protocol closes the socket itself
+            Err(_) => future::pending::<()>().await,
+        }
+        Message::close_with(TOKEN_EXPIRED_CLOSE_CODE, "Token expired")
+    });
+
+    loop {
+        let msg = tokio::select! {
+            msg = outgoing.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            msg = &mut expired => msg,
+        };
+        let is_close = matches!(msg, Message::Close(_));
+        if sink.send(msg).await.is_err() || is_close {
+            break;
+        }
     }
+}
 
-    let firebase = Firebase::new().await;
+/*
+    * Graphql endpoints, queries and mutations on / and subscriptions on /ws
+    @param schema: AppSchema
+    @param auth: AuthService used to verify websocket connections
+*/
+pub fn routes(schema: AppSchema, auth: AuthService) -> impl Endpoint {
+    Route::new()
+        .at("/", get(graphiql).post(index))
+        .at("/ws", get(ws))
+        .data(schema)
+        .data(auth)
+}
 
-    let user_iud: Arc<Mutex<UserUID>> = Arc::new(Mutex::new(UserUID("".to_string())));
-    let user: Arc<Mutex<Option<User>>> = Arc::new(Mutex::new(None));
+/*
+    * Drive the migrations from the command line
+    @param config: &PostgresConfig
+    @param args: arguments after `migrate`, i.e `up [version]`, `down [steps]` or `status`
+*/
+pub async fn run_migration_command(
+    config: &PostgresConfig,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let database = PostGreClient::new(config).await;
+    match args.first().map(|s| s.as_str()) {
+        Some("up") => {
+            let target = args.get(1).map(|v| v.parse::<i64>()).transpose()?;
+            let applied = database.migrate_up(target).await?;
+            println!("{} migration(s) applied", applied.len());
+        }
+        Some("down") => {
+            let steps = args.get(1).map(|v| v.parse::<usize>()).transpose()?;
+            let reverted = database.migrate_down(steps.unwrap_or(1)).await?;
+            println!("{} migration(s) reverted", reverted.len());
+        }
+        Some("status") => {
+            let applied = database.applied_migrations().await?;
+            for migration in database::migrations::MIGRATIONS {
+                match applied.iter().find(|a| a.version == migration.version) {
+                    Some(a) => println!(
+                        "{:>4} {:<40} applied at {}",
+                        migration.version, migration.name, a.applied_at
+                    ),
+                    None => println!("{:>4} {:<40} pending", migration.version, migration.name),
+                }
+            }
+        }
+        _ => return Err("usage: migrate <up [version] | down [steps] | status>".into()),
+    }
+    Ok(())
+}
+
+/*
+    * Start the graphql server
+    @param config: AppConfig, already validated
+*/
+pub async fn launch_server(config: AppConfig) -> Result<(), std::io::Error> {
+    // database for graphql consumption
+    let database = PostGreClient::new(&config.postgres).await;
+    database
+        .migrate_up(None)
+        .await
+        .map_err(std::io::Error::other)?;
+    let database = Database::Postgres(database);
+
+    let auth = match &config.auth {
+        AuthConfig::Firebase(firebase) => {
+            AuthService::Firebase(Arc::new(Firebase::new(firebase).await))
+        }
+        AuthConfig::Local(local) => AuthService::Local(LocalAuth::new(local, database.clone())),
+    };
+
+    let mailer = MailerService::new(&config.mail).map_err(std::io::Error::other)?;
 
-    let schema = Schema::build(Query, Mutation, EmptySubscription)
-        .data(database_arc_rw)
-        .data(firebase)
-        .data(user_iud)
-        .data(user)
+    // soft deleted users are deleted for good once the retention period is over
+    auth::suspension::spawn_purge(auth.clone(), database.unscoped(), config.users);
+
+    let schema = Schema::build(Query, Mutation, Subscription)
+        .data(database)
+        .data(auth.clone())
+        .data(mailer)
+        .data(EventBus::new())
+        .data(config.claims_fallback)
+        .data(config.invitations)
+        .data(config.impersonation)
+        .data(config.password_resets)
+        .data(config.email_verifications)
+        .extension(ImpersonationNotice)
+        .extension(RowSecurity)
         .finish();
 
+    // browsers only call the api from the configured origins
     let cors = Cors::new()
         .allow_method(Method::GET)
         .allow_method(Method::POST)
         .allow_method(Method::OPTIONS)
-        .allow_origin("http://localhost:5173")
+        .allow_origins(config.server.cors_origins.iter())
         .allow_credentials(false);
 
-    let app = Route::new().at("/", get(graphiql).post(index)).data(schema);
+    let app = routes(schema, auth).with(cors);
 
-    println!("server started on localhost:4000");
+    println!("server started on {}", config.server.listen_addr);
 
-    Server::new(TcpListener::bind("127.0.0.1:4000"))
+    Server::new(TcpListener::bind(config.server.listen_addr))
         .run(app)
         .await
-        .unwrap();
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        structs::identity::LocalIdentity,
+        traits::{identity::IdentityTrait, user::UserTrait},
+        utils::Utils,
+    };
+    use poem::listener::{Acceptor, Listener};
+    use tokio_tungstenite::tungstenite::{
+        client::IntoClientRequest, protocol::frame::coding::CloseCode, Message as WsFrame,
+    };
+
+    /*
+        * Serve the graphql routes on a random port
+        @param token_ttl: lifetime of the tokens issued by the returned provider
+        @return (websocket url, LocalAuth, uid of an existing identity)
+    */
+    async fn serve(token_ttl: Duration) -> (String, LocalAuth, LocalIdentity) {
+        let database = Utils::memory_database();
+        let mut config = Utils::local_auth_config();
+        config.token_ttl = token_ttl;
+        let auth = LocalAuth::new(&config, database.clone());
+        let identity = LocalIdentity {
+            uid: uuid::Uuid::new_v4().to_string(),
+            email: "ws@example.com".to_string(),
+            password: String::new(),
+            display_name: None,
+            email_verified: false,
+            disabled: false,
+            custom_claims: serde_json::Map::new(),
+            sessions_valid_after: None,
+            created_at: None,
+            updated_at: None,
+        };
+        database.create_identity(&identity).await.unwrap();
+
+        let schema = Schema::build(Query, Mutation, Subscription)
+            .data(database)
+            .data(EventBus::new())
+            .extension(ImpersonationNotice)
+            .extension(RowSecurity)
+            .finish();
+        let acceptor = TcpListener::bind("127.0.0.1:0")
+            .into_acceptor()
+            .await
+            .unwrap();
+        let addr = acceptor.local_addr()[0].as_socket_addr().cloned().unwrap();
+        let app = routes(schema, AuthService::Local(auth.clone()));
+        tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+        (format!("ws://{}/ws", addr), auth, identity)
+    }
+
+    /*
+        * Open a graphql-transport-ws connection and send connection_init
+        @return the first frame answered by the server
+    */
+    async fn connect(
+        url: &str,
+        token: &str,
+    ) -> (
+        tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+        WsFrame,
+    ) {
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            "graphql-transport-ws".parse().unwrap(),
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let init = serde_json::json!({"type": "connection_init", "payload": {"token": token}});
+        socket.send(WsFrame::Text(init.to_string())).await.unwrap();
+        let frame = socket.next().await.unwrap().unwrap();
+        (socket, frame)
+    }
+
+    fn close_code(frame: &WsFrame) -> Option<u16> {
+        match frame {
+            WsFrame::Close(Some(close)) => Some(close.code.into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_organization_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_organization_from_headers(&headers).unwrap(), None);
+        let id = uuid::Uuid::new_v4();
+        headers.insert(ORGANIZATION_HEADER, id.to_string().parse().unwrap());
+        assert_eq!(
+            get_organization_from_headers(&headers).unwrap(),
+            Some(OrganizationId(id))
+        );
+        headers.insert(ORGANIZATION_HEADER, "acme".parse().unwrap());
+        assert!(matches!(
+            get_organization_from_headers(&headers),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_impersonation_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_impersonation_from_headers(&headers).unwrap(), None);
+        let id = uuid::Uuid::new_v4();
+        headers.insert(IMPERSONATION_HEADER, id.to_string().parse().unwrap());
+        assert_eq!(
+            get_impersonation_from_headers(&headers).unwrap(),
+            Some(ImpersonationId(id))
+        );
+        headers.insert(IMPERSONATION_HEADER, "jane".parse().unwrap());
+        assert!(matches!(
+            get_impersonation_from_headers(&headers),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ws_accepts_a_valid_token() {
+        let (url, auth, identity) = serve(Duration::from_secs(60)).await;
+        let token = auth.issue_token(&identity).unwrap();
+        let (mut socket, frame) = connect(&url, &format!("Bearer {}", token)).await;
+        assert_eq!(
+            frame,
+            WsFrame::Text(r#"{"type":"connection_ack"}"#.to_string())
+        );
+
+        let subscribe = serde_json::json!({
+            "type": "subscribe",
+            "id": "1",
+            "payload": {"query": "subscription { myRolesChanged }"},
+        });
+        socket
+            .send(WsFrame::Text(subscribe.to_string()))
+            .await
+            .unwrap();
+        // nothing is published, the subscription stays open
+        let idle = tokio::time::timeout(Duration::from_millis(200), socket.next()).await;
+        assert!(idle.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ws_rejects_an_invalid_token() {
+        let (url, _, _) = serve(Duration::from_secs(60)).await;
+        let (_, frame) = connect(&url, "not a token").await;
+        // graphql-transport-ws closes a failed connection_init with a protocol error
+        assert_eq!(close_code(&frame), Some(u16::from(CloseCode::Protocol)));
+        match frame {
+            WsFrame::Close(Some(close)) => assert_eq!(close.reason, "Auth::Unauthorized"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_closes_when_the_token_expires() {
+        let (url, auth, identity) = serve(Duration::from_secs(1)).await;
+        let token = auth.issue_token(&identity).unwrap();
+        let (mut socket, frame) = connect(&url, &token).await;
+        assert_eq!(
+            frame,
+            WsFrame::Text(r#"{"type":"connection_ack"}"#.to_string())
+        );
+        let frame = tokio::time::timeout(Duration::from_secs(3), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(close_code(&frame), Some(TOKEN_EXPIRED_CLOSE_CODE));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_requests_keep_their_identity() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database.clone());
+        // each user signs its requests with its own token
+        let mut users = Vec::new();
+        for _ in 0..8 {
+            let uid = uuid::Uuid::new_v4().to_string();
+            database.create_test_user(&uid).await.unwrap();
+            let identity = LocalIdentity {
+                uid: uid.clone(),
+                email: format!("{}@example.com", uid),
+                password: String::new(),
+                display_name: None,
+                email_verified: false,
+                disabled: false,
+                custom_claims: serde_json::Map::new(),
+                sessions_valid_after: None,
+                created_at: None,
+                updated_at: None,
+            };
+            database.create_identity(&identity).await.unwrap();
+            users.push((uid, auth.issue_token(&identity).unwrap()));
+        }
+        let auth = AuthService::Local(auth);
+        let schema = Schema::build(Query, Mutation, Subscription)
+            .data(database)
+            .data(auth.clone())
+            .data(EventBus::new())
+            .extension(ImpersonationNotice)
+            .extension(RowSecurity)
+            .finish();
+        let app = Arc::new(routes(schema, auth));
+        let post = |app: Arc<_>, token: String, body: serde_json::Value| async move {
+            let request = poem::Request::builder()
+                .method(Method::POST)
+                .header("Authorization", format!("Bearer {}", token))
+                .content_type("application/json")
+                .body(body.to_string());
+            let response: poem::Response = Endpoint::get_response(&app, request).await;
+            response
+                .into_body()
+                .into_json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+
+        let mut handles = Vec::new();
+        for round in 0..10 {
+            for (uid, token) in users.iter() {
+                let (app, uid, token) = (app.clone(), uid.clone(), token.clone());
+                handles.push(tokio::spawn(async move {
+                    let name = format!("{}-{}", uid, round);
+                    let body = post(
+                        app.clone(),
+                        token.clone(),
+                        serde_json::json!({
+                            "query": "mutation ($userName: String!) { updateUserName(userName: $userName) { id name } }",
+                            "variables": {"userName": name},
+                        }),
+                    )
+                    .await;
+                    assert_eq!(
+                        body,
+                        serde_json::json!({"data": {"updateUserName": {"id": uid, "name": name}}})
+                    );
+
+                    let body =
+                        post(app, token, serde_json::json!({"query": "{ user { id } }"})).await;
+                    assert_eq!(body, serde_json::json!({"data": {"user": {"id": uid}}}));
+                }));
+            }
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    }
 }
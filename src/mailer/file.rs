@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use lettre::{message::Mailbox, AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::main::message;
+use crate::traits::mailer::{Email, MailError, Mailer};
+
+/*
+    * Mailer for development, nothing leaves the machine
+    Each email is written to its own .eml file in a directory, or printed without one.
+*/
+#[derive(Clone)]
+pub struct FileMailer {
+    from: Mailbox,
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    /*
+        * Create a file mailer
+        @param from: Mailbox, sender of every email
+        @param dir: Option<PathBuf>, created when missing, emails are printed when None
+        @return FileMailer
+    */
+    pub fn new(from: Mailbox, dir: Option<PathBuf>) -> FileMailer {
+        FileMailer { from, dir }
+    }
+}
+
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = message(&self.from, email)?;
+        let Some(dir) = &self.dir else {
+            println!("{}", String::from_utf8_lossy(&message.formatted()));
+            return Ok(());
+        };
+        std::fs::create_dir_all(dir)
+            .map_err(|e| MailError::Transport(format!("can't create {}: {}", dir.display(), e)))?;
+        AsyncFileTransport::<Tokio1Executor>::new(dir)
+            .send(message)
+            .await
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mailer::main::MailConfig, utils::Utils};
+
+    #[tokio::test]
+    async fn test_writes_eml_files() {
+        let dir = Utils::mail_dir();
+        let mailer = FileMailer::new(MailConfig::default().sender().unwrap(), Some(dir.clone()));
+        for to in ["jane@example.com", "john@example.com"] {
+            let email = Email {
+                to: to.to_string(),
+                subject: "Welcome".to_string(),
+                body: format!("Hello {}", to),
+            };
+            mailer.send(&email).await.unwrap();
+        }
+        let sent = Utils::take_sent_emails(&dir);
+        assert_eq!(sent.len(), 2);
+        let john = sent
+            .iter()
+            .find(|email| email.contains("To: john@example.com"))
+            .unwrap();
+        assert!(john.ends_with("Hello john@example.com"));
+        assert!(Utils::take_sent_emails(&dir).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
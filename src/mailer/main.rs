@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use lettre::{message::header::ContentType, message::Mailbox, Message};
+
+use super::{
+    file::FileMailer,
+    smtp::{SmtpConfig, SmtpMailer},
+};
+use crate::traits::mailer::{Email, MailError, Mailer};
+
+/*
+ * Where emails go, see mail.transport in the configuration
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum MailTransport {
+    // printed, for development
+    Stdout,
+    // one .eml file per email in the directory
+    File(PathBuf),
+    Smtp(SmtpConfig),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MailConfig {
+    // sender of every email, i.e "Data Intuitive <no-reply@example.com>"
+    pub from: String,
+    pub transport: MailTransport,
+}
+
+impl MailConfig {
+    /*
+        * Parse the sender
+        @return Mailbox
+    */
+    pub fn sender(&self) -> Result<Mailbox, String> {
+        self.from
+            .parse()
+            .map_err(|e| format!("invalid sender {:?}: {}", self.from, e))
+    }
+
+    /*
+        * Check the sender and the transport without creating the mailer
+        @return (), the first problem otherwise
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        self.sender()?;
+        if let MailTransport::Smtp(smtp) = &self.transport {
+            smtp.builder()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            from: "Data Intuitive <no-reply@localhost>".to_string(),
+            transport: MailTransport::Stdout,
+        }
+    }
+}
+
+/*
+    * Mailer selected by the configuration (mail.transport)
+    Resolvers only depend on this type, never on a concrete backend.
+*/
+#[derive(Clone)]
+pub enum MailerService {
+    File(FileMailer),
+    Smtp(SmtpMailer),
+}
+
+impl MailerService {
+    /*
+        * Create the mailer of a configuration
+        @param config: &MailConfig, validated when the configuration is loaded
+        @return MailerService
+    */
+    pub fn new(config: &MailConfig) -> Result<MailerService, String> {
+        let from = config.sender()?;
+        Ok(match &config.transport {
+            MailTransport::Stdout => MailerService::File(FileMailer::new(from, None)),
+            MailTransport::File(dir) => {
+                MailerService::File(FileMailer::new(from, Some(dir.clone())))
+            }
+            MailTransport::Smtp(smtp) => MailerService::Smtp(SmtpMailer::new(from, smtp)?),
+        })
+    }
+}
+
+// emails are printed when no mailer is configured, i.e in tests
+impl Default for MailerService {
+    fn default() -> Self {
+        MailerService::new(&MailConfig::default()).expect("Invalid default sender")
+    }
+}
+
+impl Mailer for MailerService {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        match self {
+            MailerService::File(file) => file.send(email).await,
+            MailerService::Smtp(smtp) => smtp.send(email).await,
+        }
+    }
+}
+
+/*
+    * Build the message of an email, whatever the backend
+    @param from: &Mailbox
+    @param email: &Email
+    @return Message, plain text
+*/
+pub(crate) fn message(from: &Mailbox, email: &Email) -> Result<Message, MailError> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| MailError::Invalid(format!("{:?}: {}", email.to, e)))?;
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.as_str())
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| MailError::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Hello".to_string(),
+            body: "Hello Jane".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let from = MailConfig::default().sender().unwrap();
+        let message = message(&from, &email("jane@example.com")).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("From: \"Data Intuitive\" <no-reply@localhost>"));
+        assert!(formatted.contains("To: jane@example.com"));
+        assert!(formatted.contains("Subject: Hello"));
+        assert!(formatted.ends_with("Hello Jane"));
+
+        assert!(matches!(
+            super::message(&from, &email("jane")),
+            Err(MailError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_sender() {
+        let config = MailConfig {
+            from: "not an address".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(MailerService::new(&config).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod file;
+pub mod main;
+pub mod smtp;
@@ -0,0 +1,232 @@
+use std::str::FromStr;
+
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, AsyncSmtpTransportBuilder},
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+
+use super::main::message;
+use crate::traits::mailer::{Email, MailError, Mailer};
+
+/*
+    * Encryption of the connection to the smtp server
+    starttls upgrades a plain connection and fails when the server can't, tls is implicit
+    from the first byte (port 465). none is for servers on the same host or network only.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SmtpTls {
+    None,
+    #[default]
+    StartTls,
+    Tls,
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            _ => Err(format!(
+                "unknown smtp tls {}, expected one of none, starttls, tls",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    // both or neither, the server is used without authentication otherwise
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+}
+
+impl SmtpConfig {
+    /*
+        * Check the configuration and prepare the transport, nothing runs yet
+        @return AsyncSmtpTransportBuilder
+    */
+    pub fn builder(&self) -> Result<AsyncSmtpTransportBuilder, String> {
+        let builder: AsyncSmtpTransportBuilder = match self.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)
+                    .map_err(|e| format!("invalid smtp host {:?}: {}", self.host, e))?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)
+                .map_err(|e| format!("invalid smtp host {:?}: {}", self.host, e))?,
+        };
+        let builder = builder.port(self.port);
+        match (&self.user, &self.password) {
+            (Some(user), Some(password)) => {
+                Ok(builder.credentials(Credentials::new(user.clone(), password.clone())))
+            }
+            (None, None) => Ok(builder),
+            _ => Err("smtp user and password must be set together".to_string()),
+        }
+    }
+
+    /*
+        * Build the transport, nothing is connected until the first email
+        The pool spawns its cleanup task, call it within the tokio runtime.
+        @return AsyncSmtpTransport
+    */
+    pub fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+        Ok(self.builder()?.build())
+    }
+}
+
+/*
+ * Mailer handing emails to an smtp server, connections are pooled
+*/
+#[derive(Clone)]
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    /*
+        * Create an smtp mailer
+        @param from: Mailbox, sender of every email
+        @param config: &SmtpConfig
+        @return SmtpMailer
+    */
+    pub fn new(from: Mailbox, config: &SmtpConfig) -> Result<SmtpMailer, String> {
+        Ok(SmtpMailer {
+            from,
+            transport: config.transport()?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        self.transport
+            .send(message(&self.from, email)?)
+            .await
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::mailer::main::MailConfig;
+
+    /*
+        * Smtp server on a random port accepting any email, or refusing some recipients
+        @param refused: recipient answered with a 550
+        @return (port, receiver of the DATA of every accepted email)
+    */
+    async fn sink(refused: &'static str) -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+                    let mut data: Option<String> = None;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Some(body) = data.as_mut() {
+                            if line == "." {
+                                sender.send(data.take().unwrap()).unwrap();
+                                writer.write_all(b"250 queued\r\n").await.unwrap();
+                            } else {
+                                body.push_str(&line);
+                                body.push('\n');
+                            }
+                            continue;
+                        }
+                        let command = line.to_uppercase();
+                        let answer: &[u8] = if command.starts_with("EHLO") {
+                            b"250 sink\r\n"
+                        } else if command.starts_with("RCPT") && line.contains(refused) {
+                            b"550 no such user\r\n"
+                        } else if command.starts_with("DATA") {
+                            data = Some(String::new());
+                            b"354 go ahead\r\n"
+                        } else if command.starts_with("QUIT") {
+                            writer.write_all(b"221 bye\r\n").await.unwrap();
+                            break;
+                        } else {
+                            b"250 ok\r\n"
+                        };
+                        writer.write_all(answer).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, receiver)
+    }
+
+    fn mailer(port: u16) -> SmtpMailer {
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            user: None,
+            password: None,
+            tls: SmtpTls::None,
+        };
+        SmtpMailer::new(MailConfig::default().sender().unwrap(), &config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sends_to_an_smtp_server() {
+        let (port, mut received) = sink("nobody@example.com").await;
+        let mailer = mailer(port);
+        let email = Email {
+            to: "jane@example.com".to_string(),
+            subject: "Verify your email".to_string(),
+            body: "Hello Jane".to_string(),
+        };
+        mailer.send(&email).await.unwrap();
+        let data = received.recv().await.unwrap();
+        assert!(data.contains("To: jane@example.com"));
+        assert!(data.contains("Subject: Verify your email"));
+        assert!(data.contains("Hello Jane"));
+
+        let refused = Email {
+            to: "nobody@example.com".to_string(),
+            ..email
+        };
+        assert!(matches!(
+            mailer.send(&refused).await,
+            Err(MailError::Transport(_))
+        ));
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut config = SmtpConfig {
+            host: "smtp.example.com".to_string(),
+            port: 587,
+            user: Some("jane".to_string()),
+            password: None,
+            tls: SmtpTls::StartTls,
+        };
+        assert!(config.builder().is_err());
+        config.password = Some("password".to_string());
+        assert!(config.builder().is_ok());
+        assert_eq!("tls".parse(), Ok(SmtpTls::Tls));
+        assert!("ssl".parse::<SmtpTls>().is_err());
+    }
+}
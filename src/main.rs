@@ -1,7 +1,37 @@
+use data_intuitive::config::main::{AppConfig, ConfigSource};
+
+/*
+ * data_intuitive [--config <path>] [--<key>=<value>...]
+ * data_intuitive migrate <up [version] | down [steps] | status> [--<key>=<value>...]
+*/
 #[tokio::main]
 pub async fn main() {
     dotenv::dotenv().ok();
-    data_intuitive::launch_server()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|s| s.as_str()) == Some("migrate") {
+        let command: Vec<String> = args[1..]
+            .iter()
+            .take_while(|arg| !arg.starts_with("--"))
+            .cloned()
+            .collect();
+        let options = &args[1 + command.len()..];
+        let config = ConfigSource::load(options).postgres().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if let Err(e) = data_intuitive::run_migration_command(&config, &command).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let config = AppConfig::load(&args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    data_intuitive::launch_server(config)
         .await
         .expect("Failed to launch server");
 }
@@ -1,72 +1,1268 @@
-use std::sync::Arc;
-
 use crate::{
-    contexts::user_uid::UserUID,
-    database::main::PostGreClient,
-    guards::{auth::AuthTokenGuard, user::UserExistGuard},
-    structs::user::User,
-    traits::user::UserTrait,
+    auth::{
+        account::{delete_account, delete_my_account},
+        main::AuthService,
+        password::{change_password, request_password_reset, reset_password},
+        signup::sign_up,
+        suspension::{reactivate_user, soft_delete_user, suspend_user},
+        verification::{change_email, send_email_verification, verify_email},
+    },
+    contexts::{identity::Identity, organization::OrganizationId},
+    database::{backend::Database, error::DatabaseError},
+    errors::main::{AppError, AppResultExt},
+    events::main::{EventBus, UserEvent},
+    guards::{
+        auth::AuthTokenGuard, permission::PermissionGuard, role::RoleGuard, user::UserExistGuard,
+    },
+    mailer::main::MailerService,
+    structs::{
+        api_key::{ApiKey, ApiKeyTicket},
+        claims::Plan,
+        email_verification::EmailVerificationConfig,
+        impersonation::{Impersonation, ImpersonationConfig},
+        invitation::{Invitation, InvitationConfig},
+        one_time_token::OneTimeToken,
+        organization::{Membership, Organization},
+        password_reset::PasswordResetConfig,
+        permission::Permission,
+        role::{Role, RoleCatalogue, RoleDefinition},
+        user::{hash_password, User},
+    },
+    traits::{
+        api_key::ApiKeyTrait,
+        auth::{AuthError, AuthProvider},
+        impersonation::ImpersonationTrait,
+        invitation::InvitationTrait,
+        mailer::Mailer,
+        organization::OrganizationTrait,
+        permission::PermissionTrait,
+        role::RoleTrait,
+        user::UserTrait,
+    },
 };
 use async_graphql::*;
-use tokio::sync::{Mutex, RwLock};
+use chrono::Utc;
+use uuid::Uuid;
 
 pub struct Mutation;
 
 #[Object]
 impl Mutation {
+    async fn sign_in<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        email: String,
+        password: String,
+    ) -> Result<String, Error> {
+        let auth = ctx.data::<AuthService>()?;
+        auth.sign_in(&email, &password).await.app_err()
+    }
+
+    /*
+        * Sign a new user up with the auth provider and the database, all or nothing
+        Identities created by clients with the sdk of the provider use createUser instead.
+        A link to verify the email is sent along.
+        @param input: UserInput, its id is ignored
+        @return User, sign in afterwards for a token
+    */
+    async fn sign_up<'ctx>(&self, ctx: &Context<'ctx>, input: User) -> Result<User, Error> {
+        let auth = ctx.data::<AuthService>()?;
+        // nobody is signed in yet, the row level security of the request would refuse the user
+        let database = ctx.data::<Database>()?.unscoped();
+        let user = sign_up(auth, &database, &input)
+            .await
+            .map_err(|e| e.extend())?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        send_verification_or_log(ctx, &user).await;
+        Ok(user)
+    }
+
+    /*
+        * Send a new link to verify the email of the signed in user, previous ones stop working
+        @return false when the email is already verified, nothing is sent then
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn send_verification_email<'ctx>(&self, ctx: &Context<'ctx>) -> Result<bool, Error> {
+        let uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let user = ctx.data::<Database>()?.get_user(&uid.0).await.app_err()?;
+        if user.email_verified {
+            return Ok(false);
+        }
+        send_verification(ctx, &user)
+            .await
+            .map_err(|e| e.extend())?;
+        Ok(true)
+    }
+
+    /*
+        * Verify an email with the token of the link sent to it
+        @param token: String
+        @return true, tokens issued afterwards say the email is verified
+    */
+    async fn verify_email<'ctx>(&self, ctx: &Context<'ctx>, token: String) -> Result<bool, Error> {
+        // the link may be opened signed out, or in another browser
+        let database = ctx.data::<Database>()?.unscoped();
+        let verification = verify_email(ctx.data::<AuthService>()?, &database, &token)
+            .await
+            .map_err(|e| e.extend())?;
+        let user = database.get_user(&verification.user_id).await.app_err()?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user));
+        Ok(true)
+    }
+
+    /*
+        * Change the email of the signed in user, a link to verify the new one is sent to it
+        @param email: String
+        @param current_password: String
+        @return User, its email is unverified until the link is followed
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn change_email<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        email: String,
+        current_password: String,
+    ) -> Result<User, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        // like the password, the email is the user's alone
+        ensure_not_api_key(identity)?;
+        if identity.impersonation().is_some() {
+            return Err(AppError::Forbidden.extend());
+        }
+        let user = change_email(
+            ctx.data::<AuthService>()?,
+            ctx.data::<Database>()?,
+            &uid.0,
+            &current_password,
+            &email,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        send_verification_or_log(ctx, &user).await;
+        Ok(user)
+    }
+
+    /*
+        * Delete the signed in user for good, its identity and everything stored about it
+        Export the data first with exportMyData, nothing can be recovered afterwards.
+        @param current_password: String
+        @return true, tokens of the user stop working
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn delete_my_account<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        current_password: String,
+    ) -> Result<bool, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        ensure_not_api_key(identity)?;
+        if identity.impersonation().is_some() {
+            return Err(AppError::Forbidden.extend());
+        }
+        // the last Admin checks read every Admin row
+        let database = ctx.data::<Database>()?.unscoped();
+        delete_my_account(
+            ctx.data::<AuthService>()?,
+            &database,
+            &uid.0,
+            &current_password,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        Ok(true)
+    }
+
+    /*
+        * Delete any user for good, like deleteMyAccount without its password
+        @param uid: String
+        @return true
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn delete_user<'ctx>(&self, ctx: &Context<'ctx>, uid: String) -> Result<bool, Error> {
+        let database = ctx.data::<Database>()?.unscoped();
+        delete_account(ctx.data::<AuthService>()?, &database, &uid)
+            .await
+            .map_err(|e| e.extend())?;
+        Ok(true)
+    }
+
+    /*
+        * Suspend a user, it can't sign in nor use the api until reactivated
+        @param uid: String
+        @param reason: String, kept with the user
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(UserExistGuard).and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn suspend_user<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        reason: String,
+    ) -> Result<User, Error> {
+        ensure_not_self(ctx, &uid)?;
+        let user = suspend_user(
+            ctx.data::<AuthService>()?,
+            ctx.data::<Database>()?,
+            &uid,
+            &reason,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        Ok(user)
+    }
+
+    /*
+        * Soft delete a user, suspended until purged for good once the retention period is over
+        @param uid: String
+        @param reason: String, kept with the user
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(UserExistGuard).and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn soft_delete_user<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        reason: String,
+    ) -> Result<User, Error> {
+        ensure_not_self(ctx, &uid)?;
+        let user = soft_delete_user(
+            ctx.data::<AuthService>()?,
+            ctx.data::<Database>()?,
+            &uid,
+            &reason,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        Ok(user)
+    }
+
+    /*
+        * Lift the suspension or the soft delete of a user not purged yet
+        @param uid: String
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(UserExistGuard).and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn reactivate_user<'ctx>(&self, ctx: &Context<'ctx>, uid: String) -> Result<User, Error> {
+        let user = reactivate_user(ctx.data::<AuthService>()?, ctx.data::<Database>()?, &uid)
+            .await
+            .map_err(|e| e.extend())?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        Ok(user)
+    }
+
+    /*
+        * Change the password of the signed in user, its other sessions are revoked
+        @param current_password: String
+        @param new_password: String
+        @return token of a new session, the one in use stops working
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn change_password<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        current_password: String,
+        new_password: String,
+    ) -> Result<String, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        // the password is the user's alone, not its keys' nor its support staff's
+        ensure_not_api_key(identity)?;
+        if identity.impersonation().is_some() {
+            return Err(AppError::Forbidden.extend());
+        }
+        let database = ctx.data::<Database>()?;
+        let email = match identity.claims().and_then(|claims| claims.email.clone()) {
+            Some(email) => email,
+            None => database.get_user(&uid.0).await.app_err()?.email,
+        };
+        change_password(
+            ctx.data::<AuthService>()?,
+            database,
+            &uid.0,
+            &email,
+            &current_password,
+            &new_password,
+        )
+        .await
+        .map_err(|e| e.extend())
+    }
+
+    /*
+        * Send a link to choose a new password to an email, if a user has it
+        The answer is the same either way, it tells nothing about who signed up.
+        @param email: String
+        @return true
+    */
+    async fn request_password_reset<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        email: String,
+    ) -> Result<bool, Error> {
+        let config = ctx
+            .data_opt::<PasswordResetConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let default_mailer = MailerService::default();
+        let mailer = ctx.data_opt::<MailerService>().unwrap_or(&default_mailer);
+        // nobody is signed in, the row level security of the request would hide the user
+        let database = ctx.data::<Database>()?.unscoped();
+        match request_password_reset(&database, mailer, &email, &config).await {
+            Ok(_) => Ok(true),
+            // a failing mailer would tell who signed up, it is only logged
+            Err(AppError::Upstream(e)) => {
+                eprintln!("password reset email not sent: {}", e);
+                Ok(true)
+            }
+            Err(e) => Err(e.extend()),
+        }
+    }
+
+    /*
+        * Choose a new password with the token of requestPasswordReset, every session of the user is revoked
+        @param token: String
+        @param new_password: String
+        @return true, sign in with the new password afterwards
+    */
+    async fn reset_password<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        token: String,
+        new_password: String,
+    ) -> Result<bool, Error> {
+        let database = ctx.data::<Database>()?.unscoped();
+        reset_password(ctx.data::<AuthService>()?, &database, &token, &new_password)
+            .await
+            .map_err(|e| e.extend())?;
+        Ok(true)
+    }
+
     #[graphql(guard = "AuthTokenGuard")]
     async fn create_user<'ctx>(&self, ctx: &Context<'ctx>, input: User) -> Result<User, Error> {
-        let _useruid = ctx.data::<Arc<Mutex<UserUID>>>().unwrap().lock().await;
-        let database = ctx
-            .data::<Arc<RwLock<PostGreClient>>>()
-            .unwrap()
-            .read()
-            .await;
+        let user_uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+        let events = ctx.data::<EventBus>()?;
         let mut input = input;
-        input.fill_id(_useruid.0.clone());
-        Ok(database.create_user(&input).await?)
+        input.fill_id(user_uid.0.clone());
+        let user = database.create_user(&input).await.app_err()?;
+        events.publish(UserEvent::ProfileChanged(user.clone()));
+        sync_claims(ctx, &user_uid.0).await?;
+        Ok(user)
     }
 
-    #[graphql(guard = "AuthTokenGuard.and(UserExistGuard)")]
+    #[graphql(
+        guard = "AuthTokenGuard.and(UserExistGuard).and(PermissionGuard::new(\"user:update\"))"
+    )]
     async fn update_user_name<'ctx>(
         &self,
         ctx: &Context<'ctx>,
         user_name: String,
     ) -> Result<User, Error> {
-        let database = ctx
-            .data::<Arc<RwLock<PostGreClient>>>()
-            .unwrap()
-            .read()
-            .await;
-        let _useruid = ctx.data::<Arc<Mutex<UserUID>>>().unwrap().lock().await;
-        Ok(database.update_user_name(&user_name, &_useruid.0).await?)
+        let user_uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+        let events = ctx.data::<EventBus>()?;
+        let user = database
+            .update_user_name(&user_name, &user_uid.0)
+            .await
+            .app_err()?;
+        events.publish(UserEvent::ProfileChanged(user.clone()));
+        Ok(user)
+    }
+
+    /*
+        * Give a role to a user, granting a role already held is a no-op
+        @param uid: String
+        @param role: Role
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn grant_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        role: Role,
+    ) -> Result<User, Error> {
+        let database = ctx.data::<Database>()?;
+        match database.save_user_role(&uid, &role).await {
+            Ok(()) | Err(DatabaseError::Conflict(_)) => {}
+            Err(e) => return Err(e).app_err(),
+        }
+        sync_claims(ctx, &uid).await?;
+        database.get_user(&uid).await.app_err()
+    }
+
+    /*
+        * Take a role from a user, the last Admin keeps the Admin role
+        @param uid: String
+        @param role: Role
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn revoke_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        role: Role,
+    ) -> Result<User, Error> {
+        let database = ctx.data::<Database>()?;
+        database.remove_user_role(&uid, &role).await.app_err()?;
+        sync_claims(ctx, &uid).await?;
+        database.get_user(&uid).await.app_err()
+    }
+
+    /*
+        * Change the plan of a user, pushed to its claims like its roles
+        @param uid: String
+        @param plan: Plan
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn set_user_plan<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        plan: Plan,
+    ) -> Result<User, Error> {
+        let user = ctx
+            .data::<Database>()?
+            .update_user_plan(&uid, plan)
+            .await
+            .app_err()?;
+        sync_claims(ctx, &uid).await?;
+        Ok(user)
+    }
+
+    /*
+        * Add a role to the catalogue, it implies its parent and every role above
+        @param name: Role
+        @param description: String
+        @param parent: Option<Role>, usually Role::USER
+        @return RoleDefinition
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn create_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: Role,
+        #[graphql(default)] description: String,
+        parent: Option<Role>,
+    ) -> Result<RoleDefinition, Error> {
+        ctx.data::<Database>()?
+            .create_role(&name, &description, parent.as_ref())
+            .await
+            .app_err()
+    }
+
+    /*
+        * Rename a custom role, the users holding it keep it under the new name
+        @param name: Role
+        @param new_name: Role
+        @return RoleDefinition
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn rename_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: Role,
+        new_name: Role,
+    ) -> Result<RoleDefinition, Error> {
+        let database = ctx.data::<Database>()?;
+        let definition = database.rename_role(&name, &new_name).await.app_err()?;
+        for user in database.get_users_by_role(&new_name).await.app_err()? {
+            sync_claims(ctx, &user.id.unwrap_or_default()).await?;
+        }
+        Ok(definition)
+    }
+
+    /*
+        * Delete a custom role, it is taken from every user holding it
+        @param name: Role
+        @return true
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn delete_role<'ctx>(&self, ctx: &Context<'ctx>, name: Role) -> Result<bool, Error> {
+        let database = ctx.data::<Database>()?;
+        let holders = database.get_users_by_role(&name).await.app_err()?;
+        database.delete_role(&name).await.app_err()?;
+        for user in holders {
+            sync_claims(ctx, &user.id.unwrap_or_default()).await?;
+        }
+        Ok(true)
+    }
+
+    /*
+        * Add a permission to the catalogue, granted to no role yet
+        @param name: String, resource:action
+        @param description: String
+        @return Permission
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn create_permission<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: String,
+        #[graphql(default)] description: String,
+    ) -> Result<Permission, Error> {
+        Permission::validate_name(&name)
+            .map_err(AppError::Validation)
+            .app_err()?;
+        ctx.data::<Database>()?
+            .create_permission(&name, &description)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Remove a permission from the catalogue and from every role
+        @param name: String
+        @return true
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn delete_permission<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: String,
+    ) -> Result<bool, Error> {
+        ctx.data::<Database>()?
+            .delete_permission(&name)
+            .await
+            .app_err()?;
+        Ok(true)
+    }
+
+    /*
+        * Grant a permission to a role, granting it twice is a no-op
+        @param role: Role
+        @param permission: String
+        @return permissions granted to the role
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn grant_permission<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        role: Role,
+        permission: String,
+    ) -> Result<Vec<String>, Error> {
+        let database = ctx.data::<Database>()?;
+        match database.grant_permission(&role, &permission).await {
+            Ok(()) | Err(DatabaseError::Conflict(_)) => {}
+            Err(e) => return Err(e).app_err(),
+        }
+        database.get_role_permissions(&role).await.app_err()
+    }
+
+    /*
+        * Revoke a permission from a role
+        @param role: Role
+        @param permission: String
+        @return permissions granted to the role
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn revoke_permission<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        role: Role,
+        permission: String,
+    ) -> Result<Vec<String>, Error> {
+        let database = ctx.data::<Database>()?;
+        database
+            .revoke_permission(&role, &permission)
+            .await
+            .app_err()?;
+        database.get_role_permissions(&role).await.app_err()
+    }
+
+    /*
+        * Create an organization, the signed in user joins it as Admin
+        @param name: String
+        @return Organization
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::USER))")]
+    async fn create_organization<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: String,
+    ) -> Result<Organization, Error> {
+        let uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        Organization::validate_name(&name)
+            .map_err(AppError::Validation)
+            .app_err()?;
+        // nobody is a member of the new row yet, the policies would hide it from its creator
+        ctx.data::<Database>()?
+            .unscoped()
+            .create_organization(&name, &uid.0)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Rename an organization, its Admins only
+        @param organization_id: Option<Uuid>, defaults to the organization of the request
+        @param name: String
+        @return Organization
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN).organization(organization_id))"
+    )]
+    async fn rename_organization<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+        name: String,
+    ) -> Result<Organization, Error> {
+        let organization_id = OrganizationId::required(ctx, organization_id)?;
+        Organization::validate_name(&name)
+            .map_err(AppError::Validation)
+            .app_err()?;
+        ctx.data::<Database>()?
+            .rename_organization(&organization_id, &name)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Delete an organization along with its memberships, its Admins only
+        @param organization_id: Option<Uuid>, defaults to the organization of the request
+        @return true
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN).organization(organization_id))"
+    )]
+    async fn delete_organization<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+    ) -> Result<bool, Error> {
+        let organization_id = OrganizationId::required(ctx, organization_id)?;
+        ctx.data::<Database>()?
+            .delete_organization(&organization_id)
+            .await
+            .app_err()?;
+        Ok(true)
     }
+
+    /*
+        * Add a member to an organization or change its role, the last Admin keeps the Admin role
+        @param organization_id: Option<Uuid>, defaults to the organization of the request
+        @param uid: String
+        @param role: Role
+        @return Membership
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN).organization(organization_id))"
+    )]
+    async fn set_member_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+        uid: String,
+        role: Role,
+    ) -> Result<Membership, Error> {
+        let organization_id = OrganizationId::required(ctx, organization_id)?;
+        ctx.data::<Database>()?
+            .save_membership(&organization_id, &uid, &role)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Remove a member from an organization, the last Admin stays
+        @param organization_id: Option<Uuid>, defaults to the organization of the request
+        @param uid: String
+        @return true
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN).organization(organization_id))"
+    )]
+    async fn remove_member<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+        uid: String,
+    ) -> Result<bool, Error> {
+        let organization_id = OrganizationId::required(ctx, organization_id)?;
+        ctx.data::<Database>()?
+            .remove_membership(&organization_id, &uid)
+            .await
+            .app_err()?;
+        Ok(true)
+    }
+
+    /*
+        * Invite an email with the role it gets once accepted,
+        Managers invite with their role or a role below it
+        @param email: String
+        @param role: Role, User by default
+        @param organization_id: Option<Uuid>, defaults to the organization of the request,
+        the invitation is to the platform when none is selected
+        @return Invitation, its token is only emailed to the invitee
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::MANAGER).active_organization(organization_id))"
+    )]
+    async fn invite_user<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        email: String,
+        #[graphql(default_with = "Role::USER")] role: Role,
+        organization_id: Option<Uuid>,
+    ) -> Result<Invitation, Error> {
+        let uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let organization_id = OrganizationId::active(ctx, organization_id);
+        let email = Invitation::normalize_email(&email)
+            .map_err(AppError::Validation)
+            .app_err()?;
+        ensure_can_invite(ctx, organization_id, &role).await?;
+        let config = ctx
+            .data_opt::<InvitationConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let token = OneTimeToken::generate();
+        let invitation = Invitation {
+            id: Uuid::new_v4(),
+            email,
+            role,
+            organization_id,
+            token_hash: token.hash,
+            invited_by: Some(uid.0.clone()),
+            expires_at: config.expires_at(Utc::now()),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        let invitation = ctx
+            .data::<Database>()?
+            .create_invitation(&invitation)
+            .await
+            .app_err()?;
+        send_invitation(ctx, &config, &invitation, &token.token).await?;
+        Ok(invitation)
+    }
+
+    /*
+        * Email an invitation a new token and expiry, the previous token stops working
+        @param id: Uuid
+        @return Invitation
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn resend_invitation<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: Uuid,
+    ) -> Result<Invitation, Error> {
+        let database = ctx.data::<Database>()?;
+        let invitation = database.get_invitation(&id).await.app_err()?;
+        ensure_can_invite(ctx, invitation.organization_id, &invitation.role).await?;
+        let config = ctx
+            .data_opt::<InvitationConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let token = OneTimeToken::generate();
+        let invitation = database
+            .renew_invitation(&id, &token.hash, config.expires_at(Utc::now()))
+            .await
+            .app_err()?;
+        send_invitation(ctx, &config, &invitation, &token.token).await?;
+        Ok(invitation)
+    }
+
+    /*
+        * Revoke an invitation before it is accepted
+        @param id: Uuid
+        @return Invitation
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn revoke_invitation<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: Uuid,
+    ) -> Result<Invitation, Error> {
+        let database = ctx.data::<Database>()?;
+        let invitation = database.get_invitation(&id).await.app_err()?;
+        ensure_can_invite(ctx, invitation.organization_id, &invitation.role).await?;
+        database.revoke_invitation(&id).await.app_err()
+    }
+
+    /*
+        * Redeem an invitation sent to the verified email of the signed in identity,
+        the user is created unless it exists and gets the role of the invitation
+        @param token: String
+        @param name: String, ignored when the user exists
+        @param password: String, ignored when the user exists
+        @return User
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn accept_invitation<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        token: String,
+        name: String,
+        password: String,
+    ) -> Result<User, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        let database = ctx.data::<Database>()?;
+        let token_hash = OneTimeToken::hash(&token);
+        // the token proves the invitation was sent to the caller, the policies only know the inviters
+        let invitation = database
+            .unscoped()
+            .get_invitation_by_token(&token_hash)
+            .await
+            .app_err()?;
+        // a leaked link is of no use to another account, nor to one claiming the email without owning it
+        let claims = identity.claims();
+        let email = claims.and_then(|claims| claims.email.as_deref());
+        if email.map(str::to_lowercase).as_deref() != Some(invitation.email.as_str()) {
+            return Err(AppError::Forbidden.extend());
+        }
+        if !claims.is_some_and(|claims| claims.email_verified) {
+            return Err(AppError::EmailNotVerified.extend());
+        }
+        let user = User {
+            id: Some(uid.0.clone()),
+            name,
+            email: invitation.email.clone(),
+            password,
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: None,
+            updated_at: None,
+        };
+        // the invitation grants the role, which the user couldn't do on its own
+        database
+            .unscoped()
+            .accept_invitation(&token_hash, &user)
+            .await
+            .app_err()?;
+        let user = database.get_user(&uid.0).await.app_err()?;
+        ctx.data::<EventBus>()?
+            .publish(UserEvent::ProfileChanged(user.clone()));
+        sync_claims(ctx, &uid.0).await?;
+        Ok(user)
+    }
+
+    /*
+        * Create an api key for the signed in user, or for another one, i.e a service account, by an Admin
+        @param name: String
+        @param scopes: Vec<Role>, roles the key acts with, User by default
+        @param uid: Option<String>, the signed in user by default
+        @return ApiKeyTicket, its key is never shown again
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn create_api_key<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        name: String,
+        #[graphql(default_with = "vec![Role::USER]")] scopes: Vec<Role>,
+        uid: Option<String>,
+    ) -> Result<ApiKeyTicket, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let signed_in = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        ensure_not_api_key(identity)?;
+        let owner = uid.unwrap_or_else(|| signed_in.0.clone());
+        ensure_self_or_admin(ctx, &owner).await?;
+        ApiKey::validate_name(&name)
+            .map_err(AppError::Validation)
+            .app_err()?;
+        validate_scopes(ctx, &owner, &scopes).await?;
+
+        let id = Uuid::new_v4();
+        let (key, secret) = ApiKey::generate(&id);
+        let api_key = ApiKey {
+            id,
+            user_id: owner,
+            name,
+            scopes,
+            key_hash: hash_password(&secret)
+                .map_err(DatabaseError::from)
+                .app_err()?,
+            created_by: Some(signed_in.0.clone()),
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        let api_key = ctx
+            .data::<Database>()?
+            .create_api_key(&api_key)
+            .await
+            .app_err()?;
+        Ok(ApiKeyTicket { api_key, key })
+    }
+
+    /*
+        * Rename an api key or change its scopes
+        @param id: Uuid
+        @param name: Option<String>
+        @param scopes: Option<Vec<Role>>
+        @return ApiKey
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn update_api_key<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: Uuid,
+        name: Option<String>,
+        scopes: Option<Vec<Role>>,
+    ) -> Result<ApiKey, Error> {
+        // a key widening its own scopes would hold what it was never given
+        ensure_not_api_key(ctx.data::<Identity>()?)?;
+        let database = ctx.data::<Database>()?;
+        let api_key = database.get_api_key(&id).await.app_err()?;
+        ensure_self_or_admin(ctx, &api_key.user_id).await?;
+        if let Some(name) = &name {
+            ApiKey::validate_name(name)
+                .map_err(AppError::Validation)
+                .app_err()?;
+        }
+        if let Some(scopes) = &scopes {
+            validate_scopes(ctx, &api_key.user_id, scopes).await?;
+        }
+        database
+            .update_api_key(&id, name.as_deref(), scopes.as_deref())
+            .await
+            .app_err()
+    }
+
+    /*
+        * Revoke an api key, requests using it are rejected at once
+        @param id: Uuid
+        @return ApiKey
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn revoke_api_key<'ctx>(&self, ctx: &Context<'ctx>, id: Uuid) -> Result<ApiKey, Error> {
+        let database = ctx.data::<Database>()?;
+        let api_key = database.get_api_key(&id).await.app_err()?;
+        ensure_self_or_admin(ctx, &api_key.user_id).await?;
+        database.revoke_api_key(&id).await.app_err()
+    }
+
+    /*
+        * Start acting as another user, requests sent with the X-Impersonate header set to the id
+        of the session are made as that user until it ends or expires, and are all recorded
+        @param uid: String, user to act as
+        @param reason: String, i.e the support ticket
+        @param minutes: Option<u32>, impersonation.max_minutes by default
+        @param read_only: bool, mutations are refused while true
+        @return Impersonation
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn start_impersonation<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: String,
+        reason: String,
+        minutes: Option<u32>,
+        #[graphql(default = true)] read_only: bool,
+    ) -> Result<Impersonation, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let admin = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        ensure_not_api_key(identity)?;
+        // sessions don't nest, the real uid stays the one recorded
+        if identity.impersonation().is_some() {
+            return Err(AppError::Forbidden.extend());
+        }
+        if admin.0 == uid {
+            return Err(
+                AppError::Validation("an Admin can't impersonate itself".to_string()).extend(),
+            );
+        }
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(AppError::Validation("a reason is required".to_string()).extend());
+        }
+        let config = ctx
+            .data_opt::<ImpersonationConfig>()
+            .copied()
+            .unwrap_or_default();
+        let expires_at = config
+            .expires_at(Utc::now(), minutes)
+            .map_err(AppError::Validation)
+            .app_err()?;
+
+        let impersonation = Impersonation {
+            id: Uuid::new_v4(),
+            admin_uid: admin.0.clone(),
+            target_uid: uid,
+            reason: reason.to_string(),
+            read_only,
+            expires_at,
+            ended_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        ctx.data::<Database>()?
+            .create_impersonation(&impersonation)
+            .await
+            .app_err()
+    }
+
+    /*
+        * End an impersonation session before it expires, any Admin can end any session
+        @param id: Uuid
+        @return Impersonation
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn end_impersonation<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: Uuid,
+    ) -> Result<Impersonation, Error> {
+        ctx.data::<Database>()?
+            .end_impersonation(&id)
+            .await
+            .app_err()
+    }
+}
+
+/*
+    * Let the user itself through, and global Admins
+    @param ctx: &Context<'_>
+    @param uid: &str, user the change is about
+*/
+async fn ensure_self_or_admin(ctx: &Context<'_>, uid: &str) -> Result<(), Error> {
+    let identity = ctx.data::<Identity>()?;
+    if identity.uid().is_some_and(|signed_in| signed_in.0 == uid) {
+        return Ok(());
+    }
+    RoleGuard::at_least(Role::ADMIN).check(ctx).await
+}
+
+/*
+    * Refuse requests authenticated by an api key, keys don't manage keys
+    @param identity: &Identity
+*/
+fn ensure_not_api_key(identity: &Identity) -> Result<(), Error> {
+    match identity.api_key_id() {
+        Some(_) => Err(AppError::Forbidden.extend()),
+        None => Ok(()),
+    }
+}
+
+/*
+    * Refuse Admins acting on themselves, one of them always stays active
+    @param ctx: &Context<'_>
+    @param uid: &str, the user acted on
+*/
+fn ensure_not_self(ctx: &Context<'_>, uid: &str) -> Result<(), Error> {
+    let identity = ctx.data::<Identity>()?;
+    match identity.uid() {
+        Some(own) if own.0 == uid => Err(AppError::Validation(
+            "an Admin can't suspend or delete itself".to_string(),
+        )
+        .extend()),
+        _ => Ok(()),
+    }
+}
+
+/*
+    * Scopes of an api key must be roles its user holds, directly or through a role above
+    @param ctx: &Context<'_>
+    @param uid: &str, user of the key
+    @param scopes: &[Role]
+*/
+async fn validate_scopes(ctx: &Context<'_>, uid: &str, scopes: &[Role]) -> Result<(), Error> {
+    if scopes.is_empty() {
+        return Err(
+            AppError::Validation("an api key needs at least one scope".to_string()).extend(),
+        );
+    }
+    let database = ctx.data::<Database>()?;
+    let held = database.get_user_roles(uid).await.app_err()?;
+    let catalogue = RoleCatalogue::new(database.get_role_definitions().await.app_err()?);
+    match scopes
+        .iter()
+        .find(|scope| !held.iter().any(|role| catalogue.implies(role, scope)))
+    {
+        Some(scope) => {
+            Err(AppError::Validation(format!("{} doesn't hold {}", uid, scope)).extend())
+        }
+        None => Ok(()),
+    }
+}
+
+/*
+    * Managers and Admins invite with a role they hold or one below it,
+    in the organization of the invitation, or globally for an invitation to the platform
+    @param ctx: &Context<'_>
+    @param organization_id: Option<Uuid>
+    @param role: &Role, given by the invitation
+*/
+async fn ensure_can_invite(
+    ctx: &Context<'_>,
+    organization_id: Option<Uuid>,
+    role: &Role,
+) -> Result<(), Error> {
+    let scoped = |guard: RoleGuard| match organization_id {
+        Some(_) => guard.organization(organization_id),
+        None => guard,
+    };
+    scoped(RoleGuard::at_least(Role::MANAGER))
+        .and(scoped(RoleGuard::at_least(role.clone())))
+        .check(ctx)
+        .await
+}
+
+/*
+    * Email the link of an invitation, with the mailer of the schema
+    Emails are printed when the schema has none. The invitation is kept when it fails, it can be resent.
+    @param ctx: &Context<'_>
+    @param config: &InvitationConfig
+    @param invitation: &Invitation
+    @param token: &str
+*/
+async fn send_invitation(
+    ctx: &Context<'_>,
+    config: &InvitationConfig,
+    invitation: &Invitation,
+    token: &str,
+) -> Result<(), Error> {
+    let default_mailer = MailerService::default();
+    let mailer = ctx.data_opt::<MailerService>().unwrap_or(&default_mailer);
+    mailer
+        .send(&config.email(invitation, token))
+        .await
+        .map_err(|e| AppError::from(e).extend())
+}
+
+/*
+    * Send a link to verify the email of a user, with the mailer of the schema
+    Emails are printed when the schema has none.
+    @param ctx: &Context<'_>
+    @param user: &User
+*/
+async fn send_verification(ctx: &Context<'_>, user: &User) -> Result<(), AppError> {
+    let uid = user.id.as_deref().ok_or(AppError::NotFound)?;
+    let config = ctx
+        .data_opt::<EmailVerificationConfig>()
+        .cloned()
+        .unwrap_or_default();
+    let default_mailer = MailerService::default();
+    let mailer = ctx.data_opt::<MailerService>().unwrap_or(&default_mailer);
+    // links are never read by requests, only redeemed by the server
+    let database = ctx
+        .data::<Database>()
+        .map_err(|e| AppError::internal(e.message))?
+        .unscoped();
+    send_email_verification(&database, mailer, uid, &user.email, &config).await?;
+    Ok(())
+}
+
+/*
+    * Same, after a change that is done whether the email goes out or not,
+    a failure is only logged and sendVerificationEmail sends another link
+    @param ctx: &Context<'_>
+    @param user: &User
+*/
+async fn send_verification_or_log(ctx: &Context<'_>, user: &User) {
+    if let Err(e) = send_verification(ctx, user).await {
+        eprintln!("verification email of {:?} not sent: {:?}", user.id, e);
+    }
+}
+
+/*
+    * Push the roles and plan of uid into the custom claims of its identity,
+    then tell its subscribers, their token must be refreshed to carry the new claims
+    @param ctx: &Context<'_>
+    @param uid: &str
+*/
+async fn sync_claims(ctx: &Context<'_>, uid: &str) -> Result<(), Error> {
+    let claims = ctx
+        .data::<Database>()?
+        .get_user_claims(uid)
+        .await
+        .app_err()?;
+    // schemas built without an auth provider only publish
+    if let Some(auth) = ctx.data_opt::<AuthService>() {
+        match auth.set_custom_claims(uid, &claims).await {
+            // users seeded straight into the database have no identity to carry claims
+            Ok(()) | Err(AuthError::NotFound) => {}
+            Err(e) => return Err(e).app_err(),
+        }
+    }
+    let events = ctx.data::<EventBus>()?;
+    events.publish(UserEvent::RolesChanged {
+        uid: uid.to_string(),
+        roles: claims.roles.clone(),
+    });
+    events.publish(UserEvent::ClaimsChanged {
+        uid: uid.to_string(),
+        claims,
+    });
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::contexts::impersonation::ImpersonationId;
     use crate::contexts::token::Token;
-    use crate::firebase::main::Firebase;
+    use crate::extensions::impersonation::ImpersonationNotice;
     use crate::queries::main::Query;
+    use crate::structs::claims::UserClaims;
+    use crate::structs::identity::LocalIdentity;
+    use crate::subscriptions::main::Subscription;
+    use crate::traits::auth::AuthClaims;
+    use crate::traits::identity::IdentityTrait;
     use crate::traits::user::UserTrait;
     use crate::utils::Utils;
+    use async_graphql::futures_util::StreamExt;
     use async_graphql::Schema;
     use uuid::Uuid;
 
     #[tokio::test]
     async fn test_create_user() {
         let uuid = Uuid::new_v4();
-        let (user_uid, user, database_rw, token) =
-            Utils::generate_testing_config(&uuid.clone().to_string())
-                .await
-                .unwrap();
+        let (database, token) = Utils::generate_testing_config(&uuid.clone().to_string())
+            .await
+            .unwrap();
         let schema = Schema::build(Query, Mutation, EmptySubscription)
-            .data(user_uid)
-            .data(user)
-            .data(database_rw)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database)
+            .data(EventBus::new())
             .data(Token(format!("Bearer {}", token)))
-            .data(Firebase::new().await)
             .finish();
 
         let query = Request::new(
@@ -86,7 +1282,8 @@ mod tests {
                 "email": "blabal@gmail.com",
                 "password": "password123456"
             }
-        })));
+        })))
+        .data(Identity::default());
         let executed_query = schema.execute(query).await;
         assert_eq!(executed_query.errors.first(), None);
         assert_eq!(
@@ -98,22 +1295,18 @@ mod tests {
     #[tokio::test]
     async fn test_update_user_name() {
         let uuid = Uuid::new_v4();
-        let (user_uid, user, database_rw, token) =
-            Utils::generate_testing_config(&uuid.to_string())
-                .await
-                .unwrap();
-        database_rw
-            .write()
+        let (database, token) = Utils::generate_testing_config(&uuid.to_string())
             .await
+            .unwrap();
+        database
             .create_test_user(&uuid.clone().to_string())
             .await
             .unwrap();
         let schema = Schema::build(Query, Mutation, EmptySubscription)
-            .data(user_uid)
-            .data(user)
-            .data(database_rw)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database)
+            .data(EventBus::new())
             .data(Token(format!("Bearer {}", token)))
-            .data(Firebase::new().await)
             .finish();
 
         let query = Request::new(
@@ -127,7 +1320,8 @@ mod tests {
         )
         .variables(Variables::from_value(value!({
             "userName": "Test User modified"
-        })));
+        })))
+        .data(Identity::default());
         let executed_query = schema.execute(query).await;
         assert_eq!(executed_query.errors.first(), None);
         assert_eq!(
@@ -135,4 +1329,1777 @@ mod tests {
             value!({"updateUserName": {"name": "Test User modified"}})
         );
     }
+
+    #[tokio::test]
+    async fn test_sign_in() {
+        let database = Utils::memory_database();
+        let auth = Utils::local_auth(database.clone());
+        let user = database.crate_random_user().await.unwrap();
+        auth.create_identity(&user).await.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(AuthService::Local(auth))
+            .data(database)
+            .finish();
+
+        let query = r#"
+            mutation SignIn($email: String!, $password: String!){
+               signIn(email: $email, password: $password)
+            }
+        "#;
+        let executed_query = schema
+            .execute(Request::new(query).variables(Variables::from_value(
+                value!({ "email": user.email.clone(), "password": user.password.clone() }),
+            )))
+            .await;
+        assert_eq!(executed_query.errors.first(), None);
+        let token = executed_query.data.into_json().unwrap()["signIn"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        // the token is accepted by the guards
+        let executed_query = schema
+            .execute(
+                Request::new("query { user { id } }")
+                    .data(Token(format!("Bearer {}", token)))
+                    .data(Identity::default()),
+            )
+            .await;
+        assert_eq!(executed_query.errors.first(), None);
+        assert_eq!(executed_query.data, value!({"user": {"id": user.id}}));
+
+        let executed_query = schema
+            .execute(Request::new(query).variables(Variables::from_value(
+                value!({ "email": user.email, "password": "wrong password" }),
+            )))
+            .await;
+        assert_eq!(executed_query.errors[0].message, "Auth::InvalidCredentials");
+    }
+
+    #[tokio::test]
+    async fn test_errors_carry_a_stable_code() {
+        let database = Utils::memory_database();
+        let uid = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+
+        let create = r#"mutation { createUser(input: {name: "again", email: "again@example.com", password: "password"}) { id } }"#;
+        let res = schema
+            .execute(Request::new(create).data(Identity::verified(&uid)))
+            .await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+        // constraint names stay on the server
+        assert!(!res.errors[0].message.contains("pkey"));
+
+        let res = schema
+            .execute(Request::new(create).data(Identity::default()))
+            .await;
+        assert_eq!(res.errors[0].message, "Auth::Unauthorized");
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+
+        let res = schema
+            .execute(
+                Request::new(r#"mutation { updateUserName(userName: "x") { id } }"#)
+                    .data(Identity::verified(&Uuid::new_v4().to_string())),
+            )
+            .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+    }
+
+    #[tokio::test]
+    async fn test_grant_and_revoke_roles() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+        let execute = |mutation: &str, uid: &str, role: &str, as_uid: &str| {
+            let query = format!(
+                r#"mutation {{ {}(uid: "{}", role: "{}") {{ id roles }} }}"#,
+                mutation, uid, role
+            );
+            schema.execute(Request::new(query).data(Identity::verified(as_uid)))
+        };
+
+        let res = execute("grantRole", &user, "Manager", &admin).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"grantRole": {"id": user.clone(), "roles": ["User", "Manager"]}})
+        );
+        // granting twice changes nothing
+        let res = execute("grantRole", &user, "Manager", &admin).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"grantRole": {"id": user.clone(), "roles": ["User", "Manager"]}})
+        );
+
+        // a Manager is not an Admin
+        let res = execute("grantRole", &user, "Admin", &user).await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+
+        let res = execute("revokeRole", &user, "Manager", &admin).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"revokeRole": {"id": user.clone(), "roles": ["User"]}})
+        );
+        let res = execute("revokeRole", &user, "Manager", &admin).await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("NOT_FOUND"))
+        );
+
+        // the only Admin can't step down, it can once someone else is Admin
+        let res = execute("revokeRole", &admin, "Admin", &admin).await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("CONFLICT"))
+        );
+        let res = execute("grantRole", &user, "Admin", &admin).await;
+        assert_eq!(res.errors.first(), None);
+        let res = execute("revokeRole", &admin, "Admin", &admin).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"revokeRole": {"id": admin.clone(), "roles": ["User"]}})
+        );
+
+        let res = execute("grantRole", &Uuid::new_v4().to_string(), "Admin", &user).await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("NOT_FOUND"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_edit_permissions() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+        let execute = |query: &str, uid: &str| {
+            schema.execute(Request::new(query.to_string()).data(Identity::verified(uid)))
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+
+        let res = execute(
+            r#"mutation { createPermission(name: "report:export", description: "Export reports") { name description } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"createPermission": {"name": "report:export", "description": "Export reports"}})
+        );
+        let res = execute(
+            r#"mutation { createPermission(name: "Report export") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = execute(
+            r#"mutation { createPermission(name: "report:export") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+
+        let grant = r#"mutation { grantPermission(role: "User", permission: "report:export") }"#;
+        let res = execute(grant, &admin).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"grantPermission": ["report:export", "user:read", "user:update"]})
+        );
+        assert_eq!(execute(grant, &admin).await.errors.first(), None);
+        let res = execute("query { myPermissions }", &user).await;
+        assert_eq!(
+            res.data,
+            value!({"myPermissions": ["report:export", "user:read", "user:update"]})
+        );
+
+        // revoking user:update from User takes updateUserName away
+        let res = execute(
+            r#"mutation { revokePermission(role: "User", permission: "user:update") }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(
+            res.data,
+            value!({"revokePermission": ["report:export", "user:read"]})
+        );
+        let res = execute(
+            r#"mutation { updateUserName(userName: "x") { name } }"#,
+            &user,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+
+        let res = execute(
+            r#"mutation { grantPermission(role: "User", permission: "nothing:here") }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+
+        let res = execute(
+            r#"mutation { deletePermission(name: "report:export") }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(res.data, value!({"deletePermission": true}));
+        let res = execute("query { myPermissions }", &user).await;
+        assert_eq!(res.data, value!({"myPermissions": ["user:read"]}));
+
+        // only Admins edit the mapping
+        let res = execute(grant, &user).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(r#"query { rolePermissions(role: "User") }"#, &user).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(r#"query { rolePermissions(role: "User") }"#, &admin).await;
+        assert_eq!(res.data, value!({"rolePermissions": ["user:read"]}));
+    }
+
+    #[tokio::test]
+    async fn test_role_and_plan_changes_sync_claims() {
+        let uid = Uuid::new_v4().to_string();
+        let (database, _) = Utils::generate_testing_config(&uid).await.unwrap();
+        database.create_test_user(&uid).await.unwrap();
+        // seeded without an identity, its claims can't be pushed
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let schema = Schema::build(Query, Mutation, Subscription)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let execute =
+            |query: String| schema.execute(Request::new(query).data(Identity::verified(&admin)));
+        let mut changes = schema.execute_stream(
+            Request::new("subscription { myClaimsChanged { roles plan } }")
+                .data(Identity::verified(&uid)),
+        );
+        let idle = std::time::Duration::from_millis(20);
+        assert!(tokio::time::timeout(idle, changes.next()).await.is_err());
+
+        let res = execute(format!(
+            r#"mutation {{ grantRole(uid: "{}", role: "Manager") {{ id }} }}"#,
+            uid
+        ))
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let res = execute(format!(
+            r#"mutation {{ setUserPlan(uid: "{}", plan: PREMIUM) {{ plan }} }}"#,
+            uid
+        ))
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(res.data, value!({"setUserPlan": {"plan": "PREMIUM"}}));
+        let res = execute(format!(
+            r#"mutation {{ grantRole(uid: "{}", role: "Manager") {{ id }} }}"#,
+            admin
+        ))
+        .await;
+        assert_eq!(res.errors.first(), None);
+
+        let identity = database.get_identity(&uid).await.unwrap().unwrap();
+        assert_eq!(
+            UserClaims::from_token(&identity.custom_claims),
+            Some(database.get_user_claims(&uid).await.unwrap())
+        );
+        let res = changes.next().await.unwrap();
+        assert_eq!(
+            res.data,
+            value!({"myClaimsChanged": {"roles": ["User", "Manager"], "plan": "FREE"}})
+        );
+        let res = changes.next().await.unwrap();
+        assert_eq!(
+            res.data,
+            value!({"myClaimsChanged": {"roles": ["User", "Manager"], "plan": "PREMIUM"}})
+        );
+
+        let res = schema
+            .execute(
+                Request::new(format!(
+                    r#"mutation {{ setUserPlan(uid: "{}", plan: FREE) {{ id }} }}"#,
+                    admin
+                ))
+                .data(Identity::verified(&uid)),
+            )
+            .await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+    }
+
+    #[tokio::test]
+    async fn test_manage_custom_roles() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+        let execute = |query: &str, uid: &str| {
+            schema.execute(Request::new(query.to_string()).data(Identity::verified(uid)))
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+
+        let res = execute(
+            r#"mutation { createRole(name: "Analyst", description: "Reads reports", parent: "User") { name parent builtIn } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"createRole": {"name": "Analyst", "parent": "User", "builtIn": false}})
+        );
+        let res = execute(
+            r#"mutation { createRole(name: "Analyst") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+        let res = execute(
+            r#"mutation { createRole(name: "Lead", parent: "Nobody") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+        // names are decoded strictly, before reaching the resolver
+        let res = execute(
+            r#"mutation { createRole(name: "Data Admin") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert!(res.errors[0].message.contains("invalid role name"));
+        let res = execute(
+            &format!(
+                r#"mutation {{ grantRole(uid: "{}", role: "Nobody") {{ id }} }}"#,
+                user
+            ),
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+
+        let res = execute(
+            &format!(
+                r#"mutation {{ grantRole(uid: "{}", role: "Analyst") {{ roles }} }}"#,
+                user
+            ),
+            &admin,
+        )
+        .await;
+        assert_eq!(
+            res.data,
+            value!({"grantRole": {"roles": ["User", "Analyst"]}})
+        );
+        let res = execute(
+            r#"mutation { renameRole(name: "Analyst", newName: "Auditor") { name } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(res.data, value!({"renameRole": {"name": "Auditor"}}));
+        let res = execute(
+            r#"query { usersByRole(role: "Auditor") { id roles } }"#,
+            &admin,
+        )
+        .await;
+        assert_eq!(
+            res.data,
+            value!({"usersByRole": [{"id": user.clone(), "roles": ["User", "Auditor"]}]})
+        );
+
+        // the built-in roles stay
+        for mutation in [
+            r#"mutation { deleteRole(name: "Manager") }"#,
+            r#"mutation { renameRole(name: "Admin", newName: "Root") { name } }"#,
+        ] {
+            let res = execute(mutation, &admin).await;
+            assert_eq!(code(&res), Some(value!("CONFLICT")));
+        }
+        let res = execute(r#"mutation { deleteRole(name: "Auditor") }"#, &user).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(r#"mutation { deleteRole(name: "Auditor") }"#, &admin).await;
+        assert_eq!(res.data, value!({"deleteRole": true}));
+        let res = execute("query { roles { name } }", &admin).await;
+        assert_eq!(
+            res.data,
+            value!({"roles": [{"name": "Admin"}, {"name": "Manager"}, {"name": "User"}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manage_organizations() {
+        let database = Utils::memory_database();
+        let owner = database.crate_random_user().await.unwrap().id.unwrap();
+        let member = database.crate_random_user().await.unwrap().id.unwrap();
+        let outsider = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+        let execute = |query: String, uid: &str, organization: Option<Uuid>| {
+            let mut request = Request::new(query).data(Identity::verified(uid));
+            if let Some(organization) = organization {
+                request = request.data(OrganizationId(organization));
+            }
+            schema.execute(request)
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+
+        let res = execute(
+            r#"mutation { createOrganization(name: "Acme") { id name } }"#.to_string(),
+            &owner,
+            None,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let acme = res.data.into_json().unwrap()["createOrganization"]["id"]
+            .as_str()
+            .unwrap()
+            .parse::<Uuid>()
+            .unwrap();
+        let res = execute(
+            r#"mutation { createOrganization(name: " ") { id } }"#.to_string(),
+            &owner,
+            None,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+
+        // members are managed by the Admins of the organization, selected by argument or by the request
+        let set_role = |uid: &str, role: &str| {
+            format!(
+                r#"mutation {{ setMemberRole(uid: "{}", role: "{}") {{ userId role }} }}"#,
+                uid, role
+            )
+        };
+        let res = execute(set_role(&member, "Manager"), &owner, Some(acme)).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"setMemberRole": {"userId": member.clone(), "role": "Manager"}})
+        );
+        let res = execute(set_role(&outsider, "User"), &member, Some(acme)).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(set_role(&outsider, "User"), &owner, None).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+
+        let res = execute(
+            format!(
+                r#"query {{ organization(organizationId: "{}") {{ name members {{ userId role }} }} }}"#,
+                acme
+            ),
+            &member,
+            None,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"organization": {"name": "Acme", "members": [
+                {"userId": owner.clone(), "role": "Admin"},
+                {"userId": member.clone(), "role": "Manager"},
+            ]}})
+        );
+        let res = execute(
+            "query { organization { name } }".to_string(),
+            &outsider,
+            Some(acme),
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(
+            "query { myOrganizations { name } }".to_string(),
+            &member,
+            None,
+        )
+        .await;
+        assert_eq!(res.data, value!({"myOrganizations": [{"name": "Acme"}]}));
+
+        // UserExistGuard lets only members through inside an organization
+        let rename_user = || r#"mutation { updateUserName(userName: "x") { name } }"#.to_string();
+        assert_eq!(
+            execute(rename_user(), &outsider, None).await.errors.first(),
+            None
+        );
+        let res = execute(rename_user(), &outsider, Some(acme)).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        assert_eq!(
+            execute(rename_user(), &member, Some(acme))
+                .await
+                .errors
+                .first(),
+            None
+        );
+
+        let res = execute(
+            format!(
+                r#"mutation {{ renameOrganization(organizationId: "{}", name: "Acme Corp") {{ name }} }}"#,
+                acme
+            ),
+            &owner,
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.data,
+            value!({"renameOrganization": {"name": "Acme Corp"}})
+        );
+        let remove_owner = format!(r#"mutation {{ removeMember(uid: "{}") }}"#, owner);
+        let res = execute(remove_owner.clone(), &owner, Some(acme)).await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+
+        let res = execute(
+            "mutation { deleteOrganization }".to_string(),
+            &member,
+            Some(acme),
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(
+            "mutation { deleteOrganization }".to_string(),
+            &owner,
+            Some(acme),
+        )
+        .await;
+        assert_eq!(res.data, value!({"deleteOrganization": true}));
+        let res = execute(
+            "query { myOrganizations { name } }".to_string(),
+            &member,
+            None,
+        )
+        .await;
+        assert_eq!(res.data, value!({"myOrganizations": []}));
+    }
+
+    #[tokio::test]
+    async fn test_invitations() {
+        let database = Utils::memory_database();
+        let manager = database.crate_random_user().await.unwrap().id.unwrap();
+        database
+            .save_user_role(&manager, &Role::MANAGER)
+            .await
+            .unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let owner = database.crate_random_user().await.unwrap().id.unwrap();
+        let organization = database
+            .create_organization("Acme", &owner)
+            .await
+            .unwrap()
+            .id;
+        let dir = Utils::mail_dir();
+        let from = crate::mailer::main::MailConfig::default().sender().unwrap();
+        let mailer = crate::mailer::file::FileMailer::new(from, Some(dir.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database.clone())
+            .data(EventBus::new())
+            .data(MailerService::File(mailer))
+            .data(InvitationConfig {
+                link: "http://app/invite".to_string(),
+                ..Default::default()
+            })
+            .finish();
+        let execute = |query: String, uid: &str, email: &str, organization: Option<Uuid>| {
+            let identity = Identity::from_claims(AuthClaims {
+                uid: uid.to_string(),
+                email: Some(email.to_string()),
+                email_verified: true,
+                ..Default::default()
+            });
+            let mut request = Request::new(query).data(identity);
+            if let Some(organization) = organization {
+                request = request.data(OrganizationId(organization));
+            }
+            schema.execute(request)
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let invite = |email: &str, role: &str| {
+            format!(
+                r#"mutation {{ inviteUser(email: "{}", role: "{}") {{ id email role organizationId status }} }}"#,
+                email, role
+            )
+        };
+
+        // Managers invite to the platform with their role at most, Users can't invite
+        let res = execute(invite(" Jane@Example.com", "Manager"), &manager, "", None).await;
+        assert_eq!(res.errors.first(), None);
+        let invitation = res.data.into_json().unwrap()["inviteUser"].clone();
+        assert_eq!(invitation["email"], "jane@example.com");
+        assert_eq!(invitation["status"], "PENDING");
+        assert_eq!(invitation["organizationId"], serde_json::Value::Null);
+        let id = invitation["id"].as_str().unwrap().to_string();
+        // the token only reaches the invitee
+        let emails = Utils::take_sent_emails(&dir);
+        assert_eq!(emails.len(), 1);
+        assert!(emails[0].contains("To: jane@example.com"));
+        assert!(emails[0].contains("http://app/invite?token="));
+        let first_token = Utils::token_in(&emails[0]);
+        let res = execute(invite("john@example.com", "Admin"), &manager, "", None).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(invite("john@example.com", "User"), &user, "", None).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(invite("john", "User"), &manager, "", None).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = execute(invite("jane@example.com", "User"), &manager, "", None).await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+
+        // the Admin of an organization invites to it, platform Managers can't
+        let res = execute(
+            invite("john@example.com", "Admin"),
+            &owner,
+            "",
+            Some(organization),
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let res = execute(
+            invite("john@example.com", "User"),
+            &manager,
+            "",
+            Some(organization),
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(
+            "query { invitations { email role } }".to_string(),
+            &owner,
+            "",
+            Some(organization),
+        )
+        .await;
+        assert_eq!(
+            res.data,
+            value!({"invitations": [{"email": "john@example.com", "role": "Admin"}]})
+        );
+
+        // resending emails another token
+        Utils::take_sent_emails(&dir);
+        let res = execute(
+            format!(
+                r#"mutation {{ resendInvitation(id: "{}") {{ status }} }}"#,
+                id
+            ),
+            &manager,
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let emails = Utils::take_sent_emails(&dir);
+        assert_eq!(emails.len(), 1);
+        let token = Utils::token_in(&emails[0]);
+        assert_ne!(token, first_token);
+        let accept = |token: &str| {
+            format!(
+                r#"mutation {{ acceptInvitation(token: "{}", name: "Jane", password: "password") {{ name email }} }}"#,
+                token
+            )
+        };
+        let jane = Uuid::new_v4().to_string();
+        let res = execute(accept(&first_token), &jane, "jane@example.com", None).await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+
+        // only the invited email can accept, once it is verified, once
+        let res = execute(accept(&token), &jane, "john@example.com", None).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let unverified = Identity::from_claims(AuthClaims {
+            uid: jane.clone(),
+            email: Some("jane@example.com".to_string()),
+            ..Default::default()
+        });
+        let res = schema
+            .execute(Request::new(accept(&token)).data(unverified))
+            .await;
+        assert_eq!(code(&res), Some(value!("EMAIL_NOT_VERIFIED")));
+        let res = execute(accept(&token), &jane, "JANE@example.com", None).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"acceptInvitation": {"name": "Jane", "email": "jane@example.com"}})
+        );
+        assert_eq!(
+            database.get_user_roles(&jane).await.unwrap(),
+            vec![Role::USER, Role::MANAGER]
+        );
+        let res = execute(accept(&token), &jane, "jane@example.com", None).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = execute(
+            format!(r#"mutation {{ revokeInvitation(id: "{}") {{ id }} }}"#, id),
+            &manager,
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+
+        // revoking needs the same rights as inviting
+        let res = execute(invite("jim@example.com", "User"), &manager, "", None).await;
+        let id = res.data.into_json().unwrap()["inviteUser"]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let revoke = format!(
+            r#"mutation {{ revokeInvitation(id: "{}") {{ status }} }}"#,
+            id
+        );
+        let res = execute(revoke.clone(), &user, "", None).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = execute(revoke, &manager, "", None).await;
+        assert_eq!(
+            res.data,
+            value!({"revokeInvitation": {"status": "REVOKED"}})
+        );
+        let res = execute(
+            "query { invitations { email } }".to_string(),
+            &manager,
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(res.data, value!({"invitations": []}));
+    }
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let service = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let as_user = |query: String, uid: &str| {
+            let identity = Identity::from_claims(AuthClaims {
+                uid: uid.to_string(),
+                ..Default::default()
+            });
+            schema.execute(Request::new(query).data(identity))
+        };
+        let with_key = |query: &str, key: &str| {
+            schema.execute(
+                Request::new(query)
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", key))),
+            )
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let create = |arguments: &str| {
+            format!(
+                r#"mutation {{ createApiKey({}) {{ key apiKey {{ id userId name scopes createdBy }} }} }}"#,
+                arguments
+            )
+        };
+        let admin_keys = format!(r#"{{ apiKeys(uid: "{}") {{ id }} }}"#, user);
+
+        // a key acts with its scopes only, even for an Admin
+        let res = as_user(create(r#"name: "etl""#), &admin).await;
+        assert_eq!(res.errors.first(), None);
+        let ticket = res.data.into_json().unwrap()["createApiKey"].clone();
+        assert_eq!(ticket["apiKey"]["scopes"], serde_json::json!(["User"]));
+        assert_eq!(ticket["apiKey"]["createdBy"], admin.as_str());
+        let user_key = ticket["key"].as_str().unwrap().to_string();
+        assert!(user_key.starts_with(ApiKey::PREFIX));
+        let res = with_key(&admin_keys, &user_key).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = with_key("{ myApiKeys { name lastUsedAt } }", &user_key).await;
+        assert_eq!(res.errors.first(), None);
+        let keys = res.data.into_json().unwrap()["myApiKeys"].clone();
+        assert_eq!(keys[0]["name"], "etl");
+        assert_ne!(keys[0]["lastUsedAt"], serde_json::Value::Null);
+
+        let res = as_user(create(r#"name: "ops", scopes: ["User", "Admin"]"#), &admin).await;
+        assert_eq!(res.errors.first(), None);
+        let ticket = res.data.into_json().unwrap()["createApiKey"].clone();
+        let admin_key = ticket["key"].as_str().unwrap().to_string();
+        let admin_key_id = ticket["apiKey"]["id"].as_str().unwrap().to_string();
+        let res = with_key(&admin_keys, &admin_key).await;
+        assert_eq!(res.errors.first(), None);
+        let permissions = |key: String| async move {
+            let res = with_key("{ myPermissions }", &key).await;
+            res.data.into_json().unwrap()["myPermissions"].clone()
+        };
+        assert_eq!(
+            permissions(user_key.clone()).await,
+            serde_json::json!(["user:read", "user:update"])
+        );
+        assert!(permissions(admin_key.clone())
+            .await
+            .as_array()
+            .unwrap()
+            .contains(&"role:write".into()));
+
+        // keys don't manage keys
+        let res = with_key(&create(r#"name: "more""#), &admin_key).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = with_key(
+            &format!(
+                r#"mutation {{ updateApiKey(id: "{}", scopes: ["User", "Admin"]) {{ id }} }}"#,
+                admin_key_id
+            ),
+            &user_key,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+
+        // scopes must be held by the user of the key
+        let res = as_user(create(r#"name: "sneaky", scopes: ["Admin"]"#), &user).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = as_user(create(r#"name: "none", scopes: []"#), &user).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = as_user(create(r#"name: " spaced""#), &user).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+
+        // Admins create keys for service accounts, others can't
+        let for_service = create(&format!(r#"name: "svc", uid: "{}""#, service));
+        let res = as_user(for_service.clone(), &user).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = as_user(for_service, &admin).await;
+        assert_eq!(res.errors.first(), None);
+        let ticket = res.data.into_json().unwrap()["createApiKey"].clone();
+        assert_eq!(ticket["apiKey"]["userId"], service.as_str());
+        let service_key_id = ticket["apiKey"]["id"].as_str().unwrap().to_string();
+        let revoke = |id: &str| {
+            format!(
+                r#"mutation {{ revokeApiKey(id: "{}") {{ revokedAt }} }}"#,
+                id
+            )
+        };
+        let res = as_user(revoke(&service_key_id), &user).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+
+        // revoked or tampered keys are rejected
+        let res = as_user(revoke(&admin_key_id), &admin).await;
+        assert_eq!(res.errors.first(), None);
+        let res = with_key(&admin_keys, &admin_key).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+        let res = with_key("{ myApiKeys { id } }", &format!("{}x", user_key)).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+    }
+
+    #[tokio::test]
+    async fn test_impersonation() {
+        let admin = Uuid::new_v4().to_string();
+        let (database, admin_token) = Utils::generate_testing_config(&admin).await.unwrap();
+        database.create_test_user(&admin).await.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let target = database.crate_random_user().await.unwrap().id.unwrap();
+        let other_admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database
+            .save_user_role(&other_admin, &Role::ADMIN)
+            .await
+            .unwrap();
+        let other_identity = LocalIdentity {
+            uid: other_admin.clone(),
+            email: format!("{}@example.com", other_admin),
+            password: String::new(),
+            display_name: None,
+            email_verified: false,
+            disabled: false,
+            custom_claims: serde_json::Map::new(),
+            sessions_valid_after: None,
+            created_at: None,
+            updated_at: None,
+        };
+        database.create_identity(&other_identity).await.unwrap();
+        let other_token = Utils::local_auth(database.clone())
+            .issue_token(&other_identity)
+            .unwrap();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database.clone())
+            .data(EventBus::new())
+            .extension(ImpersonationNotice)
+            .finish();
+        let as_user = |query: String, uid: &str| {
+            schema.execute(Request::new(query).data(Identity::verified(uid)))
+        };
+        // the way http requests come in, the header next to the token of the Admin
+        let impersonating = |query: &str, token: &str, id: &str| {
+            schema.execute(
+                Request::new(query)
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", token)))
+                    .data(ImpersonationId(Uuid::parse_str(id).unwrap())),
+            )
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let start = |arguments: String| {
+            format!(
+                r#"mutation {{ startImpersonation({}) {{ id adminUid targetUid readOnly active }} }}"#,
+                arguments
+            )
+        };
+
+        // Admins only, for a reason, for a bounded time
+        let res = as_user(
+            start(format!(r#"uid: "{}", reason: "ticket 42""#, target)),
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let session = res.data.into_json().unwrap()["startImpersonation"].clone();
+        assert_eq!(session["targetUid"], target.as_str());
+        assert_eq!(session["readOnly"], true);
+        assert_eq!(session["active"], true);
+        let id = session["id"].as_str().unwrap().to_string();
+        let res = as_user(
+            start(format!(r#"uid: "{}", reason: "curious""#, admin)),
+            &target,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        for arguments in [
+            format!(r#"uid: "{}", reason: "me""#, admin),
+            format!(r#"uid: "{}", reason: " ""#, target),
+            format!(r#"uid: "{}", reason: "ticket 42", minutes: 0"#, target),
+            format!(r#"uid: "{}", reason: "ticket 42", minutes: 61"#, target),
+        ] {
+            let res = as_user(start(arguments.clone()), &admin).await;
+            assert_eq!(
+                code(&res),
+                Some(value!("VALIDATION_FAILED")),
+                "{}",
+                arguments
+            );
+        }
+        let res = as_user(
+            start(format!(r#"uid: "{}", reason: "ticket 42""#, Uuid::new_v4())),
+            &admin,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+
+        // the Admin sees what the user sees, and the response says so
+        let res = impersonating("query Dashboard { user { id } }", &admin_token, &id).await;
+        assert_eq!(res.errors.first(), None);
+        let notice = res.extensions.get("impersonation").cloned().unwrap();
+        let data = res.data.into_json().unwrap();
+        assert_eq!(data["user"]["id"], target.as_str());
+        let notice = notice.into_json().unwrap();
+        assert_eq!(notice["id"], id.as_str());
+        assert_eq!(notice["realUid"], admin.as_str());
+        assert_eq!(notice["effectiveUid"], target.as_str());
+        assert_eq!(notice["readOnly"], true);
+        // with the roles of the user only
+        let res = impersonating("query Audit { impersonations { id } }", &admin_token, &id).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = impersonating(
+            r#"mutation Rename { updateUserName(userName: "Hijacked") { id } }"#,
+            &admin_token,
+            &id,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        assert_ne!(database.get_user(&target).await.unwrap().name, "Hijacked");
+        // sessions belong to the Admin who started them
+        let res = impersonating("{ user { id } }", &other_token, &id).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = as_user("{ user { id } }".to_string(), &admin).await;
+        assert!(!res.extensions.contains_key("impersonation"));
+
+        // every request is recorded with both uids, blocked ones included
+        let res = as_user(
+            format!(
+                r#"{{ impersonations(uid: "{}") {{ id events {{ realUid effectiveUid operationType operationName blocked }} }} }}"#,
+                target
+            ),
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let sessions = res.data.into_json().unwrap()["impersonations"].clone();
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        let events = sessions[0]["events"].as_array().unwrap().clone();
+        let operations: Vec<_> = events
+            .iter()
+            .map(|event| {
+                (
+                    event["operationType"].as_str().unwrap(),
+                    event["operationName"].as_str().unwrap(),
+                    event["blocked"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                ("query", "Dashboard", false),
+                ("query", "Audit", false),
+                ("mutation", "Rename", true),
+            ]
+        );
+        assert!(events
+            .iter()
+            .all(|event| event["realUid"] == admin.as_str()
+                && event["effectiveUid"] == target.as_str()));
+
+        // mutations go through when the session allows them
+        let res = as_user(
+            start(format!(
+                r#"uid: "{}", reason: "fix the profile", readOnly: false"#,
+                target
+            )),
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let writable = res.data.into_json().unwrap()["startImpersonation"]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = impersonating(
+            r#"mutation { updateUserName(userName: "Fixed") { id } }"#,
+            &admin_token,
+            &writable,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(database.get_user(&target).await.unwrap().name, "Fixed");
+
+        // ended sessions stop working at once
+        let res = as_user(
+            format!(
+                r#"mutation {{ endImpersonation(id: "{}") {{ active }} }}"#,
+                id
+            ),
+            &admin,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let res = impersonating("{ user { id } }", &admin_token, &id).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+    }
+
+    #[tokio::test]
+    async fn test_sign_up() {
+        let database = Utils::memory_database();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let sign_up = r#"mutation { signUp(input: { name: "Jane", email: "jane@example.com", password: "password" }) { id email roles } }"#;
+        let res = schema
+            .execute(Request::new(sign_up).data(Identity::default()))
+            .await;
+        assert_eq!(res.errors.first(), None);
+        let user = res.data.into_json().unwrap()["signUp"].clone();
+        assert_eq!(user["roles"], serde_json::json!(["User"]));
+        let uid = user["id"].as_str().unwrap().to_string();
+
+        let res = schema
+            .execute(
+                Request::new(
+                    r#"mutation { signIn(email: "jane@example.com", password: "password") }"#,
+                )
+                .data(Identity::default()),
+            )
+            .await;
+        assert_eq!(res.errors.first(), None);
+        let token = res.data.into_json().unwrap()["signIn"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = schema
+            .execute(
+                Request::new("{ user { id } }")
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", token))),
+            )
+            .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(res.data.into_json().unwrap()["user"]["id"], uid.as_str());
+
+        let res = schema
+            .execute(Request::new(sign_up).data(Identity::default()))
+            .await;
+        assert_eq!(
+            res.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("CONFLICT"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_and_reset_password() {
+        let database = Utils::memory_database();
+        let dir = Utils::mail_dir();
+        let from = crate::mailer::main::MailConfig::default().sender().unwrap();
+        let mailer = crate::mailer::file::FileMailer::new(from, Some(dir.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database.clone())
+            .data(EventBus::new())
+            .data(MailerService::File(mailer))
+            .data(PasswordResetConfig {
+                link: "http://app/reset".to_string(),
+                ..Default::default()
+            })
+            .finish();
+        let execute = |query: String, token: Option<&str>| {
+            let mut req = Request::new(query).data(Identity::default());
+            if let Some(token) = token {
+                req = req.data(Token(format!("Bearer {}", token)));
+            }
+            schema.execute(req)
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let res = execute(
+            r#"mutation { signUp(input: { name: "Jane", email: "jane@example.com", password: "password" }) { id } }"#.to_string(),
+            None,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let sign_in = |password: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ signIn(email: "jane@example.com", password: "{}") }}"#,
+                    password
+                ),
+                None,
+            )
+        };
+        let res = sign_in("password").await;
+        let first = res.data.into_json().unwrap()["signIn"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        // revocation is to the second
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+        let change = |current: &str, token: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ changePassword(currentPassword: "{}", newPassword: "new password") }}"#,
+                    current
+                ),
+                Some(token),
+            )
+        };
+        let res = change("wrong", &first).await;
+        assert_eq!(code(&res), Some(value!("INVALID_CREDENTIALS")));
+        let res = change("password", &first).await;
+        assert_eq!(res.errors.first(), None);
+        let second = res.data.into_json().unwrap()["changePassword"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = execute("{ user { id } }".to_string(), Some(&first)).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+        let res = execute("{ user { id } }".to_string(), Some(&second)).await;
+        assert_eq!(res.errors.first(), None);
+        assert!(sign_in("password").await.is_err());
+
+        // the token only reaches the email, past the verification one of the sign up
+        Utils::take_sent_emails(&dir);
+        let res = execute(
+            r#"mutation { requestPasswordReset(email: "nobody@example.com") }"#.to_string(),
+            None,
+        )
+        .await;
+        assert_eq!(res.data.into_json().unwrap()["requestPasswordReset"], true);
+        assert!(Utils::take_sent_emails(&dir).is_empty());
+        let res = execute(
+            r#"mutation { requestPasswordReset(email: "Jane@Example.com") }"#.to_string(),
+            None,
+        )
+        .await;
+        assert_eq!(res.data.into_json().unwrap()["requestPasswordReset"], true);
+        let emails = Utils::take_sent_emails(&dir);
+        assert_eq!(emails.len(), 1);
+        assert!(emails[0].contains("To: jane@example.com"));
+        assert!(emails[0].contains("http://app/reset?token="));
+        let token = Utils::token_in(&emails[0]);
+
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let reset = format!(
+            r#"mutation {{ resetPassword(token: "{}", newPassword: "third password") }}"#,
+            token
+        );
+        let res = execute(reset.clone(), None).await;
+        assert_eq!(res.errors.first(), None);
+        let res = execute(reset, None).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = execute("{ user { id } }".to_string(), Some(&second)).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+        assert!(sign_in("third password").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_change_email() {
+        let database = Utils::memory_database();
+        let dir = Utils::mail_dir();
+        let from = crate::mailer::main::MailConfig::default().sender().unwrap();
+        let mailer = crate::mailer::file::FileMailer::new(from, Some(dir.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database.clone())
+            .data(EventBus::new())
+            .data(MailerService::File(mailer))
+            .data(EmailVerificationConfig {
+                link: "http://app/verify".to_string(),
+                ..Default::default()
+            })
+            .finish();
+        let execute = |query: String, token: Option<&str>| {
+            let mut req = Request::new(query).data(Identity::default());
+            if let Some(token) = token {
+                req = req.data(Token(format!("Bearer {}", token)));
+            }
+            schema.execute(req)
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let res = execute(
+            r#"mutation { signUp(input: { name: "Jane", email: "jane@example.com", password: "password" }) { emailVerified } }"#.to_string(),
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.data.into_json().unwrap()["signUp"]["emailVerified"],
+            false
+        );
+        let res = execute(
+            r#"mutation { signIn(email: "jane@example.com", password: "password") }"#.to_string(),
+            None,
+        )
+        .await;
+        let token = res.data.into_json().unwrap()["signIn"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let verified = || async {
+            let res = execute("{ user { email emailVerified } }".to_string(), Some(&token)).await;
+            res.data.into_json().unwrap()["user"].clone()
+        };
+        let verify = |token: String| {
+            execute(
+                format!(r#"mutation {{ verifyEmail(token: "{}") }}"#, token),
+                None,
+            )
+        };
+
+        // the link sent on sign up is superseded by the one asked for
+        let signed_up = Utils::take_sent_emails(&dir);
+        assert_eq!(signed_up.len(), 1);
+        assert!(signed_up[0].contains("http://app/verify?token="));
+        let res = execute(
+            "mutation { sendVerificationEmail }".to_string(),
+            Some(&token),
+        )
+        .await;
+        assert_eq!(res.data.into_json().unwrap()["sendVerificationEmail"], true);
+        let sent = Utils::take_sent_emails(&dir);
+        assert_eq!(sent.len(), 1);
+        let res = verify(Utils::token_in(&signed_up[0])).await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+        let res = verify(Utils::token_in(&sent[0])).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(verified().await["emailVerified"], true);
+        let res = verify(Utils::token_in(&sent[0])).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = execute(
+            "mutation { sendVerificationEmail }".to_string(),
+            Some(&token),
+        )
+        .await;
+        assert_eq!(
+            res.data.into_json().unwrap()["sendVerificationEmail"],
+            false
+        );
+
+        let change = |password: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ changeEmail(email: "jane@example.org", currentPassword: "{}") {{ email emailVerified }} }}"#,
+                    password
+                ),
+                Some(&token),
+            )
+        };
+        let res = change("wrong").await;
+        assert_eq!(code(&res), Some(value!("INVALID_CREDENTIALS")));
+        let res = change("password").await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data.into_json().unwrap()["changeEmail"],
+            serde_json::json!({ "email": "jane@example.org", "emailVerified": false })
+        );
+        let sent = Utils::take_sent_emails(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("To: jane@example.org"));
+        let res = verify(Utils::token_in(&sent[0])).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            verified().await,
+            serde_json::json!({ "email": "jane@example.org", "emailVerified": true })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_export_account() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(auth.clone())
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let execute = |query: String, token: Option<&str>| {
+            let mut req = Request::new(query).data(Identity::default());
+            if let Some(token) = token {
+                req = req.data(Token(format!("Bearer {}", token)));
+            }
+            schema.execute(req)
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let sign_up = |name: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ signUp(input: {{ name: "{0}", email: "{0}@example.com", password: "password" }}) {{ id }} }}"#,
+                    name
+                ),
+                None,
+            )
+        };
+        let sign_in = |name: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ signIn(email: "{}@example.com", password: "password") }}"#,
+                    name
+                ),
+                None,
+            )
+        };
+        let mut uids = Vec::new();
+        let mut tokens = Vec::new();
+        for name in ["jane", "john", "jack"] {
+            let res = sign_up(name).await;
+            uids.push(
+                res.data.into_json().unwrap()["signUp"]["id"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            );
+        }
+        let (jane, john, jack) = (&uids[0], &uids[1], &uids[2]);
+        database.save_user_role(john, &Role::ADMIN).await.unwrap();
+        let claims = database.get_user_claims(john).await.unwrap();
+        auth.set_custom_claims(john, &claims).await.unwrap();
+        for name in ["jane", "john", "jack"] {
+            let res = sign_in(name).await;
+            tokens.push(
+                res.data.into_json().unwrap()["signIn"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            );
+        }
+
+        let res = execute("{ exportMyData }".to_string(), Some(&tokens[0])).await;
+        assert_eq!(res.errors.first(), None);
+        let export = res.data.into_json().unwrap()["exportMyData"].clone();
+        assert_eq!(export["user"]["id"], jane.as_str());
+        assert_eq!(export["user"]["email"], "jane@example.com");
+        assert_eq!(export["roles"], serde_json::json!(["User"]));
+        assert_eq!(export["identity"]["email"], "jane@example.com");
+
+        let delete_mine = |password: &str, token: &str| {
+            execute(
+                format!(
+                    r#"mutation {{ deleteMyAccount(currentPassword: "{}") }}"#,
+                    password
+                ),
+                Some(token),
+            )
+        };
+        let res = delete_mine("wrong", &tokens[0]).await;
+        assert_eq!(code(&res), Some(value!("INVALID_CREDENTIALS")));
+        let res = delete_mine("password", &tokens[0]).await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(res.data.into_json().unwrap()["deleteMyAccount"], true);
+        assert!(database.get_user(jane).await.is_err());
+        let res = execute("{ exportMyData }".to_string(), Some(&tokens[0])).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+        assert!(sign_in("jane").await.is_err());
+
+        let delete = |uid: &str, token: &str| {
+            execute(
+                format!(r#"mutation {{ deleteUser(uid: "{}") }}"#, uid),
+                Some(token),
+            )
+        };
+        let res = delete(john, &tokens[2]).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = delete(jack, &tokens[1]).await;
+        assert_eq!(res.errors.first(), None);
+        assert!(database.get_user(jack).await.is_err());
+        assert!(sign_in("jack").await.is_err());
+        let res = delete(jack, &tokens[1]).await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+        // the last Admin stays
+        let res = delete_mine("password", &tokens[1]).await;
+        assert_eq!(code(&res), Some(value!("CONFLICT")));
+    }
+
+    #[tokio::test]
+    async fn test_suspend_and_soft_delete_user() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(auth.clone())
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let execute = |query: String, token: &str| {
+            schema.execute(
+                Request::new(query)
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", token))),
+            )
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+        let mut uids = Vec::new();
+        for name in ["jane", "john"] {
+            let user = sign_up(
+                &auth,
+                &database,
+                &User {
+                    id: None,
+                    name: name.to_string(),
+                    email: format!("{}@example.com", name),
+                    password: "password".to_string(),
+                    plan: Plan::Free,
+                    email_verified: false,
+                    disabled: false,
+                    deleted_at: None,
+                    status_reason: None,
+                    created_at: None,
+                    updated_at: None,
+                },
+            )
+            .await
+            .unwrap();
+            uids.push(user.id.unwrap());
+        }
+        let (jane, john) = (&uids[0], &uids[1]);
+        database.save_user_role(john, &Role::ADMIN).await.unwrap();
+        let claims = database.get_user_claims(john).await.unwrap();
+        auth.set_custom_claims(john, &claims).await.unwrap();
+        let jane_token = auth.sign_in("jane@example.com", "password").await.unwrap();
+        let admin_token = auth.sign_in("john@example.com", "password").await.unwrap();
+        let res = execute(
+            r#"mutation { createApiKey(name: "etl") { key } }"#.to_string(),
+            &jane_token,
+        )
+        .await;
+        let key = res.data.into_json().unwrap()["createApiKey"]["key"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let status = |mutation: &str, arguments: String, token: &str| {
+            execute(
+                format!(
+                    "mutation {{ {}({}) {{ disabled deletedAt statusReason }} }}",
+                    mutation, arguments
+                ),
+                token,
+            )
+        };
+        let with_reason =
+            |uid: &str, reason: &str| format!(r#"uid: "{}", reason: "{}""#, uid, reason);
+
+        // Admins only, never on themselves, with a reason
+        let res = status("suspendUser", with_reason(john, "spam"), &jane_token).await;
+        assert_eq!(code(&res), Some(value!("FORBIDDEN")));
+        let res = status("suspendUser", with_reason(john, "spam"), &admin_token).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+        let res = status("suspendUser", with_reason(jane, " "), &admin_token).await;
+        assert_eq!(code(&res), Some(value!("VALIDATION_FAILED")));
+
+        let res = status("suspendUser", with_reason(jane, "spam"), &admin_token).await;
+        assert_eq!(res.errors.first(), None);
+        let user = res.data.into_json().unwrap()["suspendUser"].clone();
+        assert_eq!(user["disabled"], true);
+        assert_eq!(user["deletedAt"], serde_json::Value::Null);
+        assert_eq!(user["statusReason"], "spam");
+        assert!(auth.sign_in("jane@example.com", "password").await.is_err());
+        let res = execute("{ myApiKeys { id } }".to_string(), &jane_token).await;
+        assert_eq!(code(&res), Some(value!("UNAUTHENTICATED")));
+        let res = execute("{ myApiKeys { id } }".to_string(), &key).await;
+        assert_eq!(code(&res), Some(value!("ACCOUNT_SUSPENDED")));
+
+        let res = status(
+            "reactivateUser",
+            format!(r#"uid: "{}""#, jane),
+            &admin_token,
+        )
+        .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data.into_json().unwrap()["reactivateUser"]["disabled"],
+            false
+        );
+        assert!(auth.sign_in("jane@example.com", "password").await.is_ok());
+        let res = execute("{ myApiKeys { id } }".to_string(), &key).await;
+        assert_eq!(res.errors.first(), None);
+
+        let res = status("softDeleteUser", with_reason(jane, "left"), &admin_token).await;
+        assert_eq!(res.errors.first(), None);
+        let user = res.data.into_json().unwrap()["softDeleteUser"].clone();
+        assert_eq!(user["disabled"], true);
+        assert_ne!(user["deletedAt"], serde_json::Value::Null);
+        let res = execute("{ myApiKeys { id } }".to_string(), &key).await;
+        assert_eq!(code(&res), Some(value!("ACCOUNT_DELETED")));
+        // kept until purged
+        assert!(database.get_user(jane).await.is_ok());
+
+        let res = status(
+            "softDeleteUser",
+            with_reason("nobody", "left"),
+            &admin_token,
+        )
+        .await;
+        assert_eq!(code(&res), Some(value!("NOT_FOUND")));
+    }
+
+    #[tokio::test]
+    async fn test_suspended_admin_is_not_another_admin() {
+        let database = Utils::memory_database();
+        let auth = AuthService::Local(Utils::local_auth(database.clone()));
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(auth.clone())
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let mut tokens = Vec::new();
+        for name in ["alice", "bob"] {
+            let user = sign_up(
+                &auth,
+                &database,
+                &User {
+                    id: None,
+                    name: name.to_string(),
+                    email: format!("{}@example.com", name),
+                    password: "password".to_string(),
+                    plan: Plan::Free,
+                    email_verified: false,
+                    disabled: false,
+                    deleted_at: None,
+                    status_reason: None,
+                    created_at: None,
+                    updated_at: None,
+                },
+            )
+            .await
+            .unwrap();
+            let uid = user.id.unwrap();
+            database.save_user_role(&uid, &Role::ADMIN).await.unwrap();
+            let claims = database.get_user_claims(&uid).await.unwrap();
+            auth.set_custom_claims(&uid, &claims).await.unwrap();
+            let token = auth
+                .sign_in(&format!("{}@example.com", name), "password")
+                .await
+                .unwrap();
+            tokens.push((uid, token));
+        }
+        let (alice, alice_token) = &tokens[0];
+        let (bob, _) = &tokens[1];
+        let execute = |query: String| {
+            schema.execute(
+                Request::new(query)
+                    .data(Identity::default())
+                    .data(Token(format!("Bearer {}", alice_token))),
+            )
+        };
+        let revoke_own_admin = || {
+            execute(format!(
+                r#"mutation {{ revokeRole(uid: "{}", role: "Admin") {{ id }} }}"#,
+                alice
+            ))
+        };
+
+        let res = execute(format!(
+            r#"mutation {{ suspendUser(uid: "{}", reason: "spam") {{ disabled }} }}"#,
+            bob
+        ))
+        .await;
+        assert_eq!(res.errors.first(), None);
+        // bob can't act as an Admin anymore, alice is the last one
+        let res = revoke_own_admin().await;
+        assert_eq!(
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code").cloned()),
+            Some(value!("CONFLICT"))
+        );
+        assert!(database
+            .get_user_roles(alice)
+            .await
+            .unwrap()
+            .contains(&Role::ADMIN));
+
+        let res = execute(format!(
+            r#"mutation {{ reactivateUser(uid: "{}") {{ disabled }} }}"#,
+            bob
+        ))
+        .await;
+        assert_eq!(res.errors.first(), None);
+        let res = revoke_own_admin().await;
+        assert_eq!(res.errors.first(), None);
+    }
+
+    #[tokio::test]
+    async fn test_suspended_admin_loses_admin_fields() {
+        let database = Utils::memory_database();
+        let schema = Schema::build(Query, Mutation, EmptySubscription)
+            .data(database.clone())
+            .data(EventBus::new())
+            .finish();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let target = database.crate_random_user().await.unwrap().id.unwrap();
+        // verified before the suspension, as a websocket keeps its connection_init
+        let claims = database.get_user_claims(&admin).await.unwrap();
+        let grant = |role: &str| {
+            let identity = Identity::from_claims(AuthClaims {
+                uid: admin.clone(),
+                claims: claims.to_map(),
+                ..Default::default()
+            });
+            schema.execute(
+                Request::new(format!(
+                    r#"mutation {{ grantRole(uid: "{}", role: "{}") {{ id }} }}"#,
+                    target, role
+                ))
+                .data(identity),
+            )
+        };
+        let code = |res: &Response| {
+            res.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("code")
+                .cloned()
+        };
+
+        let res = grant("Manager").await;
+        assert_eq!(res.errors.first(), None);
+
+        database
+            .update_user_status(&admin, true, None, Some("spam"))
+            .await
+            .unwrap();
+        let res = grant("Admin").await;
+        assert_eq!(code(&res), Some(value!("ACCOUNT_SUSPENDED")));
+        assert!(!database
+            .get_user_roles(&target)
+            .await
+            .unwrap()
+            .contains(&Role::ADMIN));
+
+        database
+            .update_user_status(&admin, true, Some(Utc::now()), Some("left"))
+            .await
+            .unwrap();
+        let res = grant("Admin").await;
+        assert_eq!(code(&res), Some(value!("ACCOUNT_DELETED")));
+    }
 }
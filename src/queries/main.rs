@@ -1,11 +1,30 @@
-use std::sync::Arc;
-
 use crate::{
-    guards::{auth::AuthTokenGuard, user::UserExistGuard},
-    structs::user::User,
+    auth::account::export_account,
+    contexts::{identity::Identity, organization::OrganizationId},
+    database::backend::Database,
+    errors::main::{AppError, AppResultExt},
+    guards::{
+        auth::AuthTokenGuard, permission::identity_permissions, role::RoleGuard,
+        user::UserExistGuard,
+    },
+    structs::{
+        api_key::ApiKey,
+        diagnostics::PoolStatistics,
+        impersonation::Impersonation,
+        invitation::Invitation,
+        organization::Organization,
+        permission::Permission,
+        role::{Role, RoleDefinition},
+        user::User,
+    },
+    traits::{
+        api_key::ApiKeyTrait, impersonation::ImpersonationTrait, invitation::InvitationTrait,
+        organization::OrganizationTrait, permission::PermissionTrait, role::RoleTrait,
+        user::UserTrait,
+    },
 };
 use async_graphql::*;
-use tokio::sync::Mutex;
+use uuid::Uuid;
 
 pub struct Query;
 
@@ -13,18 +32,225 @@ pub struct Query;
 impl Query {
     #[graphql(guard = "AuthTokenGuard.and(UserExistGuard)")]
     async fn user<'ctx>(&self, ctx: &Context<'ctx>) -> Result<User, Error> {
-        let user = ctx.data_unchecked::<Arc<Mutex<Option<User>>>>();
-        let user = user.lock().await;
-        let user_back = user.as_ref().unwrap();
-        Ok(user_back.clone())
+        let identity = ctx.data::<Identity>()?;
+        let user = identity
+            .user()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        Ok(user.clone())
+    }
+
+    /*
+        * Users holding a role
+        @param role: Role
+        @return Vec<User>, oldest first
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn users_by_role<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        role: Role,
+    ) -> Result<Vec<User>, Error> {
+        ctx.data::<Database>()?
+            .get_users_by_role(&role)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Permissions of the signed in user, through its roles, narrowed by an api key or an impersonation
+        @return Vec<String>
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_permissions<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<String>, Error> {
+        let permissions =
+            identity_permissions(ctx.data::<Identity>()?, ctx.data::<Database>()?).await?;
+        Ok(permissions.clone())
+    }
+
+    /*
+        * The roles catalogue, built-in and custom roles
+        @return Vec<RoleDefinition>
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn roles<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<RoleDefinition>, Error> {
+        ctx.data::<Database>()?
+            .get_role_definitions()
+            .await
+            .app_err()
+    }
+
+    /*
+        * Every known permission
+        @return Vec<Permission>
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn permissions<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Permission>, Error> {
+        ctx.data::<Database>()?.get_permissions().await.app_err()
+    }
+
+    /*
+        * Permissions granted to a role itself, those of the roles below it are not listed
+        @param role: Role
+        @return Vec<String>
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn role_permissions<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        role: Role,
+    ) -> Result<Vec<String>, Error> {
+        ctx.data::<Database>()?
+            .get_role_permissions(&role)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Organizations the signed in user is a member of
+        @return Vec<Organization>, sorted by name
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_organizations<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<Vec<Organization>, Error> {
+        let uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        ctx.data::<Database>()?
+            .get_user_organizations(&uid.0)
+            .await
+            .app_err()
+    }
+
+    /*
+        * An organization and its members, for its members only
+        @param organization_id: Option<Uuid>, defaults to the organization of the request
+        @return Organization
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::USER).organization(organization_id))"
+    )]
+    async fn organization<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+    ) -> Result<Organization, Error> {
+        let organization_id = OrganizationId::required(ctx, organization_id)?;
+        ctx.data::<Database>()?
+            .get_organization(&organization_id)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Invitations neither accepted nor revoked, expired ones included so they can be resent
+        @param organization_id: Option<Uuid>, defaults to the organization of the request,
+        the invitations to the platform when none is selected
+        @return Vec<Invitation>, oldest first
+    */
+    #[graphql(
+        guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::MANAGER).active_organization(organization_id))"
+    )]
+    async fn invitations<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        organization_id: Option<Uuid>,
+    ) -> Result<Vec<Invitation>, Error> {
+        ctx.data::<Database>()?
+            .get_open_invitations(OrganizationId::active(ctx, organization_id).as_ref())
+            .await
+            .app_err()
+    }
+
+    /*
+        * Every record stored about the signed in user, as a JSON document to download
+        @return JSON, see AccountExport
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn export_my_data<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<Json<serde_json::Value>, Error> {
+        let identity = ctx.data::<Identity>()?;
+        let uid = identity
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        // for the user itself, not a machine client or an Admin acting as it
+        if identity.api_key_id().is_some() || identity.impersonation().is_some() {
+            return Err(AppError::Forbidden.extend());
+        }
+        // password resets and impersonations aren't readable by requests
+        let database = ctx.data::<Database>()?.unscoped();
+        let export = export_account(&database, &uid.0)
+            .await
+            .map_err(|e| e.extend())?;
+        Ok(Json(export.to_json()))
+    }
+
+    /*
+        * Api keys of the signed in user, revoked ones included
+        @return Vec<ApiKey>, oldest first
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_api_keys<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ApiKey>, Error> {
+        let uid = ctx
+            .data::<Identity>()?
+            .uid()
+            .ok_or_else(|| AppError::Unauthorized.extend())?;
+        ctx.data::<Database>()?
+            .get_user_api_keys(&uid.0)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Api keys of any user, i.e a service account
+        @param uid: String
+        @return Vec<ApiKey>, oldest first
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn api_keys<'ctx>(&self, ctx: &Context<'ctx>, uid: String) -> Result<Vec<ApiKey>, Error> {
+        ctx.data::<Database>()?
+            .get_user_api_keys(&uid)
+            .await
+            .app_err()
+    }
+
+    /*
+        * Impersonation sessions, for audit
+        @param uid: Option<String>, only those started by or acting as the user when set
+        @return Vec<Impersonation>, newest first
+    */
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::at_least(Role::ADMIN))")]
+    async fn impersonations<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uid: Option<String>,
+    ) -> Result<Vec<Impersonation>, Error> {
+        ctx.data::<Database>()?
+            .get_impersonations(uid.as_deref())
+            .await
+            .app_err()
+    }
+
+    #[graphql(guard = "AuthTokenGuard.and(RoleGuard::new(Role::ADMIN))")]
+    async fn pool_statistics<'ctx>(&self, ctx: &Context<'ctx>) -> Result<PoolStatistics, Error> {
+        match ctx.data::<Database>()? {
+            Database::Postgres(database) => Ok(database.statistics()),
+            Database::Memory(_) => {
+                Err(AppError::internal("no connection pool in the memory backend").extend())
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::main::AuthService;
     use crate::contexts::token::Token;
-    use crate::firebase::main::Firebase;
     use crate::traits::user::UserTrait;
     use crate::utils::Utils;
     use async_graphql::{EmptyMutation, Schema};
@@ -33,24 +259,19 @@ mod tests {
     #[tokio::test]
     async fn test_user() {
         let uuid = Uuid::new_v4();
-        let (user_uid, user, database_rw, token) =
-            Utils::generate_testing_config(&uuid.to_string())
-                .await
-                .unwrap();
-
-        let user_db = database_rw
-            .write()
+        let (database, token) = Utils::generate_testing_config(&uuid.to_string())
             .await
+            .unwrap();
+
+        let user_db = database
             .create_test_user(&uuid.clone().to_string())
             .await
             .unwrap();
 
         let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
-            .data(user_uid)
-            .data(user)
-            .data(database_rw)
+            .data(AuthService::Local(Utils::local_auth(database.clone())))
+            .data(database)
             .data(Token(format!("Bearer {}", token)))
-            .data(Firebase::new().await)
             .finish();
 
         let query = Request::new(
@@ -64,7 +285,8 @@ mod tests {
                 }
             }
             "#,
-        );
+        )
+        .data(Identity::default());
         let res = schema.execute(query).await;
         assert_eq!(res.errors.first(), None);
         assert_eq!(
@@ -78,4 +300,56 @@ mod tests {
             })
         );
     }
+
+    #[tokio::test]
+    async fn test_users_by_role() {
+        let database = Utils::memory_database();
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let manager = database.crate_random_user().await.unwrap().id.unwrap();
+        database
+            .save_user_role(&manager, &Role::MANAGER)
+            .await
+            .unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database)
+            .finish();
+
+        let query = r#"query { usersByRole(role: "Manager") { id roles } }"#;
+        let res = schema
+            .execute(Request::new(query).data(Identity::verified(&admin)))
+            .await;
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"usersByRole": [{"id": manager.clone(), "roles": ["User", "Manager"]}]})
+        );
+
+        let res = schema
+            .execute(Request::new(query).data(Identity::verified(&manager)))
+            .await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+    }
+
+    #[tokio::test]
+    async fn test_pool_statistics() {
+        let database = Database::Postgres(Utils::generate_testing_database().await.unwrap());
+        let admin = database.crate_random_user().await.unwrap().id.unwrap();
+        database.save_user_role(&admin, &Role::ADMIN).await.unwrap();
+        let user = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(database)
+            .finish();
+
+        let query = "query { poolStatistics { maxSize connectionsCreated } }";
+        let res = schema
+            .execute(Request::new(query).data(Identity::verified(&admin)))
+            .await;
+        assert_eq!(res.errors.first(), None);
+
+        let res = schema
+            .execute(Request::new(query).data(Identity::verified(&user)))
+            .await;
+        assert_eq!(res.errors[0].message, "Role::Unauthorized");
+    }
 }
@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::structs::{
+    api_key::ApiKey,
+    email_verification::EmailVerification,
+    identity::LocalIdentity,
+    impersonation::Impersonation,
+    invitation::Invitation,
+    organization::{Membership, Organization},
+    password_reset::PasswordReset,
+    role::Role,
+    user::User,
+};
+
+/*
+    * Every record stored about a user, see Query::export_my_data
+    Passwords, key and token hashes are never part of it.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    pub roles: Vec<Role>,
+    // with the local auth provider only, firebase keeps its identities
+    pub identity: Option<LocalIdentity>,
+    pub memberships: Vec<(Organization, Membership)>,
+    pub api_keys: Vec<ApiKey>,
+    pub invitations: Vec<Invitation>,
+    // sessions acting as the user or started by it
+    pub impersonations: Vec<Impersonation>,
+    pub password_resets: Vec<PasswordReset>,
+    pub email_verifications: Vec<EmailVerification>,
+}
+
+fn time(time: Option<DateTime<Utc>>) -> Value {
+    time.map_or(Value::Null, |time| Value::String(time.to_rfc3339()))
+}
+
+impl AccountExport {
+    /*
+        * The document handed to the user, keys are camelCase like the rest of the api
+        @return Value
+    */
+    pub fn to_json(&self) -> Value {
+        json!({
+            "exportedAt": time(Some(self.exported_at)),
+            "user": {
+                "id": self.user.id,
+                "name": self.user.name,
+                "email": self.user.email,
+                "emailVerified": self.user.email_verified,
+                "plan": self.user.plan,
+                "disabled": self.user.disabled,
+                "deletedAt": time(self.user.deleted_at),
+                "statusReason": self.user.status_reason,
+                "createdAt": time(self.user.created_at),
+                "updatedAt": time(self.user.updated_at),
+            },
+            "roles": self.roles,
+            "identity": self.identity.as_ref().map(|identity| json!({
+                "email": identity.email,
+                "displayName": identity.display_name,
+                "emailVerified": identity.email_verified,
+                "disabled": identity.disabled,
+                "customClaims": identity.custom_claims,
+                "sessionsValidAfter": time(identity.sessions_valid_after),
+                "createdAt": time(identity.created_at),
+                "updatedAt": time(identity.updated_at),
+            })),
+            "organizations": self.memberships.iter().map(|(organization, membership)| json!({
+                "id": organization.id,
+                "name": organization.name,
+                "role": membership.role,
+                "joinedAt": time(membership.created_at),
+            })).collect::<Vec<_>>(),
+            "apiKeys": self.api_keys.iter().map(|api_key| json!({
+                "id": api_key.id,
+                "name": api_key.name,
+                "scopes": api_key.scopes,
+                "createdBy": api_key.created_by,
+                "lastUsedAt": time(api_key.last_used_at),
+                "revokedAt": time(api_key.revoked_at),
+                "createdAt": time(api_key.created_at),
+            })).collect::<Vec<_>>(),
+            "invitations": self.invitations.iter().map(|invitation| json!({
+                "id": invitation.id,
+                "email": invitation.email,
+                "role": invitation.role,
+                "organizationId": invitation.organization_id,
+                "invitedBy": invitation.invited_by,
+                "expiresAt": time(Some(invitation.expires_at)),
+                "acceptedAt": time(invitation.accepted_at),
+                "acceptedBy": invitation.accepted_by,
+                "revokedAt": time(invitation.revoked_at),
+                "createdAt": time(invitation.created_at),
+            })).collect::<Vec<_>>(),
+            "impersonations": self.impersonations.iter().map(|impersonation| json!({
+                "id": impersonation.id,
+                "adminUid": impersonation.admin_uid,
+                "targetUid": impersonation.target_uid,
+                "reason": impersonation.reason,
+                "readOnly": impersonation.read_only,
+                "expiresAt": time(Some(impersonation.expires_at)),
+                "endedAt": time(impersonation.ended_at),
+                "createdAt": time(impersonation.created_at),
+            })).collect::<Vec<_>>(),
+            "passwordResets": self.password_resets.iter().map(|reset| json!({
+                "id": reset.id,
+                "expiresAt": time(Some(reset.expires_at)),
+                "usedAt": time(reset.used_at),
+                "createdAt": time(reset.created_at),
+            })).collect::<Vec<_>>(),
+            "emailVerifications": self.email_verifications.iter().map(|verification| json!({
+                "id": verification.id,
+                "email": verification.email,
+                "expiresAt": time(Some(verification.expires_at)),
+                "usedAt": time(verification.used_at),
+                "createdAt": time(verification.created_at),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::claims::Plan;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_to_json() {
+        let now = Utc::now();
+        let export = AccountExport {
+            exported_at: now,
+            user: User {
+                id: Some("uid".to_string()),
+                name: "Jane".to_string(),
+                email: "jane@example.com".to_string(),
+                password: "$2b$04$password-hash".to_string(),
+                plan: Plan::Premium,
+                email_verified: true,
+                disabled: false,
+                deleted_at: None,
+                status_reason: None,
+                created_at: Some(now),
+                updated_at: None,
+            },
+            roles: vec![Role::USER],
+            identity: None,
+            memberships: Vec::new(),
+            api_keys: vec![ApiKey {
+                id: Uuid::new_v4(),
+                user_id: "uid".to_string(),
+                name: "etl".to_string(),
+                scopes: vec![Role::USER],
+                key_hash: "$2b$04$key-hash".to_string(),
+                created_by: Some("uid".to_string()),
+                last_used_at: None,
+                revoked_at: None,
+                created_at: Some(now),
+                updated_at: Some(now),
+            }],
+            invitations: Vec::new(),
+            impersonations: Vec::new(),
+            password_resets: vec![PasswordReset {
+                id: Uuid::new_v4(),
+                user_id: "uid".to_string(),
+                token_hash: "token-hash".to_string(),
+                expires_at: now,
+                used_at: Some(now),
+                created_at: Some(now),
+            }],
+            email_verifications: Vec::new(),
+        };
+        let json = export.to_json();
+        assert_eq!(json["user"]["email"], "jane@example.com");
+        assert_eq!(json["user"]["plan"], "premium");
+        assert_eq!(json["user"]["updatedAt"], Value::Null);
+        assert_eq!(json["roles"], json!(["User"]));
+        assert_eq!(json["identity"], Value::Null);
+        assert_eq!(json["apiKeys"][0]["name"], "etl");
+        assert_eq!(json["passwordResets"][0]["usedAt"], now.to_rfc3339());
+        let document = json.to_string();
+        for secret in ["password-hash", "key-hash", "token-hash"] {
+            assert!(!document.contains(secret), "{} in {}", secret, document);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::structs::{
+    one_time_token::OneTimeToken,
+    role::{Role, RoleCatalogue},
+};
+
+/*
+ * Key of a machine client, it acts as its user with at most the roles in scopes
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<Role>,
+    // bcrypt of the secret part of the key
+    #[graphql(skip)]
+    pub key_hash: String,
+    pub created_by: Option<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    // sets keys apart from the tokens of the auth provider, in the Authorization header
+    pub const PREFIX: &'static str = "dik_";
+    // claim carrying the id of the key in the identity of a request it authenticates
+    pub const CLAIM: &'static str = "api_key_id";
+
+    /*
+        * Draw a new key
+        @param id: &Uuid, of the stored key
+        @return (key, secret), the secret is the part to hash
+    */
+    pub fn generate(id: &Uuid) -> (String, String) {
+        let secret = OneTimeToken::generate().token;
+        (
+            format!("{}{}_{}", ApiKey::PREFIX, id.simple(), secret),
+            secret,
+        )
+    }
+
+    /*
+        * Split a key into its id and secret
+        @param key: &str
+        @return None when it isn't shaped like a key
+    */
+    pub fn parse(key: &str) -> Option<(Uuid, &str)> {
+        let (id, secret) = key.strip_prefix(ApiKey::PREFIX)?.split_once('_')?;
+        if secret.is_empty() {
+            return None;
+        }
+        Some((Uuid::try_parse(id).ok()?, secret))
+    }
+
+    /*
+        * Roles the key grants, the scopes still implied by the roles of its user
+        Revoking a role from the user takes it from the key as well.
+        @param held: &[Role], roles of the user
+        @param catalogue: &RoleCatalogue
+        @return Vec<Role>
+    */
+    pub fn granted_roles(&self, held: &[Role], catalogue: &RoleCatalogue) -> Vec<Role> {
+        self.scopes
+            .iter()
+            .filter(|scope| held.iter().any(|role| catalogue.implies(role, scope)))
+            .cloned()
+            .collect()
+    }
+
+    /*
+        * Check a key name
+        @param name: &str, 1 to 64 characters
+        @return Result<(), String> with the reason
+    */
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if name.trim().is_empty() || name.trim() != name || name.chars().count() > 64 {
+            Err(format!(
+                "api key name {:?} must be 1 to 64 characters without surrounding spaces",
+                name
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/*
+ * Key along with its value, the only time the value is visible
+*/
+#[derive(SimpleObject, Debug, Clone)]
+pub struct ApiKeyTicket {
+    pub api_key: ApiKey,
+    // sent as `Authorization: Bearer <key>`
+    pub key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::role::RoleDefinition;
+
+    #[test]
+    fn test_generate_and_parse() {
+        let id = Uuid::new_v4();
+        let (key, secret) = ApiKey::generate(&id);
+        assert!(key.starts_with(ApiKey::PREFIX));
+        assert_eq!(ApiKey::parse(&key), Some((id, secret.as_str())));
+        assert_ne!(ApiKey::generate(&id).0, key);
+        for key in [
+            "",
+            "dik_",
+            "eyJhbGciOiJSUzI1NiJ9.e30.sig",
+            &format!("dik_{}", id.simple()),
+            &format!("dik_{}_", id.simple()),
+            "dik_nope_secret",
+        ] {
+            assert_eq!(ApiKey::parse(key), None, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn test_granted_roles() {
+        let catalogue = RoleCatalogue::new(RoleDefinition::defaults());
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            user_id: "uid".to_string(),
+            name: "etl".to_string(),
+            scopes: vec![Role::USER, Role::MANAGER],
+            key_hash: String::new(),
+            created_by: None,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        assert_eq!(
+            key.granted_roles(&[Role::USER, Role::ADMIN], &catalogue),
+            vec![Role::USER, Role::MANAGER]
+        );
+        assert_eq!(
+            key.granted_roles(&[Role::USER], &catalogue),
+            vec![Role::USER]
+        );
+        assert!(key.granted_roles(&[], &catalogue).is_empty());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(ApiKey::validate_name("nightly etl").is_ok());
+        for name in ["", " etl", &"a".repeat(65)] {
+            assert!(ApiKey::validate_name(name).is_err(), "{:?}", name);
+        }
+    }
+}
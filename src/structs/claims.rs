@@ -0,0 +1,168 @@
+use std::{fmt, str::FromStr};
+
+use async_graphql::*;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::{FromSql, ToSql};
+
+use crate::structs::role::Role;
+
+/*
+ * Subscription plan of a user
+*/
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Plan {
+    #[default]
+    Free,
+    Premium,
+}
+
+impl Plan {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Plan::Free => "free",
+            Plan::Premium => "premium",
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Plan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free" => Ok(Plan::Free),
+            "premium" => Ok(Plan::Premium),
+            _ => Err(format!("unknown plan {}, expected free or premium", s)),
+        }
+    }
+}
+
+impl FromSql<'_> for Plan {
+    fn from_sql(
+        ty: &tokio_postgres::types::Type,
+        raw: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(<&str as FromSql>::from_sql(ty, raw)?.parse()?)
+    }
+
+    fn accepts(ty: &tokio_postgres::types::Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for Plan {
+    fn to_sql(
+        &self,
+        ty: &tokio_postgres::types::Type,
+        w: &mut bytes::BytesMut,
+    ) -> Result<tokio_postgres::types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.as_str().to_sql(ty, w)
+    }
+
+    fn accepts(ty: &tokio_postgres::types::Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    tokio_postgres::types::to_sql_checked!();
+}
+
+/*
+    * Custom claims pushed to the auth provider, tokens issued afterwards carry them
+    so AuthTokenGuard doesn't query the roles on every request.
+*/
+#[derive(SimpleObject, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserClaims {
+    pub roles: Vec<Role>,
+    pub plan: Plan,
+    // bumped by the database on every change of roles or plan
+    pub claims_version: i64,
+}
+
+impl UserClaims {
+    /*
+        * Claims as stored by the auth provider
+        @return serde_json::Map
+    */
+    pub fn to_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        }
+    }
+
+    /*
+        * Read the claims back from a verified token
+        @param claims: &serde_json::Map, every claim of the token
+        @return None when the token was issued before the first sync or carries an unknown role
+    */
+    pub fn from_token(claims: &serde_json::Map<String, serde_json::Value>) -> Option<UserClaims> {
+        serde_json::from_value(serde_json::Value::Object(claims.clone())).ok()
+    }
+}
+
+/*
+ * When AuthTokenGuard reads the roles from the database instead of the token
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ClaimsFallback {
+    // trust the token, a token without claims holds no role
+    Never,
+    // only for tokens issued before the claims were first synced
+    #[default]
+    Missing,
+    // also when the database has a newer claims_version than the token, costs a query per request
+    Stale,
+}
+
+impl FromStr for ClaimsFallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(ClaimsFallback::Never),
+            "missing" => Ok(ClaimsFallback::Missing),
+            "stale" => Ok(ClaimsFallback::Stale),
+            _ => Err(format!(
+                "unknown claims fallback {}, expected one of never, missing, stale",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims_round_trip() {
+        let claims = UserClaims {
+            roles: vec![Role::USER, Role::parse("Analyst").unwrap()],
+            plan: Plan::Premium,
+            claims_version: 3,
+        };
+        let map = claims.to_map();
+        assert_eq!(
+            serde_json::Value::Object(map.clone()),
+            serde_json::json!({"roles": ["User", "Analyst"], "plan": "premium", "claims_version": 3})
+        );
+
+        // a token holds the registered claims as well
+        let mut token = map;
+        token.insert("sub".to_string(), "uid".into());
+        token.insert("exp".to_string(), 1.into());
+        assert_eq!(UserClaims::from_token(&token), Some(claims));
+
+        token.insert("roles".to_string(), serde_json::json!(["not a role"]));
+        assert_eq!(UserClaims::from_token(&token), None);
+        token.remove("roles");
+        assert_eq!(UserClaims::from_token(&token), None);
+    }
+}
@@ -0,0 +1,16 @@
+use async_graphql::*;
+
+#[derive(SimpleObject, Debug, Clone, PartialEq)]
+pub struct PoolStatistics {
+    pub min_size: u32,
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub checkouts_direct: u64,
+    pub checkouts_waited: u64,
+    pub checkouts_timed_out: u64,
+    pub checkout_wait_time_ms: u64,
+    pub connections_created: u64,
+    pub connections_closed_broken: u64,
+    pub connections_closed_invalid: u64,
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::traits::mailer::Email;
+
+/*
+    * Link proving a user owns its email, sent on sign up and on email change
+    Never shown to clients, only the user holding the token can redeem it.
+*/
+#[derive(Debug, PartialEq, Clone)]
+pub struct EmailVerification {
+    pub id: Uuid,
+    pub user_id: String,
+    // the email the link was sent to
+    pub email: String,
+    // sha256 of the token, see OneTimeToken
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl EmailVerification {
+    /*
+        * Neither used nor expired at a given time
+        @param now: DateTime<Utc>
+        @return bool
+    */
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.used_at.is_none() && self.expires_at > now
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailVerificationConfig {
+    // how long a verification link can be used, from when it is sent
+    pub ttl: Duration,
+    // page of the client calling verifyEmail, the token is appended as ?token=
+    pub link: String,
+}
+
+impl EmailVerificationConfig {
+    /*
+        * Expiry of a verification sent now
+        @param now: DateTime<Utc>
+        @return DateTime<Utc>
+    */
+    pub fn expires_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /*
+        * Email carrying the link of a verification
+        @param to: &str
+        @param token: &str
+        @return Email
+    */
+    pub fn email(&self, to: &str, token: &str) -> Email {
+        let separator = if self.link.contains('?') { '&' } else { '?' };
+        Email {
+            to: to.to_string(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "Follow this link to verify your email:\n\n{}{}token={}\n\nIt expires in {} hours, ignore this email if you didn't sign up.\n",
+                self.link,
+                separator,
+                token,
+                self.ttl.as_secs() / 3600
+            ),
+        }
+    }
+}
+
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        EmailVerificationConfig {
+            ttl: Duration::from_secs(24 * 3600),
+            link: "http://localhost:5173/verify-email".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_pending() {
+        let now = Utc::now();
+        let mut verification = EmailVerification {
+            id: Uuid::new_v4(),
+            user_id: "uid".to_string(),
+            email: "jane@example.com".to_string(),
+            token_hash: String::new(),
+            expires_at: EmailVerificationConfig::default().expires_at(now),
+            used_at: None,
+            created_at: None,
+        };
+        assert_eq!(verification.expires_at, now + chrono::Duration::hours(24));
+        assert!(verification.is_pending(now));
+        assert!(!verification.is_pending(now + chrono::Duration::hours(24)));
+        verification.used_at = Some(now);
+        assert!(!verification.is_pending(now));
+    }
+
+    #[test]
+    fn test_email() {
+        let mut config = EmailVerificationConfig::default();
+        let email = config.email("jane@example.com", "abc");
+        assert_eq!(email.to, "jane@example.com");
+        assert!(email
+            .body
+            .contains("http://localhost:5173/verify-email?token=abc\n"));
+        config.link = "https://app.example.com/?page=verify".to_string();
+        assert!(config
+            .email("jane@example.com", "abc")
+            .body
+            .contains("https://app.example.com/?page=verify&token=abc\n"));
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+
+/*
+ * Identity managed by the local auth provider, the password is a bcrypt hash
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalIdentity {
+    pub uid: String,
+    pub email: String,
+    pub password: String,
+    pub display_name: Option<String>,
+    pub email_verified: bool,
+    pub disabled: bool,
+    // copied into every token issued for the identity
+    pub custom_claims: serde_json::Map<String, serde_json::Value>,
+    // tokens issued before, to the second, are refused
+    pub sessions_valid_after: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
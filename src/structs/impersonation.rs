@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::traits::impersonation::ImpersonationTrait;
+use crate::{database::backend::Database, errors::main::AppResultExt};
+
+/*
+    * Session in which an Admin acts as another user until it ends or expires,
+    requests opt in with the X-Impersonate header
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+#[graphql(complex)]
+pub struct Impersonation {
+    pub id: Uuid,
+    // the Admin, uid the requests are really made by
+    pub admin_uid: String,
+    // uid the requests act as
+    pub target_uid: String,
+    pub reason: String,
+    // mutations are refused while true
+    pub read_only: bool,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl Impersonation {
+    #[graphql(name = "active")]
+    async fn graphql_active(&self) -> bool {
+        self.is_active(Utc::now())
+    }
+
+    /*
+        * Requests made during the session, oldest first
+        @return Vec<ImpersonationEvent>
+    */
+    async fn events<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ImpersonationEvent>> {
+        ctx.data::<Database>()?
+            .get_impersonation_events(&self.id)
+            .await
+            .app_err()
+    }
+}
+
+impl Impersonation {
+    // claim carrying an ImpersonationClaim in the identity of an impersonated request
+    pub const CLAIM: &'static str = "impersonation";
+
+    /*
+        * Neither ended nor expired at a given time
+        @param now: DateTime<Utc>
+        @return bool
+    */
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.ended_at.is_none() && self.expires_at > now
+    }
+}
+
+/*
+ * Audit record of a request made during an impersonation, blocked ones included
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+pub struct ImpersonationEvent {
+    pub id: Uuid,
+    pub impersonation_id: Uuid,
+    pub real_uid: String,
+    pub effective_uid: String,
+    // query, mutation or subscription
+    pub operation_type: String,
+    pub operation_name: Option<String>,
+    // refused because the session is read only
+    pub blocked: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/*
+    * What an impersonated request knows of its session, in the claims of its identity
+    and in the `impersonation` extension of the response so clients can show a banner
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonationClaim {
+    pub id: Uuid,
+    pub real_uid: String,
+    pub effective_uid: String,
+    pub read_only: bool,
+    // unix timestamp, like the expiry of a token
+    pub expires_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImpersonationConfig {
+    // longest session an Admin can start, and the length of one started without minutes
+    pub max_duration: Duration,
+}
+
+impl ImpersonationConfig {
+    /*
+        * Expiry of a session started now
+        @param now: DateTime<Utc>
+        @param minutes: Option<u32>, the longest session when None
+        @return DateTime<Utc>, or the reason the length is rejected
+    */
+    pub fn expires_at(
+        &self,
+        now: DateTime<Utc>,
+        minutes: Option<u32>,
+    ) -> Result<DateTime<Utc>, String> {
+        let max_minutes = self.max_duration.as_secs() / 60;
+        let minutes = u64::from(minutes.unwrap_or(max_minutes as u32));
+        if minutes == 0 || minutes > max_minutes {
+            return Err(format!(
+                "an impersonation lasts 1 to {} minutes",
+                max_minutes
+            ));
+        }
+        Ok(now + chrono::Duration::minutes(minutes as i64))
+    }
+}
+
+impl Default for ImpersonationConfig {
+    fn default() -> Self {
+        ImpersonationConfig {
+            max_duration: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_active() {
+        let now = Utc::now();
+        let mut impersonation = Impersonation {
+            id: Uuid::new_v4(),
+            admin_uid: "admin".to_string(),
+            target_uid: "user".to_string(),
+            reason: "ticket 42".to_string(),
+            read_only: true,
+            expires_at: now + chrono::Duration::minutes(5),
+            ended_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        assert!(impersonation.is_active(now));
+        assert!(!impersonation.is_active(now + chrono::Duration::minutes(5)));
+        impersonation.ended_at = Some(now);
+        assert!(!impersonation.is_active(now));
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let now = Utc::now();
+        let config = ImpersonationConfig::default();
+        assert_eq!(
+            config.expires_at(now, None),
+            Ok(now + chrono::Duration::minutes(60))
+        );
+        assert_eq!(
+            config.expires_at(now, Some(15)),
+            Ok(now + chrono::Duration::minutes(15))
+        );
+        assert!(config.expires_at(now, Some(0)).is_err());
+        assert!(config.expires_at(now, Some(61)).is_err());
+    }
+}
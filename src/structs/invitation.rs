@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{structs::role::Role, traits::mailer::Email};
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Revoked,
+    Expired,
+}
+
+/*
+    * Invitation of an email to the platform, or to an organization when organization_id is set,
+    with the role it gets once accepted
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+#[graphql(complex)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub organization_id: Option<Uuid>,
+    // sha256 of the token, see OneTimeToken
+    #[graphql(skip)]
+    pub token_hash: String,
+    pub invited_by: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub accepted_by: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl Invitation {
+    #[graphql(name = "status")]
+    async fn graphql_status(&self) -> InvitationStatus {
+        self.status(Utc::now())
+    }
+}
+
+impl Invitation {
+    /*
+        * Status of the invitation at a given time
+        @param now: DateTime<Utc>
+        @return InvitationStatus
+    */
+    pub fn status(&self, now: DateTime<Utc>) -> InvitationStatus {
+        if self.accepted_at.is_some() {
+            InvitationStatus::Accepted
+        } else if self.revoked_at.is_some() {
+            InvitationStatus::Revoked
+        } else if self.expires_at <= now {
+            InvitationStatus::Expired
+        } else {
+            InvitationStatus::Pending
+        }
+    }
+
+    /*
+        * Neither accepted nor revoked, it can be resent even once expired
+        @return bool
+    */
+    pub fn is_open(&self) -> bool {
+        self.accepted_at.is_none() && self.revoked_at.is_none()
+    }
+
+    /*
+        * Normalize an invited email
+        @param email: &str
+        @return the trimmed lowercase email, or the reason it is rejected
+    */
+    pub fn normalize_email(email: &str) -> Result<String, String> {
+        let email = email.trim().to_lowercase();
+        match email.split_once('@') {
+            Some((local, domain))
+                if !local.is_empty()
+                    && domain.contains('.')
+                    && !email.chars().any(char::is_whitespace) =>
+            {
+                Ok(email)
+            }
+            _ => Err(format!("{:?} is not an email", email)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvitationConfig {
+    // how long an invitation can be accepted, from when it is sent or resent
+    pub ttl: Duration,
+    // page of the client calling acceptInvitation, the token is appended as ?token=
+    pub link: String,
+}
+
+impl InvitationConfig {
+    /*
+        * Expiry of an invitation sent now
+        @param now: DateTime<Utc>
+        @return DateTime<Utc>
+    */
+    pub fn expires_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /*
+        * Email carrying the link of an invitation, the only place its token goes
+        @param invitation: &Invitation
+        @param token: &str
+        @return Email
+    */
+    pub fn email(&self, invitation: &Invitation, token: &str) -> Email {
+        let separator = if self.link.contains('?') { '&' } else { '?' };
+        Email {
+            to: invitation.email.clone(),
+            subject: "You are invited".to_string(),
+            body: format!(
+                "You are invited to join as {}, follow this link to accept:\n\n{}{}token={}\n\nIt expires in {} hours, sign in with this email to accept it.\n",
+                invitation.role,
+                self.link,
+                separator,
+                token,
+                self.ttl.as_secs() / 3600
+            ),
+        }
+    }
+}
+
+impl Default for InvitationConfig {
+    fn default() -> Self {
+        InvitationConfig {
+            ttl: Duration::from_secs(7 * 24 * 3600),
+            link: "http://localhost:5173/accept-invitation".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let now = Utc::now();
+        let mut invitation = Invitation {
+            id: Uuid::new_v4(),
+            email: "invited@example.com".to_string(),
+            role: Role::USER,
+            organization_id: None,
+            token_hash: String::new(),
+            invited_by: None,
+            expires_at: now + chrono::Duration::hours(1),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        assert_eq!(invitation.status(now), InvitationStatus::Pending);
+        assert_eq!(
+            invitation.status(now + chrono::Duration::hours(2)),
+            InvitationStatus::Expired
+        );
+        invitation.revoked_at = Some(now);
+        assert_eq!(invitation.status(now), InvitationStatus::Revoked);
+        assert!(!invitation.is_open());
+        invitation.accepted_at = Some(now);
+        assert_eq!(invitation.status(now), InvitationStatus::Accepted);
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(
+            Invitation::normalize_email(" Jane.Doe@Example.com "),
+            Ok("jane.doe@example.com".to_string())
+        );
+        for email in [
+            "",
+            "jane",
+            "@example.com",
+            "jane@localhost",
+            "ja ne@example.com",
+        ] {
+            assert!(Invitation::normalize_email(email).is_err(), "{:?}", email);
+        }
+    }
+
+    #[test]
+    fn test_email() {
+        let invitation = Invitation {
+            id: Uuid::new_v4(),
+            email: "invited@example.com".to_string(),
+            role: Role::MANAGER,
+            organization_id: None,
+            token_hash: String::new(),
+            invited_by: None,
+            expires_at: Utc::now(),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+        let config = InvitationConfig {
+            link: "https://app.example.com/join?from=email".to_string(),
+            ..Default::default()
+        };
+        let email = config.email(&invitation, "abc");
+        assert_eq!(email.to, "invited@example.com");
+        assert!(email.body.contains("join as Manager"));
+        assert!(email
+            .body
+            .contains("https://app.example.com/join?from=email&token=abc\n"));
+        assert!(email.body.contains("expires in 168 hours"));
+    }
+}
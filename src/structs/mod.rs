@@ -1 +1,14 @@
+pub mod account_export;
+pub mod api_key;
+pub mod claims;
+pub mod diagnostics;
+pub mod email_verification;
+pub mod identity;
+pub mod impersonation;
+pub mod invitation;
+pub mod one_time_token;
+pub mod organization;
+pub mod password_reset;
+pub mod permission;
+pub mod role;
 pub mod user;
@@ -0,0 +1,59 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/*
+    * Random single-use token, i.e of an invitation
+    The token is handed out once, only its hash is stored. It carries enough entropy
+    for a plain sha256 to be safe, which keeps it searchable unlike bcrypt.
+*/
+#[derive(Debug)]
+pub struct OneTimeToken {
+    pub token: String,
+    pub hash: String,
+}
+
+impl OneTimeToken {
+    /*
+        * Draw a new token
+        @return OneTimeToken, 244 random bits
+    */
+    pub fn generate() -> OneTimeToken {
+        let mut bytes = Vec::with_capacity(32);
+        bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+        bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        OneTimeToken {
+            hash: OneTimeToken::hash(&token),
+            token,
+        }
+    }
+
+    /*
+        * Hash of a token as stored
+        @param token: &str
+        @return String, hex encoded sha256
+    */
+    pub fn hash(token: &str) -> String {
+        Sha256::digest(token.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let first = OneTimeToken::generate();
+        let second = OneTimeToken::generate();
+        assert_ne!(first.token, second.token);
+        assert_eq!(first.token.len(), 43);
+        assert_eq!(first.hash, OneTimeToken::hash(&first.token));
+        assert_eq!(first.hash.len(), 64);
+        assert_ne!(first.hash, first.token);
+    }
+}
@@ -0,0 +1,78 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    database::backend::Database, errors::main::AppResultExt, structs::role::Role,
+    traits::organization::OrganizationTrait,
+};
+
+/*
+ * Customer owning users, each member holds a role inside it
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+#[graphql(complex)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl Organization {
+    /*
+        * Members of the organization
+        @return Vec<Membership>, oldest first
+    */
+    async fn members<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Membership>, Error> {
+        ctx.data::<Database>()?
+            .get_members(&self.id)
+            .await
+            .app_err()
+    }
+}
+
+impl Organization {
+    /*
+        * Check an organization name, surrounding spaces are not trimmed
+        @param name: &str, 1 to 128 characters
+        @return Result<(), String> with the reason
+    */
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if name.trim().is_empty() || name.trim() != name || name.chars().count() > 128 {
+            Err(format!(
+                "organization name {:?} must be 1 to 128 characters without surrounding spaces",
+                name
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/*
+ * Role of a user inside an organization
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+pub struct Membership {
+    pub organization_id: Uuid,
+    pub user_id: String,
+    pub role: Role,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(Organization::validate_name("Acme").is_ok());
+        assert!(Organization::validate_name("Acme Corp.").is_ok());
+        for name in ["", "  ", " Acme", "Acme ", &"a".repeat(129)] {
+            assert!(Organization::validate_name(name).is_err(), "{:?}", name);
+        }
+    }
+}
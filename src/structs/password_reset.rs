@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::traits::mailer::Email;
+
+/*
+    * Link to choose a new password without the current one, sent to the email of the user
+    Never shown to clients, only the user holding the token can redeem it.
+*/
+#[derive(Debug, PartialEq, Clone)]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: String,
+    // sha256 of the token, see OneTimeToken
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl PasswordReset {
+    /*
+        * Neither used nor expired at a given time
+        @param now: DateTime<Utc>
+        @return bool
+    */
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.used_at.is_none() && self.expires_at > now
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordResetConfig {
+    // how long a reset link can be used, from when it is requested
+    pub ttl: Duration,
+    // page of the client calling resetPassword, the token is appended as ?token=
+    pub link: String,
+}
+
+impl PasswordResetConfig {
+    /*
+        * Expiry of a reset requested now
+        @param now: DateTime<Utc>
+        @return DateTime<Utc>
+    */
+    pub fn expires_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /*
+        * Email carrying the link of a reset
+        @param to: &str
+        @param token: &str
+        @return Email
+    */
+    pub fn email(&self, to: &str, token: &str) -> Email {
+        let separator = if self.link.contains('?') { '&' } else { '?' };
+        Email {
+            to: to.to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Follow this link to choose a new password:\n\n{}{}token={}\n\nIt expires in {} minutes, ignore this email if you didn't ask for it.\n",
+                self.link,
+                separator,
+                token,
+                self.ttl.as_secs() / 60
+            ),
+        }
+    }
+}
+
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        PasswordResetConfig {
+            ttl: Duration::from_secs(3600),
+            link: "http://localhost:5173/reset-password".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_pending() {
+        let now = Utc::now();
+        let mut reset = PasswordReset {
+            id: Uuid::new_v4(),
+            user_id: "uid".to_string(),
+            token_hash: String::new(),
+            expires_at: PasswordResetConfig::default().expires_at(now),
+            used_at: None,
+            created_at: None,
+        };
+        assert_eq!(reset.expires_at, now + chrono::Duration::hours(1));
+        assert!(reset.is_pending(now));
+        assert!(!reset.is_pending(now + chrono::Duration::hours(1)));
+        reset.used_at = Some(now);
+        assert!(!reset.is_pending(now));
+    }
+
+    #[test]
+    fn test_email() {
+        let email = PasswordResetConfig::default().email("jane@example.com", "abc");
+        assert_eq!(email.to, "jane@example.com");
+        assert!(email
+            .body
+            .contains("http://localhost:5173/reset-password?token=abc\n"));
+        assert!(email.body.contains("expires in 60 minutes"));
+    }
+}
@@ -0,0 +1,85 @@
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+
+use crate::structs::role::Role;
+
+/*
+ * Capability checked by PermissionGuard, named `resource:action`
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+pub struct Permission {
+    pub name: String,
+    pub description: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// seeded by migrations/0004_create_permissions.up.sql
+pub const DEFAULT_PERMISSIONS: &[(&str, &str)] = &[
+    ("user:read", "Read your own profile"),
+    ("user:update", "Update your own profile"),
+    ("user:read_email", "Read the email of other users"),
+    ("dataset:read", "Read datasets"),
+    ("dataset:write", "Create and update datasets"),
+    ("role:write", "Grant and revoke roles"),
+    ("permission:write", "Edit the permissions of roles"),
+];
+
+// a role also gets the permissions of the roles below it
+pub const DEFAULT_ROLE_PERMISSIONS: &[(Role, &str)] = &[
+    (Role::USER, "user:read"),
+    (Role::USER, "user:update"),
+    (Role::MANAGER, "user:read_email"),
+    (Role::MANAGER, "dataset:read"),
+    (Role::MANAGER, "dataset:write"),
+    (Role::ADMIN, "role:write"),
+    (Role::ADMIN, "permission:write"),
+];
+
+impl Permission {
+    /*
+        * Check a permission name is `resource:action`, both lowercase ascii letters or underscores
+        @param name: &str
+        @return Result<(), String> with the reason
+    */
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        let valid = |part: &str| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        };
+        match name.split_once(':') {
+            Some((resource, action)) if valid(resource) && valid(action) => Ok(()),
+            _ => Err(format!(
+                "permission {:?} must look like resource:action",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["user:update", "dataset:read_all", "a:b"] {
+            assert_eq!(Permission::validate_name(name), Ok(()));
+        }
+        for name in [
+            "",
+            "user",
+            "user:",
+            ":update",
+            "User:update",
+            "user:up:date",
+            "user:up date",
+        ] {
+            assert!(Permission::validate_name(name).is_err(), "{}", name);
+        }
+        for (name, _) in DEFAULT_PERMISSIONS {
+            assert_eq!(Permission::validate_name(name), Ok(()));
+        }
+        for (_, name) in DEFAULT_ROLE_PERMISSIONS {
+            assert!(DEFAULT_PERMISSIONS.iter().any(|(known, _)| known == name));
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{borrow::Cow, fmt};
+
+use async_graphql::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::{FromSql, ToSql};
+
+/*
+    * Name of a role of the catalogue
+    Roles are data, only the built-in ones are known at compile time.
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Role(Cow<'static, str>);
+
+impl Role {
+    pub const USER: Role = Role(Cow::Borrowed("User"));
+    pub const MANAGER: Role = Role(Cow::Borrowed("Manager"));
+    pub const ADMIN: Role = Role(Cow::Borrowed("Admin"));
+
+    // lowest first, each is the parent of the next one
+    pub const BUILT_IN: [Role; 3] = [Role::USER, Role::MANAGER, Role::ADMIN];
+
+    /*
+        * Parse a role name, it isn't checked against the catalogue
+        @param name: &str, ascii letters, digits, `_` or `-`, at most 64 characters
+        @return Role
+    */
+    pub fn parse(name: &str) -> Result<Role, String> {
+        let valid = !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if valid {
+            Ok(Role(Cow::Owned(name.to_string())))
+        } else {
+            Err(format!("invalid role name {:?}", name))
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_built_in(&self) -> bool {
+        Role::BUILT_IN.contains(self)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Role::parse(&name)
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> Self {
+        role.0.into_owned()
+    }
+}
+
+#[Scalar]
+impl ScalarType for Role {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(name) => Role::parse(name).map_err(InputValueError::custom),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.to_string())
+    }
+}
+
+impl FromSql<'_> for Role {
+    fn from_sql(
+        ty: &tokio_postgres::types::Type,
+        raw: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let name = <&str as FromSql>::from_sql(ty, raw)?;
+        Ok(Role::parse(name)?)
+    }
+
+    fn accepts(ty: &tokio_postgres::types::Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for Role {
+    fn to_sql(
+        &self,
+        ty: &tokio_postgres::types::Type,
+        w: &mut bytes::BytesMut,
+    ) -> Result<tokio_postgres::types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.name().to_sql(ty, w)
+    }
+
+    fn accepts(ty: &tokio_postgres::types::Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    tokio_postgres::types::to_sql_checked!();
+}
+
+/*
+    * Entry of the roles catalogue
+    A role implies its parent, and so on up the chain: Admin -> Manager -> User.
+*/
+#[derive(SimpleObject, Debug, PartialEq, Clone)]
+pub struct RoleDefinition {
+    pub name: Role,
+    pub description: String,
+    pub parent: Option<Role>,
+    // built-in roles can't be renamed or deleted
+    pub built_in: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl RoleDefinition {
+    /*
+        * The built-in roles, seeded by migrations/0005_create_role_catalogue.up.sql
+        @return Vec<RoleDefinition>
+    */
+    pub fn defaults() -> Vec<RoleDefinition> {
+        let descriptions = [
+            "Every signed up user",
+            "Manages datasets and reads other users",
+            "Manages users, roles and permissions",
+        ];
+        let now = Utc::now();
+        Role::BUILT_IN
+            .iter()
+            .zip(descriptions)
+            .enumerate()
+            .map(|(i, (role, description))| RoleDefinition {
+                name: role.clone(),
+                description: description.to_string(),
+                parent: i
+                    .checked_sub(1)
+                    .map(|parent| Role::BUILT_IN[parent].clone()),
+                built_in: true,
+                created_at: Some(now),
+                updated_at: Some(now),
+            })
+            .collect()
+    }
+}
+
+/*
+ * Snapshot of the catalogue, resolves which roles a role implies
+*/
+pub struct RoleCatalogue(Vec<RoleDefinition>);
+
+impl RoleCatalogue {
+    pub fn new(definitions: Vec<RoleDefinition>) -> RoleCatalogue {
+        RoleCatalogue(definitions)
+    }
+
+    /*
+        * The role and every ancestor through the parent chain
+        @param role: &Role
+        @return Vec<Role>, the role first
+    */
+    pub fn ancestors(&self, role: &Role) -> Vec<Role> {
+        let mut chain = vec![role.clone()];
+        let mut current = role;
+        while let Some(parent) = self
+            .0
+            .iter()
+            .find(|definition| definition.name == *current)
+            .and_then(|definition| definition.parent.as_ref())
+        {
+            // parents are set at creation and must already exist, a loop means corrupted data
+            if chain.contains(parent) {
+                break;
+            }
+            chain.push(parent.clone());
+            current = parent;
+        }
+        chain
+    }
+
+    /*
+        * Roles implied by holding some roles, held ones included
+        @param held: &[Role]
+        @return Vec<Role>, sorted
+    */
+    pub fn implied(&self, held: &[Role]) -> Vec<Role> {
+        let mut implied: Vec<Role> = held.iter().flat_map(|role| self.ancestors(role)).collect();
+        implied.sort();
+        implied.dedup();
+        implied
+    }
+
+    /*
+        * Whether holding role grants what other grants
+        @param role: &Role
+        @param other: &Role
+        @return bool
+    */
+    pub fn implies(&self, role: &Role, other: &Role) -> bool {
+        self.ancestors(role).contains(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalogue() -> RoleCatalogue {
+        let mut definitions = RoleDefinition::defaults();
+        definitions.push(RoleDefinition {
+            name: Role::parse("Analyst").unwrap(),
+            description: String::new(),
+            parent: Some(Role::USER),
+            built_in: false,
+            created_at: None,
+            updated_at: None,
+        });
+        RoleCatalogue::new(definitions)
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let catalogue = catalogue();
+        let analyst = Role::parse("Analyst").unwrap();
+        assert_eq!(
+            catalogue.ancestors(&Role::ADMIN),
+            vec![Role::ADMIN, Role::MANAGER, Role::USER]
+        );
+        assert!(catalogue.implies(&Role::ADMIN, &Role::MANAGER));
+        assert!(catalogue.implies(&Role::MANAGER, &Role::USER));
+        assert!(catalogue.implies(&Role::MANAGER, &Role::MANAGER));
+        assert!(!catalogue.implies(&Role::MANAGER, &Role::ADMIN));
+        assert!(catalogue.implies(&analyst, &Role::USER));
+        assert!(!catalogue.implies(&analyst, &Role::MANAGER));
+        assert!(!catalogue.implies(&Role::ADMIN, &analyst));
+        assert_eq!(
+            catalogue.implied(&[analyst.clone(), Role::USER]),
+            vec![analyst, Role::USER]
+        );
+        // unknown roles imply nothing but themselves
+        let unknown = Role::parse("Unknown").unwrap();
+        assert_eq!(catalogue.ancestors(&unknown), vec![unknown]);
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        assert_eq!(Role::parse("Admin"), Ok(Role::ADMIN));
+        for name in ["", "Data Admin", "admin;drop", &"a".repeat(65)] {
+            assert!(Role::parse(name).is_err(), "{}", name);
+        }
+        assert!(serde_json::from_str::<Role>("\"not a role\"").is_err());
+        assert_eq!(
+            serde_json::from_str::<Role>("\"Manager\"").unwrap(),
+            Role::MANAGER
+        );
+        assert!(<Role as ScalarType>::parse(Value::from("bad name")).is_err());
+        assert!(<Role as ScalarType>::parse(Value::from(1)).is_err());
+    }
+}
@@ -1,19 +1,115 @@
+use std::time::Duration;
+
 use async_graphql::*;
 use chrono::{DateTime, Utc};
 
+use crate::{
+    database::backend::Database,
+    errors::main::{AppError, AppResultExt},
+    structs::{claims::Plan, role::Role},
+    traits::user::UserTrait,
+};
+
 #[derive(SimpleObject, Debug, PartialEq, InputObject, Clone)]
-#[graphql(input_name = "UserInput")]
+#[graphql(input_name = "UserInput", complex)]
 pub struct User {
     pub id: Option<String>,
     pub name: String,
     pub email: String,
     #[graphql(secret)]
     pub password: String,
+    // changed by Admins only, see Mutation::set_user_plan
+    #[graphql(skip_input)]
+    pub plan: Plan,
+    // set once the user follows the link sent to its email, see Mutation::verify_email
+    #[graphql(skip_input)]
+    pub email_verified: bool,
+    // suspended by an Admin, the user can't sign in nor call the api, see Mutation::suspend_user
+    #[graphql(skip_input)]
+    pub disabled: bool,
+    // soft deleted by an Admin, purged for good once the retention period is over
+    #[graphql(skip_input)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    // why the user was suspended or deleted, cleared on reactivation
+    #[graphql(skip_input)]
+    pub status_reason: Option<String>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserPurgeConfig {
+    // how long soft deleted users are kept before being deleted for good
+    pub retention: Duration,
+    // time between two purges
+    pub interval: Duration,
+}
+
+impl UserPurgeConfig {
+    /*
+        * Users soft deleted before the cutoff are due for the purge
+        @param now: DateTime<Utc>
+        @return DateTime<Utc>
+    */
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.retention)
+            .ok()
+            .and_then(|retention| now.checked_sub_signed(retention))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+}
+
+impl Default for UserPurgeConfig {
+    fn default() -> Self {
+        UserPurgeConfig {
+            retention: Duration::from_secs(30 * 24 * 3600),
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+// bcrypt cost, lowered in tests where hashing would dominate the run time
+#[cfg(not(test))]
+const PASSWORD_COST: u32 = bcrypt::DEFAULT_COST;
+#[cfg(test)]
+const PASSWORD_COST: u32 = 4;
+
+/*
+    * Hash a password before storing it
+    @param password: &str
+    @return bcrypt hash
+*/
+pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
+    bcrypt::hash(password, PASSWORD_COST)
+}
+
+#[ComplexObject]
 impl User {
+    /*
+        * Roles held by the user
+        @return Vec<Role>
+    */
+    async fn roles<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Role>, Error> {
+        let uid = self.id.as_deref().ok_or(AppError::NotFound).app_err()?;
+        ctx.data::<Database>()?.get_user_roles(uid).await.app_err()
+    }
+}
+
+impl User {
+    /*
+        * Refuse suspended and soft deleted users
+        @return (), AccountDeleted or AccountSuspended
+    */
+    pub fn ensure_active(&self) -> Result<(), AppError> {
+        if self.deleted_at.is_some() {
+            Err(AppError::AccountDeleted)
+        } else if self.disabled {
+            Err(AppError::AccountSuspended)
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn fill_id(&mut self, id: String) {
         self.id = Some(id);
         self.created_at = Some(Utc::now());
@@ -0,0 +1,151 @@
+use async_graphql::{
+    futures_util::{future, Stream, StreamExt},
+    *,
+};
+
+use crate::{
+    contexts::identity::Identity,
+    errors::main::AppError,
+    events::main::{EventBus, UserEvent},
+    guards::auth::AuthTokenGuard,
+    structs::{claims::UserClaims, role::Role, user::User},
+};
+
+pub struct Subscription;
+
+fn my_events(ctx: &Context<'_>) -> Result<impl Stream<Item = UserEvent>, Error> {
+    let uid = ctx
+        .data::<Identity>()?
+        .uid()
+        .ok_or_else(|| AppError::Unauthorized.extend())?;
+    Ok(ctx.data::<EventBus>()?.subscribe(&uid.0))
+}
+
+#[Subscription]
+impl Subscription {
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_profile_changed<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<impl Stream<Item = User>, Error> {
+        Ok(my_events(ctx)?.filter_map(|event| {
+            future::ready(match event {
+                UserEvent::ProfileChanged(user) => Some(user),
+                _ => None,
+            })
+        }))
+    }
+
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_roles_changed<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<impl Stream<Item = Vec<Role>>, Error> {
+        Ok(my_events(ctx)?.filter_map(|event| {
+            future::ready(match event {
+                UserEvent::RolesChanged { roles, .. } => Some(roles),
+                _ => None,
+            })
+        }))
+    }
+
+    /*
+        * Claims of the signed in user, each time they are pushed to the auth provider
+        The token in use still carries the previous ones, force a refresh when this fires.
+        @return Stream<UserClaims>
+    */
+    #[graphql(guard = "AuthTokenGuard")]
+    async fn my_claims_changed<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<impl Stream<Item = UserClaims>, Error> {
+        Ok(my_events(ctx)?.filter_map(|event| {
+            future::ready(match event {
+                UserEvent::ClaimsChanged { claims, .. } => Some(claims),
+                _ => None,
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations::main::Mutation;
+    use crate::queries::main::Query;
+    use crate::traits::user::UserTrait;
+    use crate::utils::Utils;
+    use async_graphql::Schema;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_my_profile_and_roles_changed() {
+        let database = Utils::memory_database();
+        let me = Uuid::new_v4().to_string();
+        let other = database.crate_random_user().await.unwrap().id.unwrap();
+        let schema = Schema::build(Query, Mutation, Subscription)
+            .data(database)
+            .data(EventBus::new())
+            .finish();
+
+        let mut profile = schema.execute_stream(
+            Request::new("subscription { myProfileChanged { id name } }")
+                .data(Identity::verified(&me)),
+        );
+        let mut roles = schema.execute_stream(
+            Request::new("subscription { myRolesChanged }").data(Identity::verified(&me)),
+        );
+        // streams are lazy, poll them once so they subscribe before anything is published
+        let idle = Duration::from_millis(20);
+        assert!(tokio::time::timeout(idle, profile.next()).await.is_err());
+        assert!(tokio::time::timeout(idle, roles.next()).await.is_err());
+
+        let update = r#"mutation { updateUserName(userName: "renamed") { name } }"#;
+        let res = schema
+            .execute(Request::new(update).data(Identity::verified(&other)))
+            .await;
+        assert_eq!(res.errors.first(), None);
+
+        let create = r#"mutation { createUser(input: {name: "me", email: "me@example.com", password: "password"}) { id } }"#;
+        let res = schema
+            .execute(Request::new(create).data(Identity::verified(&me)))
+            .await;
+        assert_eq!(res.errors.first(), None);
+        let res = schema
+            .execute(Request::new(update).data(Identity::verified(&me)))
+            .await;
+        assert_eq!(res.errors.first(), None);
+
+        // the rename of the other user is never seen
+        let res = profile.next().await.unwrap();
+        assert_eq!(res.errors.first(), None);
+        assert_eq!(
+            res.data,
+            value!({"myProfileChanged": {"id": me.clone(), "name": "me"}})
+        );
+        let res = profile.next().await.unwrap();
+        assert_eq!(
+            res.data,
+            value!({"myProfileChanged": {"id": me.clone(), "name": "renamed"}})
+        );
+        let res = roles.next().await.unwrap();
+        assert_eq!(res.data, value!({"myRolesChanged": ["User"]}));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_requires_a_token() {
+        let schema = Schema::build(Query, Mutation, Subscription)
+            .data(Utils::memory_database())
+            .data(EventBus::new())
+            .finish();
+        let res = schema
+            .execute_stream(
+                Request::new("subscription { myRolesChanged }").data(Identity::default()),
+            )
+            .next()
+            .await
+            .unwrap();
+        assert_eq!(res.errors[0].message, "Auth::Unauthorized");
+    }
+}
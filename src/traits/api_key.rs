@@ -0,0 +1,50 @@
+use uuid::Uuid;
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::{api_key::ApiKey, role::Role};
+
+#[allow(async_fn_in_trait)]
+pub trait ApiKeyTrait {
+    /*
+    * store an api key
+    @param api_key: &ApiKey
+    @return ApiKey, NotFound for an unknown user
+    */
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<ApiKey, Error>;
+    /*
+    * get an api key, revoked ones included
+    @param id: &Uuid
+    @return ApiKey
+    */
+    async fn get_api_key(&self, id: &Uuid) -> Result<ApiKey, Error>;
+    /*
+    * api keys of a user, revoked ones included
+    @param user_uid: &str
+    @return Vec<ApiKey>, oldest first
+    */
+    async fn get_user_api_keys(&self, user_uid: &str) -> Result<Vec<ApiKey>, Error>;
+    /*
+    * rename an api key or change its scopes
+    @param id: &Uuid
+    @param name: Option<&str>, unchanged when None
+    @param scopes: Option<&[Role]>, unchanged when None
+    @return ApiKey, Expired once revoked
+    */
+    async fn update_api_key(
+        &self,
+        id: &Uuid,
+        name: Option<&str>,
+        scopes: Option<&[Role]>,
+    ) -> Result<ApiKey, Error>;
+    /*
+    * revoke an api key, it stops authenticating at once
+    @param id: &Uuid
+    @return ApiKey, Expired once revoked
+    */
+    async fn revoke_api_key(&self, id: &Uuid) -> Result<ApiKey, Error>;
+    /*
+    * record that an api key authenticated a request, at most once a minute
+    @param id: &Uuid
+    */
+    async fn touch_api_key(&self, id: &Uuid) -> Result<(), Error>;
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+
+use crate::structs::{claims::UserClaims, user::User};
+
+/*
+ * Claims of a verified token, whatever the provider
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuthClaims {
+    pub uid: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    // unix timestamp in seconds
+    pub expires_at: i64,
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    Unauthorized,
+    InvalidCredentials,
+    NotFound,
+    Conflict(String),
+    Upstream(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthorized => write!(f, "Auth::Unauthorized"),
+            AuthError::InvalidCredentials => write!(f, "Auth::InvalidCredentials"),
+            AuthError::NotFound => write!(f, "Auth::NotFound"),
+            AuthError::Conflict(e) => write!(f, "Auth::Conflict: {}", e),
+            AuthError::Upstream(e) => write!(f, "Auth::Upstream: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+#[allow(async_fn_in_trait)]
+pub trait AuthProvider {
+    /*
+    * verify a token sent by a client
+    @param token: &str
+    @return AuthClaims
+    */
+    async fn verify_token(&self, token: &str) -> Result<AuthClaims, AuthError>;
+    /*
+    * sign in with email and password
+    @param email: &str
+    @param password: &str
+    @return token usable with verify_token
+    */
+    async fn sign_in(&self, email: &str, password: &str) -> Result<String, AuthError>;
+    /*
+    * create an identity, user.id is used as uid
+    @param user: &User
+    @return uid
+    */
+    async fn create_identity(&self, user: &User) -> Result<String, AuthError>;
+    /*
+    * update email, display name and password of an identity
+    @param uid: &str
+    @param user: &User
+    */
+    async fn update_identity(&self, uid: &str, user: &User) -> Result<(), AuthError>;
+    /*
+    * delete an identity
+    @param uid: &str
+    */
+    async fn delete_identity(&self, uid: &str) -> Result<(), AuthError>;
+    /*
+    * check the password of an identity
+    @param uid: &str
+    @param password: &str
+    @return false when it is wrong, NotFound for an unknown identity
+    */
+    async fn check_password(&self, uid: &str, password: &str) -> Result<bool, AuthError>;
+    /*
+    * change the password of an identity, its sessions are revoked:
+    tokens issued before stop verifying, or refreshing for providers with refresh tokens
+    @param uid: &str
+    @param new_password: &str
+    */
+    async fn change_password(&self, uid: &str, new_password: &str) -> Result<(), AuthError>;
+    /*
+    * mark the email of an identity verified, tokens issued afterwards say so
+    @param uid: &str
+    */
+    async fn set_email_verified(&self, uid: &str) -> Result<(), AuthError>;
+    /*
+    * disable or enable an identity, a disabled identity can't sign in nor refresh its tokens
+    @param uid: &str
+    @param disabled: bool
+    */
+    async fn set_disabled(&self, uid: &str, disabled: bool) -> Result<(), AuthError>;
+    /*
+    * replace the custom claims of an identity, tokens issued afterwards carry them
+    @param uid: &str
+    @param claims: &UserClaims
+    */
+    async fn set_custom_claims(&self, uid: &str, claims: &UserClaims) -> Result<(), AuthError>;
+}
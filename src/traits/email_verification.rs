@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::email_verification::EmailVerification;
+
+#[allow(async_fn_in_trait)]
+pub trait EmailVerificationTrait {
+    /*
+    * store an email verification for a user, its pending verifications stop working
+    @param user_uid: &str
+    @param email: &str, the email the link is sent to
+    @param token_hash: &str
+    @param expires_at: DateTime<Utc>
+    @return EmailVerification, NotFound for an unknown user
+    */
+    async fn create_email_verification(
+        &self,
+        user_uid: &str,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailVerification, Error>;
+    /*
+    * use a pending email verification up and mark the email of the user verified,
+    a token is redeemed once even concurrently
+    @param token_hash: &str
+    @return EmailVerification, NotFound for an unknown token,
+    Expired once used, expired or when the user changed its email since
+    */
+    async fn consume_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerification, Error>;
+    /*
+    * email verifications of a user, used and expired ones included
+    @param user_uid: &str
+    @return Vec<EmailVerification>, oldest first
+    */
+    async fn get_user_email_verifications(
+        &self,
+        user_uid: &str,
+    ) -> Result<Vec<EmailVerification>, Error>;
+}
@@ -0,0 +1,35 @@
+use crate::database::error::DatabaseError as Error;
+use crate::structs::identity::LocalIdentity;
+
+#[allow(async_fn_in_trait)]
+pub trait IdentityTrait {
+    /*
+    * save a new identity
+    @param identity: &LocalIdentity
+    */
+    async fn create_identity(&self, identity: &LocalIdentity) -> Result<(), Error>;
+    /*
+    * get identity by uid
+    @param uid: &str
+    @return Option<LocalIdentity>
+    */
+    async fn get_identity(&self, uid: &str) -> Result<Option<LocalIdentity>, Error>;
+    /*
+    * get identity by email
+    @param email: &str
+    @return Option<LocalIdentity>
+    */
+    async fn get_identity_by_email(&self, email: &str) -> Result<Option<LocalIdentity>, Error>;
+    /*
+    * update every field of an identity
+    @param identity: &LocalIdentity
+    @return false if the identity doesn't exist
+    */
+    async fn update_identity(&self, identity: &LocalIdentity) -> Result<bool, Error>;
+    /*
+    * delete identity
+    @param uid: &str
+    @return false if the identity doesn't exist
+    */
+    async fn delete_identity(&self, uid: &str) -> Result<bool, Error>;
+}
@@ -0,0 +1,54 @@
+use uuid::Uuid;
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::impersonation::{Impersonation, ImpersonationEvent};
+
+#[allow(async_fn_in_trait)]
+pub trait ImpersonationTrait {
+    /*
+    * store an impersonation session
+    @param impersonation: &Impersonation
+    @return Impersonation, NotFound for an unknown target user
+    */
+    async fn create_impersonation(
+        &self,
+        impersonation: &Impersonation,
+    ) -> Result<Impersonation, Error>;
+    /*
+    * get an impersonation session, ended and expired ones included
+    @param id: &Uuid
+    @return Impersonation
+    */
+    async fn get_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error>;
+    /*
+    * impersonation sessions, kept when their users are deleted
+    @param user_uid: Option<&str>, only those started by or acting as the user when set
+    @return Vec<Impersonation>, newest first
+    */
+    async fn get_impersonations(&self, user_uid: Option<&str>)
+        -> Result<Vec<Impersonation>, Error>;
+    /*
+    * end an impersonation session before it expires
+    @param id: &Uuid
+    @return Impersonation, Expired once ended
+    */
+    async fn end_impersonation(&self, id: &Uuid) -> Result<Impersonation, Error>;
+    /*
+    * record a request made during an impersonation session, records are never updated
+    @param event: &ImpersonationEvent
+    @return ImpersonationEvent, NotFound for an unknown session
+    */
+    async fn record_impersonation_event(
+        &self,
+        event: &ImpersonationEvent,
+    ) -> Result<ImpersonationEvent, Error>;
+    /*
+    * requests made during an impersonation session
+    @param impersonation_id: &Uuid
+    @return Vec<ImpersonationEvent>, oldest first
+    */
+    async fn get_impersonation_events(
+        &self,
+        impersonation_id: &Uuid,
+    ) -> Result<Vec<ImpersonationEvent>, Error>;
+}
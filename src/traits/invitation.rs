@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::{invitation::Invitation, user::User};
+
+#[allow(async_fn_in_trait)]
+pub trait InvitationTrait {
+    /*
+    * store an invitation
+    @param invitation: &Invitation
+    @return Invitation, Conflict while another one is open for the email in the same organization,
+    NotFound for an unknown role or organization
+    */
+    async fn create_invitation(&self, invitation: &Invitation) -> Result<Invitation, Error>;
+    /*
+    * get an invitation
+    @param id: &Uuid
+    @return Invitation
+    */
+    async fn get_invitation(&self, id: &Uuid) -> Result<Invitation, Error>;
+    /*
+    * get an invitation by the hash of its token
+    @param token_hash: &str
+    @return Invitation, whatever its status
+    */
+    async fn get_invitation_by_token(&self, token_hash: &str) -> Result<Invitation, Error>;
+    /*
+    * invitations neither accepted nor revoked, expired ones included so they can be resent
+    @param organization_id: Option<&Uuid>, None for the invitations to the platform
+    @return Vec<Invitation>, oldest first
+    */
+    async fn get_open_invitations(
+        &self,
+        organization_id: Option<&Uuid>,
+    ) -> Result<Vec<Invitation>, Error>;
+    /*
+    * invitations about a user, sent by it, accepted by it or addressed to its email
+    @param user_uid: &str
+    @return Vec<Invitation>, oldest first, NotFound for an unknown user
+    */
+    async fn get_user_invitations(&self, user_uid: &str) -> Result<Vec<Invitation>, Error>;
+    /*
+    * give an open invitation a new token, the previous one stops working
+    @param id: &Uuid
+    @param token_hash: &str
+    @param expires_at: DateTime<Utc>
+    @return Invitation, Expired once accepted or revoked
+    */
+    async fn renew_invitation(
+        &self,
+        id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invitation, Error>;
+    /*
+    * revoke an open invitation
+    @param id: &Uuid
+    @return Invitation, Expired once accepted or revoked
+    */
+    async fn revoke_invitation(&self, id: &Uuid) -> Result<Invitation, Error>;
+    /*
+    * accept a pending invitation in one transaction: the user is created unless it exists,
+    then gets the role, in the organization of the invitation if any
+    @param token_hash: &str
+    @param user: &User, its email is replaced by the invited one
+    @return Invitation, NotFound for an unknown token, Expired when it isn't pending
+    */
+    async fn accept_invitation(&self, token_hash: &str, user: &User) -> Result<Invitation, Error>;
+}
@@ -0,0 +1,39 @@
+use std::fmt;
+
+/*
+ * Plain text email, the sender is set by the mailer
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MailError {
+    // an address or header the message can't be built with
+    Invalid(String),
+    // the message couldn't be handed over, i.e the smtp server refused it
+    Transport(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Invalid(e) => write!(f, "Mail::Invalid: {}", e),
+            MailError::Transport(e) => write!(f, "Mail::Transport: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+#[allow(async_fn_in_trait)]
+pub trait Mailer {
+    /*
+    * send an email, returns once the backend accepted it
+    @param email: &Email
+    */
+    async fn send(&self, email: &Email) -> Result<(), MailError>;
+}
@@ -1 +1,12 @@
+pub mod api_key;
+pub mod auth;
+pub mod email_verification;
+pub mod identity;
+pub mod impersonation;
+pub mod invitation;
+pub mod mailer;
+pub mod organization;
+pub mod password_reset;
+pub mod permission;
+pub mod role;
 pub mod user;
@@ -0,0 +1,77 @@
+use uuid::Uuid;
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::{
+    organization::{Membership, Organization},
+    role::Role,
+};
+
+#[allow(async_fn_in_trait)]
+pub trait OrganizationTrait {
+    /*
+    * create an organization, its creator joins it as Admin
+    @param name: &str
+    @param owner_uid: &str
+    @return Organization, NotFound for an unknown owner
+    */
+    async fn create_organization(&self, name: &str, owner_uid: &str)
+        -> Result<Organization, Error>;
+    /*
+    * get an organization
+    @param id: &Uuid
+    @return Organization
+    */
+    async fn get_organization(&self, id: &Uuid) -> Result<Organization, Error>;
+    /*
+    * organizations a user is a member of
+    @param user_uid: &str
+    @return Vec<Organization>, sorted by name
+    */
+    async fn get_user_organizations(&self, user_uid: &str) -> Result<Vec<Organization>, Error>;
+    /*
+    * rename an organization
+    @param id: &Uuid
+    @param name: &str
+    @return Organization
+    */
+    async fn rename_organization(&self, id: &Uuid, name: &str) -> Result<Organization, Error>;
+    /*
+    * delete an organization and its memberships
+    @param id: &Uuid
+    */
+    async fn delete_organization(&self, id: &Uuid) -> Result<(), Error>;
+    /*
+    * members of an organization
+    @param id: &Uuid
+    @return Vec<Membership>, oldest first
+    */
+    async fn get_members(&self, id: &Uuid) -> Result<Vec<Membership>, Error>;
+    /*
+    * membership of a user in an organization
+    @param id: &Uuid
+    @param user_uid: &str
+    @return None when the user isn't a member
+    */
+    async fn get_membership(&self, id: &Uuid, user_uid: &str) -> Result<Option<Membership>, Error>;
+    /*
+    * add a member, or change the role of a member
+    @param id: &Uuid
+    @param user_uid: &str
+    @param role: &Role
+    @return Membership, NotFound for an unknown organization, user or role,
+    LastAdmin when it demotes the last Admin of the organization
+    */
+    async fn save_membership(
+        &self,
+        id: &Uuid,
+        user_uid: &str,
+        role: &Role,
+    ) -> Result<Membership, Error>;
+    /*
+    * remove a member
+    @param id: &Uuid
+    @param user_uid: &str
+    @return NotFound when not a member, LastAdmin for the last Admin of the organization
+    */
+    async fn remove_membership(&self, id: &Uuid, user_uid: &str) -> Result<(), Error>;
+}
@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::password_reset::PasswordReset;
+
+#[allow(async_fn_in_trait)]
+pub trait PasswordResetTrait {
+    /*
+    * store a password reset for the user with an email, its pending resets stop working
+    @param email: &str, normalized
+    @param token_hash: &str
+    @param expires_at: DateTime<Utc>
+    @return PasswordReset, NotFound when no user has the email
+    */
+    async fn create_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PasswordReset, Error>;
+    /*
+    * use a pending password reset up, a token is redeemed once even concurrently
+    @param token_hash: &str
+    @return PasswordReset, NotFound for an unknown token, Expired once used or expired
+    */
+    async fn consume_password_reset(&self, token_hash: &str) -> Result<PasswordReset, Error>;
+    /*
+    * password resets of a user, used and expired ones included
+    @param user_uid: &str
+    @return Vec<PasswordReset>, oldest first
+    */
+    async fn get_user_password_resets(&self, user_uid: &str) -> Result<Vec<PasswordReset>, Error>;
+}
@@ -0,0 +1,50 @@
+use crate::database::error::DatabaseError as Error;
+use crate::structs::permission::Permission;
+use crate::structs::role::Role;
+
+#[allow(async_fn_in_trait)]
+pub trait PermissionTrait {
+    /*
+    * list every known permission
+    @return Vec<Permission>, sorted by name
+    */
+    async fn get_permissions(&self) -> Result<Vec<Permission>, Error>;
+    /*
+    * add a permission to the catalogue
+    @param name: &str, resource:action
+    @param description: &str
+    @return Permission, Conflict if it exists
+    */
+    async fn create_permission(&self, name: &str, description: &str) -> Result<Permission, Error>;
+    /*
+    * remove a permission and every grant of it
+    @param name: &str
+    */
+    async fn delete_permission(&self, name: &str) -> Result<(), Error>;
+    /*
+    * permissions granted to a role itself, without those of the roles below it
+    @param role: &Role
+    @return Vec<String>, sorted
+    */
+    async fn get_role_permissions(&self, role: &Role) -> Result<Vec<String>, Error>;
+    /*
+    * grant a permission to a role
+    @param role: &Role
+    @param permission: &str
+    @return NotFound for an unknown permission, Conflict if already granted
+    */
+    async fn grant_permission(&self, role: &Role, permission: &str) -> Result<(), Error>;
+    /*
+    * revoke a permission from a role
+    @param role: &Role
+    @param permission: &str
+    @return NotFound if it wasn't granted
+    */
+    async fn revoke_permission(&self, role: &Role, permission: &str) -> Result<(), Error>;
+    /*
+    * permissions of a user, through its roles and the roles they imply
+    @param user_uid: &str
+    @return Vec<String>, sorted
+    */
+    async fn get_user_permissions(&self, user_uid: &str) -> Result<Vec<String>, Error>;
+}
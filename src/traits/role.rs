@@ -0,0 +1,37 @@
+use crate::database::error::DatabaseError as Error;
+use crate::structs::role::{Role, RoleDefinition};
+
+#[allow(async_fn_in_trait)]
+pub trait RoleTrait {
+    /*
+    * list the roles catalogue
+    @return Vec<RoleDefinition>, sorted by name
+    */
+    async fn get_role_definitions(&self) -> Result<Vec<RoleDefinition>, Error>;
+    /*
+    * add a role to the catalogue
+    @param name: &Role
+    @param description: &str
+    @param parent: Option<&Role>, the role it implies
+    @return RoleDefinition, Conflict if it exists, NotFound for an unknown parent
+    */
+    async fn create_role(
+        &self,
+        name: &Role,
+        description: &str,
+        parent: Option<&Role>,
+    ) -> Result<RoleDefinition, Error>;
+    /*
+    * rename a role, users and permissions keep it
+    @param name: &Role
+    @param new_name: &Role
+    @return RoleDefinition, BuiltInRole for a built-in role
+    */
+    async fn rename_role(&self, name: &Role, new_name: &Role) -> Result<RoleDefinition, Error>;
+    /*
+    * delete a role, it is taken from every user holding it
+    @param name: &Role
+    @return BuiltInRole for a built-in role, RoleInUse while it is the parent of another role
+    */
+    async fn delete_role(&self, name: &Role) -> Result<(), Error>;
+}
@@ -1,55 +1,148 @@
-use crate::enums::role::Role;
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DatabaseError as Error;
+use crate::structs::claims::{Plan, UserClaims};
+use crate::structs::role::Role;
 use crate::structs::user::User;
-use tokio_postgres::Error;
 
+#[allow(async_fn_in_trait)]
 pub trait UserTrait {
     /*
     * get user roles
     @param user_uid: &str
     @return Vec<Role>
     */
-    async fn get_user_roles<'a>(&self, user_uid: &'a str) -> Result<Vec<Role>, Error>;
+    async fn get_user_roles(&self, user_uid: &str) -> Result<Vec<Role>, Error>;
     /*
-    * save user roles
+    * save user roles, bumps the claims version of the user
     @param user_uid: &str
     @param roles: Vec<Role>
     */
-    async fn save_user_role<'a>(&self, user_uid: &'a str, roles: &'a Role) -> Result<(), Error>;
+    async fn save_user_role(&self, user_uid: &str, roles: &Role) -> Result<(), Error>;
+    /*
+    * remove a role from a user, the last Admin can't lose the Admin role, bumps the claims version
+    @param user_uid: &str
+    @param role: &Role
+    @return NotFound if the user doesn't hold the role, LastAdmin
+    */
+    async fn remove_user_role(&self, user_uid: &str, role: &Role) -> Result<(), Error>;
+    /*
+    * get users holding a role
+    @param role: &Role
+    @return Vec<User>, oldest first
+    */
+    async fn get_users_by_role(&self, role: &Role) -> Result<Vec<User>, Error>;
     /*
-    * create user
+    * create user along with its User role, both or neither are stored
     @param user: User
     @return User
 
     */
-    async fn create_user<'a>(&self, user: &'a User) -> Result<User, Error>;
+    async fn create_user(&self, user: &User) -> Result<User, Error>;
+    /*
+    * delete a user, its roles, memberships, api keys and the invitations addressed to it go with it
+    @param user_uid: &str
+    @return NotFound for an unknown user,
+    LastAdmin for the last Admin, of the platform or of one of its organizations
+    */
+    async fn delete_user(&self, user_uid: &str) -> Result<(), Error>;
     /*
     * update user name
     @param user_name: String
     @return User
 
     */
-    async fn update_user_name<'a>(
-        &self,
-        user_name: &'a str,
-        user_uid: &'a str,
-    ) -> Result<User, Error>;
+    async fn update_user_name(&self, user_name: &str, user_uid: &str) -> Result<User, Error>;
+
+    /*
+    * replace the bcrypt hash of the password kept with the user
+    @param user_uid: &str
+    @param password: &str, in clear
+    @return NotFound for an unknown user
+    */
+    async fn update_user_password(&self, user_uid: &str, password: &str) -> Result<(), Error>;
+
+    /*
+    * change the email of a user, the new one isn't verified yet
+    @param user_uid: &str
+    @param email: &str
+    @return User, NotFound for an unknown user
+    */
+    async fn update_user_email(&self, user_uid: &str, email: &str) -> Result<User, Error>;
 
     /*
     * get user
     @param user_uid: &str
     @return User
     */
-    async fn get_user<'a>(&self, user_uid: &'a str) -> Result<User, Error>;
+    async fn get_user(&self, user_uid: &str) -> Result<User, Error>;
+
+    /*
+    * change the plan of a user, bumps the claims version
+    @param user_uid: &str
+    @param plan: Plan
+    @return User
+    */
+    async fn update_user_plan(&self, user_uid: &str, plan: Plan) -> Result<User, Error>;
+
+    /*
+    * change the suspension and soft delete state of a user
+    @param user_uid: &str
+    @param disabled: bool
+    @param deleted_at: Option<DateTime<Utc>>, None for a user that isn't deleted
+    @param reason: Option<&str>
+    @return User, NotFound for an unknown user
+    */
+    async fn update_user_status(
+        &self,
+        user_uid: &str,
+        disabled: bool,
+        deleted_at: Option<DateTime<Utc>>,
+        reason: Option<&str>,
+    ) -> Result<User, Error>;
+
+    /*
+    * get users soft deleted before a given time
+    @param before: DateTime<Utc>
+    @return Vec<User>, oldest deletion first
+    */
+    async fn get_users_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<User>, Error>;
+
+    /*
+    * claims to push to the auth provider
+    @param user_uid: &str
+    @return UserClaims, NotFound for an unknown user
+    */
+    async fn get_user_claims(&self, user_uid: &str) -> Result<UserClaims, Error>;
 
     /*
      * crate random user into the database
      */
     #[cfg(test)]
-    async fn crate_random_user<'a>(&self) -> Result<User, Error>;
+    async fn crate_random_user(&self) -> Result<User, Error> {
+        self.create_test_user(&uuid::Uuid::new_v4().to_string())
+            .await
+    }
 
     /*
      * crate test user for mutation and query testing
      */
     #[cfg(test)]
-    async fn create_test_user<'a>(&self, uuid: &'a str) -> Result<User, Error>;
+    async fn create_test_user(&self, uuid: &str) -> Result<User, Error> {
+        let now = chrono::Utc::now();
+        let user = User {
+            id: Some(uuid.to_string()),
+            name: "test".to_string(),
+            email: format!("{}@gmail.com", uuid),
+            password: uuid.to_string(),
+            plan: Plan::Free,
+            email_verified: false,
+            disabled: false,
+            deleted_at: None,
+            status_reason: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        self.create_user(&user).await
+    }
 }
@@ -1,49 +1,161 @@
 #[cfg(test)]
+#[allow(non_snake_case)]
 pub mod Utils {
     #[cfg(test)]
-    use std::sync::Arc;
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use crate::{
+        auth::local::{LocalAuth, LocalAuthConfig},
+        config::main::{ConfigSource, FirebaseConfig, PostgresConfig},
+        database::{backend::Database, main::PostGreClient, memory::MemoryClient},
+        structs::identity::LocalIdentity,
+        traits::identity::IdentityTrait,
+    };
 
+    /*
+        * Postgres section of the configuration, from the environment and .env
+        @return PostgresConfig
+    */
     #[cfg(test)]
-    use tokio::sync::{Mutex, RwLock};
+    pub fn postgres_config() -> PostgresConfig {
+        dotenv::dotenv().ok();
+        ConfigSource::from_env()
+            .postgres()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
+    /*
+        * Firebase section of the configuration, from the environment and .env
+        @return FirebaseConfig
+    */
     #[cfg(test)]
-    use crate::{
-        contexts::user_uid::UserUID, database::main::PostGreClient, firebase::main::Firebase,
-        structs::user::User,
-    };
+    pub fn firebase_config() -> FirebaseConfig {
+        dotenv::dotenv().ok();
+        ConfigSource::from_env()
+            .firebase()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /*
+        * Local auth provider signing with the keys in testdata/auth
+        @return LocalAuthConfig
+    */
+    #[cfg(test)]
+    pub fn local_auth_config() -> LocalAuthConfig {
+        let keys = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/auth");
+        LocalAuthConfig {
+            private_key: keys.join("private.pem"),
+            public_key: keys.join("public.pem"),
+            issuer: "data_intuitive".to_string(),
+            token_ttl: Duration::from_secs(3600),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn local_auth(database: Database) -> LocalAuth {
+        LocalAuth::new(&local_auth_config(), database)
+    }
+
+    /*
+        * Fresh database for tests, tables are dropped and migrated again
+        @return PostGreClient
+    */
+    #[cfg(test)]
+    pub async fn generate_testing_database() -> Result<PostGreClient, Box<dyn std::error::Error>> {
+        let database = PostGreClient::new(&postgres_config()).await;
+        database.drop_tables().await?;
+        database.migrate_up(None).await?;
+        Ok(database)
+    }
+
+    /*
+        * Empty in-memory backend, tests using it can run in parallel
+        @return Database
+    */
+    #[cfg(test)]
+    pub fn memory_database() -> Database {
+        Database::Memory(MemoryClient::new())
+    }
 
+    /*
+        * Fresh in-memory database and a token for uuid, issued by the local auth provider
+        so the resolver tests need neither postgres nor firebase
+        @return (Database, token)
+    */
     #[cfg(test)]
     pub async fn generate_testing_config(
         uuid: &str,
-    ) -> Result<
-        (
-            Arc<Mutex<UserUID>>,
-            Arc<Mutex<Option<User>>>,
-            Arc<RwLock<PostGreClient>>,
-            String,
-        ),
-        Box<dyn std::error::Error>,
-    > {
-        let firebase = Firebase::new().await;
-        let custom_token = firebase
-            .create_custom_token(uuid, false)
-            .await
-            .expect("Error creating custom token");
-        let id_token = firebase
-            .get_id_token(&custom_token)
-            .await
-            .expect("Error getting id token");
-        let database = PostGreClient::new().await;
-        let user_uid: Arc<Mutex<UserUID>> = Arc::new(Mutex::new(UserUID("".to_string())));
-        let user: Arc<Mutex<Option<User>>> = Arc::new(Mutex::new(None));
-        let database_rw = Arc::new(RwLock::new(database));
-        database_rw.write().await.drop_tables().await?;
-        database_rw
-            .write()
-            .await
-            .create_tables_if_not_exist()
-            .await?;
-
-        Ok((user_uid, user, database_rw, id_token))
+    ) -> Result<(Database, String), Box<dyn std::error::Error>> {
+        let database = memory_database();
+        let identity = LocalIdentity {
+            uid: uuid.to_string(),
+            email: format!("{}@example.com", uuid),
+            password: String::new(),
+            display_name: None,
+            email_verified: false,
+            disabled: false,
+            custom_claims: serde_json::Map::new(),
+            sessions_valid_after: None,
+            created_at: None,
+            updated_at: None,
+        };
+        database.create_identity(&identity).await?;
+        let token = local_auth(database.clone()).issue_token(&identity)?;
+
+        Ok((database, token))
+    }
+
+    /*
+        * Directory for a file mailer, unique to the test
+        @return PathBuf, not created yet
+    */
+    #[cfg(test)]
+    pub fn mail_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("mails-{}", uuid::Uuid::new_v4()))
+    }
+
+    /*
+        * Emails written by a file mailer since the last call, they are removed
+        Files written in a row can share their modification time, take them after each send to keep the order.
+        @param dir: &Path
+        @return Vec<String>, the whole messages
+    */
+    #[cfg(test)]
+    pub fn take_sent_emails(dir: &Path) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut emails: Vec<_> = entries
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let email = std::fs::read_to_string(&path).unwrap();
+                std::fs::remove_file(path).unwrap();
+                email
+            })
+            .collect();
+        emails.sort();
+        emails
+    }
+
+    /*
+        * Token of the link in an email
+        Long lines are sent quoted-printable, their soft line breaks are undone first.
+        @param email: &str
+        @return String
+    */
+    #[cfg(test)]
+    pub fn token_in(email: &str) -> String {
+        let email = email
+            .replace("=\r\n", "")
+            .replace("=\n", "")
+            .replace("=3D", "=");
+        let (_, token) = email.split_once("token=").expect("no token in the email");
+        token
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect()
     }
 }